@@ -1,42 +1,18 @@
 use criterion::{
     black_box, criterion_group, criterion_main, BenchmarkId, Criterion, SamplingMode, Throughput,
 };
-use std::{
-    fs::File,
-    io::{BufRead, BufReader, Read},
-    path::Path,
-};
+use std::fs::File;
 use surf_n_term::{
     color::{Color, ColorLinear},
     common::clamp,
 };
-use surf_n_term::{ColorPalette, Image, Surface, SurfaceOwned, RGBA};
-
-/// This not really a ppm loader it is not doing proper validation or anything
-fn load_ppm(path: impl AsRef<Path>) -> Result<Image, Box<dyn std::error::Error>> {
-    let mut file = BufReader::new(File::open(path)?);
-
-    // specially constructed ppm header to end with a new line
-    let mut header = String::new();
-    file.read_line(&mut header)?;
-    let (height, width) = match header.trim().split(' ').collect::<Vec<_>>().as_slice() {
-        ["P6", width, height, "255"] => (height.parse::<usize>()?, width.parse::<usize>()?),
-        _ => panic!("bad ppm file header: \"{}\"", header),
-    };
-
-    // load colors
-    let mut colors = Vec::with_capacity(height * width);
-    let mut color = [0u8; 3];
-    for _ in 0..height * width {
-        file.read_exact(&mut color)?;
-        let [r, g, b] = color;
-        colors.push(RGBA::new(r, g, b, 255));
-    }
-    Ok(Image::new(SurfaceOwned::from_vec(height, width, colors)))
-}
+use surf_n_term::{ColorDistance, ColorPalette, ColorQuantizer, Dither, Image, Surface, RGBA};
 
 fn palette_benchmark(c: &mut Criterion) {
-    let img = load_ppm("benches/flamingo.ppm").expect("failed to load flamingo.ppm");
+    let img = File::open("benches/flamingo.ppm")
+        .map_err(surf_n_term::Error::from)
+        .and_then(Image::from_pnm)
+        .expect("failed to load flamingo.ppm");
     let img_colors: Vec<_> = img.iter().copied().collect();
     let bg = RGBA::new(0, 0, 0, 255);
     let p128 = ColorPalette::from_image(&img, 128, bg).unwrap();
@@ -73,6 +49,47 @@ fn palette_benchmark(c: &mut Criterion) {
     group.finish();
 }
 
+/// Peak signal-to-noise ratio between image and its quantized version
+fn psnr(img: &Image, palette: &ColorPalette) -> f64 {
+    10.0 * (255.0 * 255.0 / img.quantize_mse(palette)).log10()
+}
+
+fn quantizer_benchmark(c: &mut Criterion) {
+    let img = File::open("benches/flamingo.ppm")
+        .map_err(surf_n_term::Error::from)
+        .and_then(Image::from_pnm)
+        .expect("failed to load flamingo.ppm");
+    let bg = RGBA::new(0, 0, 0, 255);
+    let palette_size = 256;
+
+    let mut group = c.benchmark_group("quantizer");
+    group.sampling_mode(SamplingMode::Flat);
+    group.sample_size(10);
+    group.throughput(Throughput::Elements((img.width() * img.height()) as u64));
+    for quantizer in [
+        ColorQuantizer::OcTree,
+        ColorQuantizer::MedianCut,
+        ColorQuantizer::KMeans,
+    ] {
+        for distance in [ColorDistance::Rgb, ColorDistance::OkLab] {
+            let name = format!("{:?}-{:?}", quantizer, distance);
+            let palette = quantizer
+                .palette(&img, palette_size, bg, distance)
+                .expect("failed to extract palette");
+            println!("{} PSNR: {:.2}dB", name, psnr(&img, &palette));
+            group.bench_function(name, |b| {
+                b.iter(|| {
+                    let palette = quantizer
+                        .palette(&img, palette_size, bg, distance)
+                        .expect("failed to extract palette");
+                    black_box(img.quantize_palette(&palette, Dither::default(), None))
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
 fn srgb_to_linear(color: RGBA) -> ColorLinear {
     fn s2l(x: f64) -> f64 {
         if x <= 0.04045 {
@@ -141,6 +158,6 @@ fn srgb_and_linear_benchmark(c: &mut Criterion) {
 criterion_group!(
     name = benches;
     config = Criterion::default(); // .sample_size(30).warm_up_time(Duration::new(2, 0));
-    targets = palette_benchmark, srgb_and_linear_benchmark,
+    targets = palette_benchmark, quantizer_benchmark, srgb_and_linear_benchmark,
 );
 criterion_main!(benches);
@@ -0,0 +1,427 @@
+//! Vector canvas rendered as an image or a character mosaic
+use crate::{
+    color::{Blend, Color},
+    error::Error,
+    glyph::{FillRule, Path, StrokeStyle},
+    Cell, ColorLinear, Face, Image, ImageCrop, ImageMosaic, Position, Size, Surface, SurfaceMut,
+    SurfaceOwned, TerminalCaps, TerminalSize, TerminalSurfaceExt, RGBA,
+};
+use rasterize::{ActiveEdgeRasterizer, Rasterizer, Transform};
+use std::{fmt, str::FromStr};
+
+/// Units of the coordinates used by canvas paths
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum CanvasUnits {
+    /// One unit is a terminal cell (fractional values address pixels inside the cell)
+    #[default]
+    Cells,
+    /// One unit is a canvas pixel
+    Pixels,
+}
+
+impl FromStr for CanvasUnits {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "cells" => Ok(Self::Cells),
+            "pixels" => Ok(Self::Pixels),
+            _ => Err(Error::ParseError("CanvasUnits", s.to_string())),
+        }
+    }
+}
+
+/// Text placed into the canvas cells
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CanvasLabel {
+    position: Position,
+    text: String,
+    face: Face,
+}
+
+impl CanvasLabel {
+    /// Columns occupied by the label
+    fn width(&self) -> usize {
+        self.text
+            .chars()
+            .map(|c| Cell::new(self.face, Some(c)).width().get())
+            .sum()
+    }
+}
+
+/// Anti-aliased vector canvas covering a region of terminal cells
+///
+/// Paths are filled and stroked into a pixel buffer, which is drawn either
+/// as images (if terminal supports image protocols) or as a character mosaic.
+/// Labels are placed in cells on top of the graphics.
+pub struct Canvas {
+    /// Size of the canvas in cells
+    size: Size,
+    /// Size of a cell in canvas pixels
+    cell_size: Size,
+    /// Mosaic used to draw the canvas, image is used if `None`
+    mosaic: Option<ImageMosaic>,
+    /// Pixel buffer
+    pixels: SurfaceOwned<ColorLinear>,
+    /// Text labels
+    labels: Vec<CanvasLabel>,
+}
+
+impl Canvas {
+    /// Create canvas of `size` cells with each cell covering `cell_size` pixels
+    ///
+    /// Canvas is drawn as an image, which is expected to be used with the
+    /// terminal cell size (see `TerminalSize::cell_size`).
+    pub fn new(size: Size, cell_size: Size) -> Self {
+        Self {
+            size,
+            cell_size,
+            mosaic: None,
+            pixels: SurfaceOwned::new(size.height * cell_size.height, size.width * cell_size.width),
+            labels: Vec::new(),
+        }
+    }
+
+    /// Create canvas of `size` cells suitable for the terminal
+    ///
+    /// Uses terminal mosaic if configured, or half-block mosaic if terminal
+    /// does not report its size in pixels.
+    pub fn for_terminal(size: Size, term_size: TerminalSize, caps: &TerminalCaps) -> Self {
+        let cell_size = term_size.cell_size();
+        match caps.mosaic {
+            Some(mosaic) => Self::new(size, cell_size).with_mosaic(mosaic),
+            None if cell_size.is_empty() => {
+                Self::new(size, cell_size).with_mosaic(ImageMosaic::HalfBlock)
+            }
+            None => Self::new(size, cell_size),
+        }
+    }
+
+    /// Draw canvas with character mosaic, each pixel is a single mosaic dot
+    ///
+    /// Resets the content of the canvas.
+    pub fn with_mosaic(self, mosaic: ImageMosaic) -> Self {
+        let mut canvas = Self::new(self.size, mosaic.cell_size());
+        canvas.mosaic = Some(mosaic);
+        canvas.labels = self.labels;
+        canvas
+    }
+
+    /// Size of the canvas in cells
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    /// Size of a single cell in pixels
+    pub fn cell_size(&self) -> Size {
+        self.cell_size
+    }
+
+    /// Size of the canvas in pixels
+    pub fn pixel_size(&self) -> Size {
+        Size::new(self.pixels.height(), self.pixels.width())
+    }
+
+    /// Mosaic used to draw the canvas
+    pub fn mosaic(&self) -> Option<ImageMosaic> {
+        self.mosaic
+    }
+
+    /// Transformation from `units` to canvas pixels
+    pub fn transform(&self, units: CanvasUnits) -> Transform {
+        match units {
+            CanvasUnits::Cells => {
+                Transform::new_scale(self.cell_size.width as f64, self.cell_size.height as f64)
+            }
+            CanvasUnits::Pixels => Transform::identity(),
+        }
+    }
+
+    /// Fill path with color
+    pub fn fill(&mut self, path: &Path, fill_rule: FillRule, color: RGBA, units: CanvasUnits) {
+        self.draw_path(path, self.transform(units), fill_rule, color);
+    }
+
+    /// Stroke path with color, `width` is the line width in pixels
+    pub fn stroke(&mut self, path: &Path, width: f64, color: RGBA, units: CanvasUnits) {
+        let style = StrokeStyle {
+            width,
+            line_join: Default::default(),
+            line_cap: Default::default(),
+        };
+        self.stroke_with(path, style, color, units)
+    }
+
+    /// Stroke path with color and style, width of the style is in pixels
+    pub fn stroke_with(
+        &mut self,
+        path: &Path,
+        style: StrokeStyle,
+        color: RGBA,
+        units: CanvasUnits,
+    ) {
+        // stroke in pixel space so line width is not affected by cell aspect ratio
+        let mut path = path.clone();
+        path.transform(self.transform(units));
+        let outline = path.stroke(style);
+        self.draw_path(&outline, Transform::identity(), FillRule::NonZero, color);
+    }
+
+    /// Place text label starting at the cell position
+    pub fn label(&mut self, position: Position, text: impl Into<String>, face: Face) {
+        self.labels.push(CanvasLabel {
+            position,
+            text: text.into(),
+            face,
+        });
+    }
+
+    /// Remove all graphics and labels
+    pub fn clear(&mut self) {
+        self.pixels.fill(ColorLinear::default());
+        self.labels.clear();
+    }
+
+    /// Canvas graphics as an image (labels are not included)
+    pub fn image(&self) -> Image {
+        Image::new(self.image_surface())
+    }
+
+    /// Draw canvas on the surface, anchored at the top-left cell
+    pub fn draw<S>(&self, surf: &mut S)
+    where
+        S: SurfaceMut<Item = Cell>,
+    {
+        match self.mosaic {
+            Some(ImageMosaic::Braille) => {
+                // braille dots are either on or off, so coverage is thresholded
+                let mut img = self.image_surface();
+                for color in img.iter_mut() {
+                    *color = if color.0[3] >= 128 {
+                        color.with_alpha(1.0)
+                    } else {
+                        RGBA::default()
+                    };
+                }
+                surf.draw_image_mosaic(img, ImageMosaic::Braille)
+            }
+            Some(mosaic) => surf.draw_image_mosaic(self.image_surface(), mosaic),
+            None => self.draw_images(surf),
+        }
+        for label in self.labels.iter() {
+            let Position { row, col } = label.position;
+            if row >= surf.height() || col >= surf.width() {
+                continue;
+            }
+            let mut view = surf.view_mut(row, col..);
+            let mut writer = view.writer();
+            for c in label.text.chars() {
+                if !writer.put_char(c, label.face) {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Draw graphics as images which do not overlap with labels
+    ///
+    /// Terminals can not draw text over images, so image is split into parts
+    /// around the labels. Rows with the same label spans are merged into bands.
+    fn draw_images<S>(&self, surf: &mut S)
+    where
+        S: SurfaceMut<Item = Cell>,
+    {
+        let height = self.size.height.min(surf.height());
+        let width = self.size.width.min(surf.width());
+        if height == 0 || width == 0 || self.cell_size.is_empty() {
+            return;
+        }
+        let mut rows_spans: Vec<Vec<(usize, usize)>> = vec![Vec::new(); height];
+        for label in self.labels.iter() {
+            let Position { row, col } = label.position;
+            if row < height && col < width {
+                rows_spans[row].push((col, (col + label.width()).min(width)));
+            }
+        }
+        let image = self.image();
+        let mut row = 0;
+        while row < height {
+            let spans = &rows_spans[row];
+            let mut band = 1;
+            while row + band < height && rows_spans[row + band] == *spans {
+                band += 1;
+            }
+            let mut col = 0;
+            while col < width {
+                let end = spans
+                    .iter()
+                    .filter(|(start, end)| *start <= col && col < *end)
+                    .map(|(_, end)| *end)
+                    .max();
+                if let Some(end) = end {
+                    col = end;
+                    continue;
+                }
+                let end = spans
+                    .iter()
+                    .map(|(start, _)| *start)
+                    .filter(|start| *start > col)
+                    .min()
+                    .unwrap_or(width);
+                let crop = ImageCrop::new(
+                    row * self.cell_size.height,
+                    col * self.cell_size.width,
+                    band * self.cell_size.height,
+                    (end - col) * self.cell_size.width,
+                );
+                surf.view_mut(row..row + band, col..end)
+                    .draw_image(image.crop(crop));
+                col = end;
+            }
+            row += band;
+        }
+    }
+
+    /// Rasterize path and compose it over the pixels
+    fn draw_path(&mut self, path: &Path, tr: Transform, fill_rule: FillRule, color: RGBA) {
+        let size = rasterize::Size {
+            height: self.pixels.height(),
+            width: self.pixels.width(),
+        };
+        let color: ColorLinear = color.into();
+        let shape = self.pixels.shape();
+        let data = self.pixels.data_mut();
+        let rasterizer = ActiveEdgeRasterizer::default();
+        for pixel in rasterizer.mask_iter(path, tr, size, fill_rule) {
+            let dst = &mut data[shape.offset(pixel.y, pixel.x)];
+            *dst = dst.blend(color * pixel.alpha, Blend::Over);
+        }
+    }
+
+    fn image_surface(&self) -> SurfaceOwned<RGBA> {
+        SurfaceOwned::new_with(self.pixels.height(), self.pixels.width(), |row, col| {
+            self.pixels
+                .get(row, col)
+                .map(|color| RGBA::from(*color))
+                .unwrap_or_default()
+        })
+    }
+}
+
+impl fmt::Debug for Canvas {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Canvas")
+            .field("size", &self.size)
+            .field("cell_size", &self.cell_size)
+            .field("mosaic", &self.mosaic)
+            .field("labels", &self.labels)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_canvas_image() -> Result<(), Box<dyn std::error::Error>> {
+        let red = RGBA::new(255, 0, 0, 255);
+        let mut canvas = Canvas::new(Size::new(2, 4), Size::new(4, 2));
+        assert_eq!(canvas.pixel_size(), Size::new(8, 8));
+        assert_eq!("Pixels".parse::<CanvasUnits>()?, CanvasUnits::Pixels);
+
+        // fill top-left cell, coordinates are in cells
+        let path: Path = "M0,0 h1 v1 h-1 Z".parse()?;
+        canvas.fill(&path, FillRule::NonZero, red, CanvasUnits::Cells);
+        let image = canvas.image();
+        assert_eq!(image.get(0, 0), Some(&red));
+        assert_eq!(image.get(3, 1), Some(&red));
+        assert_eq!(image.get(4, 1), Some(&RGBA::default()));
+        assert_eq!(image.get(0, 2), Some(&RGBA::default()));
+
+        // line is anti-aliased and its width is in pixels
+        let path: Path = "M0,1 h4".parse()?;
+        canvas.stroke(&path, 1.0, red, CanvasUnits::Cells);
+        let image = canvas.image();
+        assert_eq!(image.get(3, 4).map(|c| c.0[3]), Some(128));
+        assert_eq!(image.get(4, 4).map(|c| c.0[3]), Some(128));
+        assert_eq!(image.get(5, 4).map(|c| c.0[3]), Some(0));
+
+        // images are split around the label
+        let face = Face::default().with_fg(Some(red));
+        canvas.label(Position::new(1, 1), "ab", face);
+        let mut surf = SurfaceOwned::new(2, 4);
+        canvas.draw(&mut surf);
+        let crop = |row, col, height, width| image.crop(ImageCrop::new(row, col, height, width));
+        let mut expected = SurfaceOwned::new(2, 4);
+        expected.view_mut(0, ..).draw_image(crop(0, 0, 4, 8));
+        expected.view_mut(1, 0..1).draw_image(crop(4, 0, 4, 2));
+        expected.view_mut(1, 3..).draw_image(crop(4, 6, 4, 2));
+        expected
+            .view_mut(1, 1..3)
+            .writer()
+            .face(face)
+            .write_all(b"ab")?;
+        for (row, col) in [(0, 0), (1, 0), (1, 1), (1, 2), (1, 3)] {
+            assert_eq!(surf.get(row, col), expected.get(row, col));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_canvas_mosaic() -> Result<(), Box<dyn std::error::Error>> {
+        let red = RGBA::new(255, 0, 0, 255);
+        let caps = TerminalCaps {
+            mosaic: Some(ImageMosaic::Braille),
+            ..Default::default()
+        };
+        let term_size = TerminalSize {
+            cells: Size::new(10, 10),
+            pixels: Size::new(0, 0),
+        };
+        let mut canvas = Canvas::for_terminal(Size::new(1, 2), term_size, &caps);
+        assert_eq!(canvas.mosaic(), Some(ImageMosaic::Braille));
+        assert_eq!(canvas.pixel_size(), Size::new(4, 4));
+
+        // diagonal line with every pixel being a braille dot
+        let path: Path = "M0,0 L4,4".parse()?;
+        canvas.stroke(&path, 1.0, red, CanvasUnits::Pixels);
+        let face = Face::default()
+            .with_fg(Some(RGBA::new(255, 255, 255, 255)))
+            .with_bg(Some(RGBA::new(0, 0, 255, 255)));
+        canvas.label(Position::new(0, 1), "x", face);
+        let mut surf = SurfaceOwned::new(1, 2);
+        canvas.draw(&mut surf);
+        assert_ne!(surf.get(0, 0), Some(&Cell::new(Face::default(), Some(' '))));
+        assert_eq!(surf.get(0, 1), Some(&Cell::new(face, Some('x'))));
+
+        // partially covered pixels become dots only if they are mostly covered
+        let mut canvas =
+            Canvas::new(Size::new(1, 1), Size::new(0, 0)).with_mosaic(ImageMosaic::Braille);
+        let path: Path = "M0,0 L1.4,0 L1.4,2 L1.7,2 L1.7,4 L0,4 Z".parse()?;
+        canvas.fill(&path, FillRule::NonZero, red, CanvasUnits::Pixels);
+        let mut surf = SurfaceOwned::new(1, 1);
+        canvas.draw(&mut surf);
+        let face = Face::default().with_fg(Some(red));
+        assert_eq!(surf.get(0, 0), Some(&Cell::new(face, Some('⣧'))));
+
+        // faint coverage does not produce any dots
+        canvas.clear();
+        let path: Path = "M0,0 L0.3,0 L0.3,4 L0,4 Z M1,0 L1.4,0 L1.4,4 L1,4 Z".parse()?;
+        canvas.fill(&path, FillRule::NonZero, red, CanvasUnits::Pixels);
+        let mut surf = SurfaceOwned::new(1, 1);
+        canvas.draw(&mut surf);
+        assert_eq!(
+            surf.get(0, 0),
+            Some(&Cell::new(Face::default(), Some('\u{2800}')))
+        );
+
+        // no pixel size reported, falls back to half-block
+        let canvas = Canvas::for_terminal(Size::new(1, 2), term_size, &Default::default());
+        assert_eq!(canvas.mosaic(), Some(ImageMosaic::HalfBlock));
+
+        Ok(())
+    }
+}
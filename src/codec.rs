@@ -0,0 +1,1241 @@
+//! Image decoders
+//!
+//! Provides:
+//!  - PNG decoder (uses `png` crate)
+//!  - PNM (PBM/PGM/PPM/PAM) decoder, requires `pnm` feature
+//!  - QOI decoder, requires `qoi` feature
+//!  - Animated PNG (APNG) decoder
+//!  - Animated GIF decoder, requires `gif` feature
+//!  - Sixel decoder
+use crate::{
+    AnimatedImage, Blend, Color, Error, Image, Size, Surface, SurfaceMut, SurfaceOwned, RGBA,
+};
+use std::{
+    io::{BufReader, Read},
+    time::Duration,
+};
+
+/// Default maximum number of pixels in the decoded image
+pub(crate) const IMAGE_MAX_PIXELS: usize = 67108864; // 64M
+
+impl Image {
+    /// Decode image guessing its format from the magic bytes
+    ///
+    /// Images with more than 64M pixels are rejected, use [`Image::decode_with_limit`]
+    /// to change the limit.
+    pub fn decode(input: impl Read) -> Result<Image, Error> {
+        Image::decode_with_limit(input, IMAGE_MAX_PIXELS)
+    }
+
+    /// Decode image guessing its format, rejects images with more than `max_pixels` pixels
+    ///
+    /// PNG images are limited by the memory limits of the `png` crate instead.
+    pub fn decode_with_limit(mut input: impl Read, max_pixels: usize) -> Result<Image, Error> {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data)?;
+        if data.starts_with(PNG_MAGIC) {
+            return Image::from_png(data.as_slice());
+        }
+        #[cfg(feature = "qoi")]
+        if data.starts_with(QOI_MAGIC) {
+            return qoi_decode(&data, max_pixels);
+        }
+        #[cfg(feature = "gif")]
+        if data.starts_with(GIF_MAGIC) {
+            // only the first frame is decoded
+            return gif_frames(data.as_slice(), 1, max_pixels)?
+                .pop()
+                .map(|(frame, _)| frame)
+                .ok_or_else(|| Error::ParseError("GIF", "image without frames".to_string()));
+        }
+        #[cfg(feature = "pnm")]
+        if matches!(data.as_slice(), [b'P', b'1'..=b'7', ..]) {
+            return pnm_decode(&data, max_pixels);
+        }
+        if data.starts_with(SIXEL_MAGIC) {
+            return sixel_decode(&data, SIXEL_MAX_SIZE, max_pixels);
+        }
+        Err(Error::ParseError(
+            "Image",
+            "unsupported image format".to_string(),
+        ))
+    }
+
+    /// Decode PNG image
+    ///
+    /// All color types and bit depths are supported, palette and transparency
+    /// chunk are expanded, 16-bit channels are rounded to 8-bit.
+    pub fn from_png(input: impl Read) -> Result<Image, Error> {
+        let mut decoder = png::Decoder::new(BufReader::new(input));
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder.read_info().map_err(png_error)?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).map_err(png_error)?;
+        png_frame_decode(&buf, &info)
+    }
+
+    /// Decode PNM image (PBM, PGM, PPM in both plain and raw variants, and PAM)
+    #[cfg(feature = "pnm")]
+    pub fn from_pnm(mut input: impl Read) -> Result<Image, Error> {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data)?;
+        pnm_decode(&data, IMAGE_MAX_PIXELS)
+    }
+
+    /// Decode QOI (Quite OK Image format) image
+    ///
+    /// Reference: [QOI specification](https://qoiformat.org/qoi-specification.pdf)
+    #[cfg(feature = "qoi")]
+    pub fn from_qoi(mut input: impl Read) -> Result<Image, Error> {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data)?;
+        qoi_decode(&data, IMAGE_MAX_PIXELS)
+    }
+
+    /// Decode sixel image
+    ///
+    /// Input must start with DCS sequence introducer. Pixels which have not been
+    /// painted are transparent if `P2=1`, otherwise they are filled with color
+    /// register `0`.
+    /// Reference: [VT330/VT340 Sixel Graphics](https://vt100.net/docs/vt3xx-gp/chapter14.html)
+    pub fn from_sixel(mut input: impl Read) -> Result<Image, Error> {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data)?;
+        sixel_decode(&data, SIXEL_MAX_SIZE, IMAGE_MAX_PIXELS)
+    }
+}
+
+impl AnimatedImage {
+    /// Decode animation guessing its format from the magic bytes
+    ///
+    /// Static images are decoded as a single frame animation.
+    pub fn decode(mut input: impl Read) -> Result<AnimatedImage, Error> {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data)?;
+        if data.starts_with(PNG_MAGIC) {
+            return AnimatedImage::from_png(data.as_slice());
+        }
+        #[cfg(feature = "gif")]
+        if data.starts_with(GIF_MAGIC) {
+            return AnimatedImage::from_gif(data.as_slice());
+        }
+        AnimatedImage::new([(Image::decode(data.as_slice())?, Duration::ZERO)])
+    }
+
+    /// Decode animated PNG (APNG) image
+    ///
+    /// PNG without animation control chunk is decoded as a single frame animation.
+    /// Reference: [APNG specification](https://wiki.mozilla.org/APNG_Specification)
+    pub fn from_png(input: impl Read) -> Result<AnimatedImage, Error> {
+        let mut decoder = png::Decoder::new(BufReader::new(input));
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder.read_info().map_err(png_error)?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let count = match reader.info().animation_control {
+            None => {
+                let info = reader.next_frame(&mut buf).map_err(png_error)?;
+                return AnimatedImage::new([(png_frame_decode(&buf, &info)?, Duration::ZERO)]);
+            }
+            Some(animation) => animation.num_frames as usize,
+        };
+        // default image is not a part of the animation if it is not preceded by `fcTL`
+        let skip_default = reader.info().frame_control.is_none();
+
+        let mut canvas =
+            SurfaceOwned::new(reader.info().height as usize, reader.info().width as usize);
+        let mut frames = Vec::with_capacity(count);
+        for index in 0..count + skip_default as usize {
+            let info = reader.next_frame(&mut buf).map_err(png_error)?;
+            if index == 0 && skip_default {
+                continue;
+            }
+            let control = reader
+                .info()
+                .frame_control
+                .ok_or_else(|| Error::ParseError("PNG", "missing frame control".to_string()))?;
+            let frame = png_frame_decode(&buf, &info)?;
+            let row = control.y_offset as usize;
+            let col = control.x_offset as usize;
+
+            let previous = (control.dispose_op == png::DisposeOp::Previous).then(|| canvas.clone());
+            frame_compose(
+                &mut canvas,
+                &frame,
+                row,
+                col,
+                control.blend_op == png::BlendOp::Over,
+            );
+            // zero denominator means hundredths of a second
+            let delay_den = if control.delay_den == 0 {
+                100
+            } else {
+                control.delay_den
+            };
+            let delay = Duration::from_secs_f64(control.delay_num as f64 / delay_den as f64);
+            frames.push((Image::new(canvas.clone()), delay));
+
+            match control.dispose_op {
+                png::DisposeOp::None => {}
+                png::DisposeOp::Background => canvas
+                    .view_mut(row..row + frame.height(), col..col + frame.width())
+                    .fill(RGBA::default()),
+                png::DisposeOp::Previous => {
+                    if let Some(previous) = previous {
+                        canvas = previous;
+                    }
+                }
+            }
+        }
+        AnimatedImage::new(frames)
+    }
+
+    /// Decode animated GIF image
+    #[cfg(feature = "gif")]
+    pub fn from_gif(input: impl Read) -> Result<AnimatedImage, Error> {
+        AnimatedImage::new(gif_frames(input, usize::MAX, IMAGE_MAX_PIXELS)?)
+    }
+}
+
+/// Decode at most `limit` frames of GIF image composed with the previous frames
+#[cfg(feature = "gif")]
+fn gif_frames(
+    input: impl Read,
+    limit: usize,
+    max_pixels: usize,
+) -> Result<Vec<(Image, Duration)>, Error> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = options
+        .read_info(BufReader::new(input))
+        .map_err(gif_error)?;
+    if decoder.height() as usize * decoder.width() as usize > max_pixels {
+        return Err(Error::ParseError("GIF", "image is too large".to_string()));
+    }
+
+    let mut canvas = SurfaceOwned::new(decoder.height() as usize, decoder.width() as usize);
+    let mut frames = Vec::new();
+    while frames.len() < limit {
+        let Some(frame) = decoder.read_next_frame().map_err(gif_error)? else {
+            break;
+        };
+        let row = frame.top as usize;
+        let col = frame.left as usize;
+        let height = frame.height as usize;
+        let width = frame.width as usize;
+        if frame.buffer.len() != height * width * 4 {
+            return Err(Error::ParseError(
+                "GIF",
+                "frame size does not match its buffer".to_string(),
+            ));
+        }
+        let colors = frame
+            .buffer
+            .chunks_exact(4)
+            .map(|color| RGBA::new(color[0], color[1], color[2], color[3]))
+            .collect();
+
+        let previous = (frame.dispose == gif::DisposalMethod::Previous).then(|| canvas.clone());
+        frame_compose(
+            &mut canvas,
+            &SurfaceOwned::from_vec(height, width, colors),
+            row,
+            col,
+            true,
+        );
+        // delay is in hundredths of a second, browsers treat too short
+        // delays as 100ms and so do we
+        let delay = if frame.delay < 2 {
+            Duration::from_millis(100)
+        } else {
+            Duration::from_millis(frame.delay as u64 * 10)
+        };
+        frames.push((Image::new(canvas.clone()), delay));
+
+        match frame.dispose {
+            gif::DisposalMethod::Any | gif::DisposalMethod::Keep => {}
+            gif::DisposalMethod::Background => canvas
+                .view_mut(row..row + height, col..col + width)
+                .fill(RGBA::default()),
+            gif::DisposalMethod::Previous => {
+                if let Some(previous) = previous {
+                    canvas = previous;
+                }
+            }
+        }
+    }
+    Ok(frames)
+}
+
+/// Draw frame on the canvas at the specified offset, if `blend` is true frame
+/// is alpha blended with the canvas otherwise it replaces canvas pixels
+fn frame_compose(
+    canvas: &mut SurfaceOwned<RGBA>,
+    frame: &impl Surface<Item = RGBA>,
+    row: usize,
+    col: usize,
+    blend: bool,
+) {
+    canvas
+        .view_mut(row..row + frame.height(), col..col + frame.width())
+        .fill_with(|row, col, dst| {
+            let src = frame.get(row, col).copied().unwrap_or_default();
+            if blend {
+                dst.blend(src, Blend::Over)
+            } else {
+                src
+            }
+        });
+}
+
+const PNG_MAGIC: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+fn png_error(error: png::DecodingError) -> Error {
+    match error {
+        png::DecodingError::IoError(error) => error.into(),
+        error => Error::ParseError("PNG", error.to_string()),
+    }
+}
+
+#[cfg(feature = "gif")]
+const GIF_MAGIC: &[u8] = b"GIF8";
+
+#[cfg(feature = "gif")]
+fn gif_error(error: gif::DecodingError) -> Error {
+    match error {
+        gif::DecodingError::Io(error) => error.into(),
+        error => Error::ParseError("GIF", error.to_string()),
+    }
+}
+
+/// Convert decoded (with `EXPAND` transformation) PNG frame into an image
+pub(crate) fn png_frame_decode(buf: &[u8], info: &png::OutputInfo) -> Result<Image, Error> {
+    let height = info.height as usize;
+    let width = info.width as usize;
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        png::ColorType::Indexed => {
+            return Err(Error::ParseError(
+                "PNG",
+                "indexed color type was not expanded".to_string(),
+            ))
+        }
+    };
+    let sample_size = match info.bit_depth {
+        png::BitDepth::Eight => 1,
+        png::BitDepth::Sixteen => 2,
+        depth => {
+            return Err(Error::ParseError(
+                "PNG",
+                format!("bit depth was not expanded: {:?}", depth),
+            ))
+        }
+    };
+    let mut colors = Vec::with_capacity(height * width);
+    for row in 0..height {
+        let line = &buf[row * info.line_size..(row + 1) * info.line_size];
+        for pixel in line.chunks_exact(channels * sample_size).take(width) {
+            let mut samples = pixel.chunks_exact(sample_size).map(|sample| match sample {
+                [value] => *value,
+                [hi, lo] => sample_u8(u16::from_be_bytes([*hi, *lo]) as usize, 65535),
+                _ => unreachable!(),
+            });
+            colors.push(samples_to_rgba(channels, &mut samples));
+        }
+    }
+    Ok(Image::new(SurfaceOwned::from_vec(height, width, colors)))
+}
+
+/// Scale sample with range `[0, maxval]` to u8
+fn sample_u8(value: usize, maxval: usize) -> u8 {
+    if maxval == 255 {
+        value.min(255) as u8
+    } else {
+        ((value.min(maxval) * 255 + maxval / 2) / maxval) as u8
+    }
+}
+
+/// Construct color from gray, gray+alpha, rgb and rgba samples
+fn samples_to_rgba(channels: usize, samples: &mut impl Iterator<Item = u8>) -> RGBA {
+    let mut next = || samples.next().unwrap_or(0);
+    match channels {
+        1 => {
+            let gray = next();
+            RGBA::new(gray, gray, gray, 255)
+        }
+        2 => {
+            let gray = next();
+            RGBA::new(gray, gray, gray, next())
+        }
+        3 => RGBA::new(next(), next(), next(), 255),
+        _ => RGBA::new(next(), next(), next(), next()),
+    }
+}
+
+/// PNM header tokenizer
+///
+/// Splits header into whitespace separated tokens skipping comments.
+#[cfg(feature = "pnm")]
+struct PnmTokens<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+#[cfg(feature = "pnm")]
+impl<'a> PnmTokens<'a> {
+    fn new(data: &'a [u8], offset: usize) -> Self {
+        Self { data, offset }
+    }
+
+    /// Next whitespace separated token
+    fn token(&mut self) -> Option<&'a [u8]> {
+        loop {
+            match self.data.get(self.offset)? {
+                b'#' => {
+                    while !matches!(self.data.get(self.offset)?, b'\n' | b'\r') {
+                        self.offset += 1;
+                    }
+                }
+                byte if byte.is_ascii_whitespace() => self.offset += 1,
+                _ => break,
+            }
+        }
+        let start = self.offset;
+        while self
+            .data
+            .get(self.offset)
+            .is_some_and(|byte| !byte.is_ascii_whitespace())
+        {
+            self.offset += 1;
+        }
+        Some(&self.data[start..self.offset])
+    }
+
+    /// Next token parsed as a number
+    fn number(&mut self) -> Result<usize, Error> {
+        let token = self
+            .token()
+            .ok_or_else(|| pnm_error("unexpected end of header"))?;
+        std::str::from_utf8(token)
+            .ok()
+            .and_then(|token| token.parse().ok())
+            .ok_or_else(|| pnm_error(format!("invalid number: {:?}", token)))
+    }
+
+    /// Skip single whitespace separating header and raster data
+    fn raster(self) -> &'a [u8] {
+        self.data.get(self.offset + 1..).unwrap_or_default()
+    }
+}
+
+#[cfg(feature = "pnm")]
+fn pnm_error(msg: impl Into<String>) -> Error {
+    Error::ParseError("PNM", msg.into())
+}
+
+/// Decode PNM image
+///
+/// Reference: [Netpbm formats](https://netpbm.sourceforge.net/doc/#formats)
+#[cfg(feature = "pnm")]
+fn pnm_decode(data: &[u8], max_pixels: usize) -> Result<Image, Error> {
+    let kind = match data {
+        [b'P', kind @ b'1'..=b'7', ..] => *kind,
+        _ => return Err(pnm_error("invalid magic")),
+    };
+    let mut tokens = PnmTokens::new(data, 2);
+
+    // header
+    let (height, width, channels, maxval) = if kind == b'7' {
+        let mut height = None;
+        let mut width = None;
+        let mut depth = None;
+        let mut maxval = None;
+        loop {
+            match tokens.token() {
+                Some(b"ENDHDR") => break,
+                Some(b"HEIGHT") => height = Some(tokens.number()?),
+                Some(b"WIDTH") => width = Some(tokens.number()?),
+                Some(b"DEPTH") => depth = Some(tokens.number()?),
+                Some(b"MAXVAL") => maxval = Some(tokens.number()?),
+                Some(b"TUPLTYPE") => {
+                    tokens.token();
+                }
+                Some(token) => {
+                    return Err(pnm_error(format!(
+                        "unknown PAM header: {}",
+                        String::from_utf8_lossy(token)
+                    )))
+                }
+                None => return Err(pnm_error("unexpected end of PAM header")),
+            }
+        }
+        match (height, width, depth, maxval) {
+            (Some(height), Some(width), Some(depth @ 1..=4), Some(maxval)) => {
+                (height, width, depth, maxval)
+            }
+            _ => return Err(pnm_error("incomplete or invalid PAM header")),
+        }
+    } else {
+        let width = tokens.number()?;
+        let height = tokens.number()?;
+        let maxval = match kind {
+            b'1' | b'4' => 1,
+            _ => tokens.number()?,
+        };
+        let channels = match kind {
+            b'3' | b'6' => 3,
+            _ => 1,
+        };
+        (height, width, channels, maxval)
+    };
+    if maxval == 0 || maxval > 65535 {
+        return Err(pnm_error(format!("invalid maxval: {}", maxval)));
+    }
+    let size = height
+        .checked_mul(width)
+        .filter(|size| *size <= max_pixels)
+        .ok_or_else(|| pnm_error("image is too large"))?;
+    // every sample takes at least one byte (one bit for P4), reject truncated
+    // raster before allocating memory for the image
+    let samples = size
+        .checked_mul(channels)
+        .ok_or_else(|| pnm_error("image is too large"))?;
+    let samples_size = if kind == b'4' {
+        samples.div_ceil(8)
+    } else {
+        samples
+    };
+    if samples_size > data.len().saturating_sub(tokens.offset) {
+        return Err(pnm_error("truncated raster"));
+    }
+
+    // raster
+    let mut colors = Vec::with_capacity(size);
+    match kind {
+        b'1' => {
+            // plain bitmap, digits might not be separated by whitespaces
+            let mut bits = data[tokens.offset..]
+                .iter()
+                .filter(|byte| matches!(byte, b'0' | b'1'));
+            for _ in 0..size {
+                let bit = bits.next().ok_or_else(|| pnm_error("truncated raster"))?;
+                colors.push(pbm_color(*bit == b'1'));
+            }
+        }
+        b'4' => {
+            let raster = tokens.raster();
+            let row_size = width.div_ceil(8);
+            if raster.len() < row_size * height {
+                return Err(pnm_error("truncated raster"));
+            }
+            for row in raster.chunks_exact(row_size).take(height) {
+                for col in 0..width {
+                    colors.push(pbm_color(row[col / 8] & (0x80 >> (col % 8)) != 0));
+                }
+            }
+        }
+        b'2' | b'3' => {
+            for _ in 0..size {
+                let mut samples = Vec::with_capacity(channels);
+                for _ in 0..channels {
+                    samples.push(sample_u8(tokens.number()?, maxval));
+                }
+                colors.push(samples_to_rgba(channels, &mut samples.into_iter()));
+            }
+        }
+        _ => {
+            let sample_size = if maxval < 256 { 1 } else { 2 };
+            let raster = tokens.raster();
+            if raster.len() < size * channels * sample_size {
+                return Err(pnm_error("truncated raster"));
+            }
+            for pixel in raster.chunks_exact(channels * sample_size).take(size) {
+                let mut samples = pixel.chunks_exact(sample_size).map(|sample| match sample {
+                    [value] => sample_u8(*value as usize, maxval),
+                    [hi, lo] => sample_u8(u16::from_be_bytes([*hi, *lo]) as usize, maxval),
+                    _ => unreachable!(),
+                });
+                colors.push(samples_to_rgba(channels, &mut samples));
+            }
+        }
+    }
+
+    Ok(Image::new(SurfaceOwned::from_vec(height, width, colors)))
+}
+
+/// PBM uses 1 for black and 0 for white
+#[cfg(feature = "pnm")]
+fn pbm_color(bit: bool) -> RGBA {
+    if bit {
+        RGBA::new(0, 0, 0, 255)
+    } else {
+        RGBA::new(255, 255, 255, 255)
+    }
+}
+
+#[cfg(feature = "qoi")]
+const QOI_MAGIC: &[u8] = b"qoif";
+
+#[cfg(feature = "qoi")]
+fn qoi_error(msg: impl Into<String>) -> Error {
+    Error::ParseError("QOI", msg.into())
+}
+
+/// Decode QOI image
+#[cfg(feature = "qoi")]
+fn qoi_decode(data: &[u8], max_pixels: usize) -> Result<Image, Error> {
+    const QOI_OP_RGB: u8 = 0xfe;
+    const QOI_OP_RGBA: u8 = 0xff;
+    const QOI_OP_INDEX: u8 = 0x00;
+    const QOI_OP_DIFF: u8 = 0x40;
+    const QOI_OP_LUMA: u8 = 0x80;
+    const QOI_OP_RUN: u8 = 0xc0;
+    const QOI_MASK: u8 = 0xc0;
+
+    if data.len() < 14 || !data.starts_with(QOI_MAGIC) {
+        return Err(qoi_error("invalid header"));
+    }
+    let width = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+    let height = u32::from_be_bytes([data[8], data[9], data[10], data[11]]) as usize;
+    let size = height
+        .checked_mul(width)
+        .filter(|size| *size <= max_pixels)
+        .ok_or_else(|| qoi_error("image is too large"))?;
+    // every chunk is at least one byte and produces at most 62 pixels (QOI_OP_RUN)
+    if size > (data.len() - 14).saturating_mul(62) {
+        return Err(qoi_error("truncated data"));
+    }
+
+    let mut index = [[0u8; 4]; 64];
+    let mut pixel = [0u8, 0, 0, 255];
+    let mut colors = Vec::with_capacity(size);
+    let mut bytes = data[14..].iter().copied();
+    let mut next = || bytes.next().ok_or_else(|| qoi_error("truncated data"));
+    while colors.len() < size {
+        let tag = next()?;
+        let mut run = 1;
+        match tag {
+            QOI_OP_RGB => {
+                pixel[0] = next()?;
+                pixel[1] = next()?;
+                pixel[2] = next()?;
+            }
+            QOI_OP_RGBA => {
+                pixel[0] = next()?;
+                pixel[1] = next()?;
+                pixel[2] = next()?;
+                pixel[3] = next()?;
+            }
+            _ => match tag & QOI_MASK {
+                QOI_OP_INDEX => pixel = index[tag as usize],
+                QOI_OP_DIFF => {
+                    pixel[0] = pixel[0].wrapping_add(((tag >> 4) & 0x03).wrapping_sub(2));
+                    pixel[1] = pixel[1].wrapping_add(((tag >> 2) & 0x03).wrapping_sub(2));
+                    pixel[2] = pixel[2].wrapping_add((tag & 0x03).wrapping_sub(2));
+                }
+                QOI_OP_LUMA => {
+                    let dg = (tag & 0x3f).wrapping_sub(32);
+                    let drdb = next()?;
+                    let dr = dg.wrapping_add(drdb >> 4).wrapping_sub(8);
+                    let db = dg.wrapping_add(drdb & 0x0f).wrapping_sub(8);
+                    pixel[0] = pixel[0].wrapping_add(dr);
+                    pixel[1] = pixel[1].wrapping_add(dg);
+                    pixel[2] = pixel[2].wrapping_add(db);
+                }
+                _ => run = (tag & 0x3f) as usize + 1, // QOI_OP_RUN
+            },
+        }
+        if tag & QOI_MASK != QOI_OP_RUN || tag == QOI_OP_RGB || tag == QOI_OP_RGBA {
+            let [r, g, b, a] = pixel;
+            let hash = (r as usize * 3 + g as usize * 5 + b as usize * 7 + a as usize * 11) % 64;
+            index[hash] = pixel;
+        }
+        for _ in 0..run.min(size - colors.len()) {
+            colors.push(RGBA(pixel));
+        }
+    }
+
+    Ok(Image::new(SurfaceOwned::from_vec(height, width, colors)))
+}
+
+const SIXEL_MAGIC: &[u8] = b"\x1bP";
+
+/// VT340 default color registers (RGB in percents)
+const SIXEL_DEFAULT_COLORS: [[u8; 3]; 16] = [
+    [0, 0, 0],
+    [20, 20, 80],
+    [80, 13, 13],
+    [20, 80, 20],
+    [80, 20, 80],
+    [20, 80, 80],
+    [80, 80, 20],
+    [53, 53, 53],
+    [26, 26, 26],
+    [33, 33, 60],
+    [60, 26, 26],
+    [33, 60, 33],
+    [60, 33, 60],
+    [33, 60, 60],
+    [60, 60, 33],
+    [80, 80, 80],
+];
+
+/// Maximum number of sixel color registers
+const SIXEL_COLOR_REGISTERS: usize = 1024;
+
+/// Maximum size of the decoded sixel image
+const SIXEL_MAX_SIZE: Size = Size {
+    height: 16384,
+    width: 16384,
+};
+
+fn sixel_error(msg: impl Into<String>) -> Error {
+    Error::ParseError("Sixel", msg.into())
+}
+
+/// Convert sixel color components in percents to RGBA
+fn sixel_rgb(red: usize, green: usize, blue: usize) -> RGBA {
+    let channel = |value: usize| (value.min(100) as f32 * 2.55).round() as u8;
+    RGBA::new(channel(red), channel(green), channel(blue), 255)
+}
+
+/// Convert sixel HLS color to RGBA
+///
+/// Sixel hue is rotated relative to the conventional HSL, blue is at 0°, and
+/// red is at 120°.
+fn sixel_hls(hue: usize, lightness: usize, saturation: usize) -> RGBA {
+    let h = ((hue + 240) % 360) as f32 / 360.0;
+    let l = lightness.min(100) as f32 / 100.0;
+    let s = saturation.min(100) as f32 / 100.0;
+    if s == 0.0 {
+        let value = (l * 255.0).round() as u8;
+        return RGBA::new(value, value, value, 255);
+    }
+    let q = if l < 0.5 {
+        l * (1.0 + s)
+    } else {
+        l + s - l * s
+    };
+    let p = 2.0 * l - q;
+    let channel = |t: f32| {
+        let t = t.rem_euclid(1.0);
+        let value = if t < 1.0 / 6.0 {
+            p + (q - p) * 6.0 * t
+        } else if t < 0.5 {
+            q
+        } else if t < 2.0 / 3.0 {
+            p + (q - p) * (2.0 / 3.0 - t) * 6.0
+        } else {
+            p
+        };
+        (value * 255.0).round() as u8
+    };
+    RGBA::new(
+        channel(h + 1.0 / 3.0),
+        channel(h),
+        channel(h - 1.0 / 3.0),
+        255,
+    )
+}
+
+/// Decode sixel image, fails if it is larger than `max_size` or `max_pixels`
+pub(crate) fn sixel_decode(data: &[u8], max_size: Size, max_pixels: usize) -> Result<Image, Error> {
+    let data = data
+        .strip_prefix(SIXEL_MAGIC)
+        .ok_or_else(|| sixel_error("missing DCS introducer"))?;
+    // DCS parameters `P1;P2;P3q`
+    let start = data
+        .iter()
+        .position(|byte| *byte == b'q')
+        .ok_or_else(|| sixel_error("missing sixel introducer"))?;
+    let (params, rest) = sixel_params(&data[..start]);
+    if !rest.is_empty() {
+        return Err(sixel_error("invalid DCS parameters"));
+    }
+    let transparent = params.get(1).copied() == Some(1);
+
+    let mut palette: Vec<RGBA> = SIXEL_DEFAULT_COLORS
+        .iter()
+        .map(|[r, g, b]| sixel_rgb(*r as usize, *g as usize, *b as usize))
+        .collect();
+    palette.resize(SIXEL_COLOR_REGISTERS, RGBA::new(0, 0, 0, 255));
+    let mut color = palette[0];
+    let mut rows: Vec<Vec<Option<RGBA>>> = Vec::new();
+    let (mut raster_height, mut raster_width) = (0, 0);
+    // size of the painted area
+    let (mut paint_height, mut paint_width) = (0, 0);
+    let (mut x, mut y) = (0usize, 0usize);
+
+    let mut data = &data[start + 1..];
+    while let Some((byte, rest)) = data.split_first() {
+        data = rest;
+        match byte {
+            // sixel data, optionally repeated
+            b'?'..=b'~' | b'!' => {
+                let (count, code) = if *byte == b'!' {
+                    let (count, rest) = sixel_params(data);
+                    let (code, rest) = rest
+                        .split_first()
+                        .filter(|(code, _)| (b'?'..=b'~').contains(*code))
+                        .ok_or_else(|| sixel_error("invalid repeat introducer"))?;
+                    data = rest;
+                    // anything wider than maximum size is an error anyway
+                    let count = count.first().copied().unwrap_or(1).max(1);
+                    (count.min(max_size.width.saturating_add(1)), code - b'?')
+                } else {
+                    (1, byte - b'?')
+                };
+                for bit in 0..6 {
+                    if code & (1 << bit) == 0 {
+                        continue;
+                    }
+                    let row_index = y + bit;
+                    if row_index >= max_size.height || x.saturating_add(count) > max_size.width {
+                        return Err(sixel_error("image is too large"));
+                    }
+                    paint_height = paint_height.max(row_index + 1);
+                    paint_width = paint_width.max(x + count);
+                    if paint_height.max(raster_height) * paint_width.max(raster_width) > max_pixels
+                    {
+                        return Err(sixel_error("image is too large"));
+                    }
+                    if rows.len() <= row_index {
+                        rows.resize_with(row_index + 1, Vec::new);
+                    }
+                    let row = &mut rows[row_index];
+                    if row.len() < x + count {
+                        row.resize(x + count, None);
+                    }
+                    row[x..x + count].fill(Some(color));
+                }
+                x = x.saturating_add(count);
+            }
+            // color introducer
+            b'#' => {
+                let (params, rest) = sixel_params(data);
+                data = rest;
+                let index = params.first().copied().unwrap_or(0);
+                if index >= SIXEL_COLOR_REGISTERS {
+                    return Err(sixel_error(format!("invalid color register: {}", index)));
+                }
+                match params[..] {
+                    [_, 1, h, l, s] => palette[index] = sixel_hls(h, l, s),
+                    [_, 2, r, g, b] => palette[index] = sixel_rgb(r, g, b),
+                    [_] | [] => {}
+                    _ => return Err(sixel_error("invalid color definition")),
+                }
+                color = palette[index];
+            }
+            // raster attributes
+            b'"' => {
+                let (params, rest) = sixel_params(data);
+                data = rest;
+                if let [_, _, width, height, ..] = params[..] {
+                    if width > max_size.width
+                        || height > max_size.height
+                        || height.max(paint_height) * width.max(paint_width) > max_pixels
+                    {
+                        return Err(sixel_error("image is too large"));
+                    }
+                    raster_width = width;
+                    raster_height = height;
+                }
+            }
+            // graphics carriage return
+            b'$' => x = 0,
+            // graphics new line
+            b'-' => {
+                x = 0;
+                y = y.saturating_add(6);
+            }
+            // string terminator
+            b'\x1b' => break,
+            b'\r' | b'\n' => {}
+            _ => return Err(sixel_error(format!("unexpected byte: {:#x}", byte))),
+        }
+    }
+
+    let height = paint_height.max(raster_height);
+    let width = paint_width.max(raster_width);
+    let bg = if transparent {
+        RGBA::new(0, 0, 0, 0)
+    } else {
+        palette[0]
+    };
+    let img = SurfaceOwned::new_with(height, width, |row, col| {
+        rows.get(row)
+            .and_then(|row| row.get(col).copied().flatten())
+            .unwrap_or(bg)
+    });
+    Ok(Image::new(img))
+}
+
+/// Parse `;` separated numeric parameters, returns parameters and the rest of input
+fn sixel_params(data: &[u8]) -> (Vec<usize>, &[u8]) {
+    let end = data
+        .iter()
+        .position(|byte| !matches!(byte, b'0'..=b'9' | b';'))
+        .unwrap_or(data.len());
+    let params = data[..end]
+        .split(|byte| *byte == b';')
+        .map(|param| sixel_number(param).unwrap_or(0))
+        .collect();
+    (params, &data[end..])
+}
+
+/// Parse decimal number, saturates on overflow
+fn sixel_number(data: &[u8]) -> Option<usize> {
+    if data.is_empty() {
+        return None;
+    }
+    Some(data.iter().fold(0usize, |acc, byte| {
+        acc.saturating_mul(10)
+            .saturating_add(byte.wrapping_sub(b'0') as usize)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Surface;
+
+    fn colors(img: &Image) -> Vec<RGBA> {
+        img.iter().copied().collect()
+    }
+
+    #[test]
+    fn test_png() -> Result<(), Error> {
+        let img = Image::new(SurfaceOwned::new_with(3, 5, |row, col| {
+            RGBA::new(row as u8 * 50, col as u8 * 40, 7, 255 - col as u8)
+        }));
+        let mut data = Vec::new();
+        img.write_png(&mut data)
+            .map_err(|err| Error::Other(err.to_string().into()))?;
+        assert_eq!(Image::from_png(data.as_slice())?, img);
+        assert_eq!(Image::decode(data.as_slice())?, img);
+
+        // palette, gray + alpha and 16-bit images
+        fn encode(
+            color: png::ColorType,
+            depth: png::BitDepth,
+            palette: Option<&[u8]>,
+            trns: Option<&[u8]>,
+            data: &[u8],
+        ) -> Vec<u8> {
+            let mut out = Vec::new();
+            let mut encoder = png::Encoder::new(&mut out, 2, 1);
+            encoder.set_color(color);
+            encoder.set_depth(depth);
+            if let Some(palette) = palette {
+                encoder.set_palette(palette.to_vec());
+            }
+            if let Some(trns) = trns {
+                encoder.set_trns(trns.to_vec());
+            }
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(data).unwrap();
+            writer.finish().unwrap();
+            out
+        }
+
+        let data = encode(
+            png::ColorType::Indexed,
+            png::BitDepth::Eight,
+            Some(&[255, 0, 0, 0, 0, 255]),
+            Some(&[128]),
+            &[0, 1],
+        );
+        assert_eq!(
+            colors(&Image::from_png(data.as_slice())?),
+            vec![RGBA::new(255, 0, 0, 128), RGBA::new(0, 0, 255, 255)]
+        );
+
+        let data = encode(
+            png::ColorType::GrayscaleAlpha,
+            png::BitDepth::Eight,
+            None,
+            None,
+            &[10, 20, 30, 40],
+        );
+        assert_eq!(
+            colors(&Image::from_png(data.as_slice())?),
+            vec![RGBA::new(10, 10, 10, 20), RGBA::new(30, 30, 30, 40)]
+        );
+
+        let data = encode(
+            png::ColorType::Rgb,
+            png::BitDepth::Sixteen,
+            None,
+            None,
+            &[
+                0xff, 0xff, 0x80, 0x80, 0, 0, 0x12, 0x34, 0, 0xff, 0xfe, 0xff,
+            ],
+        );
+        assert_eq!(
+            colors(&Image::from_png(data.as_slice())?),
+            vec![RGBA::new(255, 128, 0, 255), RGBA::new(18, 1, 254, 255)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_apng() -> Result<(), Error> {
+        let red = RGBA::new(255, 0, 0, 255);
+        let blue = RGBA::new(0, 0, 255, 255);
+
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut data, 2, 2);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(2, 0).unwrap();
+        let mut writer = encoder.write_header().unwrap();
+        writer.set_frame_delay(1, 10).unwrap();
+        writer.set_dispose_op(png::DisposeOp::None).unwrap();
+        writer.write_image_data(&red.rgba_u8().repeat(4)).unwrap();
+        writer.set_frame_delay(20, 0).unwrap();
+        writer.set_frame_dimension(1, 1).unwrap();
+        writer.set_frame_position(1, 1).unwrap();
+        writer.set_blend_op(png::BlendOp::Over).unwrap();
+        writer.write_image_data(&blue.rgba_u8()).unwrap();
+        writer.finish().unwrap();
+
+        let anim = AnimatedImage::decode(data.as_slice())?;
+        let frames: Vec<_> = anim
+            .frames()
+            .iter()
+            .map(|(frame, delay)| (colors(frame), *delay))
+            .collect();
+        assert_eq!(
+            frames,
+            vec![
+                (vec![red, red, red, red], Duration::from_millis(100)),
+                (vec![red, red, red, blue], Duration::from_millis(200)),
+            ]
+        );
+
+        // static PNG is a single frame animation
+        let mut data = Vec::new();
+        Image::new(SurfaceOwned::new_with(1, 1, |_, _| red))
+            .write_png(&mut data)
+            .map_err(|err| Error::Other(err.to_string().into()))?;
+        assert_eq!(AnimatedImage::from_png(data.as_slice())?.frames().len(), 1);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "gif")]
+    #[test]
+    fn test_gif() -> Result<(), Error> {
+        use std::borrow::Cow;
+
+        let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255];
+        let mut data = Vec::new();
+        let mut encoder = gif::Encoder::new(&mut data, 2, 1, &palette).unwrap();
+        encoder
+            .write_frame(&gif::Frame {
+                width: 2,
+                height: 1,
+                delay: 5,
+                dispose: gif::DisposalMethod::Background,
+                buffer: Cow::Borrowed(&[0, 1]),
+                ..Default::default()
+            })
+            .unwrap();
+        encoder
+            .write_frame(&gif::Frame {
+                left: 1,
+                width: 1,
+                height: 1,
+                delay: 0,
+                buffer: Cow::Borrowed(&[2]),
+                ..Default::default()
+            })
+            .unwrap();
+        drop(encoder);
+
+        let anim = AnimatedImage::decode(data.as_slice())?;
+        let frames: Vec<_> = anim
+            .frames()
+            .iter()
+            .map(|(frame, delay)| (colors(frame), *delay))
+            .collect();
+        assert_eq!(
+            frames,
+            vec![
+                (
+                    vec![RGBA::new(255, 0, 0, 255), RGBA::new(0, 255, 0, 255)],
+                    Duration::from_millis(50)
+                ),
+                (
+                    vec![RGBA::new(0, 0, 0, 0), RGBA::new(0, 0, 255, 255)],
+                    Duration::from_millis(100)
+                ),
+            ]
+        );
+        assert_eq!(Image::decode(data.as_slice())?, anim.frames()[0].0);
+        // frames after the first one are not decoded
+        let truncated = &data[..data.len() - 4];
+        assert!(AnimatedImage::decode(truncated).is_err());
+        assert_eq!(Image::decode(truncated)?, anim.frames()[0].0);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "pnm")]
+    #[test]
+    fn test_pnm() -> Result<(), Error> {
+        let black = RGBA::new(0, 0, 0, 255);
+        let white = RGBA::new(255, 255, 255, 255);
+
+        let img = Image::from_pnm("P1\n# comment\n3 2\n010\n1 0 0".as_bytes())?;
+        assert_eq!((img.height(), img.width()), (2, 3));
+        assert_eq!(colors(&img), vec![white, black, white, black, white, white]);
+
+        let img = Image::from_pnm(&b"P4 3 2\n\x40\xff"[..])?;
+        assert_eq!(colors(&img), vec![white, black, white, black, black, black]);
+
+        let img = Image::from_pnm("P2 2 1 100 0 100".as_bytes())?;
+        assert_eq!(colors(&img), vec![black, white]);
+
+        let img = Image::from_pnm("P3 1 1 255 1 2 3".as_bytes())?;
+        assert_eq!(colors(&img), vec![RGBA::new(1, 2, 3, 255)]);
+
+        let img = Image::from_pnm(&b"P6 2 1 255\n\x01\x02\x03\x04\x05\x06"[..])?;
+        assert_eq!(
+            colors(&img),
+            vec![RGBA::new(1, 2, 3, 255), RGBA::new(4, 5, 6, 255)]
+        );
+
+        let img = Image::from_pnm(&b"P5 1 1 65535\n\x80\x00"[..])?;
+        assert_eq!(colors(&img), vec![RGBA::new(128, 128, 128, 255)]);
+
+        let pam = b"P7\nWIDTH 2\nHEIGHT 1\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n\x01\x02\x03\x04\x05\x06\x07\x08";
+        let img = Image::decode(&pam[..])?;
+        assert_eq!(
+            colors(&img),
+            vec![RGBA::new(1, 2, 3, 4), RGBA::new(5, 6, 7, 8)]
+        );
+
+        assert!(Image::from_pnm(&b"P6 2 1 255\n\x01\x02\x03"[..]).is_err());
+        // huge dimensions with a truncated raster are rejected before allocation
+        assert!(Image::from_pnm(&b"P4 1000000 1000000\n\x00"[..]).is_err());
+        assert!(Image::from_pnm(&b"P2 1000000 1000000 255\n0 0"[..]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sixel() -> Result<(), Error> {
+        let red = RGBA::new(255, 0, 0, 255);
+        let green = RGBA::new(0, 255, 0, 255);
+        let blue = RGBA::new(0, 0, 255, 255);
+        let none = RGBA::new(0, 0, 0, 0);
+
+        // HLS and RGB colors, repeat, carriage return and new line
+        let data = b"\x1bP0;1;0q\"1;1;4;8#1;1;120;50;100#2;2;0;0;100#1!3A$#2@-#1~~\x1b\\";
+        let img = Image::decode(data.as_slice())?;
+        assert_eq!(img.height(), 12); // painted beyond raster attributes
+        assert_eq!(img.width(), 4);
+        assert_eq!(
+            &colors(&img)[..8],
+            &[blue, none, none, none, red, red, red, none]
+        );
+        assert_eq!(&colors(&img)[24..28], &[red, red, none, none]);
+        assert_eq!(*img.get(11, 2).unwrap(), none);
+
+        // unpainted pixels are filled with color register 0 without P2=1
+        let img = Image::from_sixel(b"\x1bPq#0;2;0;100;0#1;2;0;0;100@".as_slice())?;
+        assert_eq!(colors(&img), vec![blue]);
+        let img = Image::from_sixel(b"\x1bPq\"1;1;2;1#0;2;0;100;0#1;2;0;0;100@".as_slice())?;
+        assert_eq!(colors(&img), vec![blue, green]);
+
+        assert!(Image::from_sixel(b"\x1bPq#1;3;0;0;0".as_slice()).is_err());
+        assert!(Image::from_sixel(b"\x1bPq!10\x1b\\".as_slice()).is_err());
+        // repeat count beyond usize range must not overflow
+        let data = b"\x1bPq!99999999999999999999999?!5?@\x1b\\";
+        assert!(Image::from_sixel(data.as_slice()).is_err());
+
+        // round trip of the sixel image handler output
+        let img = Image::new(SurfaceOwned::new_with(13, 17, |row, col| {
+            if row == 3 && col == 4 {
+                none
+            } else {
+                RGBA::new(row as u8 * 19, col as u8 * 15, 128, 255)
+            }
+        }));
+        let mut handler = crate::SixelImageHandler::new(None).with_dither(crate::Dither::None);
+        let mut data = Vec::new();
+        crate::ImageHandler::draw(
+            &mut handler,
+            &mut data,
+            &img,
+            crate::Position::new(0, 0),
+            None,
+        )?;
+        let result = Image::from_sixel(data.as_slice())?;
+        assert_eq!(result.height(), img.height());
+        assert_eq!(result.width(), img.width());
+        for (src, dst) in img.iter().zip(result.iter()) {
+            let [r0, g0, b0, a0] = src.rgba_u8();
+            let [r1, g1, b1, a1] = dst.rgba_u8();
+            assert_eq!(a0, a1);
+            if a0 == 0 {
+                continue;
+            }
+            // sixel colors have percent precision
+            assert!(r0.abs_diff(r1) <= 3 && g0.abs_diff(g1) <= 3 && b0.abs_diff(b1) <= 3);
+        }
+
+        // pixel limit applies to both raster attributes and painted area
+        let raster = b"\x1bPq\"1;1;100;100\x1b\\";
+        assert!(Image::decode_with_limit(&raster[..], 9999).is_err());
+        assert!(Image::decode_with_limit(&raster[..], 10000).is_ok());
+        let paint = b"\x1bPq!100~\x1b\\";
+        assert!(Image::decode_with_limit(&paint[..], 599).is_err());
+        let img = Image::decode_with_limit(&paint[..], 600)?;
+        assert_eq!((img.height(), img.width()), (6, 100));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "qoi")]
+    #[test]
+    fn test_qoi() -> Result<(), Error> {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"qoif");
+        data.extend_from_slice(&6u32.to_be_bytes()); // width
+        data.extend_from_slice(&1u32.to_be_bytes()); // height
+        data.extend_from_slice(&[4, 0]); // channels, colorspace
+        data.extend_from_slice(&[0xfe, 10, 20, 30]); // RGB
+        data.push(0xc0 | 1); // RUN 2
+        data.push(0x40 | (3 << 4) | (2 << 2) | 1); // DIFF +1, 0, -1
+        data.extend_from_slice(&[0x80 | (32 + 4), (8 + 1) << 4 | (8 - 2)]); // LUMA
+        data.extend_from_slice(&[0xff, 1, 2, 3, 4]); // RGBA
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]); // end marker
+
+        let img = Image::decode(data.as_slice())?;
+        assert_eq!(
+            colors(&img),
+            vec![
+                RGBA::new(10, 20, 30, 255),
+                RGBA::new(10, 20, 30, 255),
+                RGBA::new(10, 20, 30, 255),
+                RGBA::new(11, 20, 29, 255),
+                RGBA::new(16, 24, 31, 255),
+                RGBA::new(1, 2, 3, 4),
+            ]
+        );
+
+        assert!(Image::from_qoi(&data[..20]).is_err());
+        assert!(Image::decode_with_limit(data.as_slice(), 5).is_err());
+
+        // header claims more pixels than the data can hold
+        data[4..8].copy_from_slice(&(62 * 23 + 1u32).to_be_bytes());
+        assert!(Image::from_qoi(data.as_slice()).is_err());
+
+        Ok(())
+    }
+}
@@ -1,7 +1,9 @@
 //! Color definition
 use crate::common::{clamp, Rnd};
 use crate::error::Error;
+use serde::{Deserialize, Serialize};
 use std::{
+    borrow::Cow,
     fmt,
     ops::{Add, Mul},
     str::FromStr,
@@ -102,7 +104,7 @@ pub fn linear_to_srgb(x0: f64) -> f64 {
 }
 
 /// Color in linear RGB color space with premultiplied alpha
-#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
 pub struct ColorLinear(pub [f64; 4]);
 
 impl Mul<f64> for ColorLinear {
@@ -240,12 +242,83 @@ impl From<ColorLinear> for RGBA {
             let r = (linear_to_srgb(r / a) * 255.0).round() as u8;
             let g = (linear_to_srgb(g / a) * 255.0).round() as u8;
             let b = (linear_to_srgb(b / a) * 255.0).round() as u8;
-            let a = (a * 255.0) as u8;
+            let a = (a * 255.0).round() as u8;
             Self([r, g, b, a])
         }
     }
 }
 
+/// Color in OKLab perceptual color space (alpha is ignored)
+///
+/// Euclidean distance in this space is a good approximation of perceived
+/// color difference. Reference: [OKLab](https://bottosson.github.io/posts/oklab/)
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+pub struct OkLab {
+    pub l: f64,
+    pub a: f64,
+    pub b: f64,
+}
+
+impl OkLab {
+    pub const fn new(l: f64, a: f64, b: f64) -> Self {
+        Self { l, a, b }
+    }
+
+    pub fn distance(&self, other: &Self) -> f64 {
+        ((self.l - other.l).powi(2) + (self.a - other.a).powi(2) + (self.b - other.b).powi(2))
+            .sqrt()
+    }
+}
+
+impl From<ColorLinear> for OkLab {
+    fn from(color: ColorLinear) -> Self {
+        let ColorLinear([r, g, b, a]) = color;
+        let (r, g, b) = if a < f64::EPSILON {
+            (0.0, 0.0, 0.0)
+        } else {
+            (r / a, g / a, b / a)
+        };
+        let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+        let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+        let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+        Self {
+            l: 0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+            a: 1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+            b: 0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+        }
+    }
+}
+
+impl From<RGBA> for OkLab {
+    fn from(color: RGBA) -> Self {
+        ColorLinear::from(color).into()
+    }
+}
+
+impl From<OkLab> for ColorLinear {
+    fn from(color: OkLab) -> Self {
+        let OkLab { l, a, b } = color;
+        let l_ = (l + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+        let m_ = (l - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+        let s_ = (l - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+        let r = 4.0767416621 * l_ - 3.3077115913 * m_ + 0.2309699292 * s_;
+        let g = -1.2684380046 * l_ + 2.6097574011 * m_ - 0.3413193965 * s_;
+        let b = -0.0041960863 * l_ - 0.7034186147 * m_ + 1.7076147010 * s_;
+        ColorLinear([
+            clamp(r, 0.0, 1.0),
+            clamp(g, 0.0, 1.0),
+            clamp(b, 0.0, 1.0),
+            1.0,
+        ])
+    }
+}
+
+impl From<OkLab> for RGBA {
+    fn from(color: OkLab) -> Self {
+        ColorLinear::from(color).into()
+    }
+}
+
 impl FromStr for RGBA {
     type Err = Error;
 
@@ -273,6 +346,26 @@ impl fmt::Display for RGBA {
     }
 }
 
+impl Serialize for RGBA {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for RGBA {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Cow::<'de, str>::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
 impl fmt::Debug for RGBA {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         let [bg_r, bg_g, bg_b] = self.rgb_u8();
@@ -362,6 +455,30 @@ mod tests {
             assert_eq!(input as u8, output);
         }
 
+        // weighted sums (as in resampling) are only approximately exact,
+        // alpha is rounded so it does not lose a step
+        for alpha in [255, 0x70, 1] {
+            let color = RGBA::new(0xfe, 0x80, 0x19, alpha);
+            let lin = ColorLinear::from(color);
+            let zero = ColorLinear::new(0.0, 0.0, 0.0, 0.0);
+            let sum = (0..10).fold(zero, |acc, _| acc + lin * 0.1);
+            assert_eq!(RGBA::from(sum), color);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_oklab() -> Result<(), Error> {
+        let white = OkLab::from(RGBA::new(255, 255, 255, 255));
+        assert!((white.l - 1.0).abs() < 1e-4);
+        assert!(white.a.abs() < 1e-4 && white.b.abs() < 1e-4);
+
+        for color in ["#d3869b", "#5a719d", "#000000", "#fe8019"] {
+            let color: RGBA = color.parse()?;
+            assert_eq!(RGBA::from(OkLab::from(color)), color);
+        }
+
         Ok(())
     }
 }
@@ -1,6 +1,7 @@
 //! Decoders
 use crate::{
     automata::{DFAState, DFA, NFA},
+    codec::{sixel_decode, IMAGE_MAX_PIXELS},
     error::Error,
     terminal::{DecModeStatus, Mouse, Size, TerminalColor, TerminalEvent, TerminalSize},
     Face, FaceAttrs, Key, KeyMod, KeyName, TerminalCommand, RGBA,
@@ -137,6 +138,9 @@ pub struct TTYDecoder {
     /// but it is not terminal (transition to other state is possible). Contains
     /// TerminalEvent and amount of data in the buffer when this event was found.
     possible: Option<(TerminalEvent, usize)>,
+    /// Maximum size of the expected sixel image, sixel sequences are not
+    /// recognized unless expected
+    sixel: Option<Size>,
 }
 
 impl Decoder for TTYDecoder {
@@ -182,6 +186,7 @@ impl TTYDecoder {
             Box::new(CursorPositionMatcher),
             Box::new(DecModeMatcher),
             Box::new(DeviceAttrsMatcher),
+            Box::new(GraphicsAttrsMatcher),
             Box::new(GraphicRenditionMatcher::default()),
             Box::new(KittyImageMatcher),
             Box::new(KittyKeyboardMatcher),
@@ -202,6 +207,7 @@ impl TTYDecoder {
                     .tag(TTYTag::Matcher(index)),
             )
         }
+        automatas.push(sixel_nfa().map(|_| TTYTag::Sixel).tag(TTYTag::Sixel));
 
         let automata = NFA::choice(automatas).compile();
         let state = automata.start();
@@ -212,13 +218,26 @@ impl TTYDecoder {
             rescheduled: Default::default(),
             buffer: Default::default(),
             possible: None,
+            sixel: None,
         }
     }
 
+    /// Expect sixel image (for example, a response to a query) no larger than `max_size`
+    ///
+    /// Only the next sixel image is decoded, otherwise `\x1bP` is an alt+shift+p key.
+    pub fn sixel_expect(&mut self, max_size: Size) {
+        self.sixel = Some(max_size);
+    }
+
     /// Process single byte
     fn decode_byte(&mut self, byte: u8) -> Option<TerminalEvent> {
         self.buffer.push(byte);
-        match self.automata.transition(self.state, byte) {
+        let transition = if self.sixel.is_none() && is_sixel_introducer(&self.buffer) {
+            None
+        } else {
+            self.automata.transition(self.state, byte)
+        };
+        match transition {
             Some(state) => {
                 self.state = state;
                 let info = self.automata.info(state);
@@ -235,6 +254,16 @@ impl TTYDecoder {
                         TTYTag::Matcher(index) => self.matchers[*index]
                             .decode(&self.buffer)
                             .unwrap_or_else(|| TerminalEvent::Raw(self.buffer.clone())),
+                        TTYTag::Sixel => {
+                            let max_size = self.sixel.take().unwrap_or_default();
+                            match sixel_decode(&self.buffer, max_size, IMAGE_MAX_PIXELS) {
+                                Ok(img) => TerminalEvent::Image(img),
+                                Err(error) => {
+                                    tracing::debug!("failed to decode sixel image: {:?}", error);
+                                    TerminalEvent::Raw(self.buffer.clone())
+                                }
+                            }
+                        }
                     };
 
                     self.possible.replace((event, self.buffer.len()));
@@ -274,6 +303,8 @@ enum TTYTag {
     Event(TerminalEvent),
     /// Index of the matcher that needs to be used to decode event
     Matcher(usize),
+    /// Sixel image, decoded only if it is expected
+    Sixel,
 }
 
 /// Same as `!` type, which is not possible to construct
@@ -459,6 +490,35 @@ impl TTYMatcher for DeviceAttrsMatcher {
     }
 }
 
+/// XTSMGRAPHICS - Graphics attributes response
+///
+/// Reference: https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h3-Functions-using-CSI-_-ordered-by-the-final-character_s_
+#[derive(Debug)]
+struct GraphicsAttrsMatcher;
+
+impl TTYMatcher for GraphicsAttrsMatcher {
+    fn matcher(&self) -> NFA<_Void> {
+        // "\x1b[?<item>;<status>;<value_1>;...<value_n>S"
+        NFA::sequence([
+            NFA::from("\x1b[?"),
+            NFA::number(),
+            NFA::from(";"),
+            NFA::number(),
+            (NFA::from(";") + NFA::number()).many(),
+            NFA::from("S"),
+        ])
+    }
+
+    fn decode(&mut self, data: &[u8]) -> Option<TerminalEvent> {
+        let mut nums = numbers_decode(&data[3..data.len() - 1], b';');
+        Some(TerminalEvent::GraphicsAttrs {
+            item: nums.next()?,
+            status: nums.next()?,
+            values: nums.collect(),
+        })
+    }
+}
+
 /// OSC - Operating System Command Response
 ///
 /// Reference: https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h3-Operating-System-Commands
@@ -617,6 +677,27 @@ impl TTYMatcher for MouseEventMatcher {
     }
 }
 
+/// Sixel image
+///
+/// Reference: https://vt100.net/docs/vt3xx-gp/chapter14.html
+fn sixel_nfa() -> NFA<_Void> {
+    // "\x1bP{params}q{data}\x1b\\"
+    NFA::sequence([
+        NFA::from("\x1bP"),                                         // DCS
+        NFA::predicate(|c| c.is_ascii_digit() || c == b';').many(), // params
+        NFA::from("q"),
+        NFA::predicate(|c| c != b'\x1b').many(), // data
+        NFA::from("\x1b\\"),                     // ST
+    ])
+}
+
+/// Check if data is a complete sixel introducer `\x1bP{params}q`
+fn is_sixel_introducer(data: &[u8]) -> bool {
+    data.strip_prefix(b"\x1bP")
+        .and_then(|data| data.strip_suffix(b"q"))
+        .is_some_and(|params| params.iter().all(|c| c.is_ascii_digit() || *c == b';'))
+}
+
 /// Request Termcap/Terminfo String (XTGETTCAP)
 ///
 /// Reference: https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h3-Application-Program-Command-functions
@@ -803,6 +884,7 @@ fn tty_event_nfa() -> NFA<TerminalEvent> {
     cmds.push(basic_key("\x1b", KeyName::Esc));
     cmds.push(basic_key("\x7f", KeyName::Backspace));
     cmds.push(basic_key("\x00", (KeyName::Char(' '), KeyMod::CTRL)));
+    cmds.push(basic_key("\x1b[Z", (KeyName::Tab, KeyMod::SHIFT)));
 
     // ascii keys with modifiers
     for byte in (0..=255u8).filter(|c| c.is_ascii_lowercase()) {
@@ -1086,6 +1168,25 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_shift_tab() -> Result<(), Error> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut decoder = TTYDecoder::new();
+
+        write!(cursor.get_mut(), "\x1b[Za")?;
+        let mut result = Vec::new();
+        decoder.decode_into(&mut cursor, &mut result)?;
+        assert_eq!(
+            result,
+            vec![
+                TerminalEvent::Key((KeyName::Tab, KeyMod::SHIFT).into()),
+                TerminalEvent::Key(KeyName::Char('a').into()),
+            ]
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_reschedule() -> Result<(), Error> {
         let mut cursor = Cursor::new(Vec::new());
@@ -1387,6 +1488,90 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_graphics_attrs() -> Result<(), Error> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut decoder = TTYDecoder::new();
+
+        write!(
+            cursor.get_mut(),
+            "\x1b[?1;0;1024S\x1b[?2;0;1000;800S\x1b[?1;3S"
+        )?;
+
+        let mut result = Vec::new();
+        decoder.decode_into(&mut cursor, &mut result)?;
+        assert_eq!(
+            result,
+            vec![
+                TerminalEvent::GraphicsAttrs {
+                    item: 1,
+                    status: 0,
+                    values: vec![1024],
+                },
+                TerminalEvent::GraphicsAttrs {
+                    item: 2,
+                    status: 0,
+                    values: vec![1000, 800],
+                },
+                TerminalEvent::GraphicsAttrs {
+                    item: 1,
+                    status: 3,
+                    values: Vec::new(),
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sixel() -> Result<(), Error> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut decoder = TTYDecoder::new();
+
+        // sixel is not expected, alt+shift+p followed by q
+        write!(cursor.get_mut(), "\x1bPqa")?;
+        let mut result = Vec::new();
+        decoder.decode_into(&mut cursor, &mut result)?;
+        assert_eq!(
+            result,
+            vec![
+                TerminalEvent::Key(KeyName::Esc.into()),
+                TerminalEvent::Key(KeyName::Char('P').into()),
+                TerminalEvent::Key(KeyName::Char('q').into()),
+                TerminalEvent::Key(KeyName::Char('a').into()),
+            ]
+        );
+
+        decoder.sixel_expect(Size::new(100, 100));
+        write!(cursor.get_mut(), "\x1bP0;1q#1;2;100;0;0!2A\x1b\\a")?;
+
+        let mut result = Vec::new();
+        decoder.decode_into(&mut cursor, &mut result)?;
+        let red = RGBA::new(255, 0, 0, 255);
+        let none = RGBA::new(0, 0, 0, 0);
+        assert_eq!(
+            result,
+            vec![
+                TerminalEvent::Image(crate::Image::new(crate::SurfaceOwned::from_vec(
+                    2,
+                    2,
+                    vec![none, none, red, red]
+                ))),
+                TerminalEvent::Key(KeyName::Char('a').into()),
+            ]
+        );
+
+        // image larger than expected is not decoded
+        decoder.sixel_expect(Size::new(100, 100));
+        write!(cursor.get_mut(), "\x1bPq\"1;1;1000;1000#1!2A\x1b\\")?;
+        let mut result = Vec::new();
+        decoder.decode_into(&mut cursor, &mut result)?;
+        assert!(matches!(result.as_slice(), [TerminalEvent::Raw(_)]));
+
+        Ok(())
+    }
+
     #[test]
     fn test_osc() -> Result<(), Error> {
         let mut cursor = Cursor::new(Vec::new());
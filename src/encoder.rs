@@ -113,7 +113,7 @@ impl Encoder for TTYEncoder {
                     write!(out, "\x1b[r")?;
                 }
             }
-            Image(_, _) | ImageErase(_, _) => {
+            Image(_, _, _) | ImageErase(_, _) => {
                 // image commands are ignored and must be handled by image handler
             }
             Termcap(caps) => {
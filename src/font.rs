@@ -0,0 +1,1347 @@
+//! TrueType/OpenType glyph outlines loading
+//!
+//! Only the parts needed to extract glyph outlines are supported: `cmap` (formats
+//! 4 and 12), `glyf`/`loca` quadratic outlines (including composite glyphs) and
+//! `CFF` cubic outlines (including CID-keyed fonts).
+use crate::{error::Error, Glyph, Size};
+use rasterize::{BBox, FillRule, Path, PathBuilder, Point};
+use std::{ops::Range, sync::Arc};
+
+/// Maximum nesting of composite glyphs and charstring subroutines
+const FONT_MAX_DEPTH: usize = 16;
+/// Maximum total number of components of a composite glyph
+const FONT_MAX_COMPONENTS: usize = 1024;
+
+/// Font loaded from TrueType/OpenType file
+#[derive(Clone)]
+pub struct Font {
+    data: Arc<[u8]>,
+    units_per_em: u16,
+    ascender: i16,
+    descender: i16,
+    num_glyphs: u16,
+    /// Selected `cmap` subtable and its format
+    cmap: Option<(usize, u16)>,
+    outlines: Outlines,
+}
+
+#[derive(Clone)]
+enum Outlines {
+    TrueType {
+        loca: Range<usize>,
+        glyf: Range<usize>,
+        long_loca: bool,
+    },
+    Cff(Cff),
+}
+
+impl Font {
+    /// Load font from TrueType/OpenType data
+    ///
+    /// For font collections the first font is loaded.
+    pub fn from_bytes(data: impl Into<Arc<[u8]>>) -> Result<Self, Error> {
+        let data = data.into();
+        let mut offset = 0;
+        if data.get(..4) == Some(b"ttcf") {
+            offset = read_u32(&data, 12)? as usize;
+        }
+        let tag = data
+            .get(offset..offset + 4)
+            .ok_or_else(|| font_error("file is too short"))?;
+        if !matches!(tag, [0, 1, 0, 0] | b"true" | b"OTTO") {
+            return Err(font_error("unsupported font format"));
+        }
+        let tables = font_tables(&data, offset)?;
+        let table = |tag: &[u8; 4]| {
+            tables
+                .iter()
+                .find_map(|(name, range)| (name == tag).then(|| range.clone()))
+        };
+
+        let head = table(b"head").ok_or_else(|| font_error("missing head table"))?;
+        let units_per_em = read_u16(&data, head.start + 18)?;
+        if units_per_em == 0 {
+            return Err(font_error("invalid units per em"));
+        }
+        let long_loca = read_u16(&data, head.start + 50)? != 0;
+        let num_glyphs = match table(b"maxp") {
+            Some(maxp) => read_u16(&data, maxp.start + 4)?,
+            None => return Err(font_error("missing maxp table")),
+        };
+        let (ascender, descender) = match table(b"hhea") {
+            Some(hhea) => (
+                read_u16(&data, hhea.start + 4)? as i16,
+                read_u16(&data, hhea.start + 6)? as i16,
+            ),
+            None => (units_per_em as i16, 0),
+        };
+        let cmap = match table(b"cmap") {
+            Some(cmap) => cmap_select(&data, cmap.start)?,
+            None => None,
+        };
+        let outlines = match (table(b"glyf"), table(b"loca"), table(b"CFF ")) {
+            (Some(glyf), Some(loca), _) => Outlines::TrueType {
+                loca,
+                glyf,
+                long_loca,
+            },
+            (_, _, Some(cff)) => Outlines::Cff(Cff::new(&data, cff.start)?),
+            _ => return Err(font_error("missing glyph outlines")),
+        };
+
+        Ok(Self {
+            data,
+            units_per_em,
+            ascender,
+            descender,
+            num_glyphs,
+            cmap,
+            outlines,
+        })
+    }
+
+    /// Load font from a file
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self, Error> {
+        Self::from_bytes(std::fs::read(path)?)
+    }
+
+    /// Size of the em box in font units
+    pub fn units_per_em(&self) -> u16 {
+        self.units_per_em
+    }
+
+    /// Number of glyphs in the font
+    pub fn glyph_count(&self) -> u16 {
+        self.num_glyphs
+    }
+
+    /// Find glyph index of the character
+    pub fn glyph_index(&self, c: char) -> Option<u16> {
+        let (offset, format) = self.cmap?;
+        let index = match format {
+            4 => cmap_format4(&self.data, offset, c as u32),
+            12 => cmap_format12(&self.data, offset, c as u32),
+            _ => None,
+        }?;
+        (index != 0 && index < self.num_glyphs).then_some(index)
+    }
+
+    /// Outline of the glyph with the specified index
+    ///
+    /// Path is in font units with y axis pointing down, and positioned inside
+    /// the em box (see `Font::view_box`).
+    pub fn glyph_outline(&self, index: u16) -> Result<Path, Error> {
+        if index >= self.num_glyphs {
+            return Err(font_error(format!("glyph index out of range: {}", index)));
+        }
+        let baseline =
+            (self.units_per_em as f64 + self.ascender as f64 + self.descender as f64) / 2.0;
+        let mut builder = Path::builder();
+        match &self.outlines {
+            Outlines::TrueType { .. } => {
+                let mut contours = Vec::new();
+                let tr = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+                self.glyf_contours(index, tr, 0, &mut 0, &mut contours)?;
+                for contour in contours {
+                    contour_build(&mut builder, &contour, baseline);
+                }
+            }
+            Outlines::Cff(cff) => {
+                let mut charstring = CharString::new(&mut builder, baseline);
+                cff.outline(&self.data, index, &mut charstring)?;
+                charstring.close();
+            }
+        }
+        Ok(builder.build())
+    }
+
+    /// Em box of the font, used as glyphs view box
+    pub fn view_box(&self) -> BBox {
+        let em = self.units_per_em as f64;
+        BBox::new((0.0, 0.0), (em, em))
+    }
+
+    /// Create glyph for the character occupying `size` cells
+    ///
+    /// Returns `None` if the font does not contain the character.
+    pub fn glyph(&self, c: char, size: Size) -> Result<Option<Glyph>, Error> {
+        let Some(index) = self.glyph_index(c) else {
+            return Ok(None);
+        };
+        let path = self.glyph_outline(index)?;
+        Ok(Some(Glyph::new(
+            path,
+            FillRule::NonZero,
+            Some(self.view_box()),
+            size,
+        )))
+    }
+
+    /// Collect contours of the TrueType glyph transformed with `tr`
+    fn glyf_contours(
+        &self,
+        index: u16,
+        tr: [f64; 6],
+        depth: usize,
+        components: &mut usize,
+        contours: &mut Vec<Vec<OutlinePoint>>,
+    ) -> Result<(), Error> {
+        let Outlines::TrueType {
+            loca,
+            glyf,
+            long_loca,
+        } = &self.outlines
+        else {
+            return Ok(());
+        };
+        if depth > FONT_MAX_DEPTH {
+            return Err(font_error("composite glyph is nested too deep"));
+        }
+        let index = index as usize;
+        let (start, end) = if *long_loca {
+            (
+                read_u32(&self.data, loca.start + index * 4)? as usize,
+                read_u32(&self.data, loca.start + index * 4 + 4)? as usize,
+            )
+        } else {
+            (
+                read_u16(&self.data, loca.start + index * 2)? as usize * 2,
+                read_u16(&self.data, loca.start + index * 2 + 2)? as usize * 2,
+            )
+        };
+        if start >= end {
+            return Ok(()); // empty glyph
+        }
+        if glyf.start + end > glyf.end {
+            return Err(font_error("glyph data is out of bounds"));
+        }
+        let data = &self.data[glyf.start + start..glyf.start + end];
+        let transform = |x: f64, y: f64| {
+            let [a, b, c, d, e, f] = tr;
+            (a * x + c * y + e, b * x + d * y + f)
+        };
+
+        let contours_count = read_u16(data, 0)? as i16;
+        if contours_count >= 0 {
+            // simple glyph
+            let contours_count = contours_count as usize;
+            let mut ends = Vec::with_capacity(contours_count);
+            for contour in 0..contours_count {
+                ends.push(read_u16(data, 10 + contour * 2)? as usize);
+            }
+            let points_count = ends.last().map_or(0, |end| end + 1);
+            let instructions = read_u16(data, 10 + contours_count * 2)? as usize;
+            let mut offset = 12 + contours_count * 2 + instructions;
+
+            let mut flags = Vec::with_capacity(points_count);
+            while flags.len() < points_count {
+                let flag = read_u8(data, offset)?;
+                offset += 1;
+                let mut repeat = 1;
+                if flag & 0x08 != 0 {
+                    repeat += read_u8(data, offset)? as usize;
+                    offset += 1;
+                }
+                flags.resize(flags.len() + repeat, flag);
+            }
+            flags.truncate(points_count);
+
+            let mut coords = [Vec::with_capacity(points_count), Vec::new()];
+            for (axis, (short, same)) in [(0x02, 0x10), (0x04, 0x20)].into_iter().enumerate() {
+                let mut value = 0i32;
+                for flag in flags.iter() {
+                    if flag & short != 0 {
+                        let delta = read_u8(data, offset)? as i32;
+                        offset += 1;
+                        value += if flag & same != 0 { delta } else { -delta };
+                    } else if flag & same == 0 {
+                        value += read_u16(data, offset)? as i16 as i32;
+                        offset += 2;
+                    }
+                    coords[axis].push(value);
+                }
+            }
+
+            let mut start = 0;
+            for end in ends {
+                if end < start || end >= points_count {
+                    return Err(font_error("invalid contour end point"));
+                }
+                let contour = (start..=end)
+                    .map(|point| {
+                        let (x, y) = transform(coords[0][point] as f64, coords[1][point] as f64);
+                        OutlinePoint {
+                            x,
+                            y,
+                            on_curve: flags[point] & 0x01 != 0,
+                        }
+                    })
+                    .collect();
+                contours.push(contour);
+                start = end + 1;
+            }
+        } else {
+            // composite glyph
+            let mut offset = 10;
+            loop {
+                *components += 1;
+                if *components > FONT_MAX_COMPONENTS {
+                    return Err(font_error("composite glyph has too many components"));
+                }
+                let flags = read_u16(data, offset)?;
+                let component = read_u16(data, offset + 2)?;
+                offset += 4;
+                let (arg0, arg1) = if flags & 0x0001 != 0 {
+                    let args = (
+                        read_u16(data, offset)? as i16 as f64,
+                        read_u16(data, offset + 2)? as i16 as f64,
+                    );
+                    offset += 4;
+                    args
+                } else {
+                    let args = (
+                        read_u8(data, offset)? as i8 as f64,
+                        read_u8(data, offset + 1)? as i8 as f64,
+                    );
+                    offset += 2;
+                    args
+                };
+                // point matching is not supported, such components are not moved
+                let (dx, dy) = if flags & 0x0002 != 0 {
+                    (arg0, arg1)
+                } else {
+                    (0.0, 0.0)
+                };
+                let f2dot14 = |offset: usize| -> Result<f64, Error> {
+                    Ok(read_u16(data, offset)? as i16 as f64 / 16384.0)
+                };
+                let [mut a, mut b, mut c, mut d] = [1.0, 0.0, 0.0, 1.0];
+                if flags & 0x0008 != 0 {
+                    a = f2dot14(offset)?;
+                    d = a;
+                    offset += 2;
+                } else if flags & 0x0040 != 0 {
+                    a = f2dot14(offset)?;
+                    d = f2dot14(offset + 2)?;
+                    offset += 4;
+                } else if flags & 0x0080 != 0 {
+                    a = f2dot14(offset)?;
+                    b = f2dot14(offset + 2)?;
+                    c = f2dot14(offset + 4)?;
+                    d = f2dot14(offset + 6)?;
+                    offset += 8;
+                }
+                // compose component transform with the current one
+                let (e, f) = transform(dx, dy);
+                let [ta, tb, tc, td, _, _] = tr;
+                let component_tr = [
+                    ta * a + tc * b,
+                    tb * a + td * b,
+                    ta * c + tc * d,
+                    tb * c + td * d,
+                    e,
+                    f,
+                ];
+                self.glyf_contours(component, component_tr, depth + 1, components, contours)?;
+                if flags & 0x0020 == 0 {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for Font {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Font")
+            .field("units_per_em", &self.units_per_em)
+            .field("glyph_count", &self.num_glyphs)
+            .field("cff", &matches!(self.outlines, Outlines::Cff(..)))
+            .finish()
+    }
+}
+
+fn font_error(msg: impl Into<String>) -> Error {
+    Error::ParseError("Font", msg.into())
+}
+
+fn read_u8(data: &[u8], offset: usize) -> Result<u8, Error> {
+    data.get(offset)
+        .copied()
+        .ok_or_else(|| font_error("unexpected end of data"))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, Error> {
+    match data.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+        None => Err(font_error("unexpected end of data")),
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, Error> {
+    match data.get(offset..offset + 4) {
+        Some(bytes) => Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+        None => Err(font_error("unexpected end of data")),
+    }
+}
+
+/// Read table directory of the font starting at `offset`
+fn font_tables(data: &[u8], offset: usize) -> Result<Vec<([u8; 4], Range<usize>)>, Error> {
+    let count = read_u16(data, offset + 4)? as usize;
+    let mut tables = Vec::with_capacity(count);
+    for index in 0..count {
+        let record = offset + 12 + index * 16;
+        let tag = read_u32(data, record)?.to_be_bytes();
+        let start = read_u32(data, record + 8)? as usize;
+        let end = start + read_u32(data, record + 12)? as usize;
+        if end > data.len() {
+            return Err(font_error("table is out of bounds"));
+        }
+        tables.push((tag, start..end));
+    }
+    Ok(tables)
+}
+
+/// Select unicode `cmap` subtable, returns its offset and format
+fn cmap_select(data: &[u8], cmap: usize) -> Result<Option<(usize, u16)>, Error> {
+    let count = read_u16(data, cmap + 2)? as usize;
+    let mut selected: Option<(usize, u16)> = None;
+    for index in 0..count {
+        let record = cmap + 4 + index * 8;
+        let platform = read_u16(data, record)?;
+        let encoding = read_u16(data, record + 2)?;
+        // unicode platform, or windows symbol, BMP and full unicode encodings
+        if !(platform == 0 || (platform == 3 && matches!(encoding, 0 | 1 | 10))) {
+            continue;
+        }
+        let offset = cmap + read_u32(data, record + 4)? as usize;
+        let format = read_u16(data, offset)?;
+        match (format, selected) {
+            (12, _) | (4, None) => selected = Some((offset, format)),
+            _ => {}
+        }
+    }
+    Ok(selected)
+}
+
+/// Lookup glyph index in segment mapping to delta values subtable
+fn cmap_format4(data: &[u8], offset: usize, code: u32) -> Option<u16> {
+    let code = u16::try_from(code).ok()?;
+    let segments = read_u16(data, offset + 6).ok()? as usize / 2;
+    let ends = offset + 14;
+    let starts = ends + segments * 2 + 2;
+    let deltas = starts + segments * 2;
+    let range_offsets = deltas + segments * 2;
+    // segments are sorted by end code
+    let (mut low, mut high) = (0, segments);
+    while low < high {
+        let mid = (low + high) / 2;
+        if read_u16(data, ends + mid * 2).ok()? < code {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    let segment = low;
+    if segment >= segments {
+        return None;
+    }
+    let start = read_u16(data, starts + segment * 2).ok()?;
+    if code < start {
+        return None;
+    }
+    let delta = read_u16(data, deltas + segment * 2).ok()?;
+    let range_offset_addr = range_offsets + segment * 2;
+    let range_offset = read_u16(data, range_offset_addr).ok()? as usize;
+    if range_offset == 0 {
+        return Some(code.wrapping_add(delta));
+    }
+    let addr = range_offset_addr + range_offset + (code - start) as usize * 2;
+    match read_u16(data, addr).ok()? {
+        0 => None,
+        index => Some(index.wrapping_add(delta)),
+    }
+}
+
+/// Lookup glyph index in segmented coverage subtable
+fn cmap_format12(data: &[u8], offset: usize, code: u32) -> Option<u16> {
+    let groups = read_u32(data, offset + 12).ok()? as usize;
+    let (mut low, mut high) = (0, groups);
+    while low < high {
+        let mid = (low + high) / 2;
+        let group = offset + 16 + mid * 12;
+        let start = read_u32(data, group).ok()?;
+        let end = read_u32(data, group + 4).ok()?;
+        if code < start {
+            high = mid;
+        } else if code > end {
+            low = mid + 1;
+        } else {
+            // overflowing glyph index means there is no glyph
+            let index = read_u32(data, group + 8).ok()?.checked_add(code - start)?;
+            return u16::try_from(index).ok();
+        }
+    }
+    None
+}
+
+/// Point of TrueType outline
+#[derive(Debug, Clone, Copy)]
+struct OutlinePoint {
+    x: f64,
+    y: f64,
+    on_curve: bool,
+}
+
+/// Convert quadratic TrueType contour into a closed sub-path
+fn contour_build(builder: &mut PathBuilder, contour: &[OutlinePoint], baseline: f64) {
+    let (Some(first), Some(last)) = (contour.first(), contour.last()) else {
+        return;
+    };
+    let point = |p: &OutlinePoint| Point::new(p.x, baseline - p.y);
+    let mid = |p0: Point, p1: Point| Point::new((p0.x() + p1.x()) / 2.0, (p0.y() + p1.y()) / 2.0);
+    let (start, points) = if first.on_curve {
+        (point(first), &contour[1..])
+    } else if last.on_curve {
+        (point(last), contour)
+    } else {
+        (mid(point(first), point(last)), contour)
+    };
+    builder.move_to(start);
+    let mut control: Option<Point> = None;
+    for p in points {
+        let p_pos = point(p);
+        if p.on_curve {
+            match control.take() {
+                Some(control) => builder.quad_to(control, p_pos),
+                None => builder.line_to(p_pos),
+            };
+        } else {
+            // two consecutive off-curve points imply on-curve point between them
+            if let Some(control) = control {
+                builder.quad_to(control, mid(control, p_pos));
+            }
+            control = Some(p_pos);
+        }
+    }
+    if let Some(control) = control {
+        builder.quad_to(control, start);
+    }
+    builder.close();
+}
+
+/// CFF INDEX structure
+#[derive(Debug, Clone, Copy, Default)]
+struct CffIndex {
+    count: usize,
+    offset_size: usize,
+    /// Position of the offsets array
+    offsets: usize,
+    /// Position of the byte preceding objects data
+    data: usize,
+}
+
+impl CffIndex {
+    /// Parse index at `offset`, returns index and position after it
+    fn new(data: &[u8], offset: usize) -> Result<(Self, usize), Error> {
+        let count = read_u16(data, offset)? as usize;
+        if count == 0 {
+            return Ok((Self::default(), offset + 2));
+        }
+        let offset_size = read_u8(data, offset + 2)? as usize;
+        if !(1..=4).contains(&offset_size) {
+            return Err(font_error("invalid CFF index offset size"));
+        }
+        let index = Self {
+            count,
+            offset_size,
+            offsets: offset + 3,
+            data: offset + 2 + (count + 1) * offset_size,
+        };
+        let end = index.offset(data, count)?;
+        Ok((index, end))
+    }
+
+    fn offset(&self, data: &[u8], index: usize) -> Result<usize, Error> {
+        let start = self.offsets + index * self.offset_size;
+        let bytes = data
+            .get(start..start + self.offset_size)
+            .ok_or_else(|| font_error("unexpected end of data"))?;
+        let offset = bytes
+            .iter()
+            .fold(0usize, |offset, byte| (offset << 8) | *byte as usize);
+        Ok(self.data + offset)
+    }
+
+    fn get<'a>(&self, data: &'a [u8], index: usize) -> Result<&'a [u8], Error> {
+        if index >= self.count {
+            return Err(font_error("CFF index out of range"));
+        }
+        let start = self.offset(data, index)?;
+        let end = self.offset(data, index + 1)?;
+        data.get(start..end)
+            .ok_or_else(|| font_error("CFF object is out of bounds"))
+    }
+
+    /// Subroutine number bias
+    fn bias(&self) -> i32 {
+        if self.count < 1240 {
+            107
+        } else if self.count < 33900 {
+            1131
+        } else {
+            32768
+        }
+    }
+}
+
+/// Parse CFF DICT into a list of operators with their operands
+fn cff_dict(data: &[u8]) -> Result<Vec<(u16, Vec<f64>)>, Error> {
+    let mut entries = Vec::new();
+    let mut operands = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let b0 = data[offset];
+        offset += 1;
+        match b0 {
+            0..=21 => {
+                let op = if b0 == 12 {
+                    offset += 1;
+                    1200 + read_u8(data, offset - 1)? as u16
+                } else {
+                    b0 as u16
+                };
+                entries.push((op, std::mem::take(&mut operands)));
+            }
+            28 => {
+                operands.push(read_u16(data, offset)? as i16 as f64);
+                offset += 2;
+            }
+            29 => {
+                operands.push(read_u32(data, offset)? as i32 as f64);
+                offset += 4;
+            }
+            30 => {
+                // real number encoded as nibbles
+                let mut real = String::new();
+                'real: loop {
+                    let byte = read_u8(data, offset)?;
+                    offset += 1;
+                    for nibble in [byte >> 4, byte & 0x0f] {
+                        match nibble {
+                            0..=9 => real.push((b'0' + nibble) as char),
+                            0xa => real.push('.'),
+                            0xb => real.push('e'),
+                            0xc => real.push_str("e-"),
+                            0xe => real.push('-'),
+                            0xf => break 'real,
+                            _ => {}
+                        }
+                    }
+                }
+                operands.push(real.parse().unwrap_or(0.0));
+            }
+            32..=246 => operands.push(b0 as f64 - 139.0),
+            247..=250 => {
+                let b1 = read_u8(data, offset)? as f64;
+                offset += 1;
+                operands.push((b0 as f64 - 247.0) * 256.0 + b1 + 108.0);
+            }
+            251..=254 => {
+                let b1 = read_u8(data, offset)? as f64;
+                offset += 1;
+                operands.push(-(b0 as f64 - 251.0) * 256.0 - b1 - 108.0);
+            }
+            _ => return Err(font_error("invalid CFF dict operand")),
+        }
+    }
+    Ok(entries)
+}
+
+/// Find operands of the DICT operator
+fn cff_dict_get(dict: &[(u16, Vec<f64>)], op: u16) -> Option<&[f64]> {
+    dict.iter()
+        .find_map(|(dict_op, operands)| (*dict_op == op).then_some(operands.as_slice()))
+}
+
+/// Offset `base` by the DICT operand, which must be a valid offset into `data`
+fn cff_offset(data: &[u8], base: usize, operand: f64) -> Result<usize, Error> {
+    if !(operand.is_finite() && operand >= 0.0 && operand < data.len() as f64) {
+        return Err(font_error("invalid CFF offset"));
+    }
+    base.checked_add(operand as usize)
+        .ok_or_else(|| font_error("invalid CFF offset"))
+}
+
+/// Compact Font Format outlines
+#[derive(Debug, Clone)]
+struct Cff {
+    charstrings: CffIndex,
+    global_subrs: CffIndex,
+    /// Local subroutines of each font DICT
+    local_subrs: Vec<CffIndex>,
+    /// Position of FDSelect structure for CID-keyed fonts
+    fd_select: Option<usize>,
+}
+
+impl Cff {
+    fn new(data: &[u8], cff: usize) -> Result<Self, Error> {
+        let header_size = read_u8(data, cff + 2)? as usize;
+        let (_names, offset) = CffIndex::new(data, cff + header_size)?;
+        let (top_dicts, offset) = CffIndex::new(data, offset)?;
+        let (_strings, offset) = CffIndex::new(data, offset)?;
+        let (global_subrs, _) = CffIndex::new(data, offset)?;
+        let top_dict = cff_dict(top_dicts.get(data, 0)?)?;
+
+        let charstrings = match cff_dict_get(&top_dict, 17) {
+            Some([offset]) => CffIndex::new(data, cff_offset(data, cff, *offset)?)?.0,
+            _ => return Err(font_error("missing CFF charstrings")),
+        };
+        let private_subrs = |dict: &[(u16, Vec<f64>)]| -> Result<CffIndex, Error> {
+            let Some([size, offset]) = cff_dict_get(dict, 18) else {
+                return Ok(CffIndex::default());
+            };
+            let private = cff_offset(data, cff, *offset)?;
+            let private_data = data
+                .get(private..cff_offset(data, private, *size)?)
+                .ok_or_else(|| font_error("CFF private dict is out of bounds"))?;
+            match cff_dict_get(&cff_dict(private_data)?, 19) {
+                Some([subrs]) => Ok(CffIndex::new(data, cff_offset(data, private, *subrs)?)?.0),
+                _ => Ok(CffIndex::default()),
+            }
+        };
+        let (local_subrs, fd_select) =
+            match (cff_dict_get(&top_dict, 1236), cff_dict_get(&top_dict, 1237)) {
+                (Some([fd_array]), Some([fd_select])) => {
+                    let (fd_array, _) = CffIndex::new(data, cff_offset(data, cff, *fd_array)?)?;
+                    let local_subrs = (0..fd_array.count)
+                        .map(|index| private_subrs(&cff_dict(fd_array.get(data, index)?)?))
+                        .collect::<Result<_, _>>()?;
+                    (local_subrs, Some(cff_offset(data, cff, *fd_select)?))
+                }
+                _ => (vec![private_subrs(&top_dict)?], None),
+            };
+
+        Ok(Self {
+            charstrings,
+            global_subrs,
+            local_subrs,
+            fd_select,
+        })
+    }
+
+    /// Find font DICT index used by the glyph
+    fn font_dict(&self, data: &[u8], glyph: u16) -> Result<usize, Error> {
+        let Some(fd_select) = self.fd_select else {
+            return Ok(0);
+        };
+        match read_u8(data, fd_select)? {
+            0 => Ok(read_u8(data, fd_select + 1 + glyph as usize)? as usize),
+            3 => {
+                let ranges = read_u16(data, fd_select + 1)? as usize;
+                for range in 0..ranges {
+                    let record = fd_select + 3 + range * 3;
+                    let first = read_u16(data, record)?;
+                    let next = read_u16(data, record + 3)?;
+                    if (first..next).contains(&glyph) {
+                        return Ok(read_u8(data, record + 2)? as usize);
+                    }
+                }
+                Err(font_error("glyph is missing in CFF FDSelect"))
+            }
+            _ => Err(font_error("unsupported CFF FDSelect format")),
+        }
+    }
+
+    fn outline(&self, data: &[u8], glyph: u16, out: &mut CharString<'_>) -> Result<(), Error> {
+        let local_subrs = self
+            .local_subrs
+            .get(self.font_dict(data, glyph)?)
+            .copied()
+            .unwrap_or_default();
+        let charstring = self.charstrings.get(data, glyph as usize)?;
+        out.run(data, charstring, &self.global_subrs, &local_subrs, 0)?;
+        Ok(())
+    }
+}
+
+/// Type 2 charstring interpreter state
+struct CharString<'a> {
+    builder: &'a mut PathBuilder,
+    baseline: f64,
+    stack: Vec<f64>,
+    x: f64,
+    y: f64,
+    stems: usize,
+    open: bool,
+}
+
+impl<'a> CharString<'a> {
+    fn new(builder: &'a mut PathBuilder, baseline: f64) -> Self {
+        Self {
+            builder,
+            baseline,
+            stack: Vec::new(),
+            x: 0.0,
+            y: 0.0,
+            stems: 0,
+            open: false,
+        }
+    }
+
+    fn point(&self, x: f64, y: f64) -> Point {
+        Point::new(x, self.baseline - y)
+    }
+
+    fn close(&mut self) {
+        if self.open {
+            self.builder.close();
+            self.open = false;
+        }
+    }
+
+    fn move_to(&mut self, dx: f64, dy: f64) {
+        self.close();
+        self.x += dx;
+        self.y += dy;
+        self.builder.move_to(self.point(self.x, self.y));
+        self.open = true;
+    }
+
+    fn line_to(&mut self, dx: f64, dy: f64) {
+        self.x += dx;
+        self.y += dy;
+        self.builder.line_to(self.point(self.x, self.y));
+    }
+
+    fn curve_to(&mut self, d: [f64; 6]) {
+        let (x1, y1) = (self.x + d[0], self.y + d[1]);
+        let (x2, y2) = (x1 + d[2], y1 + d[3]);
+        self.x = x2 + d[4];
+        self.y = y2 + d[5];
+        self.builder.cubic_to(
+            self.point(x1, y1),
+            self.point(x2, y2),
+            self.point(self.x, self.y),
+        );
+    }
+
+    /// Execute charstring, returns `true` if `endchar` was reached
+    fn run(
+        &mut self,
+        data: &[u8],
+        code: &[u8],
+        global_subrs: &CffIndex,
+        local_subrs: &CffIndex,
+        depth: usize,
+    ) -> Result<bool, Error> {
+        if depth > FONT_MAX_DEPTH {
+            return Err(font_error("CFF subroutines are nested too deep"));
+        }
+        let mut offset = 0;
+        while offset < code.len() {
+            let b0 = code[offset];
+            offset += 1;
+            match b0 {
+                28 => {
+                    self.stack.push(read_u16(code, offset)? as i16 as f64);
+                    offset += 2;
+                    continue;
+                }
+                32..=246 => {
+                    self.stack.push(b0 as f64 - 139.0);
+                    continue;
+                }
+                247..=250 => {
+                    let b1 = read_u8(code, offset)? as f64;
+                    offset += 1;
+                    self.stack.push((b0 as f64 - 247.0) * 256.0 + b1 + 108.0);
+                    continue;
+                }
+                251..=254 => {
+                    let b1 = read_u8(code, offset)? as f64;
+                    offset += 1;
+                    self.stack.push(-(b0 as f64 - 251.0) * 256.0 - b1 - 108.0);
+                    continue;
+                }
+                255 => {
+                    self.stack
+                        .push(read_u32(code, offset)? as i32 as f64 / 65536.0);
+                    offset += 4;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let args = std::mem::take(&mut self.stack);
+            match b0 {
+                // hstem, vstem, hstemhm, vstemhm
+                1 | 3 | 18 | 23 => self.stems += args.len() / 2,
+                // hintmask, cntrmask (preceding arguments are implicit vstem)
+                19 | 20 => {
+                    self.stems += args.len() / 2;
+                    offset += self.stems.div_ceil(8);
+                }
+                // rmoveto
+                21 => {
+                    let [dx, dy] = args_last(&args);
+                    self.move_to(dx, dy);
+                }
+                // hmoveto
+                22 => {
+                    let [dx] = args_last(&args);
+                    self.move_to(dx, 0.0);
+                }
+                // vmoveto
+                4 => {
+                    let [dy] = args_last(&args);
+                    self.move_to(0.0, dy);
+                }
+                // rlineto
+                5 => {
+                    for d in args.chunks_exact(2) {
+                        self.line_to(d[0], d[1]);
+                    }
+                }
+                // hlineto, vlineto
+                6 | 7 => {
+                    for (index, d) in args.iter().enumerate() {
+                        if (index % 2 == 0) == (b0 == 6) {
+                            self.line_to(*d, 0.0);
+                        } else {
+                            self.line_to(0.0, *d);
+                        }
+                    }
+                }
+                // rrcurveto
+                8 => {
+                    for d in args.chunks_exact(6) {
+                        self.curve_to([d[0], d[1], d[2], d[3], d[4], d[5]]);
+                    }
+                }
+                // rcurveline
+                24 => {
+                    let curves = args.len().saturating_sub(2) / 6;
+                    for d in args.chunks_exact(6).take(curves) {
+                        self.curve_to([d[0], d[1], d[2], d[3], d[4], d[5]]);
+                    }
+                    if let [dx, dy] = args[curves * 6..] {
+                        self.line_to(dx, dy);
+                    }
+                }
+                // rlinecurve
+                25 => {
+                    let lines = args.len().saturating_sub(6) / 2;
+                    for d in args.chunks_exact(2).take(lines) {
+                        self.line_to(d[0], d[1]);
+                    }
+                    if let [a, b, c, d, e, f] = args[lines * 2..] {
+                        self.curve_to([a, b, c, d, e, f]);
+                    }
+                }
+                // vvcurveto
+                26 => {
+                    let (mut dx1, rest) = match args.len() % 2 {
+                        1 => (args[0], &args[1..]),
+                        _ => (0.0, &args[..]),
+                    };
+                    for d in rest.chunks_exact(4) {
+                        self.curve_to([dx1, d[0], d[1], d[2], 0.0, d[3]]);
+                        dx1 = 0.0;
+                    }
+                }
+                // hhcurveto
+                27 => {
+                    let (mut dy1, rest) = match args.len() % 2 {
+                        1 => (args[0], &args[1..]),
+                        _ => (0.0, &args[..]),
+                    };
+                    for d in rest.chunks_exact(4) {
+                        self.curve_to([d[0], dy1, d[1], d[2], d[3], 0.0]);
+                        dy1 = 0.0;
+                    }
+                }
+                // vhcurveto, hvcurveto
+                30 | 31 => {
+                    let mut horizontal = b0 == 31;
+                    let mut rest = &args[..];
+                    while rest.len() >= 4 {
+                        let last = if rest.len() == 5 { rest[4] } else { 0.0 };
+                        if horizontal {
+                            self.curve_to([rest[0], 0.0, rest[1], rest[2], last, rest[3]]);
+                        } else {
+                            self.curve_to([0.0, rest[0], rest[1], rest[2], rest[3], last]);
+                        }
+                        horizontal = !horizontal;
+                        rest = &rest[4..];
+                    }
+                }
+                // callsubr, callgsubr
+                10 | 29 => {
+                    let mut args = args;
+                    let subrs = if b0 == 10 { local_subrs } else { global_subrs };
+                    let index = args
+                        .pop()
+                        .ok_or_else(|| font_error("missing CFF subroutine index"))?;
+                    let index = usize::try_from(index as i32 + subrs.bias())
+                        .map_err(|_| font_error("invalid CFF subroutine index"))?;
+                    self.stack = args;
+                    let subr = subrs.get(data, index)?;
+                    if self.run(data, subr, global_subrs, local_subrs, depth + 1)? {
+                        return Ok(true);
+                    }
+                }
+                // return
+                11 => {
+                    self.stack = args;
+                    return Ok(false);
+                }
+                // endchar
+                14 => {
+                    self.close();
+                    return Ok(true);
+                }
+                // escape
+                12 => {
+                    let op = read_u8(code, offset)?;
+                    offset += 1;
+                    self.flex(op, &args);
+                }
+                _ => {}
+            }
+        }
+        Ok(false)
+    }
+
+    /// Flex operators, each one is a pair of curves
+    fn flex(&mut self, op: u8, args: &[f64]) {
+        match (op, args) {
+            // flex
+            (35, [d @ .., _fd]) if d.len() == 12 => {
+                self.curve_to([d[0], d[1], d[2], d[3], d[4], d[5]]);
+                self.curve_to([d[6], d[7], d[8], d[9], d[10], d[11]]);
+            }
+            // hflex
+            (34, [dx1, dx2, dy2, dx3, dx4, dx5, dx6]) => {
+                self.curve_to([*dx1, 0.0, *dx2, *dy2, *dx3, 0.0]);
+                self.curve_to([*dx4, 0.0, *dx5, -dy2, *dx6, 0.0]);
+            }
+            // hflex1
+            (36, [dx1, dy1, dx2, dy2, dx3, dx4, dx5, dy5, dx6]) => {
+                self.curve_to([*dx1, *dy1, *dx2, *dy2, *dx3, 0.0]);
+                self.curve_to([*dx4, 0.0, *dx5, *dy5, *dx6, -(dy1 + dy2 + dy5)]);
+            }
+            // flex1
+            (37, [d @ .., d6]) if d.len() == 10 => {
+                let dx: f64 = d.iter().step_by(2).sum();
+                let dy: f64 = d.iter().skip(1).step_by(2).sum();
+                let (dx6, dy6) = if dx.abs() > dy.abs() {
+                    (*d6, -dy)
+                } else {
+                    (-dx, *d6)
+                };
+                self.curve_to([d[0], d[1], d[2], d[3], d[4], d[5]]);
+                self.curve_to([d[6], d[7], d[8], d[9], dx6, dy6]);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Last `N` arguments of the operator (leading argument might be glyph width)
+fn args_last<const N: usize>(args: &[f64]) -> [f64; N] {
+    let mut result = [0.0; N];
+    let skip = args.len().saturating_sub(N);
+    for (dst, src) in result.iter_mut().zip(&args[skip..]) {
+        *dst = *src;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rasterize::Transform;
+
+    fn be16(value: i32) -> [u8; 2] {
+        (value as u16).to_be_bytes()
+    }
+
+    fn be32(value: u32) -> [u8; 4] {
+        value.to_be_bytes()
+    }
+
+    /// Assemble font file from tables
+    fn font_build(version: &[u8; 4], tables: Vec<(&[u8; 4], Vec<u8>)>) -> Vec<u8> {
+        let mut font = version.to_vec();
+        font.extend(be16(tables.len() as i32));
+        font.extend([0; 6]);
+        let mut offset = 12 + tables.len() * 16;
+        for (tag, table) in tables.iter() {
+            font.extend(*tag);
+            font.extend([0; 4]);
+            font.extend(be32(offset as u32));
+            font.extend(be32(table.len() as u32));
+            offset += table.len().div_ceil(4) * 4;
+        }
+        for (_, mut table) in tables {
+            table.resize(table.len().div_ceil(4) * 4, 0);
+            font.extend(table);
+        }
+        font
+    }
+
+    fn head_maxp(num_glyphs: i32) -> (Vec<u8>, Vec<u8>) {
+        let mut head = vec![0; 54];
+        head[18..20].copy_from_slice(&be16(1000));
+        let mut maxp = vec![0; 6];
+        maxp[4..6].copy_from_slice(&be16(num_glyphs));
+        (head, maxp)
+    }
+
+    fn path_bbox(path: &Path) -> Option<BBox> {
+        path.bbox(Transform::identity())
+    }
+
+    #[test]
+    fn test_font_truetype() -> Result<(), Error> {
+        let (head, maxp) = head_maxp(3);
+        let mut hhea = vec![0; 36];
+        hhea[4..6].copy_from_slice(&be16(800));
+        hhea[6..8].copy_from_slice(&be16(-200));
+
+        // 'A'..'B' mapped with delta, U+E000 with glyph id array
+        let mut cmap = Vec::new();
+        cmap.extend(be16(0));
+        cmap.extend(be16(1));
+        cmap.extend([0, 3, 0, 1]);
+        cmap.extend(be32(12));
+        cmap.extend(be16(4));
+        cmap.extend([0; 4]);
+        cmap.extend(be16(6)); // 3 segments
+        cmap.extend([0; 6]);
+        for end in [0x42, 0xe000, 0xffff] {
+            cmap.extend(be16(end));
+        }
+        cmap.extend([0; 2]);
+        for start in [0x41, 0xe000, 0xffff] {
+            cmap.extend(be16(start));
+        }
+        for delta in [1 - 0x41, 0, 1] {
+            cmap.extend(be16(delta));
+        }
+        for range_offset in [0, 4, 0] {
+            cmap.extend(be16(range_offset));
+        }
+        cmap.extend(be16(2));
+
+        let mut glyf = Vec::new();
+        // simple square
+        glyf.extend(be16(1));
+        glyf.extend([0; 8]);
+        glyf.extend(be16(3));
+        glyf.extend(be16(0));
+        glyf.extend([1; 4]);
+        for x in [0, 500, 0, -500] {
+            glyf.extend(be16(x));
+        }
+        for y in [0, 0, 500, 0] {
+            glyf.extend(be16(y));
+        }
+        let simple_len = glyf.len();
+        // composite moved by (100, 200)
+        glyf.extend(be16(-1));
+        glyf.extend([0; 8]);
+        glyf.extend(be16(0x0003));
+        glyf.extend(be16(1));
+        glyf.extend(be16(100));
+        glyf.extend(be16(200));
+        let mut loca = Vec::new();
+        for offset in [0, 0, simple_len, glyf.len()] {
+            loca.extend(be16(offset as i32 / 2));
+        }
+
+        let data = font_build(
+            &[0, 1, 0, 0],
+            vec![
+                (b"cmap", cmap),
+                (b"glyf", glyf),
+                (b"head", head),
+                (b"hhea", hhea),
+                (b"loca", loca),
+                (b"maxp", maxp),
+            ],
+        );
+        let font = Font::from_bytes(data)?;
+        assert_eq!(font.units_per_em(), 1000);
+        assert_eq!(font.glyph_count(), 3);
+        assert_eq!(font.glyph_index('A'), Some(1));
+        assert_eq!(font.glyph_index('B'), Some(2));
+        assert_eq!(font.glyph_index('\u{e000}'), Some(2));
+        assert_eq!(font.glyph_index('C'), None);
+
+        // baseline is placed so that ascender and descender are centered in em box
+        let bbox = path_bbox(&font.glyph_outline(1)?);
+        assert_eq!(bbox, Some(BBox::new((0.0, 300.0), (500.0, 800.0))));
+        let bbox = path_bbox(&font.glyph_outline(2)?);
+        assert_eq!(bbox, Some(BBox::new((100.0, 100.0), (600.0, 600.0))));
+        assert!(path_bbox(&font.glyph_outline(0)?).is_none());
+        assert!(font.glyph_outline(3).is_err());
+
+        let glyph = font.glyph('A', Size::new(1, 2))?.expect("missing glyph");
+        assert_eq!(glyph.size(), Size::new(1, 2));
+        assert!(font.glyph('C', Size::new(1, 2))?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_font_components_budget() -> Result<(), Error> {
+        let (head, maxp) = head_maxp(2);
+        // composite of empty glyphs with too many components
+        let mut glyf = be16(-1).to_vec();
+        glyf.extend([0; 8]);
+        for component in 0..=FONT_MAX_COMPONENTS {
+            let more = if component < FONT_MAX_COMPONENTS {
+                0x0020
+            } else {
+                0
+            };
+            glyf.extend(be16(more));
+            glyf.extend(be16(0));
+            glyf.extend([0; 2]);
+        }
+        let mut loca = Vec::new();
+        for offset in [0, 0, glyf.len()] {
+            loca.extend(be16(offset as i32 / 2));
+        }
+        let data = font_build(
+            &[0, 1, 0, 0],
+            vec![
+                (b"glyf", glyf),
+                (b"head", head),
+                (b"loca", loca),
+                (b"maxp", maxp),
+            ],
+        );
+        let font = Font::from_bytes(data)?;
+        assert!(font.glyph_outline(1).is_err());
+        Ok(())
+    }
+
+    fn cff_index(items: &[&[u8]]) -> Vec<u8> {
+        let mut index = Vec::new();
+        index.extend(be16(items.len() as i32));
+        if items.is_empty() {
+            return index;
+        }
+        index.push(1);
+        let mut offset = 1;
+        index.push(offset as u8);
+        for item in items {
+            offset += item.len();
+            index.push(offset as u8);
+        }
+        for item in items {
+            index.extend(*item);
+        }
+        index
+    }
+
+    fn cff_int(value: u32) -> Vec<u8> {
+        let mut data = vec![29];
+        data.extend(be32(value));
+        data
+    }
+
+    #[test]
+    fn test_font_cff() -> Result<(), Error> {
+        let (head, maxp) = head_maxp(2);
+
+        // 'B' mapped to glyph 1 with segmented coverage
+        let mut cmap = Vec::new();
+        cmap.extend(be16(0));
+        cmap.extend(be16(1));
+        cmap.extend([0, 0, 0, 3]);
+        cmap.extend(be32(12));
+        cmap.extend(be16(12));
+        cmap.extend([0; 10]);
+        cmap.extend(be32(1));
+        cmap.extend(be32(0x42));
+        cmap.extend(be32(0x42));
+        cmap.extend(be32(1));
+
+        // 100 100 rmoveto 500 hlineto -107 callgsubr -107 callsubr endchar
+        let glyph: &[u8] = &[239, 239, 21, 248, 136, 6, 32, 29, 32, 10, 14];
+        // 0 500 rlineto return
+        let global_subr: &[u8] = &[139, 248, 136, 5, 11];
+        // -500 hlineto return
+        let local_subr: &[u8] = &[252, 136, 6, 11];
+
+        let header = [1, 0, 4, 1];
+        let names = cff_index(&[b"A"]);
+        let strings = cff_index(&[]);
+        let global_subrs = cff_index(&[global_subr]);
+        let charstrings = cff_index(&[&[14], glyph]);
+        let top_dict_size = 17;
+        let charstrings_offset =
+            header.len() + names.len() + 5 + top_dict_size + strings.len() + global_subrs.len();
+        let private_offset = charstrings_offset + charstrings.len();
+        let mut private = cff_int(6);
+        private.push(19);
+        let mut top_dict = cff_int(charstrings_offset as u32);
+        top_dict.push(17);
+        top_dict.extend(cff_int(private.len() as u32));
+        top_dict.extend(cff_int(private_offset as u32));
+        top_dict.push(18);
+        assert_eq!(top_dict.len(), top_dict_size);
+
+        let mut cff = header.to_vec();
+        cff.extend(names);
+        cff.extend(cff_index(&[&top_dict]));
+        cff.extend(strings);
+        cff.extend(global_subrs);
+        cff.extend(charstrings);
+        cff.extend(private);
+        cff.extend(cff_index(&[local_subr]));
+
+        let data = font_build(
+            b"OTTO",
+            vec![
+                (b"CFF ", cff),
+                (b"cmap", cmap),
+                (b"head", head),
+                (b"maxp", maxp),
+            ],
+        );
+        let font = Font::from_bytes(data)?;
+        assert_eq!(font.glyph_index('B'), Some(1));
+        let bbox = path_bbox(&font.glyph_outline(1)?);
+        assert_eq!(bbox, Some(BBox::new((100.0, 400.0), (600.0, 900.0))));
+        assert!(path_bbox(&font.glyph_outline(0)?).is_none());
+
+        // DICT operands used as offsets are validated
+        let data = [0; 8];
+        assert_eq!(cff_offset(&data, 2, 3.0)?, 5);
+        for operand in [-1.0, 8.0, f64::NAN, f64::INFINITY] {
+            assert!(cff_offset(&data, 2, operand).is_err());
+        }
+        assert!(cff_offset(&data, usize::MAX, 1.0).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_font_cmap_overflow() {
+        // group starting at the largest glyph index
+        let mut cmap = be16(12).to_vec();
+        cmap.extend([0; 10]);
+        cmap.extend(be32(1));
+        cmap.extend(be32(0x41));
+        cmap.extend(be32(0x42));
+        cmap.extend(be32(u32::MAX));
+        assert_eq!(cmap_format12(&cmap, 0, 0x41), None);
+        assert_eq!(cmap_format12(&cmap, 0, 0x42), None);
+    }
+}
@@ -0,0 +1,244 @@
+//! Fuzzy matching in the spirit of fzf
+//!
+//! Pattern characters are matched as a subsequence of the haystack, the best
+//! alignment is found with a Smith-Waterman like dynamic programming which
+//! rewards consecutive matches and matches at word boundaries.
+
+/// Score of each matched character
+const SCORE_MATCH: i32 = 16;
+/// Penalty for starting a gap between matched characters
+const SCORE_GAP_START: i32 = -3;
+/// Penalty for each additional character of the gap
+const SCORE_GAP_EXTENSION: i32 = -1;
+/// Match after a whitespace or at the start of the haystack
+const BONUS_BOUNDARY_WHITE: i32 = 10;
+/// Match after a delimiter such as `/`, `_` or `.`
+const BONUS_BOUNDARY: i32 = 8;
+/// Match at camelCase transition or at the start of a number
+const BONUS_CAMEL: i32 = 7;
+/// Minimal bonus of the match following previous match
+const BONUS_CONSECUTIVE: i32 = 4;
+/// Bonus of the first pattern character is multiplied by this value
+const BONUS_FIRST_CHAR_MULTIPLIER: i32 = 2;
+/// Marker of the unreachable state
+const SCORE_NONE: i32 = i32::MIN / 2;
+
+/// Result of the successful fuzzy match
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FuzzyMatch {
+    /// Score of the match, higher is better
+    pub score: i32,
+    /// Character indices of the matched characters in the haystack
+    pub positions: Vec<usize>,
+}
+
+/// Fuzzy matcher for a pattern
+///
+/// Matching is case insensitive unless pattern contains upper case characters.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct FuzzyMatcher {
+    pattern: String,
+    needle: Vec<char>,
+    case_sensitive: bool,
+}
+
+impl FuzzyMatcher {
+    /// Create matcher for the pattern
+    pub fn new(pattern: &str) -> Self {
+        let case_sensitive = pattern.chars().any(char::is_uppercase);
+        let needle = pattern
+            .chars()
+            .map(|c| if case_sensitive { c } else { fold_case(c) })
+            .collect();
+        Self {
+            pattern: pattern.to_string(),
+            needle,
+            case_sensitive,
+        }
+    }
+
+    /// Pattern used by the matcher
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Whether pattern is empty (empty pattern matches everything)
+    pub fn is_empty(&self) -> bool {
+        self.needle.is_empty()
+    }
+
+    /// Whether all haystacks matched by this matcher are also matched by the other one
+    ///
+    /// Used to refine previous results instead of matching all haystacks again.
+    pub fn is_refined_by(&self, other: &Self) -> bool {
+        self.case_sensitive == other.case_sensitive && other.needle.starts_with(&self.needle)
+    }
+
+    /// Score of the haystack, `None` if it does not match
+    pub fn score(&self, haystack: &str) -> Option<i32> {
+        self.run(haystack, false).map(|result| result.score)
+    }
+
+    /// Score and matched positions of the haystack, `None` if it does not match
+    pub fn matches(&self, haystack: &str) -> Option<FuzzyMatch> {
+        self.run(haystack, true)
+    }
+
+    fn run(&self, haystack: &str, positions: bool) -> Option<FuzzyMatch> {
+        let chars: Vec<char> = haystack.chars().collect();
+        let folded: Vec<char> = if self.case_sensitive {
+            chars.clone()
+        } else {
+            chars.iter().copied().map(fold_case).collect()
+        };
+        // quick check that needle is a subsequence of the haystack
+        let mut iter = folded.iter();
+        if !self.needle.iter().all(|c| iter.any(|h| h == c)) {
+            return None;
+        }
+        let (m, n) = (self.needle.len(), chars.len());
+        if m == 0 {
+            return Some(FuzzyMatch {
+                score: 0,
+                positions: Vec::new(),
+            });
+        }
+
+        let bonus: Vec<i32> = (0..n)
+            .map(|j| char_bonus(j.checked_sub(1).map(|k| chars[k]), chars[j]))
+            .collect();
+        // score[i * n + j] is the best score of needle[..=i] with needle[i] matched at j
+        let mut score = vec![SCORE_NONE; m * n];
+        // index at which the previous needle character was matched
+        let mut from = vec![usize::MAX; if positions { m * n } else { 0 }];
+        for i in 0..m {
+            // best predecessor separated by a gap of at least one character
+            let mut gap = (SCORE_NONE, usize::MAX);
+            for j in 0..n {
+                if i > 0 && j >= 2 {
+                    if gap.0 > SCORE_NONE {
+                        gap.0 += SCORE_GAP_EXTENSION;
+                    }
+                    let prev = score[(i - 1) * n + j - 2];
+                    if prev > SCORE_NONE && prev + SCORE_GAP_START >= gap.0 {
+                        gap = (prev + SCORE_GAP_START, j - 2);
+                    }
+                }
+                if folded[j] != self.needle[i] {
+                    continue;
+                }
+                let (best, prev) = if i == 0 {
+                    (bonus[j] * BONUS_FIRST_CHAR_MULTIPLIER, usize::MAX)
+                } else {
+                    let consecutive = match j.checked_sub(1) {
+                        Some(k) if score[(i - 1) * n + k] > SCORE_NONE => {
+                            score[(i - 1) * n + k] + bonus[j].max(BONUS_CONSECUTIVE)
+                        }
+                        _ => SCORE_NONE,
+                    };
+                    let gapped = if gap.0 > SCORE_NONE {
+                        gap.0 + bonus[j]
+                    } else {
+                        SCORE_NONE
+                    };
+                    if consecutive >= gapped {
+                        (consecutive, j.wrapping_sub(1))
+                    } else {
+                        (gapped, gap.1)
+                    }
+                };
+                if best <= SCORE_NONE {
+                    continue;
+                }
+                score[i * n + j] = best + SCORE_MATCH;
+                if positions {
+                    from[i * n + j] = prev;
+                }
+            }
+        }
+
+        let (end, best) = (0..n)
+            .map(|j| (j, score[(m - 1) * n + j]))
+            .max_by_key(|(j, score)| (*score, std::cmp::Reverse(*j)))?;
+        if best <= SCORE_NONE {
+            return None;
+        }
+        let mut result = FuzzyMatch {
+            score: best,
+            positions: Vec::new(),
+        };
+        if positions {
+            result.positions.resize(m, 0);
+            let mut j = end;
+            for i in (0..m).rev() {
+                result.positions[i] = j;
+                j = from[i * n + j];
+            }
+        }
+        Some(result)
+    }
+}
+
+fn fold_case(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Bonus of matching character `cur` following `prev` character
+fn char_bonus(prev: Option<char>, cur: char) -> i32 {
+    match prev {
+        None => BONUS_BOUNDARY_WHITE,
+        Some(prev) if prev.is_whitespace() => BONUS_BOUNDARY_WHITE,
+        Some(prev) if !prev.is_alphanumeric() && cur.is_alphanumeric() => BONUS_BOUNDARY,
+        Some(prev) if !prev.is_alphanumeric() => 0,
+        Some(prev) if prev.is_lowercase() && cur.is_uppercase() => BONUS_CAMEL,
+        Some(prev) if !prev.is_numeric() && cur.is_numeric() => BONUS_CAMEL,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_match() {
+        let matcher = FuzzyMatcher::new("fb");
+        assert_eq!(matcher.score("xyz"), None);
+        assert_eq!(matcher.score("bf"), None);
+        assert_eq!(
+            matcher.matches("foo_bar").map(|m| m.positions),
+            Some(vec![0, 4])
+        );
+        // boundary match is preferred over the first occurrence
+        assert_eq!(
+            matcher.matches("afb fooBar").map(|m| m.positions),
+            Some(vec![4, 7])
+        );
+
+        // smart case
+        assert!(FuzzyMatcher::new("foo").score("FOO").is_some());
+        assert!(FuzzyMatcher::new("Foo").score("foo").is_none());
+
+        // empty pattern matches everything
+        assert_eq!(FuzzyMatcher::new("").score("anything"), Some(0));
+
+        // refinement
+        let matcher = FuzzyMatcher::new("ab");
+        assert!(matcher.is_refined_by(&FuzzyMatcher::new("abc")));
+        assert!(!matcher.is_refined_by(&FuzzyMatcher::new("ac")));
+        assert!(!matcher.is_refined_by(&FuzzyMatcher::new("aB")));
+    }
+
+    #[test]
+    fn test_fuzzy_rank() {
+        let matcher = FuzzyMatcher::new("wid");
+        let score = |haystack| matcher.score(haystack).unwrap_or(SCORE_NONE);
+        // consecutive is better than scattered
+        assert!(score("src/widgets.rs") > score("src/wxixd.rs"));
+        // word boundary is better than the middle of the word
+        assert!(score("widget") > score("overwide"));
+        // camel case humps are boundaries
+        let matcher = FuzzyMatcher::new("tw");
+        assert!(matcher.score("TerminalWriter") > matcher.score("outweigh"));
+    }
+}
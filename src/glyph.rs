@@ -1,55 +1,319 @@
 use crate::{
-    Color, ColorLinear, Face, Image, Size, Surface, SurfaceMut, SurfaceOwned, TerminalSize, RGBA,
+    color::Blend, error::Error, surface::Shape, Color, ColorLinear, Face, Image, Size, Surface,
+    SurfaceMut, SurfaceOwned, TerminalSize, RGBA,
+};
+use rasterize::{ActiveEdgeRasterizer, Align, Paint, Rasterizer, Transform};
+pub use rasterize::{
+    BBox, FillRule, GradLinear, GradRadial, GradSpread, GradStop, LinColor, LineCap, LineJoin,
+    Path, StrokeStyle, Units,
 };
-use rasterize::{ActiveEdgeRasterizer, Align, Rasterizer, Transform};
-pub use rasterize::{BBox, FillRule, Path};
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeMap,
+    fmt,
     hash::{Hash, Hasher},
     io::Write,
-    sync::Arc,
+    sync::{Arc, Mutex},
 };
 use tracing::debug_span;
 
-/// Glyph defined as an SVG path
+/// Default limit of pixel memory (in bytes) used by the glyph cache
+const GLYPH_CACHE_SIZE: usize = 67108864; // 64MB
+
+lazy_static::lazy_static! {
+    static ref GLYPH_CACHE: GlyphCache = GlyphCache::default();
+}
+
+/// Paint used to fill glyph layer
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GlyphPaint {
+    /// Foreground color of the face
+    #[default]
+    Fg,
+    /// Background color of the face
+    Bg,
+    /// Solid color
+    Color(RGBA),
+    /// Linear gradient
+    LinearGradient(GradLinear),
+    /// Radial gradient
+    RadialGradient(GradRadial),
+}
+
+/// Single layer of the glyph, layers are composed on top of each other
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GlyphLayer {
+    path: Arc<Path>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    fill_rule: FillRule,
+    #[serde(default, skip_serializing_if = "is_default")]
+    paint: GlyphPaint,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stroke: Option<StrokeStyle>,
+}
+
+impl GlyphLayer {
+    /// Create layer filling the path with the paint
+    pub fn new(path: impl Into<Arc<Path>>, paint: GlyphPaint) -> Self {
+        Self {
+            path: path.into(),
+            fill_rule: FillRule::default(),
+            paint,
+            stroke: None,
+        }
+    }
+
+    /// Set fill rule
+    pub fn with_fill_rule(mut self, fill_rule: FillRule) -> Self {
+        self.fill_rule = fill_rule;
+        self
+    }
+
+    /// Stroke the path instead of filling it
+    pub fn with_stroke(mut self, stroke: StrokeStyle) -> Self {
+        self.stroke = Some(stroke);
+        self
+    }
+
+    /// Path of the layer
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Paint of the layer
+    pub fn paint(&self) -> &GlyphPaint {
+        &self.paint
+    }
+
+    /// Path that is actually filled and its fill rule, taking stroke into account
+    fn outline(&self) -> (Path, FillRule) {
+        match self.stroke {
+            Some(stroke) => (self.path.stroke(stroke), FillRule::NonZero),
+            None => (self.path.as_ref().clone(), self.fill_rule),
+        }
+    }
+}
+
+/// Glyph defined as a list of SVG path layers
 #[derive(Debug, Clone)]
 pub struct Glyph {
-    /// Rasterize path representing the glyph
-    path: Arc<Path>,
+    /// Layers of the glyph
+    layers: Arc<[GlyphLayer]>,
     /// View box
     view_box: BBox,
-    /// Fill rule
-    fill_rule: FillRule,
     /// Glyph size in cells
     size: Size,
     /// Hash that is used to determine equality of the paths
     hash: u64,
+    /// Text used instead of the glyph if terminal cannot draw images
+    fallback: Option<Arc<str>>,
 }
 
 impl Glyph {
+    /// Create single layer glyph filled with the foreground color
     pub fn new(
         path: impl Into<Arc<Path>>,
         fill_rule: FillRule,
         view_box: Option<BBox>,
         size: Size,
     ) -> Self {
-        let path = path.into();
+        let layer = GlyphLayer::new(path, GlyphPaint::Fg).with_fill_rule(fill_rule);
+        Self::new_layers([layer], view_box, size)
+    }
+
+    /// Create glyph from layers
+    ///
+    /// If `view_box` is not specified, bounding box of all layers is used.
+    pub fn new_layers(
+        layers: impl IntoIterator<Item = GlyphLayer>,
+        view_box: Option<BBox>,
+        size: Size,
+    ) -> Self {
+        let layers: Arc<[GlyphLayer]> = layers.into_iter().collect();
         let view_box = view_box
-            .or_else(|| path.bbox(Transform::identity()))
+            .or_else(|| layers_bbox(&layers))
             .unwrap_or_else(|| BBox::new((0.0, 0.0), (1.0, 1.0)));
 
         let mut hasher = GlyphHasher::new();
-        path.write_svg_path(&mut hasher).unwrap();
+        for layer in layers.iter() {
+            layer.path.write_svg_path(&mut hasher).unwrap();
+            write!(
+                hasher,
+                "{:?}{:?}{:?}",
+                layer.fill_rule, layer.paint, layer.stroke
+            )
+            .unwrap();
+        }
         write!(hasher, "{:?}", view_box).unwrap();
         let hash = hasher.finish();
 
         Self {
-            path,
+            layers,
             view_box,
             hash,
             size,
-            fill_rule,
+            fallback: None,
+        }
+    }
+
+    /// Create glyph from SVG document
+    ///
+    /// Only a subset of SVG is supported: `viewBox`, `path`, `rect`, `circle`,
+    /// `ellipse`, `polygon`, `polyline`, `transform` and `fill-rule` attributes
+    /// (or the same `style` properties). Elements without fill and content of
+    /// `defs`, `clipPath`, `mask`, etc. are ignored.
+    /// Each filled element becomes a separate layer, explicit fill colors are
+    /// preserved, other fills use the face foreground color.
+    pub fn from_svg(svg: &str, size: Size) -> Result<Self, Error> {
+        let mut parser = SvgParser::new(svg);
+        let mut view_box = None;
+        let mut layers = Vec::new();
+        // transform, fill rule, paint and ignored state of the open elements
+        let mut stack: Vec<(Transform, FillRule, Option<GlyphPaint>, bool)> = Vec::new();
+        while let Some(tag) = parser.tag()? {
+            let SvgTag::Open {
+                name,
+                attrs,
+                closed,
+            } = tag
+            else {
+                stack.pop();
+                continue;
+            };
+            let (parent_tr, parent_fill_rule, parent_paint, parent_ignored) =
+                stack.last().cloned().unwrap_or((
+                    Transform::identity(),
+                    FillRule::NonZero,
+                    Some(GlyphPaint::Fg),
+                    false,
+                ));
+            let attr = |key: &str| -> Option<&str> {
+                let style = attrs.iter().find_map(|(k, v)| (*k == "style").then_some(v));
+                style
+                    .and_then(|style| {
+                        style.split(';').find_map(|decl| {
+                            let (k, v) = decl.split_once(':')?;
+                            (k.trim() == key).then_some(v.trim())
+                        })
+                    })
+                    .or_else(|| attrs.iter().find_map(|(k, v)| (*k == key).then_some(*v)))
+            };
+            let tr = match attr("transform") {
+                Some(tr) => parent_tr * tr.parse::<Transform>().map_err(svg_error)?,
+                None => parent_tr,
+            };
+            let element_fill_rule = match attr("fill-rule") {
+                Some("evenodd") => FillRule::EvenOdd,
+                Some("nonzero") => FillRule::NonZero,
+                Some(value) => return Err(svg_error(format!("invalid fill-rule: {}", value))),
+                None => parent_fill_rule,
+            };
+            let paint = match attr("fill") {
+                Some("none") => None,
+                Some(fill) => {
+                    Some(RGBA::from_str_opt(fill).map_or(GlyphPaint::Fg, GlyphPaint::Color))
+                }
+                None => parent_paint,
+            };
+            let ignored = parent_ignored
+                || attr("display") == Some("none")
+                || matches!(
+                    name,
+                    "defs" | "clipPath" | "mask" | "pattern" | "symbol" | "marker" | "style"
+                );
+            if !closed {
+                stack.push((tr, element_fill_rule, paint.clone(), ignored));
+            }
+            if ignored {
+                continue;
+            }
+
+            let number =
+                |key: &str| -> Result<f64, Error> { attr(key).map_or(Ok(0.0), svg_length) };
+            let mut path = match name {
+                "svg" if view_box.is_none() && stack.len() <= 1 => {
+                    view_box = match attr("viewBox") {
+                        Some(view_box) => match svg_numbers(view_box)?.as_slice() {
+                            [x, y, width, height] => {
+                                Some(BBox::new((*x, *y), (x + width, y + height)))
+                            }
+                            _ => return Err(svg_error("invalid viewBox")),
+                        },
+                        None => match (attr("width"), attr("height")) {
+                            (Some(width), Some(height)) => Some(BBox::new(
+                                (0.0, 0.0),
+                                (svg_length(width)?, svg_length(height)?),
+                            )),
+                            _ => None,
+                        },
+                    };
+                    continue;
+                }
+                "path" => match attr("d") {
+                    Some(d) => d.parse::<Path>().map_err(svg_error)?,
+                    None => continue,
+                },
+                "rect" => {
+                    let (width, height) = (number("width")?, number("height")?);
+                    let (rx, ry) = match (attr("rx"), attr("ry")) {
+                        (None, None) => (0.0, 0.0),
+                        (Some(rx), None) => (svg_length(rx)?, svg_length(rx)?),
+                        (None, Some(ry)) => (svg_length(ry)?, svg_length(ry)?),
+                        (Some(rx), Some(ry)) => (svg_length(rx)?, svg_length(ry)?),
+                    };
+                    Path::builder()
+                        .move_to((number("x")?, number("y")?))
+                        .rbox((width, height), (rx, ry))
+                        .build()
+                }
+                "circle" => Path::builder()
+                    .move_to((number("cx")?, number("cy")?))
+                    .circle(number("r")?)
+                    .build(),
+                "ellipse" => {
+                    let mut path = Path::builder().circle(1.0).build();
+                    path.transform(
+                        Transform::new_translate(number("cx")?, number("cy")?)
+                            .pre_scale(number("rx")?, number("ry")?),
+                    );
+                    path
+                }
+                "polygon" | "polyline" => {
+                    let points = svg_numbers(attr("points").unwrap_or_default())?;
+                    let mut builder = Path::builder();
+                    for (index, point) in points.chunks_exact(2).enumerate() {
+                        if index == 0 {
+                            builder.move_to((point[0], point[1]));
+                        } else {
+                            builder.line_to((point[0], point[1]));
+                        }
+                    }
+                    builder.close().build()
+                }
+                _ => continue,
+            };
+            let Some(paint) = paint else {
+                continue;
+            };
+            path.transform(tr);
+            layers.push(GlyphLayer::new(path, paint).with_fill_rule(element_fill_rule));
         }
+        Ok(Glyph::new_layers(layers, view_box, size))
+    }
+
+    /// Set text used instead of the glyph if terminal cannot draw images
+    ///
+    /// Each character occupies one cell, text is truncated or padded with spaces
+    /// to the width of the glyph.
+    pub fn with_fallback(mut self, fallback: impl AsRef<str>) -> Self {
+        self.fallback = Some(fallback.as_ref().into());
+        self
+    }
+
+    /// Text fallback of the glyph
+    pub fn fallback(&self) -> Option<&str> {
+        self.fallback.as_deref()
     }
 
     /// Rasterize glyph into an image with provided face.
@@ -68,27 +332,91 @@ impl Glyph {
             .unwrap_or_else(|| RGBA::new(255, 255, 255, 255))
             .into();
 
-        let _ = debug_span!("glyph rasterize", path=%self.path.to_svg_path(), ?face, ?size).enter();
+        let _guard =
+            debug_span!("glyph rasterize", layers = self.layers.len(), ?face, ?size).entered();
         let rasterizer = ActiveEdgeRasterizer::default();
-        let mut surf = SurfaceOwned::new_with(size.height, size.width, |_, _| bg_rgba);
+        let mut surf = SurfaceOwned::new_with(size.height, size.width, |_, _| bg);
         let shape = surf.shape();
         let data = surf.data_mut();
-        for pixel in rasterizer.mask_iter(&self.path, tr, size, self.fill_rule) {
-            data[shape.offset(pixel.y, pixel.x)] = bg.lerp(fg, pixel.alpha).into();
+        for layer in self.layers.iter() {
+            let (path, fill_rule) = layer.outline();
+            let pixels = rasterizer.mask_iter(&path, tr, size, fill_rule);
+            let solid = match &layer.paint {
+                GlyphPaint::Fg => fg,
+                GlyphPaint::Bg => bg,
+                GlyphPaint::Color(color) => (*color).into(),
+                GlyphPaint::LinearGradient(grad) => {
+                    gradient_fill(grad, &path, tr, pixels, shape, data);
+                    continue;
+                }
+                GlyphPaint::RadialGradient(grad) => {
+                    gradient_fill(grad, &path, tr, pixels, shape, data);
+                    continue;
+                }
+            };
+            for pixel in pixels {
+                let dst = &mut data[shape.offset(pixel.y, pixel.x)];
+                *dst = dst.blend(solid * pixel.alpha, Blend::Over);
+            }
         }
 
-        Image::new(surf)
+        Image::new(SurfaceOwned::new_with(
+            size.height,
+            size.width,
+            |row, col| RGBA::from(data[shape.offset(row, col)]),
+        ))
     }
 
     /// Size of the glyph in cells
     pub fn size(&self) -> Size {
         self.size
     }
+
+    /// Layers of the glyph
+    pub fn layers(&self) -> &[GlyphLayer] {
+        &self.layers
+    }
+}
+
+/// Bounding box of all layers
+fn layers_bbox(layers: &[GlyphLayer]) -> Option<BBox> {
+    layers
+        .iter()
+        .filter_map(|layer| layer.outline().0.bbox(Transform::identity()))
+        .reduce(|bbox, other| bbox.union(other))
+}
+
+/// Compose gradient over the pixels of the path
+fn gradient_fill(
+    paint: &dyn Paint,
+    path: &Path,
+    tr: Transform,
+    pixels: impl Iterator<Item = rasterize::Pixel>,
+    shape: Shape,
+    data: &mut [ColorLinear],
+) {
+    let units_tr = match paint.units() {
+        Some(Units::BoundingBox) => match path.bbox(Transform::identity()) {
+            Some(bbox) => tr * bbox.unit_transform() * paint.transform(),
+            None => return,
+        },
+        _ => tr * paint.transform(),
+    };
+    let Some(pixel_tr) = units_tr.invert() else {
+        return;
+    };
+    for pixel in pixels {
+        let point = pixel_tr.apply((pixel.x as f64 + 0.5, pixel.y as f64 + 0.5).into());
+        let [r, g, b, a]: [f32; 4] = paint.at(point).into();
+        let color = ColorLinear([r as f64, g as f64, b as f64, a as f64]);
+        let dst = &mut data[shape.offset(pixel.y, pixel.x)];
+        *dst = dst.blend(color * pixel.alpha, Blend::Over);
+    }
 }
 
 impl PartialEq for Glyph {
     fn eq(&self, other: &Self) -> bool {
-        self.hash == other.hash && self.fill_rule == other.fill_rule && self.size == other.size
+        self.hash == other.hash && self.size == other.size && self.fallback == other.fallback
     }
 }
 
@@ -97,10 +425,133 @@ impl Eq for Glyph {}
 impl Hash for Glyph {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.hash.hash(state);
-        self.fill_rule.hash(state);
+        self.fallback.hash(state);
     }
 }
 
+/// Glyph cache statistics
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GlyphCacheStats {
+    /// Number of lookups served from the cache
+    pub hits: usize,
+    /// Number of lookups that required rasterization
+    pub misses: usize,
+    /// Number of cached images
+    pub count: usize,
+    /// Pixel memory (in bytes) used by cached images
+    pub size: usize,
+}
+
+struct GlyphCacheInner {
+    imgs: lru::LruCache<(Glyph, Face, Size), Image>,
+    size: usize,
+    max_size: usize,
+    hits: usize,
+    misses: usize,
+}
+
+/// LRU cache of rasterized glyphs bounded by pixel memory
+///
+/// Cache is keyed by glyph, face and cell size in pixels, hence it can be
+/// shared between renderers and terminals, and stays valid across resizes.
+/// Cloning produces a handle to the same cache.
+#[derive(Clone)]
+pub struct GlyphCache {
+    inner: Arc<Mutex<GlyphCacheInner>>,
+}
+
+impl GlyphCache {
+    /// Create new cache holding at most `max_size` bytes of pixels
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(GlyphCacheInner {
+                imgs: lru::LruCache::unbounded(),
+                size: 0,
+                max_size,
+                hits: 0,
+                misses: 0,
+            })),
+        }
+    }
+
+    /// Cache shared by all renderers by default
+    pub fn shared() -> Self {
+        GLYPH_CACHE.clone()
+    }
+
+    /// Rasterize glyph or return previously rasterized image
+    pub fn rasterize(&self, glyph: &Glyph, face: Face, term_size: TerminalSize) -> Image {
+        let key = (glyph.clone(), face, term_size.cell_size());
+        if let Some(image) = self.inner.lock().expect("lock poisoned").get_hit(&key) {
+            return image;
+        }
+        // rasterize without holding the lock
+        let image = glyph.rasterize(face, term_size);
+        let mut inner = self.inner.lock().expect("lock poisoned");
+        inner.size += image_size(&image);
+        if let Some(prev) = inner.imgs.put(key, image.clone()) {
+            inner.size -= image_size(&prev);
+        }
+        while inner.size > inner.max_size {
+            match inner.imgs.pop_lru() {
+                Some((_, lru_image)) => inner.size -= image_size(&lru_image),
+                None => break,
+            }
+        }
+        image
+    }
+
+    /// Cache statistics
+    pub fn stats(&self) -> GlyphCacheStats {
+        let inner = self.inner.lock().expect("lock poisoned");
+        GlyphCacheStats {
+            hits: inner.hits,
+            misses: inner.misses,
+            count: inner.imgs.len(),
+            size: inner.size,
+        }
+    }
+
+    /// Remove all cached images
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().expect("lock poisoned");
+        inner.imgs.clear();
+        inner.size = 0;
+    }
+}
+
+impl GlyphCacheInner {
+    /// Lookup image and update hit/miss counters
+    fn get_hit(&mut self, key: &(Glyph, Face, Size)) -> Option<Image> {
+        match self.imgs.get(key) {
+            Some(image) => {
+                self.hits += 1;
+                Some(image.clone())
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+}
+
+impl Default for GlyphCache {
+    fn default() -> Self {
+        Self::new(GLYPH_CACHE_SIZE)
+    }
+}
+
+impl fmt::Debug for GlyphCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GlyphCache({:?})", self.stats())
+    }
+}
+
+fn image_size(image: &Image) -> usize {
+    image.width() * image.height() * 4
+}
+
 struct GlyphHasher {
     hasher: fnv::FnvHasher,
 }
@@ -130,12 +581,18 @@ impl Write for GlyphHasher {
 
 #[derive(Serialize, Deserialize)]
 struct GlyphSerde {
-    path: Arc<Path>,
+    /// Single layer filled with the foreground color
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    path: Option<Arc<Path>>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     view_box: Option<BBox>,
     #[serde(default, skip_serializing_if = "is_default")]
     fill_rule: FillRule,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    layers: Vec<GlyphLayer>,
     size: Size,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fallback: Option<Arc<str>>,
 }
 
 impl Serialize for Glyph {
@@ -143,15 +600,23 @@ impl Serialize for Glyph {
     where
         S: serde::Serializer,
     {
-        let view_box = match self.path.bbox(Transform::identity()) {
+        let view_box = match layers_bbox(&self.layers) {
             Some(bbox) if bbox == self.view_box => None,
             _ => Some(self.view_box),
         };
+        let (path, fill_rule, layers) = match &*self.layers {
+            [layer] if layer.paint == GlyphPaint::Fg && layer.stroke.is_none() => {
+                (Some(layer.path.clone()), layer.fill_rule, Vec::new())
+            }
+            layers => (None, FillRule::default(), layers.to_vec()),
+        };
         GlyphSerde {
-            path: self.path.clone(),
+            path,
             view_box,
-            fill_rule: self.fill_rule,
+            fill_rule,
+            layers,
             size: self.size,
+            fallback: self.fallback.clone(),
         }
         .serialize(serializer)
     }
@@ -163,12 +628,228 @@ impl<'de> Deserialize<'de> for Glyph {
         D: serde::Deserializer<'de>,
     {
         let glyph = GlyphSerde::deserialize(deserializer)?;
-        Ok(Glyph::new(
-            glyph.path,
-            glyph.fill_rule,
-            glyph.view_box,
-            glyph.size,
-        ))
+        let layers = glyph
+            .path
+            .map(|path| GlyphLayer::new(path, GlyphPaint::Fg).with_fill_rule(glyph.fill_rule))
+            .into_iter()
+            .chain(glyph.layers);
+        Ok(Glyph {
+            fallback: glyph.fallback,
+            ..Glyph::new_layers(layers, glyph.view_box, glyph.size)
+        })
+    }
+}
+
+/// Collection of named glyphs
+///
+/// Serialized as a map from icon name to glyph, so icon packs can be shipped
+/// as data files.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct IconRegistry {
+    icons: BTreeMap<String, Glyph>,
+}
+
+impl IconRegistry {
+    /// Create empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add glyph to the registry, returns previous glyph with the same name
+    pub fn insert(&mut self, name: impl Into<String>, glyph: Glyph) -> Option<Glyph> {
+        self.icons.insert(name.into(), glyph)
+    }
+
+    /// Parse SVG document and add it to the registry
+    pub fn insert_svg(
+        &mut self,
+        name: impl Into<String>,
+        svg: &str,
+        size: Size,
+    ) -> Result<Option<Glyph>, Error> {
+        Ok(self.insert(name, Glyph::from_svg(svg, size)?))
+    }
+
+    /// Load all `*.svg` files from the directory, named by their file stem
+    ///
+    /// Returns number of loaded icons.
+    pub fn load_dir(
+        &mut self,
+        dir: impl AsRef<std::path::Path>,
+        size: Size,
+    ) -> Result<usize, Error> {
+        let mut count = 0;
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("svg") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let svg = std::fs::read_to_string(&path)?;
+            self.insert_svg(name, &svg, size)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Find glyph by name
+    pub fn get(&self, name: &str) -> Option<&Glyph> {
+        self.icons.get(name)
+    }
+
+    /// Remove glyph by name
+    pub fn remove(&mut self, name: &str) -> Option<Glyph> {
+        self.icons.remove(name)
+    }
+
+    /// Number of icons in the registry
+    pub fn len(&self) -> usize {
+        self.icons.len()
+    }
+
+    /// Check if registry is empty
+    pub fn is_empty(&self) -> bool {
+        self.icons.is_empty()
+    }
+
+    /// Iterate over all icons sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Glyph)> {
+        self.icons
+            .iter()
+            .map(|(name, glyph)| (name.as_str(), glyph))
+    }
+}
+
+impl Extend<(String, Glyph)> for IconRegistry {
+    fn extend<T: IntoIterator<Item = (String, Glyph)>>(&mut self, iter: T) {
+        self.icons.extend(iter)
+    }
+}
+
+fn svg_error(error: impl ToString) -> Error {
+    Error::ParseError("SVG", error.to_string())
+}
+
+/// Parse list of numbers separated by whitespace and/or commas
+fn svg_numbers(value: &str) -> Result<Vec<f64>, Error> {
+    value
+        .split(|c: char| c == ',' || c.is_ascii_whitespace())
+        .filter(|number| !number.is_empty())
+        .map(|number| number.parse().map_err(svg_error))
+        .collect()
+}
+
+/// Parse length, only user units (optionally with `px` suffix) are supported
+fn svg_length(value: &str) -> Result<f64, Error> {
+    let value = value.trim();
+    value
+        .strip_suffix("px")
+        .unwrap_or(value)
+        .parse()
+        .map_err(svg_error)
+}
+
+/// XML tag of SVG document
+#[derive(Debug, PartialEq)]
+enum SvgTag<'a> {
+    /// Opening tag, `closed` is set for self-closing tags
+    Open {
+        name: &'a str,
+        attrs: Vec<(&'a str, &'a str)>,
+        closed: bool,
+    },
+    /// Closing tag
+    Close,
+}
+
+/// Minimal XML parser, only yields tags skipping text, comments, etc.
+struct SvgParser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> SvgParser<'a> {
+    fn new(svg: &'a str) -> Self {
+        Self { rest: svg }
+    }
+
+    fn tag(&mut self) -> Result<Option<SvgTag<'a>>, Error> {
+        loop {
+            let Some(start) = self.rest.find('<') else {
+                return Ok(None);
+            };
+            self.rest = &self.rest[start..];
+            // skip declarations, comments and processing instructions
+            let skip = [
+                ("<!--", "-->"),
+                ("<![CDATA[", "]]>"),
+                ("<?", "?>"),
+                ("<!", ">"),
+            ]
+            .into_iter()
+            .find(|(open, _)| self.rest.starts_with(open));
+            if let Some((open, close)) = skip {
+                let end = self
+                    .rest
+                    .find(close)
+                    .ok_or_else(|| svg_error(format!("unterminated {}", open)))?;
+                self.rest = &self.rest[end + close.len()..];
+                continue;
+            }
+            if let Some(rest) = self.rest.strip_prefix("</") {
+                let end = rest
+                    .find('>')
+                    .ok_or_else(|| svg_error("unterminated tag"))?;
+                self.rest = &rest[end + 1..];
+                return Ok(Some(SvgTag::Close));
+            }
+            return self.tag_open().map(Some);
+        }
+    }
+
+    fn tag_open(&mut self) -> Result<SvgTag<'a>, Error> {
+        let rest = &self.rest[1..];
+        let name_end = rest
+            .find(|c: char| c.is_ascii_whitespace() || c == '/' || c == '>')
+            .ok_or_else(|| svg_error("unterminated tag"))?;
+        let name = &rest[..name_end];
+        let mut rest = &rest[name_end..];
+        let mut attrs = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(tail) = rest.strip_prefix("/>") {
+                self.rest = tail;
+                return Ok(SvgTag::Open {
+                    name,
+                    attrs,
+                    closed: true,
+                });
+            }
+            if let Some(tail) = rest.strip_prefix('>') {
+                self.rest = tail;
+                return Ok(SvgTag::Open {
+                    name,
+                    attrs,
+                    closed: false,
+                });
+            }
+            let (key, tail) = rest
+                .split_once('=')
+                .ok_or_else(|| svg_error(format!("invalid attribute in <{}>", name)))?;
+            let tail = tail.trim_start();
+            let quote = tail
+                .chars()
+                .next()
+                .filter(|c| *c == '"' || *c == '\'')
+                .ok_or_else(|| svg_error(format!("unquoted attribute in <{}>", name)))?;
+            let value_end = tail[1..]
+                .find(quote)
+                .ok_or_else(|| svg_error("unterminated attribute"))?;
+            attrs.push((key.trim(), &tail[1..value_end + 1]));
+            rest = &tail[value_end + 2..];
+        }
     }
 }
 
@@ -182,6 +863,7 @@ pub(crate) fn is_default<T: Default + PartialEq>(val: &T) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::str::FromStr;
     const TEST_ICON: &str = "M1,1 h18 v18 h-18 Z";
 
     #[test]
@@ -204,6 +886,221 @@ mod tests {
         });
         assert_eq!(term, serde_json::from_value(term_json)?);
 
+        let term = term.with_fallback("[]");
+        let term_str = serde_json::to_string(&term)?;
+        assert_eq!(term, serde_json::from_str(term_str.as_ref())?);
+        assert_eq!(term.fallback(), Some("[]"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_glyph_layers() -> Result<(), Box<dyn std::error::Error>> {
+        let red = RGBA::new(255, 0, 0, 255);
+        let green = RGBA::new(0, 255, 0, 255);
+        let blue = RGBA::new(0, 0, 255, 255);
+        let grad = GradLinear::new(
+            vec![
+                GradStop::new(0.0, LinColor::new(0.0, 0.0, 0.0, 1.0)),
+                GradStop::new(1.0, LinColor::new(1.0, 1.0, 1.0, 1.0)),
+            ],
+            Units::UserSpaceOnUse,
+            true,
+            GradSpread::Pad,
+            Transform::identity(),
+            (0.0, 0.0),
+            (10.0, 0.0),
+        );
+        let layers = vec![
+            GlyphLayer::new(Path::from_str("M0,0 h10 v10 h-10 Z")?, GlyphPaint::Fg),
+            GlyphLayer::new(Path::from_str("M2,2 h6 v6 h-6 Z")?, GlyphPaint::Color(red)),
+            GlyphLayer::new(Path::from_str("M0,1.5 h10")?, GlyphPaint::Color(green)).with_stroke(
+                StrokeStyle {
+                    width: 1.0,
+                    line_join: LineJoin::default(),
+                    line_cap: LineCap::default(),
+                },
+            ),
+            GlyphLayer::new(
+                Path::from_str("M0,8 h10 v2 h-10 Z")?,
+                GlyphPaint::LinearGradient(grad),
+            ),
+        ];
+        let glyph = Glyph::new_layers(layers, None, Size::new(1, 1));
+        assert_eq!(glyph.view_box, BBox::new((0.0, 0.0), (10.0, 10.0)));
+
+        let term_size = TerminalSize {
+            cells: Size::new(1, 1),
+            pixels: Size::new(12, 12), // one pixel margin
+        };
+        let face = Face::default().with_fg(Some(blue));
+        let img = glyph.rasterize(face, term_size);
+        assert_eq!(img.get(0, 0), Some(&RGBA::new(0, 0, 0, 0)));
+        assert_eq!(img.get(1, 1), Some(&blue));
+        assert_eq!(img.get(6, 6), Some(&red));
+        assert_eq!(img.get(2, 6), Some(&green));
+        let left = img.get(10, 1).unwrap().luma();
+        let right = img.get(10, 10).unwrap().luma();
+        assert!(left < 0.3 && right > 0.9, "{} {}", left, right);
+
+        // paint and stroke are part of the glyph identity
+        let plain = Glyph::new(
+            Path::from_str("M2,2 h6 v6 h-6 Z")?,
+            FillRule::NonZero,
+            None,
+            Size::new(1, 1),
+        );
+        let colored = Glyph::new_layers(
+            [GlyphLayer::new(
+                Path::from_str("M2,2 h6 v6 h-6 Z")?,
+                GlyphPaint::Color(red),
+            )],
+            None,
+            Size::new(1, 1),
+        );
+        assert_ne!(plain, colored);
+
+        let glyph_str = serde_json::to_string(&glyph)?;
+        assert_eq!(glyph, serde_json::from_str(&glyph_str)?);
+        let colored_json = serde_json::json!({
+            "layers": [{"path": "M2,2 h6 v6 h-6 Z", "paint": {"color": "#ff0000"}}],
+            "size": [1, 1],
+        });
+        assert_eq!(colored, serde_json::from_value(colored_json)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_glyph_cache() -> Result<(), Box<dyn std::error::Error>> {
+        let glyph = Glyph::new(
+            "M1,1 h8 v8 h-8 Z".parse::<Path>()?,
+            FillRule::NonZero,
+            None,
+            Size::new(1, 1),
+        );
+        let face = Face::default();
+        let small = TerminalSize {
+            cells: Size::new(10, 10),
+            pixels: Size::new(80, 40),
+        };
+        let large = TerminalSize {
+            cells: Size::new(5, 5),
+            pixels: Size::new(80, 40),
+        };
+
+        // image of a single glyph takes 8 * 4 * 4 bytes
+        let cache = GlyphCache::new(128);
+        let img = cache.rasterize(&glyph, face, small);
+        assert_eq!((img.height(), img.width()), (8, 4));
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.count), (0, 1, 1));
+        assert_eq!(stats.size, 128);
+
+        // same cell size in pixels is a hit even if terminal size has changed
+        let other = TerminalSize {
+            cells: Size::new(20, 20),
+            pixels: Size::new(160, 80),
+        };
+        assert_eq!(cache.rasterize(&glyph, face, other), img);
+        assert_eq!(cache.clone().stats().hits, 1);
+
+        // different cell size evicts previous image as it does not fit
+        let img_large = cache.rasterize(&glyph, face, large);
+        assert_eq!((img_large.height(), img_large.width()), (16, 8));
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.count), (1, 2, 0));
+        assert_eq!(stats.size, 0);
+
+        // different face is a miss
+        let cache = GlyphCache::new(1024);
+        cache.rasterize(&glyph, face, small);
+        cache.rasterize(&glyph, face.with_fg(Some("#ff0000".parse()?)), small);
+        cache.rasterize(&glyph, face, small);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.count), (1, 2, 2));
+        cache.clear();
+        assert_eq!(cache.stats().count, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_glyph_svg() -> Result<(), Box<dyn std::error::Error>> {
+        let svg = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <!-- test icon -->
+            <svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 24 24" width="24px">
+              <defs><rect id="unused" width="100" height="100"/></defs>
+              <path fill="none" d="M0 0h24v24H0z"/>
+              <g transform="translate(2, 3)" style="fill-rule: evenodd">
+                <rect x="1" y="1" width="4" height="2"/>
+                <polygon points="10,1 12,1 12,3"/>
+              </g>
+              <circle cx="12" cy="18" r="2"/>
+              <ellipse cx="20" cy="20" rx="2" ry="1" transform="scale(1)"/>
+            </svg>"#;
+        let glyph = Glyph::from_svg(svg, Size::new(1, 2))?;
+        assert_eq!(glyph.view_box, BBox::new((0.0, 0.0), (24.0, 24.0)));
+        let fill_rules: Vec<_> = glyph.layers().iter().map(|layer| layer.fill_rule).collect();
+        use FillRule::*;
+        assert_eq!(fill_rules, vec![EvenOdd, EvenOdd, NonZero, NonZero]);
+        let bbox = layers_bbox(glyph.layers()).expect("empty glyph");
+        assert_eq!(bbox, BBox::new((3.0, 4.0), (22.0, 21.0)));
+
+        // invalid documents
+        assert!(Glyph::from_svg("<svg viewBox=\"0 0 1\"/>", Size::new(1, 1)).is_err());
+        assert!(Glyph::from_svg("<svg><path d=\"M1 1 X\"/></svg>", Size::new(1, 1)).is_err());
+        assert!(Glyph::from_svg("<svg><!-- </svg>", Size::new(1, 1)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_icon_registry() -> Result<(), Box<dyn std::error::Error>> {
+        let mut icons = IconRegistry::new();
+        icons.insert_svg(
+            "square",
+            r#"<svg viewBox="0 0 20 20"><path d="M1,1 h18 v18 h-18 Z"/></svg>"#,
+            Size::new(1, 2),
+        )?;
+        let path: Path = TEST_ICON.parse()?;
+        let square = Glyph::new(
+            path,
+            FillRule::NonZero,
+            Some(BBox::new((0.0, 0.0), (20.0, 20.0))),
+            Size::new(1, 2),
+        );
+        assert_eq!(icons.get("square"), Some(&square));
+        assert_eq!(icons.get("missing"), None);
+
+        let dir = std::env::temp_dir().join(format!("surf-n-term-icons-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(
+            dir.join("dot.svg"),
+            r#"<svg viewBox="0 0 2 2"><circle cx="1" cy="1" r="1"/></svg>"#,
+        )?;
+        std::fs::write(dir.join("readme.txt"), "not an icon")?;
+        let loaded = icons.load_dir(&dir, Size::new(1, 1));
+        std::fs::remove_dir_all(&dir)?;
+        assert_eq!(loaded?, 1);
+        assert_eq!(
+            icons.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+            vec!["dot", "square"]
+        );
+
+        let icons_str = serde_json::to_string(&icons)?;
+        assert_eq!(icons, serde_json::from_str(&icons_str)?);
+        let icons_json = serde_json::json!({
+            "square": {
+                "path": TEST_ICON,
+                "view_box": [0, 0, 20, 20],
+                "size": [1, 2],
+            }
+        });
+        let icons: IconRegistry = serde_json::from_value(icons_json)?;
+        assert_eq!(icons.get("square"), Some(&square));
+        assert_eq!(icons.len(), 1);
+
         Ok(())
     }
 }
@@ -7,29 +7,53 @@
 use crate::{
     common::{clamp, Rnd},
     encoder::Base64Encoder,
-    Blend, Color, Error, Position, Shape, Size, Surface, SurfaceMut, SurfaceOwned, TerminalEvent,
-    TerminalSize, RGBA,
+    Blend, Color, ColorLinear, Error, OkLab, Position, Shape, Size, Surface, SurfaceMut,
+    SurfaceOwned, TerminalEvent, TerminalSize, TerminalWaker, RGBA,
 };
 use flate2::{write::ZlibEncoder, Compression};
+pub use rasterize::Align;
 use std::{
     borrow::Cow,
     cmp::Ordering,
-    collections::{hash_map::Entry, HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
+    f64::consts::PI,
     fmt,
+    hash::Hasher,
     io::Write,
     iter::FromIterator,
     ops::{Add, AddAssign, Mul},
     str::FromStr,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering as AtomicOrdering},
+        mpsc, Arc, Mutex,
+    },
+    time::Duration,
 };
 
 const IMAGE_CACHE_SIZE: usize = 134217728; // 128MB
+/// Memory used by encoded images which have not been drawn yet
+const IMAGE_ENCODE_READY_SIZE: usize = 67108864; // 64MB
+/// Number of resized images kept by `Image::resize`
+const IMAGE_RESIZE_CACHE_SIZE: usize = 64;
+/// Size of the blue noise threshold matrix
+const BLUE_NOISE_SIZE: usize = 16;
+
+lazy_static::lazy_static! {
+    /// Resized images keyed by content hash, size and filter
+    ///
+    /// Shared by all image handlers and threads, since the same image is resized to
+    /// the same cell size on every frame it is drawn, and images are immutable.
+    static ref IMAGE_RESIZE_CACHE: Mutex<lru::LruCache<(u64, Size, ImageFilter), Image>> =
+        Mutex::new(lru::LruCache::new(IMAGE_RESIZE_CACHE_SIZE));
+    static ref BLUE_NOISE: Vec<f32> = blue_noise(BLUE_NOISE_SIZE);
+}
 
 /// Arc wrapped RGBA surface with precomputed hash
 #[derive(Clone)]
 pub struct Image {
     surf: Arc<dyn Surface<Item = RGBA> + Send + Sync>,
     hash: u64,
+    animation: Option<AnimatedImage>,
 }
 
 impl Image {
@@ -38,9 +62,17 @@ impl Image {
         Self {
             hash: surf.hash(),
             surf: Arc::new(surf),
+            animation: None,
         }
     }
 
+    /// Animation this image is representing
+    ///
+    /// Surface of such image is the first frame of the animation.
+    pub fn animation(&self) -> Option<&AnimatedImage> {
+        self.animation.as_ref()
+    }
+
     /// Image size in bytes
     pub fn size(&self) -> usize {
         self.surf.height() * self.surf.width() * 4
@@ -59,56 +91,254 @@ impl Image {
 
     /// Quantize image
     ///
-    /// Perform palette extraction and Floyd–Steinberg dithering.
+    /// Perform palette extraction and dithering.
     #[tracing::instrument(level = "debug")]
     pub fn quantize(
         &self,
         palette_size: usize,
-        dither: bool,
+        dither: Dither,
         bg: Option<RGBA>,
     ) -> Option<(ColorPalette, SurfaceOwned<usize>)> {
         let bg = bg.unwrap_or_else(|| RGBA::new(0, 0, 0, 255));
         let palette = ColorPalette::from_image(self, palette_size, bg)?;
+        let qimg = self.quantize_palette(&palette, dither, Some(bg));
+        Some((palette, qimg))
+    }
+
+    /// Map image onto provided palette
+    pub fn quantize_palette(
+        &self,
+        palette: &ColorPalette,
+        dither: Dither,
+        bg: Option<RGBA>,
+    ) -> SurfaceOwned<usize> {
+        let bg = bg.unwrap_or_else(|| RGBA::new(0, 0, 0, 255));
         let mut qimg = SurfaceOwned::new(self.height(), self.width());
+        let color_at = |row: usize, col: usize| -> Option<RGBA> {
+            let color = *self.get(row, col)?;
+            if color.rgba_u8()[3] < 255 {
+                Some(bg.blend(color, Blend::Over))
+            } else {
+                Some(color)
+            }
+        };
 
-        // quantize and dither
-        let mut errors: Vec<ColorError> = Vec::new();
-        let ewidth = self.width() + 2; // to avoid check for the first and the last pixels
-        if dither {
-            errors.resize_with(ewidth * 2, ColorError::new);
-        }
-        for row in 0..self.height() {
-            if dither {
-                // swap error rows
-                for col in 0..ewidth {
-                    errors[col] = errors[col + ewidth];
-                    errors[col + ewidth] = ColorError::new();
+        match dither {
+            Dither::None => {
+                for row in 0..self.height() {
+                    for col in 0..self.width() {
+                        if let Some(color) = color_at(row, col) {
+                            qimg.set(row, col, palette.find(color).0);
+                        }
+                    }
                 }
             }
-            // quantize and spread the error
-            for col in 0..self.width() {
-                let mut color = *self.get(row, col)?;
-                if color.rgba_u8()[3] < 255 {
-                    color = bg.blend(color, Blend::Over);
-                }
-                if dither {
-                    color = errors[col + 1].add(color); // account for error
+            Dither::Ordered { matrix, strength } => {
+                // offset is proportional to the expected distance between palette
+                // colors, assuming they are evenly distributed along each channel
+                let levels = (palette.size() as f32).cbrt() - 1.0;
+                let spread = strength * 255.0 / levels.max(1.0);
+                for row in 0..self.height() {
+                    for col in 0..self.width() {
+                        if let Some(color) = color_at(row, col) {
+                            let offset = (matrix.threshold(row, col) - 0.5) * spread;
+                            let error = ColorError([offset; 3]);
+                            qimg.set(row, col, palette.find(error.add(color)).0);
+                        }
+                    }
                 }
-                let (qindex, qcolor) = palette.find(color);
-                qimg.set(row, col, qindex);
-                if dither {
-                    // spread the error according to Floyd–Steinberg dithering matrix:
-                    // [[0   , X   , 7/16],
-                    // [3/16, 5/16, 1/16]]
-                    let error = ColorError::between(color, qcolor);
-                    errors[col + 2] += error * 0.4375; // 7/16
-                    errors[col + ewidth] += error * 0.1875; // 3/16
-                    errors[col + ewidth + 1] += error * 0.3125; // 5/16
-                    errors[col + ewidth + 2] += error * 0.0625; // 1/16
+            }
+            Dither::Diffusion {
+                kernel,
+                strength,
+                serpentine,
+            } => {
+                // error rows, padded to avoid checks for the first and the last pixels
+                let weights = kernel.weights();
+                let erows = weights.iter().map(|(_, dy, _)| dy + 1).max().unwrap_or(1);
+                let ewidth = self.width() + 4;
+                let mut errors = vec![ColorError::new(); ewidth * erows];
+                for row in 0..self.height() {
+                    // rotate error rows
+                    errors.rotate_left(ewidth);
+                    errors[ewidth * (erows - 1)..].fill(ColorError::new());
+
+                    let reverse = serpentine && row % 2 == 1;
+                    for step in 0..self.width() {
+                        let col = if reverse {
+                            self.width() - step - 1
+                        } else {
+                            step
+                        };
+                        let Some(color) = color_at(row, col) else {
+                            continue;
+                        };
+                        let color = errors[col + 2].add(color); // account for error
+                        let (qindex, qcolor) = palette.find(color);
+                        qimg.set(row, col, qindex);
+                        // spread the error according to the kernel
+                        let error = ColorError::between(color, qcolor) * strength;
+                        for (dx, dy, weight) in weights {
+                            let dx = if reverse { -dx } else { *dx };
+                            let ecol = (col as isize + 2 + dx) as usize;
+                            errors[dy * ewidth + ecol] += error * *weight;
+                        }
+                    }
                 }
             }
         }
-        Some((palette, qimg))
+        qimg
+    }
+
+    /// Mean squared error of sRGB components of the image mapped onto the palette
+    /// without dithering, used to compare quality of palettes
+    pub fn quantize_mse(&self, palette: &ColorPalette) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let qimg = self.quantize_palette(palette, Dither::None, None);
+        let error: f64 = self
+            .iter()
+            .zip(qimg.iter())
+            .map(|(color, index)| {
+                let [r0, g0, b0] = color.rgb_u8();
+                let [r1, g1, b1] = palette.get(*index).rgb_u8();
+                (r0 as f64 - r1 as f64).powi(2)
+                    + (g0 as f64 - g1 as f64).powi(2)
+                    + (b0 as f64 - b1 as f64).powi(2)
+            })
+            .sum();
+        error / (self.width() * self.height() * 3) as f64
+    }
+
+    /// Crop image to the provided region
+    ///
+    /// Region is clamped to the bounds of the image, and the same image is
+    /// returned if the region covers it entirely.
+    pub fn crop(&self, crop: ImageCrop) -> Image {
+        let crop = crop.clamp(self.height(), self.width());
+        if crop.row == 0
+            && crop.col == 0
+            && crop.height == self.height()
+            && crop.width == self.width()
+        {
+            return self.clone();
+        }
+        let view = self.view(
+            crop.row..crop.row + crop.height,
+            crop.col..crop.col + crop.width,
+        );
+        Image::new(view.to_owned_surf())
+    }
+
+    /// Resize image to the specified size in pixels
+    ///
+    /// Resampling is done in linear RGB color space with premultiplied alpha, so
+    /// averaging of colors is gamma-correct. Recently resized images are cached.
+    pub fn resize(&self, height: usize, width: usize, filter: ImageFilter) -> Image {
+        if height == self.height() && width == self.width() {
+            return self.clone();
+        }
+        let key = (self.hash, Size::new(height, width), filter);
+        if let Some(img) = IMAGE_RESIZE_CACHE
+            .lock()
+            .ok()
+            .and_then(|mut cache| cache.get(&key).cloned())
+        {
+            return img;
+        }
+
+        let _guard = tracing::debug_span!("resize image", ?height, ?width, ?filter).entered();
+        let src = self.map(|_, _, color| ColorLinear::from(*color));
+        // horizontal pass
+        let weights = resample_weights(self.width(), width, filter);
+        let hpass = SurfaceOwned::new_with(self.height(), width, |row, col| {
+            let (start, ref weights) = weights[col];
+            weights
+                .iter()
+                .enumerate()
+                .fold(ColorLinear::default(), |acc, (index, weight)| {
+                    acc + src.get(row, start + index).copied().unwrap_or_default() * *weight
+                })
+        });
+        // vertical pass
+        let weights = resample_weights(self.height(), height, filter);
+        let vpass = SurfaceOwned::new_with(height, width, |row, col| {
+            let (start, ref weights) = weights[row];
+            let ColorLinear([r, g, b, a]) =
+                weights
+                    .iter()
+                    .enumerate()
+                    .fold(ColorLinear::default(), |acc, (index, weight)| {
+                        acc + hpass.get(start + index, col).copied().unwrap_or_default() * *weight
+                    });
+            // filters with negative lobes can produce values out of range
+            let a = clamp(a, 0.0, 1.0);
+            RGBA::from(ColorLinear([
+                clamp(r, 0.0, a),
+                clamp(g, 0.0, a),
+                clamp(b, 0.0, a),
+                a,
+            ]))
+        });
+
+        let img = Image::new(vpass);
+        if let Ok(mut cache) = IMAGE_RESIZE_CACHE.lock() {
+            cache.put(key, img.clone());
+        }
+        img
+    }
+
+    /// Scale and crop image so it would fit into the region of the specified size
+    ///
+    /// Returns resulting image, which is never larger than the region, and its
+    /// offset in pixels inside the region. `align` specifies vertical and horizontal
+    /// alignment of the image inside the region.
+    pub fn fit(
+        &self,
+        region: Size,
+        fit: ImageFit,
+        align: (Align, Align),
+        filter: ImageFilter,
+    ) -> (Image, Position) {
+        if region.is_empty() || self.is_empty() {
+            return (
+                Image::new(SurfaceOwned::<RGBA>::new(0, 0)),
+                Position::new(0, 0),
+            );
+        }
+        let height_ratio = region.height as f64 / self.height() as f64;
+        let width_ratio = region.width as f64 / self.width() as f64;
+        let scaled = |ratio: f64| {
+            Size::new(
+                ((self.height() as f64 * ratio).round() as usize).max(1),
+                ((self.width() as f64 * ratio).round() as usize).max(1),
+            )
+        };
+        let size = match fit {
+            ImageFit::Contain => scaled(height_ratio.min(width_ratio)),
+            ImageFit::Cover => scaled(height_ratio.max(width_ratio)),
+            ImageFit::Stretch => region,
+            ImageFit::None => Size::new(self.height(), self.width()),
+        };
+        let img = self.resize(size.height, size.width, filter);
+
+        // crop parts that are not fitting into the region
+        let (vertical, horizontal) = align;
+        let crop = ImageCrop::new(
+            align_offset(vertical, size.height.saturating_sub(region.height)),
+            align_offset(horizontal, size.width.saturating_sub(region.width)),
+            region.height,
+            region.width,
+        );
+        let img = img.crop(crop);
+
+        // position image inside the region
+        let offset = Position::new(
+            align_offset(vertical, region.height - img.height()),
+            align_offset(horizontal, region.width - img.width()),
+        );
+        (img, offset)
     }
 
     /// Write image as PNG
@@ -174,6 +404,302 @@ impl Surface for Image {
     }
 }
 
+/// Minimal delay between animation frames
+const ANIMATION_MIN_DELAY: Duration = Duration::from_millis(10);
+
+/// Animated image
+///
+/// Sequence of frames of the same size, each frame is shown for the specified
+/// delay, animation is looped indefinitely.
+#[derive(Clone)]
+pub struct AnimatedImage {
+    frames: Arc<[(Image, Duration)]>,
+    hash: u64,
+}
+
+impl AnimatedImage {
+    /// Create animation from frames and their delays
+    ///
+    /// Delays shorter than 10ms are increased to 10ms.
+    pub fn new(frames: impl IntoIterator<Item = (Image, Duration)>) -> Result<Self, Error> {
+        let frames: Arc<[(Image, Duration)]> = frames
+            .into_iter()
+            .map(|(frame, delay)| (frame, delay.max(ANIMATION_MIN_DELAY)))
+            .collect();
+        let (first, _) = frames
+            .first()
+            .ok_or(Error::Other(Cow::Borrowed("animation without frames")))?;
+        if frames
+            .iter()
+            .any(|(frame, _)| frame.height() != first.height() || frame.width() != first.width())
+        {
+            return Err(Error::Other(Cow::Borrowed(
+                "animation frames must have the same size",
+            )));
+        }
+        let mut hasher = fnv::FnvHasher::default();
+        for (frame, delay) in frames.iter() {
+            hasher.write_u64(frame.hash());
+            hasher.write_u128(delay.as_millis());
+        }
+        Ok(Self {
+            frames,
+            hash: hasher.finish(),
+        })
+    }
+
+    /// Frames with their delays
+    pub fn frames(&self) -> &[(Image, Duration)] {
+        &self.frames
+    }
+
+    /// Duration of a single loop of the animation
+    pub fn duration(&self) -> Duration {
+        self.frames.iter().map(|(_, delay)| *delay).sum()
+    }
+
+    /// Frame shown after `elapsed` time since the start of the animation,
+    /// and the time left until the next frame.
+    pub fn frame_at(&self, elapsed: Duration) -> (&Image, Duration) {
+        let duration = self.duration().as_nanos();
+        let mut offset = Duration::from_nanos((elapsed.as_nanos() % duration) as u64);
+        for (frame, delay) in self.frames.iter() {
+            if offset < *delay {
+                return (frame, *delay - offset);
+            }
+            offset -= *delay;
+        }
+        let (frame, delay) = &self.frames[0];
+        (frame, *delay)
+    }
+
+    /// Image representing the whole animation
+    ///
+    /// Image handlers which support animations play it, others show the first frame.
+    pub fn image(&self) -> Image {
+        let (first, _) = &self.frames[0];
+        Image {
+            surf: first.surf.clone(),
+            hash: self.hash,
+            animation: Some(self.clone()),
+        }
+    }
+
+    /// Image size in bytes, including all frames
+    pub fn size(&self) -> usize {
+        self.frames.iter().map(|(frame, _)| frame.size()).sum()
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+}
+
+impl PartialEq for AnimatedImage {
+    fn eq(&self, other: &Self) -> bool {
+        self.hash == other.hash
+    }
+}
+
+impl Eq for AnimatedImage {}
+
+impl fmt::Debug for AnimatedImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "AnimatedImage({}, frames={})",
+            self.hash,
+            self.frames.len()
+        )
+    }
+}
+
+/// Rectangular region of the image in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct ImageCrop {
+    /// Top row of the region
+    pub row: usize,
+    /// Left column of the region
+    pub col: usize,
+    /// Height of the region
+    pub height: usize,
+    /// Width of the region
+    pub width: usize,
+}
+
+impl ImageCrop {
+    pub fn new(row: usize, col: usize, height: usize, width: usize) -> Self {
+        Self {
+            row,
+            col,
+            height,
+            width,
+        }
+    }
+
+    /// Check if region does not contain any pixels
+    pub fn is_empty(&self) -> bool {
+        self.height == 0 || self.width == 0
+    }
+
+    /// Restrict region to the image of the specified size
+    pub fn clamp(self, height: usize, width: usize) -> Self {
+        let row = self.row.min(height);
+        let col = self.col.min(width);
+        Self {
+            row,
+            col,
+            height: self.height.min(height - row),
+            width: self.width.min(width - col),
+        }
+    }
+}
+
+/// Resampling filter used to resize images
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum ImageFilter {
+    /// Nearest neighbor, fast but produces blocky results
+    Nearest,
+    /// Linear interpolation (tent filter)
+    Bilinear,
+    /// Windowed sinc with three lobes, sharpest result
+    #[default]
+    Lanczos,
+    /// Area averaging, best suited for downsampling
+    Box,
+}
+
+impl ImageFilter {
+    /// Radius of the filter kernel in source pixels (when upsampling)
+    fn support(self) -> f64 {
+        use ImageFilter::*;
+        match self {
+            Nearest | Box => 0.5,
+            Bilinear => 1.0,
+            Lanczos => 3.0,
+        }
+    }
+
+    /// Filter kernel
+    fn kernel(self, x: f64) -> f64 {
+        use ImageFilter::*;
+        let x = x.abs();
+        match self {
+            Nearest | Box => {
+                if x <= 0.5 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Bilinear => (1.0 - x).max(0.0),
+            Lanczos => {
+                if x < f64::EPSILON {
+                    1.0
+                } else if x < 3.0 {
+                    let px = PI * x;
+                    3.0 * px.sin() * (px / 3.0).sin() / (px * px)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for ImageFilter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use ImageFilter::*;
+        match s.to_ascii_lowercase().as_str() {
+            "nearest" => Ok(Nearest),
+            "bilinear" => Ok(Bilinear),
+            "lanczos" => Ok(Lanczos),
+            "box" => Ok(Box),
+            _ => Err(Error::ParseError(
+                "ImageFilter",
+                format!("invalid image filter: {}", s),
+            )),
+        }
+    }
+}
+
+/// Calculate weights used to resample a row of `src` pixels into `dst` pixels
+///
+/// For each destination pixel returns index of the first contributing source
+/// pixel and normalized weights of all contributing pixels.
+fn resample_weights(src: usize, dst: usize, filter: ImageFilter) -> Vec<(usize, Vec<f64>)> {
+    let scale = src as f64 / dst as f64;
+    if filter == ImageFilter::Nearest {
+        return (0..dst)
+            .map(|index| {
+                let src_index = ((index as f64 + 0.5) * scale) as usize;
+                (src_index.min(src - 1), vec![1.0])
+            })
+            .collect();
+    }
+    // when downsampling kernel is stretched so it would cover all source pixels
+    let filter_scale = scale.max(1.0);
+    let support = filter.support() * filter_scale;
+    (0..dst)
+        .map(|index| {
+            let center = (index as f64 + 0.5) * scale;
+            let start = (center - support).floor().max(0.0) as usize;
+            let end = ((center + support).ceil() as usize).min(src);
+            let mut weights: Vec<f64> = (start..end)
+                .map(|src_index| filter.kernel((src_index as f64 + 0.5 - center) / filter_scale))
+                .collect();
+            let total: f64 = weights.iter().sum();
+            if total.abs() > f64::EPSILON {
+                weights.iter_mut().for_each(|weight| *weight /= total);
+            }
+            (start, weights)
+        })
+        .collect()
+}
+
+/// How image is fitted into a region
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum ImageFit {
+    /// Scale preserving aspect ratio so the whole image is visible
+    #[default]
+    Contain,
+    /// Scale preserving aspect ratio so the whole region is covered, excess is cropped
+    Cover,
+    /// Scale to the size of the region ignoring aspect ratio
+    Stretch,
+    /// Keep original size, excess is cropped
+    None,
+}
+
+impl FromStr for ImageFit {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use ImageFit::*;
+        match s.to_ascii_lowercase().as_str() {
+            "contain" => Ok(Contain),
+            "cover" => Ok(Cover),
+            "stretch" => Ok(Stretch),
+            "none" => Ok(None),
+            _ => Err(Error::ParseError(
+                "ImageFit",
+                format!("invalid image fit: {}", s),
+            )),
+        }
+    }
+}
+
+/// Offset needed to align an object given the amount of free space
+fn align_offset(align: Align, space: usize) -> usize {
+    match align {
+        Align::Min => 0,
+        Align::Mid => space / 2,
+        Align::Max => space,
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ImageHandlerKind {
     Kitty,
@@ -220,7 +746,14 @@ pub trait ImageHandler: Send + Sync {
     /// Draw image
     ///
     /// Send an appropriate terminal escape sequence so the image would be rendered.
-    fn draw(&mut self, out: &mut dyn Write, img: &Image, pos: Position) -> Result<(), Error>;
+    /// If `crop` is specified only this region of the image is shown.
+    fn draw(
+        &mut self,
+        out: &mut dyn Write,
+        img: &Image,
+        pos: Position,
+        crop: Option<ImageCrop>,
+    ) -> Result<(), Error>;
 
     /// Erase image at specified position
     ///
@@ -237,8 +770,17 @@ pub trait ImageHandler: Send + Sync {
 
     /// Handle events from the terminal
     ///
-    /// True means event has been handled and should not be propagated to a user
-    fn handle(&mut self, event: &TerminalEvent) -> Result<bool, Error>;
+    /// True means event has been handled and should not be propagated to a user.
+    /// `out` can be used to respond to the event, for example to retransmit an image.
+    fn handle(&mut self, out: &mut dyn Write, event: &TerminalEvent) -> Result<bool, Error>;
+
+    /// Prepare image to be drawn
+    ///
+    /// False means image is being encoded in the background and it should not be
+    /// drawn yet, terminal is woken up once encoding is done.
+    fn prepare(&mut self, _img: &Image, _crop: Option<ImageCrop>) -> bool {
+        true
+    }
 }
 
 impl<'a> ImageHandler for Box<dyn ImageHandler> {
@@ -246,8 +788,14 @@ impl<'a> ImageHandler for Box<dyn ImageHandler> {
         (**self).kind()
     }
 
-    fn draw(&mut self, out: &mut dyn Write, img: &Image, pos: Position) -> Result<(), Error> {
-        (**self).draw(out, img, pos)
+    fn draw(
+        &mut self,
+        out: &mut dyn Write,
+        img: &Image,
+        pos: Position,
+        crop: Option<ImageCrop>,
+    ) -> Result<(), Error> {
+        (**self).draw(out, img, pos, crop)
     }
 
     fn erase(
@@ -259,8 +807,172 @@ impl<'a> ImageHandler for Box<dyn ImageHandler> {
         (**self).erase(out, img, pos)
     }
 
-    fn handle(&mut self, event: &TerminalEvent) -> Result<bool, Error> {
-        (**self).handle(event)
+    fn handle(&mut self, out: &mut dyn Write, event: &TerminalEvent) -> Result<bool, Error> {
+        (**self).handle(out, event)
+    }
+
+    fn prepare(&mut self, img: &Image, crop: Option<ImageCrop>) -> bool {
+        (**self).prepare(img, crop)
+    }
+}
+
+type ImageEncodeJob = Box<dyn FnOnce() + Send + 'static>;
+
+/// Pool of threads used to encode images in the background
+///
+/// Terminal is woken up with `TerminalEvent::Wake` once a job is done.
+#[derive(Clone)]
+pub struct ImageEncodePool {
+    sender: mpsc::Sender<ImageEncodeJob>,
+    waker: TerminalWaker,
+}
+
+impl ImageEncodePool {
+    /// Create pool with specified number of threads
+    ///
+    /// Threads exit once all clones of the pool are dropped.
+    pub fn new(threads: usize, waker: TerminalWaker) -> Result<Self, Error> {
+        let (sender, receiver) = mpsc::channel::<ImageEncodeJob>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for index in 0..threads.max(1) {
+            let receiver = receiver.clone();
+            std::thread::Builder::new()
+                .name(format!("image-encode-{}", index))
+                .spawn(move || loop {
+                    let job = match receiver.lock() {
+                        Ok(receiver) => receiver.recv(),
+                        Err(_) => return,
+                    };
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => return,
+                    }
+                })?;
+        }
+        Ok(Self { sender, waker })
+    }
+
+    /// Run job on the pool, false if pool is no longer running
+    fn spawn(&self, job: impl FnOnce() + Send + 'static) -> bool {
+        let waker = self.waker.clone();
+        let job = Box::new(move || {
+            job();
+            if let Err(error) = waker.wake() {
+                tracing::warn!("failed to wake terminal: {:?}", error);
+            }
+        });
+        self.sender.send(job).is_ok()
+    }
+}
+
+/// Images encoded in the background, identified by a key
+///
+/// Without a pool images are expected to be encoded synchronously.
+struct ImageEncodeQueue {
+    pool: Option<ImageEncodePool>,
+    state: Arc<Mutex<ImageEncodeState>>,
+}
+
+/// State shared between the queue and the encoding jobs
+struct ImageEncodeState {
+    /// Keys which are being encoded
+    pending: HashSet<u64>,
+    /// Encoded images, `None` if encoding has failed
+    ready: lru::LruCache<u64, Option<Vec<u8>>>,
+    /// Memory (in bytes) used by encoded images
+    size: usize,
+    max_size: usize,
+}
+
+impl ImageEncodeState {
+    /// Store encoded image evicting least recently used ones over the budget
+    fn insert(&mut self, key: u64, data: Option<Vec<u8>>) {
+        self.pending.remove(&key);
+        self.size += data.as_ref().map_or(0, Vec::len);
+        if let Some(prev) = self.ready.put(key, data) {
+            self.size -= prev.as_ref().map_or(0, Vec::len);
+        }
+        while self.size > self.max_size {
+            match self.ready.pop_lru() {
+                Some((_, lru_data)) => self.size -= lru_data.as_ref().map_or(0, Vec::len),
+                None => break,
+            }
+        }
+    }
+}
+
+impl ImageEncodeQueue {
+    fn new() -> Self {
+        Self {
+            pool: None,
+            state: Arc::new(Mutex::new(ImageEncodeState {
+                pending: HashSet::new(),
+                ready: lru::LruCache::unbounded(),
+                size: 0,
+                max_size: IMAGE_ENCODE_READY_SIZE,
+            })),
+        }
+    }
+
+    fn with_pool(pool: ImageEncodePool) -> Self {
+        Self {
+            pool: Some(pool),
+            ..Self::new()
+        }
+    }
+
+    /// Check if image is ready, otherwise schedule its encoding
+    ///
+    /// Failed encodings are reported as ready, so they would be retried
+    /// synchronously and the error is propagated. Encoded images which
+    /// have been evicted before being taken are scheduled again.
+    fn prepare(
+        &mut self,
+        key: u64,
+        encode: impl FnOnce() -> Result<Vec<u8>, Error> + Send + 'static,
+    ) -> bool {
+        let Some(pool) = &self.pool else {
+            return true;
+        };
+        let Ok(mut state) = self.state.lock() else {
+            return true;
+        };
+        if state.ready.get(&key).is_some() {
+            return true;
+        }
+        if state.pending.contains(&key) {
+            return false;
+        }
+        let shared = self.state.clone();
+        let scheduled = pool.spawn(move || {
+            // panicking encoder is reported as failed, so the key does not stay pending
+            let data = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(encode)) {
+                Ok(Ok(data)) => Some(data),
+                Ok(Err(error)) => {
+                    tracing::warn!("image encoding failed: {:?}", error);
+                    None
+                }
+                Err(_) => {
+                    tracing::warn!("image encoding panicked");
+                    None
+                }
+            };
+            if let Ok(mut state) = shared.lock() {
+                state.insert(key, data);
+            }
+        });
+        if scheduled {
+            state.pending.insert(key);
+        }
+        !scheduled
+    }
+
+    /// Take encoded image if it is available
+    fn take(&mut self, key: u64) -> Option<Vec<u8>> {
+        let mut state = self.state.lock().ok()?;
+        let data = state.ready.pop(&key)??;
+        state.size -= data.len();
+        Some(data)
     }
 }
 
@@ -272,7 +984,13 @@ impl ImageHandler for DummyImageHandler {
         ImageHandlerKind::Dummy
     }
 
-    fn draw(&mut self, _out: &mut dyn Write, _img: &Image, _pos: Position) -> Result<(), Error> {
+    fn draw(
+        &mut self,
+        _out: &mut dyn Write,
+        _img: &Image,
+        _pos: Position,
+        _crop: Option<ImageCrop>,
+    ) -> Result<(), Error> {
         Ok(())
     }
 
@@ -285,7 +1003,7 @@ impl ImageHandler for DummyImageHandler {
         Ok(())
     }
 
-    fn handle(&mut self, _event: &TerminalEvent) -> Result<bool, Error> {
+    fn handle(&mut self, _out: &mut dyn Write, _event: &TerminalEvent) -> Result<bool, Error> {
         Ok(false)
     }
 }
@@ -318,7 +1036,18 @@ impl ImageHandler for ItermImageHandler {
         ImageHandlerKind::ITerm
     }
 
-    fn draw(&mut self, out: &mut dyn Write, img: &Image, _pos: Position) -> Result<(), Error> {
+    fn draw(
+        &mut self,
+        out: &mut dyn Write,
+        img: &Image,
+        _pos: Position,
+        crop: Option<ImageCrop>,
+    ) -> Result<(), Error> {
+        // iTerm has no way to show only part of the image
+        let img = &match crop {
+            Some(crop) => img.crop(crop),
+            None => img.clone(),
+        };
         if let Some(data) = self.imgs.get(&img.hash()) {
             out.write_all(data.as_slice())?;
             return Ok(());
@@ -356,7 +1085,7 @@ impl ImageHandler for ItermImageHandler {
         Ok(())
     }
 
-    fn handle(&mut self, _event: &TerminalEvent) -> Result<bool, Error> {
+    fn handle(&mut self, _out: &mut dyn Write, _event: &TerminalEvent) -> Result<bool, Error> {
         Ok(false)
     }
 }
@@ -365,32 +1094,351 @@ impl ImageHandler for ItermImageHandler {
 ///
 /// Reference: [Kitty Graphic Protocol](https://sw.kovidgoyal.net/kitty/graphics-protocol/)
 pub struct KittyImageHandler {
-    imgs: HashMap<u64, usize>, // hash -> size in bytes
+    imgs: lru::LruCache<u64, KittyImage>, // image id -> transferred image
+    size: usize,
+    budget: usize,
+    transfer: KittyTransfer,
+    queue: ImageEncodeQueue,
+}
+
+/// Image which data has been transferred to the terminal
+struct KittyImage {
+    img: Image,
+    size: usize,
+    /// Currently shown placements, placement id -> (position, crop)
+    placements: HashMap<u64, (Position, Option<ImageCrop>)>,
+    /// Mediums used to transfer image data
+    media: Vec<KittyMedium>,
 }
 
 impl KittyImageHandler {
     pub fn new() -> Self {
         Self {
-            imgs: Default::default(),
+            imgs: lru::LruCache::unbounded(),
+            size: 0,
+            budget: IMAGE_CACHE_SIZE,
+            transfer: KittyTransfer::Direct,
+            queue: ImageEncodeQueue::new(),
         }
     }
-}
 
-impl Default for KittyImageHandler {
-    fn default() -> Self {
-        Self::new()
+    /// Compress images in the background using provided pool
+    pub fn with_encode_pool(mut self, pool: ImageEncodePool) -> Self {
+        self.queue = ImageEncodeQueue::with_pool(pool);
+        self
     }
-}
 
-/// Kitty id/placement_id must not exceed this value
-const KITTY_MAX_ID: u64 = 4294967295;
-/// We are using position to derive placement_id, and this is the limit
-/// on terminal dimension (width and height).
-const KITTY_MAX_DIM: u64 = 65536;
+    /// Set medium used to transfer image data to the terminal
+    pub fn with_transfer(mut self, transfer: KittyTransfer) -> Self {
+        self.transfer = transfer;
+        self
+    }
 
-/// Identification for image data
-fn kitty_image_id(img: &Image) -> u64 {
-    img.hash() % KITTY_MAX_ID
+    /// Set memory budget in bytes for image data kept by the terminal
+    ///
+    /// Once budget is exceeded, data of the least recently used images which
+    /// are not currently shown is freed.
+    pub fn with_budget(mut self, budget: usize) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Free data of the least recently used images until we are within the budget
+    fn evict(&mut self, out: &mut dyn Write) -> Result<(), Error> {
+        if self.size <= self.budget {
+            return Ok(());
+        }
+        let unused: Vec<u64> = self
+            .imgs
+            .iter()
+            .rev()
+            .filter_map(|(img_id, entry)| entry.placements.is_empty().then_some(*img_id))
+            .collect();
+        for img_id in unused {
+            if self.size <= self.budget {
+                break;
+            }
+            if let Some(entry) = self.imgs.pop(&img_id) {
+                tracing::debug!(image_handler = "kitty", ?entry.img, "evict image");
+                self.size -= entry.size;
+                entry.media.iter().for_each(KittyMedium::remove);
+                // a=d - action delete image
+                // d=I - delete by image id and free its data
+                write!(out, "\x1b_Ga=d,d=I,i={}\x1b\\", img_id)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Transfer image data, `control` is the control data without format and medium
+    ///
+    /// Falls back to direct transfer if medium can not be created.
+    fn transfer(
+        &mut self,
+        out: &mut dyn Write,
+        control: &str,
+        img: &impl Surface<Item = RGBA>,
+        media: &mut Vec<KittyMedium>,
+    ) -> Result<(), Error> {
+        if self.transfer != KittyTransfer::Direct {
+            let mut data = Vec::with_capacity(img.height() * img.width() * 4);
+            for color in img.iter() {
+                data.extend_from_slice(&color.rgba_u8());
+            }
+            match KittyMedium::create(self.transfer, &data) {
+                Ok(medium) => {
+                    // f=32 - RGBA pixel format
+                    // t    - transmission medium, payload is base64 encoded path
+                    write!(out, "\x1b_G{},f=32,t={};", control, self.transfer.key())?;
+                    out.write_all(&medium.payload()?)?;
+                    out.write_all(b"\x1b\\")?;
+                    media.push(medium);
+                    return Ok(());
+                }
+                Err(error) => {
+                    tracing::warn!(
+                        "kitty {:?} transfer failed, falling back to direct: {:?}",
+                        self.transfer,
+                        error
+                    );
+                    self.transfer = KittyTransfer::Direct;
+                }
+            }
+        }
+        kitty_transfer_direct(out, control, img)
+    }
+
+    /// Transfer animation frames (except the first one which is transferred
+    /// as the image itself) and start playback
+    ///
+    /// Only the region which differs from the previous frame is sent.
+    fn transfer_animation(
+        &mut self,
+        out: &mut dyn Write,
+        img_id: u64,
+        anim: &AnimatedImage,
+        media: &mut Vec<KittyMedium>,
+    ) -> Result<(), Error> {
+        let frames = anim.frames();
+        for (index, window) in frames.windows(2).enumerate() {
+            let [(prev, _), (frame, delay)] = window else {
+                continue;
+            };
+            let (row, col, height, width) = image_diff(prev, frame).unwrap_or((0, 0, 1, 1));
+            // a=f  - action is transmit animation frame
+            // c    - 1-based number of the frame used as a base canvas (previous frame)
+            // x,y  - offset of the transmitted region inside the frame
+            // X=1  - replace pixels of the base frame instead of blending
+            // z    - frame gap in milliseconds
+            let control = format!(
+                "a=f,i={},c={},x={},y={},v={},s={},X=1,z={}",
+                img_id,
+                index + 1,
+                col,
+                row,
+                height,
+                width,
+                delay.as_millis()
+            );
+            self.transfer(
+                out,
+                &control,
+                &frame.view(row..row + height, col..col + width),
+                media,
+            )?;
+        }
+        // a=a - action is animation control
+        // r=1 - first (root) frame, z - its gap
+        let (_, delay) = &frames[0];
+        write!(
+            out,
+            "\x1b_Ga=a,i={},r=1,z={}\x1b\\",
+            img_id,
+            delay.as_millis()
+        )?;
+        // s=3 - run animation normally
+        // v=1 - loop indefinitely
+        write!(out, "\x1b_Ga=a,i={},s=3,v=1\x1b\\", img_id)?;
+        Ok(())
+    }
+}
+
+impl Default for KittyImageHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for KittyImageHandler {
+    fn drop(&mut self) {
+        for (_, entry) in self.imgs.iter() {
+            entry.media.iter().for_each(KittyMedium::remove);
+        }
+    }
+}
+
+/// Medium used to transfer image data to kitty
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum KittyTransfer {
+    /// Data is sent through the terminal (`t=d`)
+    #[default]
+    Direct,
+    /// Data is written to a file read by the terminal (`t=f`), file is removed
+    /// once the image is evicted
+    File,
+    /// Data is written to a temporary file read and removed by the terminal (`t=t`)
+    TempFile,
+    /// Data is written to a POSIX shared memory object read and unlinked
+    /// by the terminal (`t=s`)
+    SharedMemory,
+}
+
+impl KittyTransfer {
+    /// Value of the transmission medium key
+    pub(crate) fn key(self) -> char {
+        use KittyTransfer::*;
+        match self {
+            Direct => 'd',
+            File => 'f',
+            TempFile => 't',
+            SharedMemory => 's',
+        }
+    }
+}
+
+impl FromStr for KittyTransfer {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use KittyTransfer::*;
+        match s.to_ascii_lowercase().as_str() {
+            "direct" => Ok(Direct),
+            "file" => Ok(File),
+            "tempfile" => Ok(TempFile),
+            "shm" => Ok(SharedMemory),
+            _ => Err(Error::ParseError(
+                "KittyTransfer",
+                format!("invalid kitty transfer medium: {}", s),
+            )),
+        }
+    }
+}
+
+/// File or shared memory object holding image data read by the terminal
+#[derive(Debug)]
+pub(crate) struct KittyMedium {
+    transfer: KittyTransfer,
+    path: String,
+}
+
+impl KittyMedium {
+    /// Create new medium holding provided data
+    pub(crate) fn create(transfer: KittyTransfer, data: &[u8]) -> Result<Self, Error> {
+        use std::{fs::OpenOptions, os::unix::fs::OpenOptionsExt};
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let name = format!(
+            "surf-n-term-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, AtomicOrdering::Relaxed)
+        );
+        let path = match transfer {
+            KittyTransfer::Direct => {
+                return Err(Error::Other(Cow::Borrowed(
+                    "direct transfer does not require medium",
+                )))
+            }
+            // kitty only removes temporary files which contain this string in the path
+            KittyTransfer::TempFile => std::env::temp_dir()
+                .join(format!("tty-graphics-protocol-{}", name))
+                .to_string_lossy()
+                .into_owned(),
+            KittyTransfer::File => std::env::temp_dir()
+                .join(name)
+                .to_string_lossy()
+                .into_owned(),
+            KittyTransfer::SharedMemory => format!("/{}", name),
+        };
+        let medium = Self { transfer, path };
+
+        if transfer == KittyTransfer::SharedMemory {
+            use nix::{
+                fcntl::OFlag,
+                sys::{mman, stat::Mode},
+            };
+            use std::{fs::File, os::unix::io::FromRawFd};
+
+            if data.is_empty() {
+                return Err(Error::Other(Cow::Borrowed("empty shared memory object")));
+            }
+            let fd = mman::shm_open(
+                medium.path.as_str(),
+                OFlag::O_CREAT | OFlag::O_EXCL | OFlag::O_RDWR,
+                Mode::S_IRUSR | Mode::S_IWUSR,
+            )?;
+            // file takes ownership of the descriptor and closes it
+            let file = unsafe { File::from_raw_fd(fd) };
+            let result = file
+                .set_len(data.len() as u64)
+                .map_err(Error::from)
+                .and_then(|_| unsafe {
+                    let ptr = mman::mmap(
+                        std::ptr::null_mut(),
+                        data.len(),
+                        mman::ProtFlags::PROT_WRITE,
+                        mman::MapFlags::MAP_SHARED,
+                        fd,
+                        0,
+                    )?;
+                    std::ptr::copy_nonoverlapping(data.as_ptr(), ptr as *mut u8, data.len());
+                    mman::munmap(ptr, data.len())?;
+                    Ok(())
+                });
+            if let Err(error) = result {
+                medium.remove();
+                return Err(error);
+            }
+        } else {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&medium.path)?;
+            if let Err(error) = file.write_all(data) {
+                medium.remove();
+                return Err(error.into());
+            }
+        }
+        Ok(medium)
+    }
+
+    /// Base64 encoded path to the medium, used as a payload of the command
+    pub(crate) fn payload(&self) -> Result<Vec<u8>, Error> {
+        let mut payload = Base64Encoder::new(Vec::new());
+        payload.write_all(self.path.as_bytes())?;
+        Ok(payload.finish()?)
+    }
+
+    /// Remove medium unless it has already been removed by the terminal
+    pub(crate) fn remove(&self) {
+        let _ = match self.transfer {
+            KittyTransfer::SharedMemory => {
+                nix::sys::mman::shm_unlink(self.path.as_str()).map_err(Error::from)
+            }
+            _ => std::fs::remove_file(&self.path).map_err(Error::from),
+        };
+    }
+}
+
+/// Kitty id/placement_id must not exceed this value
+const KITTY_MAX_ID: u64 = 4294967295;
+/// We are using position to derive placement_id, and this is the limit
+/// on terminal dimension (width and height).
+const KITTY_MAX_DIM: u64 = 65536;
+
+/// Identification for image data
+fn kitty_image_id(img: &Image) -> u64 {
+    img.hash() % KITTY_MAX_ID
 }
 
 /// Identification of particular placement of the image
@@ -402,72 +1450,161 @@ fn kitty_placement_id(pos: Position) -> u64 {
     (pos.row as u64 % KITTY_MAX_DIM) + (pos.col as u64 % KITTY_MAX_DIM) * KITTY_MAX_DIM
 }
 
+/// Transfer zlib compressed image data in chunks through the terminal,
+/// `control` is the control data of the first chunk without format
+fn kitty_transfer_direct(
+    out: &mut dyn Write,
+    control: &str,
+    img: &impl Surface<Item = RGBA>,
+) -> Result<(), Error> {
+    kitty_transfer_payload(out, control, &kitty_payload(img)?)
+}
+
+/// Zlib compressed and base64 encoded RGBA image data
+fn kitty_payload(img: &impl Surface<Item = RGBA>) -> Result<Vec<u8>, Error> {
+    let mut payload_write =
+        ZlibEncoder::new(Base64Encoder::new(Vec::new()), Compression::default());
+    for color in img.iter() {
+        payload_write.write_all(&color.rgba_u8())?;
+    }
+    Ok(payload_write.finish()?.finish()?)
+}
+
+/// Directly transfer already encoded payload
+fn kitty_transfer_payload(out: &mut dyn Write, control: &str, payload: &[u8]) -> Result<(), Error> {
+    // NOTE:
+    //  - data needs to be transferred in chunks
+    //  - chunks should be multiple of 4, otherwise kitty complains that it is not
+    //    valid base64 encoded data.
+    let chunks = payload.chunks(4096);
+    let count = chunks.len();
+    for (index, chunk) in chunks.enumerate() {
+        // control data
+        // m - whether more chunks will follow or not
+        let more = if index + 1 < count { 1 } else { 0 };
+        if index == 0 {
+            // f=32 - RGBA pixel format
+            // o=z  - zlib compressed data
+            write!(out, "\x1b_G{},f=32,o=z,m={};", control, more)?;
+        } else {
+            // only first chunk requires all attributes
+            write!(out, "\x1b_Gm={};", more)?;
+        }
+        // data
+        out.write_all(chunk)?;
+        // epilogue
+        out.write_all(b"\x1b\\")?;
+    }
+    Ok(())
+}
+
+/// Bounding box `(row, col, height, width)` of pixels which differ between
+/// two images of the same size, `None` if images are identical
+fn image_diff(first: &Image, second: &Image) -> Option<(usize, usize, usize, usize)> {
+    let (mut row_min, mut col_min) = (usize::MAX, usize::MAX);
+    let (mut row_max, mut col_max) = (0, 0);
+    for row in 0..first.height() {
+        for col in 0..first.width() {
+            if first.get(row, col) != second.get(row, col) {
+                row_min = row_min.min(row);
+                row_max = row_max.max(row);
+                col_min = col_min.min(col);
+                col_max = col_max.max(col);
+            }
+        }
+    }
+    if row_min > row_max {
+        return None;
+    }
+    Some((
+        row_min,
+        col_min,
+        row_max - row_min + 1,
+        col_max - col_min + 1,
+    ))
+}
+
 impl ImageHandler for KittyImageHandler {
     fn kind(&self) -> ImageHandlerKind {
         ImageHandlerKind::Kitty
     }
 
-    fn draw(&mut self, out: &mut dyn Write, img: &Image, pos: Position) -> Result<(), Error> {
-        tracing::trace!(image_handler = "kitty", ?pos, ?img, "draw image");
+    fn prepare(&mut self, img: &Image, _crop: Option<ImageCrop>) -> bool {
+        // only direct transfer requires compression
+        let img_id = kitty_image_id(img);
+        if self.transfer != KittyTransfer::Direct || self.imgs.contains(&img_id) {
+            return true;
+        }
+        let img = img.clone();
+        self.queue.prepare(img_id, move || kitty_payload(&img))
+    }
+
+    fn draw(
+        &mut self,
+        out: &mut dyn Write,
+        img: &Image,
+        pos: Position,
+        crop: Option<ImageCrop>,
+    ) -> Result<(), Error> {
+        tracing::trace!(image_handler = "kitty", ?pos, ?img, ?crop, "draw image");
         let img_id = kitty_image_id(img);
 
         // transfer image if it has not been transferred yet
-        if let Entry::Vacant(entry) = self.imgs.entry(img_id) {
+        if !self.imgs.contains(&img_id) {
             let _ =
                 tracing::debug_span!("transfer image", image_handler = "kitty", ?pos, ?img).enter();
-            // zlib compressed and base64 encoded RGBA image data
-            let mut payload_write =
-                ZlibEncoder::new(Base64Encoder::new(Vec::new()), Compression::default());
-            for color in img.iter() {
-                payload_write.write_all(&color.rgba_u8())?;
-            }
-            let payload = payload_write.finish()?.finish()?;
-
-            // NOTE:
-            //  - data needs to be transferred in chunks
-            //  - chunks should be multiple of 4, otherwise kitty complains that it is not
-            //    valid base64 encoded data.
-            let chunks = payload.chunks(4096);
-            let count = chunks.len();
-            for (index, chunk) in chunks.enumerate() {
-                // control data
-                let more = if index + 1 < count { 1 } else { 0 };
-                if index == 0 {
-                    // a=t  - action is transmit only
-                    // f=32 - RGBA pixel format
-                    // o=z  - zlib compressed data
-                    // i    - image data identifier
-                    // v    - height of the image
-                    // s    - width of the image
-                    // m    - whether more chunks will follow or not
-                    write!(
-                        out,
-                        "\x1b_Ga=t,f=32,o=z,i={},v={},s={},m={};",
-                        img_id,
-                        img.height(),
-                        img.width(),
-                        more
-                    )?;
-                } else {
-                    // only first chunk requires all attributes
-                    write!(out, "\x1b_Gm={};", more)?;
+            // a=t - action is transmit only
+            // i   - image data identifier
+            // v   - height of the image
+            // s   - width of the image
+            let control = format!("a=t,i={},v={},s={}", img_id, img.height(), img.width());
+            let mut media = Vec::new();
+            match self.queue.take(img_id) {
+                Some(payload) if self.transfer == KittyTransfer::Direct => {
+                    kitty_transfer_payload(out, &control, &payload)?
                 }
-                // data
-                out.write_all(chunk)?;
-                // epilogue
-                out.write_all(b"\x1b\\")?;
+                _ => self.transfer(out, &control, img, &mut media)?,
+            }
+            if let Some(anim) = img.animation().filter(|anim| anim.frames().len() > 1) {
+                self.transfer_animation(out, img_id, anim, &mut media)?;
             }
 
             // remember that image data has been send
-            entry.insert(img.size());
+            let size = img
+                .animation()
+                .map_or_else(|| img.size(), |anim| anim.size());
+            self.size += size;
+            self.imgs.put(
+                img_id,
+                KittyImage {
+                    img: img.clone(),
+                    size,
+                    placements: HashMap::new(),
+                    media,
+                },
+            );
         }
 
         // request image to be shown
         let placement_id = kitty_placement_id(pos);
+        if let Some(entry) = self.imgs.get_mut(&img_id) {
+            entry.placements.insert(placement_id, (pos, crop));
+        }
+        self.evict(out)?;
         // a=p - action is put image
         // i   - image data identifier
         // p   - placement identifier
-        write!(out, "\x1b_Ga=p,i={},p={};\x1b\\", img_id, placement_id)?;
+        write!(out, "\x1b_Ga=p,i={},p={}", img_id, placement_id)?;
+        if let Some(crop) = crop {
+            // x,y - top-left corner of the source rectangle
+            // w,h - width and height of the source rectangle
+            write!(
+                out,
+                ",x={},y={},w={},h={}",
+                crop.col, crop.row, crop.width, crop.height
+            )?;
+        }
+        out.write_all(b";\x1b\\")?;
         Ok(())
     }
 
@@ -478,6 +1615,14 @@ impl ImageHandler for KittyImageHandler {
         pos: Option<Position>,
     ) -> Result<(), Error> {
         tracing::trace!(image_handler = "kitty", ?pos, ?img, "erase image");
+        if let Some(entry) = self.imgs.peek_mut(&kitty_image_id(img)) {
+            match pos {
+                Some(pos) => {
+                    entry.placements.remove(&kitty_placement_id(pos));
+                }
+                None => entry.placements.clear(),
+            }
+        }
         // Delete image by image id and placement id
         // a=d - action delete image
         // d=i - delete by image and placement id without freeing data
@@ -495,21 +1640,52 @@ impl ImageHandler for KittyImageHandler {
         Ok(())
     }
 
-    fn handle(&mut self, event: &TerminalEvent) -> Result<bool, Error> {
-        match event {
-            TerminalEvent::KittyImage { id, error } => {
-                let filter = if !error.is_none() {
-                    tracing::warn!("kitty image error: {:?}", error);
-                    // remove element from cache, and propagate event to
-                    // the user which will cause the redraw
-                    self.imgs.remove(id);
-                    false
-                } else {
-                    true
-                };
-                Ok(filter)
+    fn handle(&mut self, out: &mut dyn Write, event: &TerminalEvent) -> Result<bool, Error> {
+        let (id, error) = match event {
+            TerminalEvent::KittyImage {
+                id,
+                error: Some(error),
+            } => (id, error),
+            TerminalEvent::KittyImage { error: None, .. } => return Ok(true),
+            _ => return Ok(false),
+        };
+        let entry = match self.imgs.pop(id) {
+            Some(entry) => entry,
+            None => {
+                tracing::warn!("kitty image error: {:?}", error);
+                return Ok(false);
+            }
+        };
+        self.size -= entry.size;
+        entry.media.iter().for_each(KittyMedium::remove);
+        let medium_failed = !entry.media.is_empty() && !error.starts_with("ENOENT");
+        if medium_failed {
+            tracing::warn!(
+                "kitty {:?} transfer failed, falling back to direct: {:?}",
+                self.transfer,
+                error
+            );
+            self.transfer = KittyTransfer::Direct;
+        }
+        if error.starts_with("ENOENT") || medium_failed {
+            // image data has been evicted by the terminal or it failed to read
+            // the medium, retransmit it and restore all its placements
+            tracing::debug!(image_handler = "kitty", ?entry.img, "retransmit image");
+            if entry.placements.is_empty() {
+                return Ok(true);
             }
-            _ => Ok(false),
+            // placements are put at the cursor position, which is restored afterwards
+            out.write_all(b"\x1b[s")?;
+            for (pos, crop) in entry.placements.into_values() {
+                write!(out, "\x1b[{};{}H", pos.row + 1, pos.col + 1)?;
+                self.draw(out, &entry.img, pos, crop)?;
+            }
+            out.write_all(b"\x1b[u")?;
+            Ok(true)
+        } else {
+            // propagate event to the user which will cause the redraw
+            tracing::warn!("kitty image error: {:?}", error);
+            Ok(false)
         }
     }
 }
@@ -520,16 +1696,125 @@ impl ImageHandler for KittyImageHandler {
 pub struct SixelImageHandler {
     imgs: lru::LruCache<u64, Vec<u8>>,
     size: usize,
+    encoder: SixelEncoder,
+    queue: ImageEncodeQueue,
+}
+
+/// Parameters used to encode sixel images
+#[derive(Debug, Clone, Copy)]
+struct SixelEncoder {
     bg: Option<RGBA>,
+    palette_size: usize,
+    quantizer: ColorQuantizer,
+    distance: ColorDistance,
+    dither: Dither,
+    max_size: Option<Size>,
+}
+
+impl SixelEncoder {
+    /// Encode image as sixel image
+    fn encode(&self, img: &Image) -> Result<Vec<u8>, Error> {
+        let _guard = tracing::debug_span!("encode image", image_handler = "sixel").entered();
+        // downscale images which do not fit into maximum sixel geometry
+        let img = &match self.max_size {
+            Some(max) if img.height() > max.height || img.width() > max.width => {
+                let scale = (max.height as f64 / img.height() as f64)
+                    .min(max.width as f64 / img.width() as f64);
+                let height = ((img.height() as f64 * scale) as usize).max(1);
+                let width = ((img.width() as f64 * scale) as usize).max(1);
+                img.resize(height, width, ImageFilter::default())
+            }
+            _ => img.clone(),
+        };
+        // sixel color chanel has a range [0,100] colors, we need to reduce it before
+        // quantization, it will produce smaller or/and better palette for this color depth
+        let dimg = Image::new(img.map(|_, _, color| {
+            let [red, green, blue, alpha] = color.rgba_u8();
+            let red = ((red as f32 / 2.55).round() * 2.55) as u8;
+            let green = ((green as f32 / 2.55).round() * 2.55) as u8;
+            let blue = ((blue as f32 / 2.55).round() * 2.55) as u8;
+            RGBA::new(red, green, blue, alpha)
+        }));
+        let bg = self.bg.unwrap_or_else(|| RGBA::new(0, 0, 0, 255));
+        let mut sixel_image = Vec::new();
+        if let Some(palette) = self
+            .quantizer
+            .palette(&dimg, self.palette_size, bg, self.distance)
+        {
+            let qimg = dimg.quantize_palette(&palette, self.dither, Some(bg));
+            sixel_encode(&mut sixel_image, &dimg, &palette, &qimg)?;
+        }
+        Ok(sixel_image)
+    }
 }
 
 impl SixelImageHandler {
+    /// Default number of colors in the palette
+    pub const PALETTE_SIZE: usize = 256;
+
     pub fn new(bg: Option<RGBA>) -> Self {
         SixelImageHandler {
             imgs: lru::LruCache::unbounded(),
             size: 0,
-            bg,
+            encoder: SixelEncoder {
+                bg,
+                palette_size: Self::PALETTE_SIZE,
+                quantizer: ColorQuantizer::default(),
+                distance: ColorDistance::default(),
+                dither: Dither::default(),
+                max_size: None,
+            },
+            queue: ImageEncodeQueue::new(),
+        }
+    }
+
+    /// Set maximum number of colors in the palette
+    pub fn with_palette_size(mut self, palette_size: usize) -> Self {
+        self.encoder.palette_size = palette_size.max(2);
+        self
+    }
+
+    /// Limit palette size to the number of color registers supported by the terminal
+    pub fn with_color_registers(mut self, color_registers: usize) -> Self {
+        self.encoder.palette_size = self.encoder.palette_size.min(color_registers.max(2));
+        self
+    }
+
+    /// Set quantizer used to extract palette
+    pub fn with_quantizer(mut self, quantizer: ColorQuantizer) -> Self {
+        self.encoder.quantizer = quantizer;
+        self
+    }
+
+    /// Set color distance used to map colors onto the palette
+    pub fn with_distance(mut self, distance: ColorDistance) -> Self {
+        self.encoder.distance = distance;
+        self
+    }
+
+    /// Set dithering used to map colors onto the palette
+    pub fn with_dither(mut self, dither: Dither) -> Self {
+        self.encoder.dither = dither;
+        self
+    }
+
+    /// Limit size (in pixels) of the images, larger images are downscaled
+    pub fn with_max_size(mut self, max_size: Size) -> Self {
+        if !max_size.is_empty() {
+            self.encoder.max_size = Some(max_size);
         }
+        self
+    }
+
+    /// Encode images in the background using provided pool
+    pub fn with_encode_pool(mut self, pool: ImageEncodePool) -> Self {
+        self.queue = ImageEncodeQueue::with_pool(pool);
+        self
+    }
+
+    /// Maximum number of colors in the palette
+    pub fn palette_size(&self) -> usize {
+        self.encoder.palette_size
     }
 }
 
@@ -538,118 +1823,51 @@ impl ImageHandler for SixelImageHandler {
         ImageHandlerKind::Sixel
     }
 
-    fn draw(&mut self, out: &mut dyn Write, img: &Image, pos: Position) -> Result<(), Error> {
-        tracing::debug!(image_handler = "sixel", ?pos, ?img, "draw image");
-        if let Some(sixel_image) = self.imgs.get(&img.hash()) {
+    fn prepare(&mut self, img: &Image, crop: Option<ImageCrop>) -> bool {
+        let key = image_crop_key(img, crop);
+        if self.imgs.contains(&key) {
+            return true;
+        }
+        let encoder = self.encoder;
+        let img = img.clone();
+        self.queue.prepare(key, move || {
+            // sixel has no way to show only part of the image
+            let img = match crop {
+                Some(crop) => img.crop(crop),
+                None => img,
+            };
+            encoder.encode(&img)
+        })
+    }
+
+    fn draw(
+        &mut self,
+        out: &mut dyn Write,
+        img: &Image,
+        pos: Position,
+        crop: Option<ImageCrop>,
+    ) -> Result<(), Error> {
+        tracing::debug!(image_handler = "sixel", ?pos, ?img, ?crop, "draw image");
+        let key = image_crop_key(img, crop);
+        if let Some(sixel_image) = self.imgs.get(&key) {
             out.write_all(sixel_image.as_slice())?;
             return Ok(());
         }
-        let _ = tracing::debug_span!("encode image", image_handler = "sixel");
-        // sixel color chanel has a range [0,100] colors, we need to reduce it before
-        // quantization, it will produce smaller or/and better palette for this color depth
-        let dimg = Image::new(img.map(|_, _, color| {
-            let [red, green, blue, alpha] = color.rgba_u8();
-            let red = ((red as f32 / 2.55).round() * 2.55) as u8;
-            let green = ((green as f32 / 2.55).round() * 2.55) as u8;
-            let blue = ((blue as f32 / 2.55).round() * 2.55) as u8;
-            RGBA::new(red, green, blue, alpha)
-        }));
-        let (palette, qimg) = match dimg.quantize(256, true, self.bg) {
-            None => return Ok(()),
-            Some(qimg) => qimg,
-        };
-
-        let mut sixel_image = Vec::new();
-        // header
-        sixel_image.write_all(b"\x1bPq")?;
-        write!(sixel_image, "\"1;1;{};{}", qimg.width(), qimg.height())?;
-        // palette
-        for (index, color) in palette.colors().iter().enumerate() {
-            let [red, green, blue] = color.rgb_u8();
-            let red = (red as f32 / 2.55).round() as u8;
-            let green = (green as f32 / 2.55).round() as u8;
-            let blue = (blue as f32 / 2.55).round() as u8;
-            write!(sixel_image, "#{};2;{};{};{}", index, red, green, blue)?;
-        }
-        // color_index -> [(offset, sixel_code)]
-        let mut sixel_lines: HashMap<usize, Vec<(usize, u8)>> = HashMap::new();
-        let mut colors: HashSet<usize> = HashSet::with_capacity(6);
-        for row in (0..qimg.height()).step_by(6) {
-            sixel_lines.clear();
-            // extract sixel line
-            for col in 0..img.width() {
-                // extract sixel
-                let mut sixel = [0usize; 6];
-                for (i, s) in sixel.iter_mut().enumerate() {
-                    if let Some(index) = qimg.get(row + i, col) {
-                        *s = *index;
-                    }
-                }
-                // construct sixel
-                colors.clear();
-                colors.extend(sixel.iter().copied());
-                for color in colors.iter() {
-                    let mut code = 0;
-                    for (s_index, s_color) in sixel.iter().enumerate() {
-                        if s_color == color {
-                            code |= 1 << s_index;
-                        }
-                    }
-                    sixel_lines
-                        .entry(*color)
-                        .or_insert_with(Vec::new)
-                        .push((col, code + 63));
-                }
-            }
-            // render sixel line
-            for (color, sixel_line) in sixel_lines.iter() {
-                write!(sixel_image, "#{}", color)?;
-
-                let mut offset = 0;
-                let mut codes = sixel_line.iter().peekable();
-                while let Some((column, code)) = codes.next() {
-                    // find shift needed to get to the correct offset
-                    let shift = column - offset;
-                    if shift > 0 {
-                        if shift > 3 {
-                            write!(sixel_image, "!{}?", shift)?;
-                        } else {
-                            for _ in 0..shift {
-                                sixel_image.write_all(b"?")?;
-                            }
-                        }
-                    }
-                    // find repeated sixels
-                    let mut repeats = 1;
-                    while let Some((column_next, code_next)) = codes.peek() {
-                        if *column_next != column + repeats || code_next != code {
-                            break;
-                        }
-                        repeats += 1;
-                        codes.next();
-                    }
-                    // write sixel
-                    if repeats > 3 {
-                        write!(sixel_image, "!{}", repeats)?;
-                        sixel_image.write_all(&[*code])?;
-                    } else {
-                        for _ in 0..repeats {
-                            sixel_image.write_all(&[*code])?;
-                        }
-                    }
-                    offset = column + repeats;
-                }
-                sixel_image.write_all(b"$")?;
+        let sixel_image = match self.queue.take(key) {
+            Some(sixel_image) => sixel_image,
+            None => {
+                // sixel has no way to show only part of the image
+                let img = match crop {
+                    Some(crop) => img.crop(crop),
+                    None => img.clone(),
+                };
+                self.encoder.encode(&img)?
             }
-            sixel_image.write_all(b"-")?;
-        }
-        // EOF sixel
-        sixel_image.write_all(b"\x1b\\")?;
-
+        };
         out.write_all(sixel_image.as_slice())?;
 
         self.size += sixel_image.len();
-        self.imgs.put(img.hash(), sixel_image);
+        self.imgs.put(key, sixel_image);
         if self.size > IMAGE_CACHE_SIZE {
             if let Some((_, lru_image)) = self.imgs.pop_lru() {
                 self.size -= lru_image.len();
@@ -668,14 +1886,124 @@ impl ImageHandler for SixelImageHandler {
         Ok(())
     }
 
-    fn handle(&mut self, _event: &TerminalEvent) -> Result<bool, Error> {
+    fn handle(&mut self, _out: &mut dyn Write, _event: &TerminalEvent) -> Result<bool, Error> {
         Ok(false)
     }
 }
 
+/// Cache key of the cropped image
+fn image_crop_key(img: &Image, crop: Option<ImageCrop>) -> u64 {
+    match crop {
+        None => img.hash(),
+        Some(crop) => {
+            let mut hasher = fnv::FnvHasher::default();
+            hasher.write_u64(img.hash());
+            for value in [crop.row, crop.col, crop.height, crop.width] {
+                hasher.write_usize(value);
+            }
+            hasher.finish()
+        }
+    }
+}
+
+/// Encode quantized image as sixel image
+///
+/// Fully transparent pixels of `img` are left unpainted, in which case
+/// transparent background mode (`P2=1`) is requested.
+fn sixel_encode(
+    out: &mut Vec<u8>,
+    img: &Image,
+    palette: &ColorPalette,
+    qimg: &SurfaceOwned<usize>,
+) -> Result<(), Error> {
+    /// Write `count` repeats of the sixel `code`
+    fn sixel_run(out: &mut Vec<u8>, count: usize, code: u8) -> Result<(), Error> {
+        if count > 3 {
+            write!(out, "!{}", count)?;
+            out.push(code);
+        } else {
+            out.resize(out.len() + count, code);
+        }
+        Ok(())
+    }
+
+    let transparent = |row: usize, col: usize| match img.get(row, col) {
+        Some(color) => color.rgba_u8()[3] == 0,
+        None => true,
+    };
+    let has_transparency = img.iter().any(|color| color.rgba_u8()[3] == 0);
+
+    // header
+    if has_transparency {
+        out.write_all(b"\x1bP0;1q")?;
+    } else {
+        out.write_all(b"\x1bPq")?;
+    }
+    write!(out, "\"1;1;{};{}", qimg.width(), qimg.height())?;
+    // palette
+    for (index, color) in palette.colors().iter().enumerate() {
+        let [red, green, blue] = color.rgb_u8();
+        let red = (red as f32 / 2.55).round() as u8;
+        let green = (green as f32 / 2.55).round() as u8;
+        let blue = (blue as f32 / 2.55).round() as u8;
+        write!(out, "#{};2;{};{};{}", index, red, green, blue)?;
+    }
+    // color_index -> [(offset, sixel_code)]
+    let mut sixel_lines: BTreeMap<usize, Vec<(usize, u8)>> = BTreeMap::new();
+    for row in (0..qimg.height()).step_by(6) {
+        if row != 0 {
+            out.write_all(b"-")?;
+        }
+        // extract sixel line
+        sixel_lines.clear();
+        for col in 0..qimg.width() {
+            for offset in 0..6 {
+                if transparent(row + offset, col) {
+                    continue;
+                }
+                let Some(color) = qimg.get(row + offset, col) else {
+                    continue;
+                };
+                let line = sixel_lines.entry(*color).or_default();
+                match line.last_mut() {
+                    Some((line_col, code)) if *line_col == col => *code |= 1 << offset,
+                    _ => line.push((col, 1 << offset)),
+                }
+            }
+        }
+        // render sixel line
+        for (index, (color, sixel_line)) in sixel_lines.iter().enumerate() {
+            if index != 0 {
+                out.write_all(b"$")?;
+            }
+            write!(out, "#{}", color)?;
+            let mut offset = 0;
+            let mut codes = sixel_line.iter().peekable();
+            while let Some((col, code)) = codes.next() {
+                // skip to the column
+                sixel_run(out, col - offset, b'?')?;
+                // find repeated sixels
+                let mut repeats = 1;
+                while let Some((col_next, code_next)) = codes.peek() {
+                    if *col_next != col + repeats || code_next != code {
+                        break;
+                    }
+                    repeats += 1;
+                    codes.next();
+                }
+                sixel_run(out, repeats, code + 63)?;
+                offset = col + repeats;
+            }
+        }
+    }
+    // EOF sixel
+    out.write_all(b"\x1b\\")?;
+    Ok(())
+}
+
 /// Color like object to track quantization
 ///
-/// Used in Floyd–Steinberg dithering.
+/// Used in error diffusion dithering.
 #[derive(Clone, Copy)]
 struct ColorError([f32; 3]);
 
@@ -735,6 +2063,266 @@ impl Mul<f32> for ColorError {
     }
 }
 
+/// Dithering used during image quantization
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dither {
+    /// Nearest palette color is used without dithering
+    None,
+    /// Ordered dithering with a threshold matrix
+    ///
+    /// Result for each pixel depends only on its position, which makes it
+    /// stable between animation frames.
+    Ordered { matrix: DitherMatrix, strength: f32 },
+    /// Error diffusion dithering
+    Diffusion {
+        kernel: DitherKernel,
+        strength: f32,
+        serpentine: bool,
+    },
+}
+
+impl Dither {
+    /// Set dithering strength, `1.0` corresponds to the full strength
+    pub fn with_strength(mut self, value: f32) -> Self {
+        match &mut self {
+            Dither::None => {}
+            Dither::Ordered { strength, .. } | Dither::Diffusion { strength, .. } => {
+                *strength = value.max(0.0)
+            }
+        }
+        self
+    }
+
+    /// Enable serpentine scanning (only affects error diffusion)
+    pub fn with_serpentine(mut self, value: bool) -> Self {
+        if let Dither::Diffusion { serpentine, .. } = &mut self {
+            *serpentine = value;
+        }
+        self
+    }
+}
+
+impl Default for Dither {
+    fn default() -> Self {
+        DitherKernel::FloydSteinberg.into()
+    }
+}
+
+impl From<DitherKernel> for Dither {
+    fn from(kernel: DitherKernel) -> Self {
+        Dither::Diffusion {
+            kernel,
+            strength: 1.0,
+            serpentine: false,
+        }
+    }
+}
+
+impl From<DitherMatrix> for Dither {
+    fn from(matrix: DitherMatrix) -> Self {
+        Dither::Ordered {
+            matrix,
+            strength: 1.0,
+        }
+    }
+}
+
+impl FromStr for Dither {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Dither::None),
+            "bayer" => Ok(DitherMatrix::Bayer.into()),
+            "blue-noise" => Ok(DitherMatrix::BlueNoise.into()),
+            "floyd-steinberg" => Ok(DitherKernel::FloydSteinberg.into()),
+            "atkinson" => Ok(DitherKernel::Atkinson.into()),
+            "sierra" => Ok(DitherKernel::Sierra.into()),
+            "jarvis" => Ok(DitherKernel::Jarvis.into()),
+            _ => Err(Error::ParseError(
+                "Dither",
+                format!("invalid dither: {}", s),
+            )),
+        }
+    }
+}
+
+/// Threshold matrix used by ordered dithering
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DitherMatrix {
+    /// 8x8 Bayer matrix
+    Bayer,
+    /// 16x16 blue noise generated with void-and-cluster method
+    BlueNoise,
+}
+
+impl DitherMatrix {
+    /// Threshold in `[0, 1)` range for the pixel
+    pub fn threshold(self, row: usize, col: usize) -> f32 {
+        match self {
+            DitherMatrix::Bayer => {
+                let (x, y) = (col & 7, row & 7);
+                let mut value = 0;
+                for bit in 0..3 {
+                    value = (value << 2) | (((x ^ y) >> bit) & 1) << 1 | ((y >> bit) & 1);
+                }
+                (value as f32 + 0.5) / 64.0
+            }
+            DitherMatrix::BlueNoise => {
+                let (x, y) = (col % BLUE_NOISE_SIZE, row % BLUE_NOISE_SIZE);
+                BLUE_NOISE[y * BLUE_NOISE_SIZE + x]
+            }
+        }
+    }
+}
+
+/// Error diffusion kernel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DitherKernel {
+    FloydSteinberg,
+    /// Spreads only 3/4 of the error, produces less noisy results
+    Atkinson,
+    Sierra,
+    /// Jarvis, Judice, and Ninke kernel
+    Jarvis,
+}
+
+impl DitherKernel {
+    /// List of `(column offset, row offset, weight)`
+    fn weights(self) -> &'static [(isize, usize, f32)] {
+        match self {
+            // [[0   , X   , 7/16],
+            // [3/16, 5/16, 1/16]]
+            DitherKernel::FloydSteinberg => &[
+                (1, 0, 7.0 / 16.0),
+                (-1, 1, 3.0 / 16.0),
+                (0, 1, 5.0 / 16.0),
+                (1, 1, 1.0 / 16.0),
+            ],
+            DitherKernel::Atkinson => &[
+                (1, 0, 1.0 / 8.0),
+                (2, 0, 1.0 / 8.0),
+                (-1, 1, 1.0 / 8.0),
+                (0, 1, 1.0 / 8.0),
+                (1, 1, 1.0 / 8.0),
+                (0, 2, 1.0 / 8.0),
+            ],
+            DitherKernel::Sierra => &[
+                (1, 0, 5.0 / 32.0),
+                (2, 0, 3.0 / 32.0),
+                (-2, 1, 2.0 / 32.0),
+                (-1, 1, 4.0 / 32.0),
+                (0, 1, 5.0 / 32.0),
+                (1, 1, 4.0 / 32.0),
+                (2, 1, 2.0 / 32.0),
+                (-1, 2, 2.0 / 32.0),
+                (0, 2, 3.0 / 32.0),
+                (1, 2, 2.0 / 32.0),
+            ],
+            DitherKernel::Jarvis => &[
+                (1, 0, 7.0 / 48.0),
+                (2, 0, 5.0 / 48.0),
+                (-2, 1, 3.0 / 48.0),
+                (-1, 1, 5.0 / 48.0),
+                (0, 1, 7.0 / 48.0),
+                (1, 1, 5.0 / 48.0),
+                (2, 1, 3.0 / 48.0),
+                (-2, 2, 1.0 / 48.0),
+                (-1, 2, 3.0 / 48.0),
+                (0, 2, 5.0 / 48.0),
+                (1, 2, 3.0 / 48.0),
+                (2, 2, 1.0 / 48.0),
+            ],
+        }
+    }
+}
+
+/// Generate `size x size` blue noise thresholds with void-and-cluster method
+///
+/// Reference: [The void-and-cluster method for dither array generation](https://cv.ulichney.com/papers/1993-void-cluster.pdf)
+fn blue_noise(size: usize) -> Vec<f32> {
+    let count = size * size;
+    // toroidal gaussian filter used to measure density of the pattern
+    let filter: Vec<f32> = (0..count)
+        .map(|index| {
+            let (dy, dx) = (index / size, index % size);
+            let (dy, dx) = (dy.min(size - dy), dx.min(size - dx));
+            (-((dx * dx + dy * dy) as f32) / 4.5).exp()
+        })
+        .collect();
+    let update = |energy: &mut [f32], index: usize, sign: f32| {
+        let (row, col) = (index / size, index % size);
+        for (other, value) in energy.iter_mut().enumerate() {
+            let dy = (other / size + size - row) % size;
+            let dx = (other % size + size - col) % size;
+            *value += sign * filter[dy * size + dx];
+        }
+    };
+    // most dense minority pixel
+    let cluster = |pattern: &[bool], energy: &[f32]| {
+        (0..count)
+            .filter(|index| pattern[*index])
+            .max_by(|i0, i1| energy[*i0].total_cmp(&energy[*i1]))
+            .unwrap_or(0)
+    };
+    // least dense majority pixel
+    let void = |pattern: &[bool], energy: &[f32]| {
+        (0..count)
+            .filter(|index| !pattern[*index])
+            .min_by(|i0, i1| energy[*i0].total_cmp(&energy[*i1]))
+            .unwrap_or(0)
+    };
+
+    // initial random pattern
+    let ones = count / 10;
+    let mut pattern = vec![false; count];
+    let mut energy = vec![0.0; count];
+    let mut rnd = Rnd::new();
+    let mut placed = 0;
+    while placed < ones {
+        let index = rnd.next_u32() as usize % count;
+        if !pattern[index] {
+            pattern[index] = true;
+            update(&mut energy, index, 1.0);
+            placed += 1;
+        }
+    }
+    // move points from clusters to voids until pattern is stable
+    loop {
+        let index = cluster(&pattern, &energy);
+        pattern[index] = false;
+        update(&mut energy, index, -1.0);
+        let void_index = void(&pattern, &energy);
+        pattern[void_index] = true;
+        update(&mut energy, void_index, 1.0);
+        if void_index == index {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0; count];
+    // rank initial pattern by removing clusters
+    let mut pattern_rank = pattern.clone();
+    let mut energy_rank = energy.clone();
+    for rank in (0..ones).rev() {
+        let index = cluster(&pattern_rank, &energy_rank);
+        pattern_rank[index] = false;
+        update(&mut energy_rank, index, -1.0);
+        ranks[index] = rank;
+    }
+    // rank the rest by filling voids
+    for rank in ones..count {
+        let index = void(&pattern, &energy);
+        pattern[index] = true;
+        update(&mut energy, index, 1.0);
+        ranks[index] = rank;
+    }
+    ranks
+        .into_iter()
+        .map(|rank| (rank as f32 + 0.5) / count as f32)
+        .collect()
+}
+
 #[derive(Debug, Clone, Copy)]
 struct OcTreeLeaf {
     red_acc: usize,
@@ -1191,37 +2779,114 @@ impl Iterator for OcTreePath {
     }
 }
 
-/// 3-dimensional KDTree which is used to quickly find nearest (euclidean distance)
-/// color from the palette.
-///
-/// Reference: [k-d tree](https://en.wikipedia.org/wiki/K-d_tree)
-pub struct KDTree {
+/// 3-dimensional KDTree which is used to quickly find nearest (euclidean distance)
+/// color from the palette.
+///
+/// Reference: [k-d tree](https://en.wikipedia.org/wiki/K-d_tree)
+pub struct KDTree {
+    tree: KDPointTree,
+}
+
+impl KDTree {
+    /// Create k-d tree from the list of colors
+    pub fn new(colors: &[RGBA]) -> Self {
+        let points: Vec<_> = colors
+            .iter()
+            .map(|c| ColorDistance::Rgb.point(*c))
+            .collect();
+        Self {
+            tree: KDPointTree::new(&points),
+        }
+    }
+
+    /// Find nearest neighbor color (euclidean distance) in the palette
+    pub fn find(&self, color: RGBA) -> (usize, RGBA) {
+        let (index, point) = self.tree.find(ColorDistance::Rgb.point(color));
+        let [r, g, b] = point.map(|c| c as u8);
+        (index, RGBA::new(r, g, b, 255))
+    }
+
+    /// Render k-d tree as graphviz digraph (for debugging)
+    pub fn to_digraph(&self, mut out: impl Write) -> std::io::Result<()> {
+        fn to_digraph_rec(
+            out: &mut impl Write,
+            nodes: &[KDNode],
+            index: usize,
+        ) -> std::io::Result<()> {
+            let node = nodes[index];
+            let d = match node.dim {
+                0 => "R",
+                1 => "G",
+                2 => "B",
+                _ => unreachable!(),
+            };
+            let [r, g, b] = node.point.map(|c| c as u8);
+            let color = RGBA::new(r, g, b, 255);
+            let fg = color.best_contrast(RGBA::new(255, 255, 255, 255), RGBA::new(0, 0, 0, 255));
+            writeln!(
+                out,
+                "  {} [style=filled, fontcolor=\"{}\" fillcolor=\"{}\", label=\"{} {} {:?}\"]",
+                index,
+                fg,
+                color,
+                d,
+                node.point[node.dim] as u8,
+                [r, g, b],
+            )?;
+            if let Some(left) = node.left {
+                writeln!(out, "  {} -> {} [color=green]", index, left)?;
+                to_digraph_rec(out, nodes, left)?;
+            }
+            if let Some(right) = node.right {
+                writeln!(out, "  {} -> {} [color=red]", index, right)?;
+                to_digraph_rec(out, nodes, right)?;
+            }
+            Ok(())
+        }
+
+        let nodes = &self.tree.nodes;
+        writeln!(out, "digraph KDTree {{")?;
+        writeln!(out, "  rankdir=\"LR\"")?;
+        to_digraph_rec(&mut out, nodes, nodes.len() - 1)?;
+        writeln!(out, "}}")?;
+        Ok(())
+    }
+}
+
+/// 3-dimensional k-d tree of points in the color distance space
+struct KDPointTree {
     nodes: Vec<KDNode>,
 }
 
 #[derive(Debug, Clone, Copy)]
 struct KDNode {
-    color: [u8; 3],
-    color_index: usize,
+    point: [f32; 3],
+    index: usize,
     dim: usize,
     left: Option<usize>,
     right: Option<usize>,
 }
 
-impl KDTree {
-    /// Create k-d tree from the list of colors
-    pub fn new(colors: &[RGBA]) -> Self {
+fn kd_dist(p0: [f32; 3], p1: [f32; 3]) -> f32 {
+    let [x0, y0, z0] = p0;
+    let [x1, y1, z1] = p1;
+    (x0 - x1).powi(2) + (y0 - y1).powi(2) + (z0 - z1).powi(2)
+}
+
+impl KDPointTree {
+    /// Create k-d tree from the list of points
+    fn new(points: &[[f32; 3]]) -> Self {
         fn build_rec(
             dim: usize,
             nodes: &mut Vec<KDNode>,
-            colors: &mut [(usize, [u8; 3])],
+            points: &mut [(usize, [f32; 3])],
         ) -> Option<usize> {
-            match colors {
+            match points {
                 [] => return None,
-                [(color_index, color)] => {
+                [(index, point)] => {
                     nodes.push(KDNode {
-                        color: *color,
-                        color_index: *color_index,
+                        point: *point,
+                        index: *index,
                         dim,
                         left: None,
                         right: None,
@@ -1230,15 +2895,15 @@ impl KDTree {
                 }
                 _ => (),
             }
-            colors.sort_by_key(|(_, c)| c[dim]);
-            let index = colors.len() / 2;
+            points.sort_by(|(_, p0), (_, p1)| p0[dim].total_cmp(&p1[dim]));
+            let median = points.len() / 2;
             let dim_next = (dim + 1) % 3;
-            let left = build_rec(dim_next, nodes, &mut colors[..index]);
-            let right = build_rec(dim_next, nodes, &mut colors[(index + 1)..]);
-            let (color_index, color) = colors[index];
+            let left = build_rec(dim_next, nodes, &mut points[..median]);
+            let right = build_rec(dim_next, nodes, &mut points[(median + 1)..]);
+            let (index, point) = points[median];
             nodes.push(KDNode {
-                color,
-                color_index,
+                point,
+                index,
                 dim,
                 left,
                 right,
@@ -1247,25 +2912,19 @@ impl KDTree {
         }
 
         let mut nodes = Vec::new();
-        let mut colors: Vec<_> = colors.iter().map(|c| c.rgb_u8()).enumerate().collect();
-        build_rec(0, &mut nodes, &mut colors);
+        let mut points: Vec<_> = points.iter().copied().enumerate().collect();
+        build_rec(0, &mut nodes, &mut points);
         Self { nodes }
     }
 
-    /// Find nearest neighbor color (euclidean distance) in the palette
-    pub fn find(&self, color: RGBA) -> (usize, RGBA) {
-        fn dist(rgb: [u8; 3], node: &KDNode) -> i32 {
-            let [r0, g0, b0] = rgb;
-            let [r1, g1, b1] = node.color;
-            (r0 as i32 - r1 as i32).pow(2)
-                + (g0 as i32 - g1 as i32).pow(2)
-                + (b0 as i32 - b1 as i32).pow(2)
-        }
-
-        fn find_rec(nodes: &[KDNode], index: usize, target: [u8; 3]) -> (KDNode, i32) {
+    /// Find nearest neighbor point (euclidean distance)
+    ///
+    /// Returns index of the point and the point itself.
+    fn find(&self, target: [f32; 3]) -> (usize, [f32; 3]) {
+        fn find_rec(nodes: &[KDNode], index: usize, target: [f32; 3]) -> (KDNode, f32) {
             let node = nodes[index];
-            let node_dist = dist(target, &node);
-            let (next, other) = if target[node.dim] < node.color[node.dim] {
+            let node_dist = kd_dist(target, node.point);
+            let (next, other) = if target[node.dim] < node.point[node.dim] {
                 (node.left, node.right)
             } else {
                 (node.right, node.left)
@@ -1282,7 +2941,7 @@ impl KDTree {
                 }
             };
             // check if the other branch is closer then best match we have found so far.
-            let other_dist = (target[node.dim] as i32 - node.color[node.dim] as i32).pow(2);
+            let other_dist = (target[node.dim] - node.point[node.dim]).powi(2);
             if other_dist >= guess_dist {
                 return (guess, guess_dist);
             }
@@ -1299,56 +2958,269 @@ impl KDTree {
             }
         }
 
-        let node = find_rec(&self.nodes, self.nodes.len() - 1, color.rgb_u8()).0;
-        let [r, g, b] = node.color;
-        (node.color_index, RGBA::new(r, g, b, 255))
+        let (node, _) = find_rec(&self.nodes, self.nodes.len() - 1, target);
+        (node.index, node.point)
     }
+}
 
-    /// Render k-d tree as graphviz digraph (for debugging)
-    pub fn to_digraph(&self, mut out: impl Write) -> std::io::Result<()> {
-        fn to_digraph_rec(
-            out: &mut impl Write,
-            nodes: &[KDNode],
-            index: usize,
-        ) -> std::io::Result<()> {
-            let node = nodes[index];
-            let d = match node.dim {
-                0 => "R",
-                1 => "G",
-                2 => "B",
-                _ => unreachable!(),
-            };
-            let [r, g, b] = node.color;
-            let color = RGBA::new(r, g, b, 255);
-            let fg = color.best_contrast(RGBA::new(255, 255, 255, 255), RGBA::new(0, 0, 0, 255));
-            writeln!(
-                out,
-                "  {} [style=filled, fontcolor=\"{}\" fillcolor=\"{}\", label=\"{} {} {:?}\"]",
-                index, fg, color, d, node.color[node.dim], node.color,
-            )?;
-            if let Some(left) = node.left {
-                writeln!(out, "  {} -> {} [color=green]", index, left)?;
-                to_digraph_rec(out, nodes, left)?;
+/// Color space in which distance between colors is measured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ColorDistance {
+    /// Euclidean distance between sRGB components
+    #[default]
+    Rgb,
+    /// Euclidean distance in perceptual OKLab color space
+    OkLab,
+}
+
+impl ColorDistance {
+    /// Convert color to the point in the distance space
+    pub fn point(self, color: RGBA) -> [f32; 3] {
+        match self {
+            ColorDistance::Rgb => {
+                let [r, g, b] = color.rgb_u8();
+                [r as f32, g as f32, b as f32]
             }
-            if let Some(right) = node.right {
-                writeln!(out, "  {} -> {} [color=red]", index, right)?;
-                to_digraph_rec(out, nodes, right)?;
+            ColorDistance::OkLab => {
+                // scaled so lightness has roughly the same range as sRGB components
+                let OkLab { l, a, b } = OkLab::from(color);
+                [(l * 255.0) as f32, (a * 255.0) as f32, (b * 255.0) as f32]
             }
-            Ok(())
         }
+    }
 
-        writeln!(out, "digraph KDTree {{")?;
-        writeln!(out, "  rankdir=\"LR\"")?;
-        to_digraph_rec(&mut out, &self.nodes, self.nodes.len() - 1)?;
-        writeln!(out, "}}")?;
-        Ok(())
+    /// Convert point in the distance space back to the color
+    pub fn color(self, point: [f32; 3]) -> RGBA {
+        match self {
+            ColorDistance::Rgb => {
+                let [r, g, b] = point.map(|c| c.round().clamp(0.0, 255.0) as u8);
+                RGBA::new(r, g, b, 255)
+            }
+            ColorDistance::OkLab => {
+                let [l, a, b] = point.map(|c| c as f64 / 255.0);
+                OkLab::new(l, a, b).into()
+            }
+        }
+    }
+}
+
+impl FromStr for ColorDistance {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use ColorDistance::*;
+        match s.to_ascii_lowercase().as_str() {
+            "rgb" => Ok(Rgb),
+            "oklab" => Ok(OkLab),
+            _ => Err(Error::ParseError(
+                "ColorDistance",
+                format!("invalid color distance: {}", s),
+            )),
+        }
+    }
+}
+
+/// Algorithm used to extract palette from an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ColorQuantizer {
+    /// Fast octree based quantizer
+    #[default]
+    OcTree,
+    /// Recursively split color box along its longest axis at the median
+    MedianCut,
+    /// K-means refinement of the median cut palette
+    KMeans,
+}
+
+impl ColorQuantizer {
+    /// Number of k-means refinement iterations
+    const KMEANS_ITERATIONS: usize = 8;
+
+    /// Extract palette with at most `palette_size` colors from the image
+    ///
+    /// Transparent colors are blended with `bg` before palette extraction.
+    pub fn palette(
+        self,
+        img: impl Surface<Item = RGBA>,
+        palette_size: usize,
+        bg: RGBA,
+        distance: ColorDistance,
+    ) -> Option<ColorPalette> {
+        if img.is_empty() || palette_size == 0 {
+            return None;
+        }
+        let colors = match self {
+            ColorQuantizer::OcTree => {
+                let palette = ColorPalette::from_image(img, palette_size, bg)?;
+                return Some(palette.with_distance(distance));
+            }
+            ColorQuantizer::MedianCut => {
+                let hist = color_histogram(img, bg, distance);
+                median_cut(&hist, palette_size)
+            }
+            ColorQuantizer::KMeans => {
+                let hist = color_histogram(img, bg, distance);
+                let mut centers = median_cut(&hist, palette_size);
+                kmeans(&hist, &mut centers, Self::KMEANS_ITERATIONS);
+                centers
+            }
+        };
+        let mut colors: Vec<_> = colors.into_iter().map(|p| distance.color(p)).collect();
+        colors.sort_by_key(|c| c.rgba_u8());
+        colors.dedup();
+        Some(ColorPalette::new(colors)?.with_distance(distance))
+    }
+}
+
+impl FromStr for ColorQuantizer {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use ColorQuantizer::*;
+        match s.to_ascii_lowercase().as_str() {
+            "octree" => Ok(OcTree),
+            "median-cut" => Ok(MedianCut),
+            "kmeans" => Ok(KMeans),
+            _ => Err(Error::ParseError(
+                "ColorQuantizer",
+                format!("invalid color quantizer: {}", s),
+            )),
+        }
+    }
+}
+
+/// Unique colors of the image (as points in the distance space) with their counts
+fn color_histogram(
+    img: impl Surface<Item = RGBA>,
+    bg: RGBA,
+    distance: ColorDistance,
+) -> Vec<([f32; 3], f32)> {
+    let mut counts: HashMap<RGBA, usize> = HashMap::new();
+    for color in img.iter() {
+        let color = if color.rgba_u8()[3] < 255 {
+            bg.blend(*color, Blend::Over)
+        } else {
+            *color
+        };
+        *counts.entry(color).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .map(|(color, count)| (distance.point(color), count as f32))
+        .collect()
+}
+
+/// Weighted mean of the histogram entries
+fn histogram_mean(hist: &[([f32; 3], f32)]) -> [f32; 3] {
+    let mut acc = [0.0f32; 3];
+    let mut total = 0.0;
+    for (point, count) in hist {
+        for dim in 0..3 {
+            acc[dim] += point[dim] * count;
+        }
+        total += count;
+    }
+    acc.map(|c| c / total)
+}
+
+/// Median cut palette extraction
+///
+/// Box with the largest weighted extent is split along its longest axis at
+/// the weighted median, until there are `palette_size` boxes.
+fn median_cut(hist: &[([f32; 3], f32)], palette_size: usize) -> Vec<[f32; 3]> {
+    // returns (score, axis) of the box, box is not splittable if score is zero
+    fn box_score(hist: &[([f32; 3], f32)]) -> (f32, usize) {
+        if hist.len() < 2 {
+            return (0.0, 0);
+        }
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        let mut total = 0.0;
+        for (point, count) in hist {
+            for dim in 0..3 {
+                min[dim] = min[dim].min(point[dim]);
+                max[dim] = max[dim].max(point[dim]);
+            }
+            total += count;
+        }
+        let (axis, extent) = (0..3)
+            .map(|dim| (dim, max[dim] - min[dim]))
+            .max_by(|(_, e0), (_, e1)| e0.total_cmp(e1))
+            .unwrap_or((0, 0.0));
+        (extent * total.sqrt(), axis)
+    }
+
+    let mut hist = hist.to_vec();
+    // boxes are stored as ranges into the histogram
+    let mut boxes = Vec::with_capacity(palette_size);
+    boxes.push(0..hist.len());
+    while boxes.len() < palette_size {
+        let Some((index, (score, axis))) = boxes
+            .iter()
+            .map(|range| box_score(&hist[range.clone()]))
+            .enumerate()
+            .max_by(|(_, (s0, _)), (_, (s1, _))| s0.total_cmp(s1))
+        else {
+            break;
+        };
+        if score <= 0.0 {
+            break;
+        }
+        let range = boxes.swap_remove(index);
+        let items = &mut hist[range.clone()];
+        items.sort_by(|(p0, _), (p1, _)| p0[axis].total_cmp(&p1[axis]));
+        let half = items.iter().map(|(_, count)| count).sum::<f32>() / 2.0;
+        let mut acc = 0.0;
+        let mut split = 1;
+        for (index, (_, count)) in items.iter().enumerate() {
+            acc += count;
+            if acc >= half {
+                split = (index + 1).clamp(1, items.len() - 1);
+                break;
+            }
+        }
+        boxes.push(range.start..range.start + split);
+        boxes.push(range.start + split..range.end);
+    }
+    boxes
+        .into_iter()
+        .map(|range| histogram_mean(&hist[range]))
+        .collect()
+}
+
+/// Refine centers with Lloyd's k-means iterations
+fn kmeans(hist: &[([f32; 3], f32)], centers: &mut [[f32; 3]], iterations: usize) {
+    for _ in 0..iterations {
+        let kdtree = KDPointTree::new(centers);
+        let mut acc = vec![([0.0f32; 3], 0.0f32); centers.len()];
+        for (point, count) in hist {
+            let (index, _) = kdtree.find(*point);
+            let (sum, total) = &mut acc[index];
+            for dim in 0..3 {
+                sum[dim] += point[dim] * count;
+            }
+            *total += count;
+        }
+        let mut moved = false;
+        for (center, (sum, total)) in centers.iter_mut().zip(acc) {
+            if total == 0.0 {
+                continue;
+            }
+            let center_new = sum.map(|c| c / total);
+            moved |= kd_dist(*center, center_new) > 0.25;
+            *center = center_new;
+        }
+        if !moved {
+            break;
+        }
     }
 }
 
 /// Color palette which implements fast NNS with euclidean distance.
 pub struct ColorPalette {
     colors: Vec<RGBA>,
-    kdtree: KDTree,
+    distance: ColorDistance,
+    kdtree: KDPointTree,
 }
 
 impl ColorPalette {
@@ -1357,8 +3229,27 @@ impl ColorPalette {
         if colors.is_empty() {
             None
         } else {
-            let kdtree = KDTree::new(&colors);
-            Some(Self { colors, kdtree })
+            let distance = ColorDistance::default();
+            let points: Vec<_> = colors.iter().map(|c| distance.point(*c)).collect();
+            let kdtree = KDPointTree::new(&points);
+            Some(Self {
+                colors,
+                distance,
+                kdtree,
+            })
+        }
+    }
+
+    /// Use specified color distance to find nearest colors
+    pub fn with_distance(self, distance: ColorDistance) -> Self {
+        if self.distance == distance {
+            return self;
+        }
+        let points: Vec<_> = self.colors.iter().map(|c| distance.point(*c)).collect();
+        Self {
+            kdtree: KDPointTree::new(&points),
+            distance,
+            colors: self.colors,
         }
     }
 
@@ -1410,11 +3301,17 @@ impl ColorPalette {
         &self.colors
     }
 
+    /// Color distance used to find nearest colors
+    pub fn distance(&self) -> ColorDistance {
+        self.distance
+    }
+
     /// Find nearest color in the palette
     ///
     /// Returns index of the color and color itself
     pub fn find(&self, color: RGBA) -> (usize, RGBA) {
-        self.kdtree.find(color)
+        let (index, _) = self.kdtree.find(self.distance.point(color));
+        (index, self.colors[index])
     }
 
     /// Find nearest color in the palette by going over all colors
@@ -1422,17 +3319,12 @@ impl ColorPalette {
     /// This is a slower version of the find method, used only for testing
     /// find correctness and speed.
     pub fn find_naive(&self, color: RGBA) -> (usize, RGBA) {
-        fn dist(c0: RGBA, c1: RGBA) -> i32 {
-            let [r0, g0, b0] = c0.rgb_u8();
-            let [r1, g1, b1] = c1.rgb_u8();
-            (r0 as i32 - r1 as i32).pow(2)
-                + (g0 as i32 - g1 as i32).pow(2)
-                + (b0 as i32 - b1 as i32).pow(2)
-        }
-        let best_dist = dist(color, self.colors[0]);
+        let target = self.distance.point(color);
+        let dist = |index: usize| kd_dist(target, self.distance.point(self.colors[index]));
+        let best_dist = dist(0);
         let (best_index, _) =
             (1..self.colors.len()).fold((0, best_dist), |(best_index, best_dist), index| {
-                let dist = dist(color, self.colors[index]);
+                let dist = dist(index);
                 if dist < best_dist {
                     (index, dist)
                 } else {
@@ -1573,4 +3465,497 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_palette_distance() {
+        // k-d tree of colors matches palette with default distance
+        let colors: Vec<_> = RGBA::random().take(64).collect();
+        let kdtree = KDTree::new(&colors);
+        let palette = ColorPalette::new(colors).unwrap();
+        for color in RGBA::random().take(1024) {
+            assert_eq!(kdtree.find(color), palette.find(color));
+        }
+        let mut digraph = Vec::new();
+        kdtree.to_digraph(&mut digraph).unwrap();
+        assert!(digraph.starts_with(b"digraph KDTree {"));
+
+        // nearest neighbor is found in every color distance space
+        for distance in [ColorDistance::Rgb, ColorDistance::OkLab] {
+            let dist = |c0: RGBA, c1: RGBA| kd_dist(distance.point(c0), distance.point(c1));
+            let mut gen = RGBA::random();
+            let palette = ColorPalette::new((&mut gen).take(256).collect())
+                .unwrap()
+                .with_distance(distance);
+            let mut colors: Vec<_> = gen.take(65_536).collect();
+            colors.extend(palette.colors().iter().copied());
+            for (index, color) in colors.iter().enumerate() {
+                let (_, find) = palette.find(*color);
+                let (_, find_naive) = palette.find_naive(*color);
+                if find != find_naive && dist(*color, find) != dist(*color, find_naive) {
+                    panic!(
+                        "[{:?}] failed to find colors[{}]={:?}: find_naive={:?} find={:?}",
+                        distance, index, color, find_naive, find
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_quantizer() -> Result<(), Error> {
+        assert_eq!(
+            "median-cut".parse::<ColorQuantizer>()?,
+            ColorQuantizer::MedianCut
+        );
+        assert_eq!("OkLab".parse::<ColorDistance>()?, ColorDistance::OkLab);
+        assert!("lab".parse::<ColorDistance>().is_err());
+
+        let img = Image::new(SurfaceOwned::new_with(64, 64, |row, col| {
+            RGBA::new(
+                (row * 4) as u8,
+                (col * 4) as u8,
+                ((row + col) * 2) as u8,
+                255,
+            )
+        }));
+        let bg = RGBA::new(0, 0, 0, 255);
+
+        let median_cut = ColorQuantizer::MedianCut
+            .palette(&img, 16, bg, ColorDistance::Rgb)
+            .unwrap();
+        assert_eq!(median_cut.size(), 16);
+        let kmeans = ColorQuantizer::KMeans
+            .palette(&img, 16, bg, ColorDistance::Rgb)
+            .unwrap();
+        assert!(kmeans.size() <= 16);
+        let octree = ColorQuantizer::OcTree
+            .palette(&img, 16, bg, ColorDistance::Rgb)
+            .unwrap();
+        assert!(img.quantize_mse(&median_cut) < img.quantize_mse(&octree));
+        assert!(img.quantize_mse(&kmeans) <= img.quantize_mse(&median_cut));
+
+        let oklab = ColorQuantizer::KMeans
+            .palette(&img, 16, bg, ColorDistance::OkLab)
+            .unwrap();
+        assert_eq!(oklab.distance(), ColorDistance::OkLab);
+        assert!(oklab.size() <= 16);
+
+        // fewer unique colors than palette size
+        let img = Image::new(SurfaceOwned::new_with(4, 4, |row, _| {
+            if row < 2 {
+                RGBA::new(255, 0, 0, 255)
+            } else {
+                RGBA::new(0, 0, 255, 255)
+            }
+        }));
+        let palette = ColorQuantizer::MedianCut
+            .palette(&img, 16, bg, ColorDistance::Rgb)
+            .unwrap();
+        assert_eq!(
+            palette.colors(),
+            &[RGBA::new(0, 0, 255, 255), RGBA::new(255, 0, 0, 255)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dither() -> Result<(), Error> {
+        // threshold matrices contain each level exactly once
+        for (matrix, size) in [
+            (DitherMatrix::Bayer, 8),
+            (DitherMatrix::BlueNoise, BLUE_NOISE_SIZE),
+        ] {
+            let mut levels: Vec<_> = (0..size * size)
+                .map(|index| {
+                    let threshold = matrix.threshold(index / size, index % size);
+                    (threshold * (size * size) as f32) as usize
+                })
+                .collect();
+            levels.sort_unstable();
+            assert_eq!(levels, (0..size * size).collect::<Vec<_>>());
+        }
+        assert_eq!(DitherMatrix::Bayer.threshold(0, 1), 32.5 / 64.0);
+        assert_eq!(DitherMatrix::Bayer.threshold(9, 9), 16.5 / 64.0);
+
+        // dithered gray on black and white palette should preserve its intensity
+        let palette =
+            ColorPalette::new(vec![RGBA::new(0, 0, 0, 255), RGBA::new(255, 255, 255, 255)])
+                .unwrap();
+        let img = Image::new(SurfaceOwned::new_with(32, 32, |_, _| {
+            RGBA::new(64, 64, 64, 255)
+        }));
+        // (atkinson does not spread the whole error and loses some intensity)
+        for (name, tolerance) in [
+            ("bayer", 0.05),
+            ("Blue-Noise", 0.05),
+            ("floyd-steinberg", 0.05),
+            ("atkinson", 0.1),
+            ("sierra", 0.05),
+            ("jarvis", 0.05),
+        ] {
+            for serpentine in [false, true] {
+                let dither = name.parse::<Dither>()?.with_serpentine(serpentine);
+                let qimg = img.quantize_palette(&palette, dither, None);
+                let white = qimg.iter().filter(|index| **index == 1).count();
+                let ratio = white as f32 / (32.0 * 32.0);
+                assert!(
+                    (ratio - 0.25).abs() < tolerance,
+                    "{:?} ratio: {}",
+                    dither,
+                    ratio
+                );
+            }
+        }
+
+        // no dithering and zero strength map to the nearest color
+        for dither in [
+            Dither::None,
+            Dither::from(DitherMatrix::Bayer).with_strength(0.0),
+            Dither::default().with_strength(0.0),
+        ] {
+            let qimg = img.quantize_palette(&palette, dither, None);
+            assert!(qimg.iter().all(|index| *index == 0));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sixel() -> Result<(), Error> {
+        let red = RGBA::new(255, 0, 0, 255);
+        let blue = RGBA::new(0, 0, 255, 255);
+        let palette = ColorPalette::new(vec![red, blue]).unwrap();
+        let img = Image::new(SurfaceOwned::new_with(2, 6, |row, col| match (row, col) {
+            (1, 0) => RGBA::new(0, 0, 0, 0),
+            (0, 5) => blue,
+            _ => red,
+        }));
+        let qimg = img.quantize_palette(&palette, Dither::None, None);
+        let mut out = Vec::new();
+        sixel_encode(&mut out, &img, &palette, &qimg)?;
+        assert_eq!(
+            String::from_utf8_lossy(&out),
+            "\x1bP0;1q\"1;1;6;2#0;2;100;0;0#1;2;0;0;100#0@!4BA$#1!5?@\x1b\\"
+        );
+
+        // images are downscaled to fit maximum geometry
+        let mut handler = SixelImageHandler::new(None)
+            .with_max_size(Size::new(4, 4))
+            .with_dither(Dither::None);
+        let img = Image::new(SurfaceOwned::new_with(8, 16, |_, _| red));
+        let mut out = Vec::new();
+        handler.draw(&mut out, &img, Position::new(0, 0), None)?;
+        assert!(out.starts_with(b"\x1bPq\"1;1;4;2#0;"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_pool() -> Result<(), Error> {
+        let (send, recv) = std::sync::mpsc::channel();
+        let send = Mutex::new(send);
+        let waker = TerminalWaker::new(move || {
+            let _ = send.lock().unwrap().send(());
+            Ok(())
+        });
+        let pool = ImageEncodePool::new(2, waker)?;
+        let mut handler = SixelImageHandler::new(None).with_encode_pool(pool);
+        let mut handler_sync = SixelImageHandler::new(None);
+
+        let img = Image::new(SurfaceOwned::new_with(12, 7, |row, col| {
+            RGBA::new(row as u8 * 20, col as u8 * 30, 77, 255)
+        }));
+        let crop = Some(ImageCrop::new(1, 2, 6, 4));
+        assert!(handler_sync.prepare(&img, crop));
+        assert!(!handler.prepare(&img, crop));
+        recv.recv_timeout(Duration::from_secs(10))
+            .map_err(|error| Error::Other(error.to_string().into()))?;
+        assert!(handler.prepare(&img, crop));
+
+        let mut out = Vec::new();
+        handler.draw(&mut out, &img, Position::new(0, 0), crop)?;
+        let mut out_sync = Vec::new();
+        handler_sync.draw(&mut out_sync, &img, Position::new(0, 0), crop)?;
+        assert!(!out.is_empty());
+        assert_eq!(out, out_sync);
+        assert!(handler.prepare(&img, crop));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_queue_eviction() -> Result<(), Error> {
+        let (send, recv) = std::sync::mpsc::channel();
+        let send = Mutex::new(send);
+        let waker = TerminalWaker::new(move || {
+            let _ = send.lock().unwrap().send(());
+            Ok(())
+        });
+        let mut queue = ImageEncodeQueue::with_pool(ImageEncodePool::new(1, waker)?);
+        queue.state.lock().unwrap().max_size = 8;
+        let wait = || {
+            recv.recv_timeout(Duration::from_secs(10))
+                .map_err(|error| Error::Other(error.to_string().into()))
+        };
+
+        // encoded images over the budget are evicted
+        for key in 0..3u64 {
+            assert!(!queue.prepare(key, move || Ok(vec![key as u8; 4])));
+            wait()?;
+        }
+        assert_eq!(queue.state.lock().unwrap().size, 8);
+        assert!(queue.prepare(1, || Ok(Vec::new())));
+        assert!(queue.prepare(2, || Ok(Vec::new())));
+
+        // evicted image is encoded again
+        assert!(!queue.prepare(0, || Ok(vec![7; 4])));
+        wait()?;
+        assert_eq!(queue.take(0), Some(vec![7; 4]));
+        assert_eq!(queue.take(0), None);
+        assert_eq!(queue.state.lock().unwrap().size, 4);
+
+        // panicking encoder is reported as failed
+        assert!(!queue.prepare(5, || panic!("encoder panic")));
+        wait()?;
+        assert!(queue.state.lock().unwrap().pending.is_empty());
+        assert!(queue.prepare(5, || Ok(Vec::new())));
+        assert_eq!(queue.take(5), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_crop() {
+        let img = Image::new(SurfaceOwned::new_with(4, 5, |row, col| {
+            RGBA::new(row as u8, col as u8, 0, 255)
+        }));
+        assert_eq!(img.crop(ImageCrop::new(0, 0, 4, 5)), img);
+        assert_eq!(img.crop(ImageCrop::new(0, 0, 10, 10)), img);
+
+        let crop = img.crop(ImageCrop::new(1, 2, 2, 10));
+        assert_eq!(crop.height(), 2);
+        assert_eq!(crop.width(), 3);
+        assert_eq!(crop.get(0, 0), Some(&RGBA::new(1, 2, 0, 255)));
+        assert_eq!(crop.get(1, 2), Some(&RGBA::new(2, 4, 0, 255)));
+
+        assert!(ImageCrop::new(5, 0, 1, 1).clamp(4, 5).is_empty());
+    }
+
+    #[test]
+    fn test_image_resize() {
+        let color = RGBA::new(211, 134, 155, 255);
+        let img = Image::new(SurfaceOwned::new_with(7, 5, |_, _| color));
+        for filter in [
+            ImageFilter::Nearest,
+            ImageFilter::Bilinear,
+            ImageFilter::Lanczos,
+            ImageFilter::Box,
+        ] {
+            for (height, width) in [(3, 2), (14, 11)] {
+                let result = img.resize(height, width, filter);
+                assert_eq!(result.height(), height);
+                assert_eq!(result.width(), width);
+                assert!(result.iter().all(|c| *c == color), "{:?}", filter);
+            }
+        }
+
+        // averaging must be done in linear color space
+        let black = RGBA::new(0, 0, 0, 255);
+        let white = RGBA::new(255, 255, 255, 255);
+        let img = Image::new(SurfaceOwned::new_with(4, 4, |row, col| {
+            if (row + col) % 2 == 0 {
+                black
+            } else {
+                white
+            }
+        }));
+        let result = img.resize(1, 1, ImageFilter::Box);
+        assert_eq!(result.get(0, 0), Some(&RGBA::new(188, 188, 188, 255)));
+    }
+
+    #[test]
+    fn test_image_fit() {
+        let img = Image::new(SurfaceOwned::new_with(20, 40, |_, _| {
+            RGBA::new(1, 2, 3, 255)
+        }));
+        let region = Size::new(40, 40);
+        let mid = (Align::Mid, Align::Mid);
+
+        let (result, offset) = img.fit(region, ImageFit::Contain, mid, ImageFilter::Box);
+        assert_eq!((result.height(), result.width()), (20, 40));
+        assert_eq!(offset, Position::new(10, 0));
+
+        let (result, offset) = img.fit(
+            region,
+            ImageFit::Contain,
+            (Align::Max, Align::Min),
+            ImageFilter::Box,
+        );
+        assert_eq!((result.height(), result.width()), (20, 40));
+        assert_eq!(offset, Position::new(20, 0));
+
+        let (result, offset) = img.fit(region, ImageFit::Cover, mid, ImageFilter::Box);
+        assert_eq!((result.height(), result.width()), (40, 40));
+        assert_eq!(offset, Position::new(0, 0));
+
+        let (result, offset) = img.fit(region, ImageFit::Stretch, mid, ImageFilter::Box);
+        assert_eq!((result.height(), result.width()), (40, 40));
+        assert_eq!(offset, Position::new(0, 0));
+
+        let (result, offset) = img.fit(Size::new(10, 80), ImageFit::None, mid, ImageFilter::Box);
+        assert_eq!((result.height(), result.width()), (10, 40));
+        assert_eq!(offset, Position::new(0, 20));
+    }
+
+    #[test]
+    fn test_animation() -> Result<(), Error> {
+        let frame = |color: RGBA| Image::new(SurfaceOwned::new_with(2, 3, |_, _| color));
+        let red = frame(RGBA::new(255, 0, 0, 255));
+        let mut green = SurfaceOwned::new_with(2, 3, |_, _| RGBA::new(255, 0, 0, 255));
+        green.set(1, 1, RGBA::new(0, 255, 0, 255));
+        let green = Image::new(green);
+        let anim = AnimatedImage::new([
+            (red.clone(), Duration::from_millis(100)),
+            (green.clone(), Duration::from_millis(50)),
+        ])?;
+        assert_eq!(anim.duration(), Duration::from_millis(150));
+        assert_eq!(
+            anim.frame_at(Duration::from_millis(30)),
+            (&red, Duration::from_millis(70))
+        );
+        assert_eq!(
+            anim.frame_at(Duration::from_millis(260)),
+            (&green, Duration::from_millis(40))
+        );
+        assert!(AnimatedImage::new([(frame(RGBA::default()), Duration::ZERO)]).is_ok());
+        assert!(AnimatedImage::new([
+            (red.clone(), Duration::ZERO),
+            (Image::new(SurfaceOwned::new(1, 1)), Duration::ZERO)
+        ])
+        .is_err());
+
+        // image representing animation is the first frame with a different hash
+        let img = anim.image();
+        assert_eq!(img.animation(), Some(&anim));
+        assert_eq!(img.data(), red.data());
+        assert_ne!(img, red);
+
+        // kitty transfers only changed region of the frame and starts playback
+        let mut out = Vec::new();
+        KittyImageHandler::new().draw(&mut out, &img, Position::new(0, 0), None)?;
+        let out = String::from_utf8_lossy(&out);
+        let id = kitty_image_id(&img);
+        assert!(out.contains(&format!(
+            "a=f,i={},c=1,x=1,y=1,v=1,s=1,X=1,z=50,f=32,o=z",
+            id
+        )));
+        assert!(out.contains(&format!("\x1b_Ga=a,i={},r=1,z=100\x1b\\", id)));
+        assert!(out.contains(&format!("\x1b_Ga=a,i={},s=3,v=1\x1b\\", id)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kitty_eviction() -> Result<(), Error> {
+        let imgs: Vec<_> = (0..4u8)
+            .map(|index| {
+                Image::new(SurfaceOwned::new_with(1, 1, |_, _| {
+                    RGBA::new(index, 0, 0, 255)
+                }))
+            })
+            .collect();
+        let ids: Vec<_> = imgs.iter().map(kitty_image_id).collect();
+        let mut handler = KittyImageHandler::new().with_budget(8);
+        let mut out = Vec::new();
+
+        // shown images are never evicted
+        handler.draw(&mut out, &imgs[0], Position::new(0, 0), None)?;
+        handler.draw(&mut out, &imgs[1], Position::new(0, 1), None)?;
+        handler.draw(&mut out, &imgs[2], Position::new(0, 2), None)?;
+        assert!(!String::from_utf8_lossy(&out).contains("d=I"));
+
+        // least recently used image which is not shown is evicted
+        handler.erase(&mut out, &imgs[0], Some(Position::new(0, 0)))?;
+        out.clear();
+        handler.draw(&mut out, &imgs[3], Position::new(1, 0), None)?;
+        let out_str = String::from_utf8_lossy(&out);
+        assert!(out_str.contains(&format!("\x1b_Ga=d,d=I,i={}\x1b\\", ids[0])));
+        assert!(!out_str.contains(&format!("d=I,i={}", ids[1])));
+        assert_eq!(handler.size, 12);
+
+        // image evicted by the terminal is retransmitted
+        out.clear();
+        let event = TerminalEvent::KittyImage {
+            id: ids[1],
+            error: Some("ENOENT:image not found".to_string()),
+        };
+        assert!(handler.handle(&mut out, &event)?);
+        let out_str = String::from_utf8_lossy(&out);
+        assert!(out_str.contains(&format!("a=t,i={},v=1,s=1,f=32,o=z", ids[1])));
+        assert!(out_str.starts_with("\x1b[s"));
+        assert!(out_str.ends_with("\x1b[u"));
+        let cursor = out_str.find("\x1b[1;2H").expect("cursor is not moved");
+        let place = out_str
+            .find(&format!(
+                "a=p,i={},p={}",
+                ids[1],
+                kitty_placement_id(Position::new(0, 1))
+            ))
+            .expect("placement is not restored");
+        assert!(cursor < place);
+
+        // other errors and unknown images are propagated
+        let event = TerminalEvent::KittyImage {
+            id: 1,
+            error: Some("EINVAL:bad request".to_string()),
+        };
+        assert!(!handler.handle(&mut out, &event)?);
+        let event = TerminalEvent::KittyImage {
+            id: ids[2],
+            error: None,
+        };
+        assert!(handler.handle(&mut out, &event)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_kitty_transfer() -> Result<(), Error> {
+        assert_eq!("shm".parse::<KittyTransfer>()?, KittyTransfer::SharedMemory);
+        assert!("pipe".parse::<KittyTransfer>().is_err());
+
+        let img = Image::new(SurfaceOwned::new_with(1, 2, |_, col| {
+            RGBA::new(col as u8, 2, 3, 255)
+        }));
+        let img_id = kitty_image_id(&img);
+        let mut handler = KittyImageHandler::new().with_transfer(KittyTransfer::TempFile);
+        let mut out = Vec::new();
+        handler.draw(&mut out, &img, Position::new(0, 0), None)?;
+
+        // data is written to the temporary file and its path is sent
+        let entry = handler.imgs.peek(&img_id).expect("image is not cached");
+        let path = entry.media[0].path.clone();
+        assert!(path.contains("tty-graphics-protocol"));
+        assert_eq!(std::fs::read(&path)?, vec![0, 2, 3, 255, 1, 2, 3, 255]);
+        let mut expected = format!("\x1b_Ga=t,i={},v=1,s=2,f=32,t=t;", img_id).into_bytes();
+        expected.extend(entry.media[0].payload()?);
+        expected.extend(b"\x1b\\");
+        assert!(out.starts_with(&expected));
+
+        // terminal failed to read the medium, fallback to direct transfer
+        out.clear();
+        let event = TerminalEvent::KittyImage {
+            id: img_id,
+            error: Some("EBADF:failed to open file".to_string()),
+        };
+        assert!(handler.handle(&mut out, &event)?);
+        assert_eq!(handler.transfer, KittyTransfer::Direct);
+        assert!(!std::path::Path::new(&path).exists());
+        assert!(
+            String::from_utf8_lossy(&out).contains(&format!("a=t,i={},v=1,s=2,f=32,o=z", img_id))
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,630 @@
+//! Layout engine splitting surfaces into areas by constraints
+use crate::{Error, Size, SurfaceMut, SurfaceMutView, TerminalDisplay};
+use std::{fmt, str::FromStr};
+
+/// Direction in which layout children are placed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Direction {
+    /// Children are placed left to right
+    Horizontal,
+    /// Children are placed top to bottom
+    #[default]
+    Vertical,
+}
+
+impl FromStr for Direction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "horizontal" => Ok(Self::Horizontal),
+            "vertical" => Ok(Self::Vertical),
+            _ => Err(Error::ParseError("Direction", s.to_string())),
+        }
+    }
+}
+
+/// Size constraint of the layout child along the layout direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Constraint {
+    /// Exact number of cells
+    Fixed(usize),
+    /// Percentage of the available space
+    Percent(usize),
+    /// Fraction (numerator, denominator) of the available space
+    Ratio(usize, usize),
+    /// At least provided number of cells, grows to take free space
+    Min(usize),
+    /// At most provided number of cells, grows to take free space
+    Max(usize),
+    /// Takes free space proportionally to its weight
+    Fill(usize),
+}
+
+impl Constraint {
+    /// Base size and weight used to distribute free space
+    fn resolve(self, length: usize) -> (usize, usize) {
+        match self {
+            Self::Fixed(size) => (size, 0),
+            Self::Percent(percent) => (length.saturating_mul(percent) / 100, 0),
+            Self::Ratio(_, 0) => (0, 0),
+            Self::Ratio(num, den) => (length.saturating_mul(num) / den, 0),
+            Self::Min(size) => (size, 1),
+            Self::Max(_) => (0, 1),
+            Self::Fill(weight) => (0, weight),
+        }
+    }
+
+    /// Upper bound of the size
+    fn limit(self) -> usize {
+        match self {
+            Self::Max(size) => size,
+            _ => usize::MAX,
+        }
+    }
+}
+
+impl Default for Constraint {
+    fn default() -> Self {
+        Self::Fill(1)
+    }
+}
+
+impl FromStr for Constraint {
+    type Err = Error;
+
+    /// Parse constraint from `10`, `50%`, `1/3`, `min:5`, `max:5`, `fill` or `fill:2`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || Error::ParseError("Constraint", s.to_string());
+        let number = |value: &str| value.trim().parse::<usize>().map_err(|_| error());
+        let s = s.trim();
+        if let Some(percent) = s.strip_suffix('%') {
+            Ok(Self::Percent(number(percent)?))
+        } else if let Some((num, den)) = s.split_once('/') {
+            Ok(Self::Ratio(number(num)?, number(den)?))
+        } else if let Some(size) = s.strip_prefix("min:") {
+            Ok(Self::Min(number(size)?))
+        } else if let Some(size) = s.strip_prefix("max:") {
+            Ok(Self::Max(number(size)?))
+        } else if let Some(weight) = s.strip_prefix("fill:") {
+            Ok(Self::Fill(number(weight)?))
+        } else if s == "fill" {
+            Ok(Self::Fill(1))
+        } else {
+            Ok(Self::Fixed(number(s)?))
+        }
+    }
+}
+
+/// Space around an area in cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Margin {
+    pub top: usize,
+    pub right: usize,
+    pub bottom: usize,
+    pub left: usize,
+}
+
+impl Margin {
+    pub fn new(top: usize, right: usize, bottom: usize, left: usize) -> Self {
+        Self {
+            top,
+            right,
+            bottom,
+            left,
+        }
+    }
+
+    /// Same margin on all sides
+    pub fn all(size: usize) -> Self {
+        Self::new(size, size, size, size)
+    }
+
+    /// Vertical (top and bottom) and horizontal (left and right) margin
+    pub fn symmetric(vertical: usize, horizontal: usize) -> Self {
+        Self::new(vertical, horizontal, vertical, horizontal)
+    }
+}
+
+/// Rectangular area of the surface in cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Rect {
+    pub row: usize,
+    pub col: usize,
+    pub height: usize,
+    pub width: usize,
+}
+
+impl Rect {
+    pub fn new(row: usize, col: usize, height: usize, width: usize) -> Self {
+        Self {
+            row,
+            col,
+            height,
+            width,
+        }
+    }
+
+    /// Size of the area
+    pub fn size(&self) -> Size {
+        Size::new(self.height, self.width)
+    }
+
+    /// Check if area does not contain any cells
+    pub fn is_empty(&self) -> bool {
+        self.height == 0 || self.width == 0
+    }
+
+    /// Area shrunk by the margin
+    pub fn shrink(&self, margin: Margin) -> Self {
+        let height = self
+            .height
+            .saturating_sub(margin.top.saturating_add(margin.bottom));
+        let width = self
+            .width
+            .saturating_sub(margin.left.saturating_add(margin.right));
+        Self {
+            row: self.row + margin.top.min(self.height),
+            col: self.col + margin.left.min(self.width),
+            height,
+            width,
+        }
+    }
+
+    /// Mutable view of the area of the surface
+    pub fn view_mut<'a, S>(&self, surf: &'a mut S) -> SurfaceMutView<'a, S::Item>
+    where
+        S: SurfaceMut,
+    {
+        surf.view_mut(
+            self.row..self.row + self.height,
+            self.col..self.col + self.width,
+        )
+    }
+}
+
+/// Child of the layout
+enum LayoutChild<'a> {
+    /// Leaf area sized by constraint
+    Area(Constraint),
+    /// Leaf area sized by the content size hint
+    Content(&'a dyn TerminalDisplay),
+    /// Nested layout
+    Layout(Constraint, Layout<'a>),
+}
+
+/// Container splitting an area between its children
+///
+/// Children are either leaves, which produce areas, or nested layouts. Areas
+/// are returned in the order leaves were added (depth first for nested layouts).
+///
+/// Margin is the space left around the container, and padding is the space
+/// left inside of each child area. With wrapping enabled, children which do
+/// not fit the current line are moved to the next one (like flexbox does).
+#[derive(Default)]
+pub struct Layout<'a> {
+    direction: Direction,
+    margin: Margin,
+    padding: Margin,
+    gap: usize,
+    wrap: bool,
+    children: Vec<LayoutChild<'a>>,
+}
+
+impl<'a> Layout<'a> {
+    pub fn new(direction: Direction) -> Self {
+        Self {
+            direction,
+            ..Default::default()
+        }
+    }
+
+    /// Layout placing children top to bottom
+    pub fn vertical() -> Self {
+        Self::new(Direction::Vertical)
+    }
+
+    /// Layout placing children left to right
+    pub fn horizontal() -> Self {
+        Self::new(Direction::Horizontal)
+    }
+
+    pub fn with_margin(mut self, margin: Margin) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    pub fn with_padding(mut self, padding: Margin) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Number of cells between children
+    pub fn with_gap(mut self, gap: usize) -> Self {
+        self.gap = gap;
+        self
+    }
+
+    /// Move children which do not fit to the next line
+    pub fn with_wrap(mut self, wrap: bool) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    /// Add leaf area sized by the constraint
+    pub fn area(mut self, constraint: Constraint) -> Self {
+        self.children.push(LayoutChild::Area(constraint));
+        self
+    }
+
+    /// Add leaf area sized by `TerminalDisplay::size_hint` of the content
+    ///
+    /// Content without size hint takes free space as `Constraint::Fill(1)`.
+    pub fn content(mut self, content: &'a dyn TerminalDisplay) -> Self {
+        self.children.push(LayoutChild::Content(content));
+        self
+    }
+
+    /// Add nested layout sized by the constraint
+    pub fn layout(mut self, constraint: Constraint, layout: Layout<'a>) -> Self {
+        self.children.push(LayoutChild::Layout(constraint, layout));
+        self
+    }
+
+    /// Split area of provided size, returns areas of all leaves
+    pub fn split(&self, size: Size) -> Vec<Rect> {
+        let mut areas = Vec::new();
+        self.split_rect(Rect::new(0, 0, size.height, size.width), &mut areas);
+        areas
+    }
+
+    /// Call function with the index and the view of each leaf area of the surface
+    pub fn for_each_view<S, F, E>(&self, surf: &mut S, mut f: F) -> Result<(), E>
+    where
+        S: SurfaceMut,
+        F: FnMut(usize, SurfaceMutView<'_, S::Item>) -> Result<(), E>,
+    {
+        let size = Size::new(surf.height(), surf.width());
+        for (index, area) in self.split(size).into_iter().enumerate() {
+            f(index, area.view_mut(surf))?;
+        }
+        Ok(())
+    }
+
+    fn split_rect(&self, rect: Rect, areas: &mut Vec<Rect>) {
+        let rect = rect.shrink(self.margin);
+        let rects = if self.wrap {
+            self.place_wrapped(rect)
+        } else {
+            self.place(rect)
+        };
+        for (child, child_rect) in self.children.iter().zip(rects) {
+            match child {
+                LayoutChild::Layout(_, layout) => {
+                    layout.split_rect(child_rect.shrink(self.padding), areas)
+                }
+                _ => areas.push(child_rect.shrink(self.padding)),
+            }
+        }
+    }
+
+    /// Main axis length and cross axis length of the area
+    fn axes(&self, rect: Rect) -> (usize, usize) {
+        match self.direction {
+            Direction::Horizontal => (rect.width, rect.height),
+            Direction::Vertical => (rect.height, rect.width),
+        }
+    }
+
+    /// Child area at `offset` along the main axis with size `(main, cross)`
+    fn child_rect(&self, rect: Rect, offset: (usize, usize), size: (usize, usize)) -> Rect {
+        match self.direction {
+            Direction::Horizontal => {
+                Rect::new(rect.row + offset.1, rect.col + offset.0, size.1, size.0)
+            }
+            Direction::Vertical => {
+                Rect::new(rect.row + offset.0, rect.col + offset.1, size.0, size.1)
+            }
+        }
+    }
+
+    /// Size hint of the content as `(main, cross)` sizes
+    fn content_hint(&self, content: &dyn TerminalDisplay, rect: Rect) -> Option<(usize, usize)> {
+        let hint = content.size_hint(rect.size())?;
+        Some(match self.direction {
+            Direction::Horizontal => (hint.width, hint.height),
+            Direction::Vertical => (hint.height, hint.width),
+        })
+    }
+
+    /// Constraint of the child, content is converted to fixed size
+    fn child_constraint(&self, child: &LayoutChild<'_>, rect: Rect) -> Constraint {
+        match child {
+            LayoutChild::Area(constraint) | LayoutChild::Layout(constraint, _) => *constraint,
+            LayoutChild::Content(content) => match self.content_hint(*content, rect) {
+                Some((main, _)) => Constraint::Fixed(main),
+                None => Constraint::Fill(1),
+            },
+        }
+    }
+
+    /// Place children along a single line
+    fn place(&self, rect: Rect) -> Vec<Rect> {
+        let (main, cross) = self.axes(rect);
+        let gaps = self
+            .gap
+            .saturating_mul(self.children.len().saturating_sub(1));
+        let length = main.saturating_sub(gaps);
+        let constraints: Vec<_> = self
+            .children
+            .iter()
+            .map(|child| self.child_constraint(child, rect))
+            .collect();
+        let sizes = distribute(&constraints, length);
+        let mut offset = 0;
+        sizes
+            .into_iter()
+            .map(|size| {
+                // children which do not fit are truncated
+                let size = size.min(main.saturating_sub(offset));
+                let child = self.child_rect(rect, (offset.min(main), 0), (size, cross));
+                offset = offset.saturating_add(size).saturating_add(self.gap);
+                child
+            })
+            .collect()
+    }
+
+    /// Place children along multiple lines, moving children that do not fit to the next line
+    fn place_wrapped(&self, rect: Rect) -> Vec<Rect> {
+        let (main, cross) = self.axes(rect);
+        let mut rects = Vec::with_capacity(self.children.len());
+        let mut offset = 0;
+        let mut line_offset: usize = 0;
+        let mut line_size = 0;
+        for child in self.children.iter() {
+            let (size, cross_size) = match child {
+                LayoutChild::Content(content) => self
+                    .content_hint(*content, rect)
+                    .unwrap_or((main.saturating_sub(offset), 1)),
+                LayoutChild::Area(constraint) | LayoutChild::Layout(constraint, _) => {
+                    match constraint.resolve(main) {
+                        // growing children take the rest of the line
+                        (base, weight) if weight > 0 => {
+                            let rest = main.saturating_sub(offset).min(constraint.limit());
+                            (base.max(rest), 1)
+                        }
+                        (base, _) => (base, 1),
+                    }
+                }
+            };
+            if offset > 0 && offset.saturating_add(size) > main {
+                line_offset = line_offset
+                    .saturating_add(line_size)
+                    .saturating_add(self.gap);
+                line_size = 0;
+                offset = 0;
+            }
+            let size = size.min(main);
+            let cross_size = cross_size.min(cross.saturating_sub(line_offset));
+            rects.push(self.child_rect(rect, (offset, line_offset.min(cross)), (size, cross_size)));
+            line_size = line_size.max(cross_size);
+            offset = offset.saturating_add(size).saturating_add(self.gap);
+        }
+        rects
+    }
+}
+
+impl<'a> fmt::Debug for Layout<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Layout")
+            .field("direction", &self.direction)
+            .field("margin", &self.margin)
+            .field("padding", &self.padding)
+            .field("gap", &self.gap)
+            .field("wrap", &self.wrap)
+            .field("children", &self.children.len())
+            .finish()
+    }
+}
+
+/// Resolve constraints to sizes so they fit the provided length
+///
+/// Free space is distributed between growing constraints proportionally to their
+/// weights, if there is not enough space the last children are truncated.
+fn distribute(constraints: &[Constraint], length: usize) -> Vec<usize> {
+    let (mut sizes, weights): (Vec<_>, Vec<_>) = constraints
+        .iter()
+        .map(|constraint| constraint.resolve(length))
+        .unzip();
+    let used = sizes
+        .iter()
+        .fold(0, |acc: usize, size| acc.saturating_add(*size));
+    let mut free = length.saturating_sub(used);
+    let mut active: Vec<_> = (0..sizes.len())
+        .filter(|index| weights[*index] > 0 && sizes[*index] < constraints[*index].limit())
+        .collect();
+    while free > 0 && !active.is_empty() {
+        let total = active
+            .iter()
+            .fold(0, |acc: usize, index| acc.saturating_add(weights[*index]));
+        let mut used = 0;
+        for index in active.iter().copied() {
+            let share = free.saturating_mul(weights[index]) / total;
+            let share = share.min(constraints[index].limit() - sizes[index]);
+            sizes[index] += share;
+            used += share;
+        }
+        // remainder is given one cell at a time
+        for index in active.iter().copied() {
+            if used >= free {
+                break;
+            }
+            if sizes[index] < constraints[index].limit() {
+                sizes[index] += 1;
+                used += 1;
+            }
+        }
+        free -= used;
+        active.retain(|index| sizes[*index] < constraints[*index].limit());
+        if used == 0 {
+            break;
+        }
+    }
+    sizes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cell, Surface, SurfaceOwned, TerminalSurface, TerminalSurfaceExt};
+    use std::io::Write;
+
+    struct Label(&'static str);
+
+    impl TerminalDisplay for Label {
+        fn display(&self, surf: &mut TerminalSurface<'_>) -> Result<(), Error> {
+            surf.writer().write_all(self.0.as_bytes())?;
+            Ok(())
+        }
+
+        fn size_hint(&self, _surf_size: Size) -> Option<Size> {
+            Some(Size::new(1, self.0.chars().count()))
+        }
+    }
+
+    #[test]
+    fn test_constraint() -> Result<(), Error> {
+        assert_eq!("10".parse::<Constraint>()?, Constraint::Fixed(10));
+        assert_eq!("50%".parse::<Constraint>()?, Constraint::Percent(50));
+        assert_eq!("1/3".parse::<Constraint>()?, Constraint::Ratio(1, 3));
+        assert_eq!("min:2".parse::<Constraint>()?, Constraint::Min(2));
+        assert_eq!("fill".parse::<Constraint>()?, Constraint::Fill(1));
+        assert!("fill:x".parse::<Constraint>().is_err());
+        assert_eq!("Vertical".parse::<Direction>()?, Direction::Vertical);
+        assert_eq!(
+            "fill:18446744073709551615".parse::<Constraint>()?,
+            Constraint::Fill(usize::MAX)
+        );
+
+        use Constraint::*;
+        assert_eq!(
+            distribute(&[Fixed(3), Percent(50), Fill(1)], 20),
+            vec![3, 10, 7]
+        );
+        assert_eq!(distribute(&[Fill(1), Fill(2)], 10), vec![4, 6]);
+        assert_eq!(distribute(&[Max(2), Min(3), Fill(1)], 10), vec![2, 6, 2]);
+        assert_eq!(distribute(&[Ratio(1, 3), Fixed(30)], 12), vec![4, 30]);
+
+        // huge values saturate instead of overflowing
+        let sizes = distribute(&[Fill(usize::MAX), Fill(1)], 10);
+        assert_eq!(sizes.iter().sum::<usize>(), 10);
+        assert_eq!(
+            distribute(
+                &[Percent(usize::MAX), Ratio(usize::MAX, 1), Min(usize::MAX)],
+                10
+            ),
+            vec![usize::MAX / 100, usize::MAX, usize::MAX]
+        );
+        let layout = |wrap| {
+            Layout::horizontal()
+                .with_wrap(wrap)
+                .with_gap(usize::MAX)
+                .area(Fixed(usize::MAX))
+                .area(Fill(usize::MAX))
+                .split(Size::new(2, 10))
+        };
+        assert_eq!(
+            layout(false),
+            vec![Rect::new(0, 0, 2, 10), Rect::new(0, 10, 2, 0)]
+        );
+        assert_eq!(
+            layout(true),
+            vec![Rect::new(0, 0, 1, 10), Rect::new(2, 0, 0, 0)]
+        );
+        assert_eq!(
+            Rect::new(0, 0, 2, 10).shrink(Margin::all(usize::MAX)),
+            Rect::new(2, 10, 0, 0)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_layout() {
+        let layout = Layout::vertical()
+            .with_margin(Margin::all(1))
+            .area(Constraint::Fixed(1))
+            .layout(
+                Constraint::Fill(1),
+                Layout::horizontal()
+                    .with_gap(1)
+                    .area(Constraint::Percent(25))
+                    .area(Constraint::Fill(1)),
+            )
+            .area(Constraint::Fixed(2));
+        assert_eq!(
+            layout.split(Size::new(12, 22)),
+            vec![
+                Rect::new(1, 1, 1, 20),
+                Rect::new(2, 1, 7, 4),
+                Rect::new(2, 6, 7, 15),
+                Rect::new(9, 1, 2, 20),
+            ]
+        );
+
+        // padding and truncation of children which do not fit
+        let layout = Layout::horizontal()
+            .with_padding(Margin::symmetric(0, 1))
+            .area(Constraint::Fixed(4))
+            .area(Constraint::Fixed(4));
+        assert_eq!(
+            layout.split(Size::new(1, 6)),
+            vec![Rect::new(0, 1, 1, 2), Rect::new(0, 5, 1, 0)]
+        );
+    }
+
+    #[test]
+    fn test_layout_content() -> Result<(), Error> {
+        let (open, save, quit) = (Label("open"), Label("save"), Label("quit"));
+
+        // content is sized by its size hint
+        let layout = Layout::horizontal()
+            .content(&open)
+            .area(Constraint::Fill(1))
+            .content(&quit);
+        assert_eq!(
+            layout.split(Size::new(1, 12)),
+            vec![
+                Rect::new(0, 0, 1, 4),
+                Rect::new(0, 4, 1, 4),
+                Rect::new(0, 8, 1, 4),
+            ]
+        );
+
+        // toolbar wraps to the next line
+        let toolbar = Layout::horizontal()
+            .with_wrap(true)
+            .with_gap(1)
+            .content(&open)
+            .content(&save)
+            .content(&quit);
+        assert_eq!(
+            toolbar.split(Size::new(3, 10)),
+            vec![
+                Rect::new(0, 0, 1, 4),
+                Rect::new(0, 5, 1, 4),
+                Rect::new(2, 0, 1, 4),
+            ]
+        );
+
+        // views are passed to the function
+        let items = [&open, &save, &quit];
+        let mut surf = SurfaceOwned::<Cell>::new(3, 10);
+        toolbar.for_each_view(&mut surf, |index, mut view| items[index].display(&mut view))?;
+        let text: String = (0..surf.width())
+            .filter_map(|col| surf.get(2, col)?.character())
+            .collect();
+        assert_eq!(text.trim(), "quit");
+
+        Ok(())
+    }
+}
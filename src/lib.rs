@@ -36,28 +36,45 @@
 #![deny(warnings)]
 
 pub mod automata;
+pub mod canvas;
+mod codec;
 pub mod color;
 pub mod common;
 pub mod decoder;
 pub mod encoder;
 pub mod error;
 pub mod face;
+pub mod font;
+pub mod fuzzy;
 pub mod glyph;
 pub mod image;
 pub mod keys;
+pub mod layout;
 pub mod render;
 pub mod surface;
 pub mod terminal;
 mod unix;
 pub mod widgets;
 
-pub use color::{Blend, Color, ColorLinear, RGBA};
+pub use canvas::{Canvas, CanvasUnits};
+pub use color::{Blend, Color, ColorLinear, OkLab, RGBA};
 pub use error::Error;
 pub use face::{Face, FaceAttrs};
-pub use glyph::{BBox, FillRule, Glyph, Path};
-pub use image::{ColorPalette, Image, ImageHandler, KittyImageHandler, SixelImageHandler};
+pub use font::Font;
+pub use fuzzy::{FuzzyMatch, FuzzyMatcher};
+pub use glyph::{
+    BBox, FillRule, Glyph, GlyphCache, GlyphCacheStats, GlyphLayer, GlyphPaint, IconRegistry, Path,
+};
+pub use image::{
+    AnimatedImage, ColorDistance, ColorPalette, ColorQuantizer, Dither, DitherKernel, DitherMatrix,
+    Image, ImageCrop, ImageEncodePool, ImageFilter, ImageFit, ImageHandler, KittyImageHandler,
+    KittyTransfer, SixelImageHandler,
+};
 pub use keys::{Key, KeyMap, KeyMod, KeyName};
-pub use render::{Cell, TerminalDisplay, TerminalSurface, TerminalSurfaceExt, TerminalWriter};
+pub use layout::{Constraint, Layout, Margin, Rect};
+pub use render::{
+    Cell, ImageMosaic, TerminalDisplay, TerminalSurface, TerminalSurfaceExt, TerminalWriter,
+};
 pub use surface::{
     Shape, Surface, SurfaceIter, SurfaceMut, SurfaceMutIter, SurfaceMutView, SurfaceOwned,
     SurfaceOwnedView, SurfaceView,
@@ -1,10 +1,26 @@
 //! Terminal rendering logic
 use crate::{
-    decoder::Decoder, error::Error, Face, FaceAttrs, Glyph, Image, Position, Size, Surface,
-    SurfaceMut, SurfaceMutIter, SurfaceMutView, SurfaceOwned, Terminal, TerminalCommand,
-    TerminalSize, RGBA,
+    color::{Blend, Color},
+    decoder::Decoder,
+    error::Error,
+    image::{Align, ImageFilter, ImageFit},
+    AnimatedImage, Face, FaceAttrs, Glyph, GlyphCache, Image, ImageCrop, Position, Size, Surface,
+    SurfaceMut, SurfaceMutIter, SurfaceMutView, SurfaceOwned, Terminal, TerminalCaps,
+    TerminalCommand, TerminalSize, RGBA,
+};
+use std::{
+    cmp::{max, min},
+    num::NonZeroUsize,
+    str::FromStr,
+    time::Duration,
+};
+use unicode_width::UnicodeWidthChar;
+
+/// Cell size in pixels assumed for mosaic rendering if terminal does not report it
+const MOSAIC_CELL_SIZE: Size = Size {
+    height: 16,
+    width: 8,
 };
-use std::{cmp::max, collections::HashMap, num::NonZeroUsize};
 
 /// Terminal cell kind
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -17,13 +33,26 @@ enum CellKind {
     Damaged,
 }
 
+/// Part of the image visible through the surface it was drawn on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct ImageClip {
+    /// Number of cells (rows and columns) of the image hidden above and to
+    /// the left of the cell holding the image.
+    offset: Position,
+    /// Maximum number of visible cells
+    size: Size,
+}
+
 /// Terminal cell
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Cell {
     face: Face,
     character: Option<char>,
     image: Option<Image>,
+    image_clip: Option<ImageClip>,
     glyph: Option<Glyph>,
+    /// Time left until the next frame of the animation drawn in the cell
+    animation: Option<Duration>,
     kind: CellKind,
 }
 
@@ -34,7 +63,9 @@ impl Cell {
             face,
             character,
             image: None,
+            image_clip: None,
             glyph: None,
+            animation: None,
             kind: CellKind::Content,
         }
     }
@@ -45,7 +76,9 @@ impl Cell {
             face: Default::default(),
             character: None,
             image: Some(image),
+            image_clip: None,
             glyph: None,
+            animation: None,
             kind: CellKind::Content,
         }
     }
@@ -56,27 +89,58 @@ impl Cell {
             face,
             character: None,
             image: None,
+            image_clip: None,
             glyph: Some(glyph),
+            animation: None,
             kind: CellKind::Content,
         }
     }
 
-    /// Width occupied by cell (can be != 1 for Glyph)
+    /// Create new cell from image which is clipped to `size` cells, with the
+    /// first `offset` rows and columns of the image cells hidden.
+    fn new_image_clipped(image: Image, offset: Position, size: Size) -> Self {
+        Self {
+            image_clip: Some(ImageClip { offset, size }),
+            ..Self::new_image(image)
+        }
+    }
+
+    /// Face of the cell
+    pub fn face(&self) -> Face {
+        self.face
+    }
+
+    /// Character of the cell
+    pub fn character(&self) -> Option<char> {
+        self.character
+    }
+
+    /// Width occupied by cell (can be != 1 for Glyph and wide characters)
     pub fn width(&self) -> NonZeroUsize {
-        let width = self
-            .glyph
-            .as_ref()
-            .map_or_else(|| 1, |g| max(1, g.size().width));
+        let width = match &self.glyph {
+            Some(glyph) => max(1, glyph.size().width),
+            None => max(1, self.char_width()),
+        };
         NonZeroUsize::new(width).expect("zero cell width")
     }
 
+    /// Number of columns the terminal advances when drawing the character of the cell
+    fn char_width(&self) -> usize {
+        match (self.character, &self.image, &self.glyph) {
+            (Some(c), None, None) => char_width(c),
+            _ => 1,
+        }
+    }
+
     /// Create damaged cell
     fn new_damaged() -> Self {
         Self {
             face: Default::default(),
             character: None,
             image: None,
+            image_clip: None,
             glyph: None,
+            animation: None,
             kind: CellKind::Damaged,
         }
     }
@@ -88,12 +152,20 @@ impl Default for Cell {
             face: Default::default(),
             character: None,
             image: None,
+            image_clip: None,
             glyph: None,
+            animation: None,
             kind: CellKind::Content,
         }
     }
 }
 
+/// Number of columns occupied by the character when displayed in the terminal
+pub(crate) fn char_width(c: char) -> usize {
+    // control characters are displayed as a single space
+    UnicodeWidthChar::width(c).unwrap_or(1)
+}
+
 pub type TerminalSurface<'a> = SurfaceMutView<'a, Cell>;
 
 /// Terminal renderer
@@ -112,13 +184,22 @@ pub struct TerminalRenderer {
     /// Current terminal size
     size: TerminalSize,
     /// Cache of rendered glyphs
-    glyph_cache: HashMap<Cell, Image>,
+    glyph_cache: GlyphCache,
+    /// Time left until the next frame of animations drawn in the last frame
+    animation: Option<Duration>,
 }
 
 impl TerminalRenderer {
     /// Create new terminal renderer
     pub fn new<T: Terminal + ?Sized>(term: &mut T, clear: bool) -> Result<Self, Error> {
-        let size = term.size()?;
+        let mut size = term.size()?;
+        if size.pixels.is_empty() && term.capabilities().mosaic.is_some() {
+            // mosaic still needs cell size to rasterize glyphs and layout images
+            size.pixels = Size::new(
+                size.cells.height * MOSAIC_CELL_SIZE.height,
+                size.cells.width * MOSAIC_CELL_SIZE.width,
+            );
+        }
         term.execute(TerminalCommand::Face(Default::default()))?;
         term.execute(TerminalCommand::CursorTo(Position::new(0, 0)))?;
         let mut back = SurfaceOwned::new(size.cells.height, size.cells.width);
@@ -131,10 +212,30 @@ impl TerminalRenderer {
             front: SurfaceOwned::new(size.cells.height, size.cells.width),
             back,
             size,
-            glyph_cache: HashMap::new(),
+            glyph_cache: GlyphCache::shared(),
+            animation: None,
         })
     }
 
+    /// Use provided glyph cache instead of the shared one
+    pub fn with_glyph_cache(mut self, glyph_cache: GlyphCache) -> Self {
+        self.glyph_cache = glyph_cache;
+        self
+    }
+
+    /// Time left until the next frame of animations drawn in the last frame
+    ///
+    /// `Terminal::run_render` wakes up after this time so animations can be redrawn.
+    pub fn animation_timeout(&self) -> Option<Duration> {
+        self.animation
+    }
+
+    /// Glyph cache used by the renderer, its statistics are available with
+    /// [`GlyphCache::stats`]
+    pub fn glyph_cache(&self) -> &GlyphCache {
+        &self.glyph_cache
+    }
+
     /// Clear terminal
     pub fn clear<T: Terminal + ?Sized>(&mut self, term: &mut T) -> Result<(), Error> {
         // erase all images
@@ -170,7 +271,7 @@ impl TerminalRenderer {
     /// Render the current frame
     pub fn frame<T: Terminal + ?Sized>(&mut self, term: &mut T) -> Result<(), Error> {
         // Rasterize all glyphs
-        self.glyphs_reasterize(term.size()?);
+        self.glyphs_reasterize(self.size, term.capabilities());
 
         // Images can overlap and newly rendered image might be erased by erase command
         // addressed to images of the previous frame. That is why we are erasing all images
@@ -181,7 +282,7 @@ impl TerminalRenderer {
                     (Some(front), Some(back)) => (front, back),
                     _ => break,
                 };
-                if front.image != back.image {
+                if front.image != back.image || front.image_clip != back.image_clip {
                     if let Some(img) = &back.image {
                         term.execute(TerminalCommand::ImageErase(
                             img.clone(),
@@ -190,7 +291,7 @@ impl TerminalRenderer {
                     }
                 }
                 // mark all cells effected by the image as damaged
-                if let Some(size) = front.image.as_ref().map(|img| img.size_cells(self.size)) {
+                if let Some((size, _)) = image_region(self.size, front) {
                     let mut view = self
                         .front
                         .view_mut(row..row + size.height, col..col + size.width);
@@ -204,6 +305,15 @@ impl TerminalRenderer {
         for row in 0..self.back.height() {
             let mut col = 0;
             while col < self.back.width() {
+                // cells covered by the wide character are drawn by the terminal along with it
+                let width = self.front.get(row, col).map_or(1, |cell| cell.char_width());
+                if width > 1
+                    && self.front.get(row, col).map(|cell| cell.kind) == Some(CellKind::Content)
+                {
+                    for cell in self.front.view_mut(row, col + 1..col + width).iter_mut() {
+                        cell.kind = CellKind::Ignore;
+                    }
+                }
                 let (front, back) = match (self.front.get(row, col), self.back.get(row, col)) {
                     (Some(front), Some(back)) => (front, back),
                     _ => break,
@@ -224,20 +334,46 @@ impl TerminalRenderer {
                     term.execute(TerminalCommand::CursorTo(self.cursor))?;
                 }
                 // handle image
-                if let Some(image) = front.image.clone() {
-                    let image_changed = front.image != back.image;
-                    // make sure surface under image is not changed
-                    let size = image.size_cells(self.size);
+                if let Some((size, crop)) = image_region(self.size, front) {
+                    let image_changed =
+                        front.image != back.image || front.image_clip != back.image_clip;
+                    let image = front.image.clone().expect("image region without image");
                     let mut view = self
                         .front
                         .view_mut(row..row + size.height, col..col + size.width);
+                    let caps_mosaic = term.capabilities().mosaic;
+                    let mosaic = match caps_mosaic {
+                        Some(mosaic) => Some(mosaic),
+                        // image is being encoded in the background, draw its preview
+                        // instead, image is drawn on one of the next frames once ready.
+                        None if image_changed && !term.image_prepare(&image, crop) => {
+                            Some(ImageMosaic::HalfBlock)
+                        }
+                        None => None,
+                    };
+                    if let Some(mosaic) = mosaic {
+                        image_mosaic(view, &image, crop, mosaic);
+                        if let Some(cell) = self.front.get_mut(row, col) {
+                            cell.image = None; // mosaic might be empty
+                        }
+                        continue;
+                    }
+                    // make sure surface under image is not changed
                     for cell in view.iter_mut() {
                         cell.kind = CellKind::Ignore;
                     }
                     // render image if changed
                     if image_changed {
+                        // image might cover less cells than reserved (e.g. downscaled
+                        // sixel), erase reserved area so previous content is not visible
+                        for offset in 0..size.height {
+                            let pos = Position::new(row + offset, col);
+                            term.execute(TerminalCommand::CursorTo(pos))?;
+                            term.execute(TerminalCommand::EraseChars(size.width))?;
+                        }
+                        term.execute(TerminalCommand::CursorTo(self.cursor))?;
                         // issue render command
-                        term.execute(TerminalCommand::Image(image, Position::new(row, col)))?;
+                        term.execute(TerminalCommand::Image(image, Position::new(row, col), crop))?;
                         // set position large enough so it would trigger position update
                         self.cursor = Position::new(100000, 1000000);
                     }
@@ -264,11 +400,12 @@ impl TerminalRenderer {
                     }
                 } else {
                     term.execute(TerminalCommand::Char(chr))?;
-                    self.cursor.col += 1;
-                    col += 1;
+                    self.cursor.col += width;
+                    col += width;
                 }
             }
         }
+        self.animation = self.front.iter().filter_map(|cell| cell.animation).min();
         // swap buffers
         std::mem::swap(&mut self.front, &mut self.back);
         self.front.clear();
@@ -277,19 +414,36 @@ impl TerminalRenderer {
 
     /// Rasterize all glyphs in the front surface
     ///
-    /// All glyphs are replaced with rasterized image
-    fn glyphs_reasterize(&mut self, term_size: TerminalSize) {
+    /// Glyphs are replaced with their text fallback if terminal does not support
+    /// glyphs, the rest is rasterized only if it can be drawn as image or mosaic.
+    fn glyphs_reasterize(&mut self, term_size: TerminalSize, caps: &TerminalCaps) {
+        if !caps.glyphs {
+            for row in 0..self.front.height() {
+                for col in 0..self.front.width() {
+                    let Some((face, width, text)) = self.front.get(row, col).and_then(|cell| {
+                        let glyph = cell.glyph.as_ref()?;
+                        let text = glyph.fallback()?.to_owned();
+                        Some((cell.face, glyph.size().width, text))
+                    }) else {
+                        continue;
+                    };
+                    let mut chars = text.chars();
+                    for cell in self
+                        .front
+                        .view_mut(row, col..col + max(width, 1))
+                        .iter_mut()
+                    {
+                        *cell = Cell::new(face, Some(chars.next().unwrap_or(' ')));
+                    }
+                }
+            }
+        }
+        if !caps.glyphs && caps.mosaic.is_none() {
+            return;
+        }
         for cell in self.front.iter_mut() {
             if let Some(glyph) = &cell.glyph {
-                let image = match self.glyph_cache.get(cell) {
-                    Some(image) => image.clone(),
-                    None => {
-                        let image = glyph.rasterize(cell.face, term_size);
-                        self.glyph_cache.insert(cell.clone(), image.clone());
-                        image
-                    }
-                };
-                cell.image = Some(image);
+                cell.image = Some(self.glyph_cache.rasterize(glyph, cell.face, term_size));
             }
         }
     }
@@ -314,14 +468,241 @@ impl TerminalRenderer {
     }
 }
 
+/// Visible size in cells and cropped region in pixels of the image stored in the cell
+///
+/// Returns `None` if cell does not contain an image or it is completely clipped,
+/// crop is `None` if the whole image is visible.
+fn image_region(term_size: TerminalSize, cell: &Cell) -> Option<(Size, Option<ImageCrop>)> {
+    let image = cell.image.as_ref()?;
+    let size = image.size_cells(term_size);
+    let clip = match cell.image_clip {
+        None => return Some((size, None)),
+        Some(clip) => clip,
+    };
+    let cell_size = term_size.cell_size();
+    let crop = ImageCrop::new(
+        clip.offset.row * cell_size.height,
+        clip.offset.col * cell_size.width,
+        clip.size.height * cell_size.height,
+        clip.size.width * cell_size.width,
+    )
+    .clamp(image.height(), image.width());
+    if crop.is_empty() {
+        return None;
+    }
+    let size = Size {
+        height: min(
+            size.height.saturating_sub(clip.offset.row),
+            clip.size.height,
+        ),
+        width: min(size.width.saturating_sub(clip.offset.col), clip.size.width),
+    };
+    if crop == ImageCrop::new(0, 0, image.height(), image.width()) {
+        Some((size, None))
+    } else {
+        Some((size, Some(crop)))
+    }
+}
+
+/// Draw image with character mosaic scaled to fit the view
+fn image_mosaic(
+    mut view: TerminalSurface<'_>,
+    img: &Image,
+    crop: Option<ImageCrop>,
+    mosaic: ImageMosaic,
+) {
+    let img = match crop {
+        Some(crop) => img.crop(crop),
+        None => img.clone(),
+    };
+    if view.is_empty() || img.is_empty() {
+        return;
+    }
+    let cell_size = mosaic.cell_size();
+    let img = img.resize(
+        view.height() * cell_size.height,
+        view.width() * cell_size.width,
+        ImageFilter::Box,
+    );
+    view.draw_image_mosaic(&img, mosaic);
+}
+
+/// Character mosaic used to draw images without image protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ImageMosaic {
+    /// Upper half block, 1x2 pixels per cell
+    #[default]
+    HalfBlock,
+    /// Quadrant blocks, 2x2 pixels per cell
+    Quadrant,
+    /// Sextant blocks, 2x3 pixels per cell
+    Sextant,
+    /// Braille patterns, 2x4 pixels per cell
+    Braille,
+}
+
+impl ImageMosaic {
+    /// Number of image pixels covered by a single cell
+    pub fn cell_size(self) -> Size {
+        match self {
+            ImageMosaic::HalfBlock => Size::new(2, 1),
+            ImageMosaic::Quadrant => Size::new(2, 2),
+            ImageMosaic::Sextant => Size::new(3, 2),
+            ImageMosaic::Braille => Size::new(4, 2),
+        }
+    }
+
+    /// Character with foreground pixels set according to row-major `mask`
+    fn character(self, mask: u8) -> char {
+        match self {
+            ImageMosaic::HalfBlock => [' ', '▀', '▄', '█'][mask as usize & 3],
+            ImageMosaic::Quadrant => [
+                ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█',
+            ][mask as usize & 15],
+            ImageMosaic::Sextant => match mask & 63 {
+                0 => ' ',
+                21 => '▌',
+                42 => '▐',
+                63 => '█',
+                mask => {
+                    // U+1FB00 block skips masks that have dedicated block characters
+                    let index = mask as u32 - 1 - (mask > 21) as u32 - (mask > 42) as u32;
+                    char::from_u32(0x1FB00 + index).unwrap_or(' ')
+                }
+            },
+            ImageMosaic::Braille => {
+                // braille dots are numbered column-major with last row added later
+                const DOTS: [u8; 8] = [0x01, 0x08, 0x02, 0x10, 0x04, 0x20, 0x40, 0x80];
+                let bits = (0..8)
+                    .filter(|index| mask & (1 << index) != 0)
+                    .fold(0u32, |bits, index| bits | DOTS[index] as u32);
+                char::from_u32(0x2800 + bits).unwrap_or(' ')
+            }
+        }
+    }
+}
+
+impl FromStr for ImageMosaic {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "half-block" => Ok(ImageMosaic::HalfBlock),
+            "quadrant" => Ok(ImageMosaic::Quadrant),
+            "sextant" => Ok(ImageMosaic::Sextant),
+            "braille" => Ok(ImageMosaic::Braille),
+            _ => Err(Error::ParseError(
+                "ImageMosaic",
+                format!("invalid image mosaic: {}", s),
+            )),
+        }
+    }
+}
+
+/// Split pixels into two colors
+///
+/// Returns foreground, background and mask of the pixels assigned to the foreground.
+fn mosaic_fit(pixels: &[RGBA]) -> (RGBA, RGBA, u8) {
+    let dist = |c0: RGBA, c1: RGBA| -> u32 {
+        c0.0.iter()
+            .zip(c1.0.iter())
+            .map(|(v0, v1)| (*v0 as i32 - *v1 as i32).pow(2) as u32)
+            .sum()
+    };
+    // start from the most distant pair of pixels
+    let mut seeds = (0, 0, 0);
+    for (i, c0) in pixels.iter().enumerate() {
+        for (j, c1) in pixels.iter().enumerate().skip(i + 1) {
+            let d = dist(*c0, *c1);
+            if d > seeds.2 {
+                seeds = (i, j, d);
+            }
+        }
+    }
+    let (mut fg, mut bg) = match pixels.get(seeds.0) {
+        Some(color) if seeds.2 > 0 => (*color, pixels[seeds.1]),
+        Some(color) => return (*color, *color, 0),
+        None => return (RGBA::default(), RGBA::default(), 0),
+    };
+    // refine with a few iterations of 2-means
+    let mut mask = 0;
+    for _ in 0..3 {
+        mask = 0;
+        let mut sums = [[0u32; 5]; 2];
+        for (index, color) in pixels.iter().enumerate() {
+            let cluster = if dist(*color, fg) < dist(*color, bg) {
+                mask |= 1 << index;
+                0
+            } else {
+                1
+            };
+            let sum = &mut sums[cluster];
+            for (s, c) in sum.iter_mut().zip(color.0) {
+                *s += c as u32;
+            }
+            sum[4] += 1;
+        }
+        let mean = |sum: [u32; 5], prev: RGBA| {
+            if sum[4] == 0 {
+                return prev;
+            }
+            let [r, g, b, a, n] = sum;
+            let c = |v: u32| ((v + n / 2) / n) as u8;
+            RGBA::new(c(r), c(g), c(b), c(a))
+        };
+        fg = mean(sums[0], fg);
+        bg = mean(sums[1], bg);
+    }
+    (fg, bg, mask)
+}
+
 /// Terminal surface extension trait
 pub trait TerminalSurfaceExt: SurfaceMut<Item = Cell> {
     /// Draw box
     fn draw_box(&mut self, face: Option<Face>);
     /// Draw image encoded as ascii blocks
     fn draw_image_ascii(&mut self, img: impl Surface<Item = RGBA>);
+    /// Draw image with character mosaic
+    ///
+    /// Each cell covers `mosaic.cell_size()` pixels of the image, which are
+    /// approximated with two colors. Transparent pixels are blended with the
+    /// background of the cell they are drawn over.
+    fn draw_image_mosaic(&mut self, img: impl Surface<Item = RGBA>, mosaic: ImageMosaic);
     /// Draw image
+    ///
+    /// Image is anchored at the top-left cell and clipped by the surface boundaries.
     fn draw_image(&mut self, img: Image);
+    /// Draw image which is partially scrolled out of the surface
+    ///
+    /// First `offset.row` rows and `offset.col` columns of the image cells are
+    /// hidden, the rest is anchored at the top-left cell and clipped by the surface
+    /// boundaries.
+    fn draw_image_offset(&mut self, img: Image, offset: Position);
+    /// Draw image scaled to fit the surface
+    ///
+    /// `cell_size` is the size of the terminal cell in pixels (see `TerminalSize::cell_size`),
+    /// `align` is vertical and horizontal alignment of the image inside the surface.
+    fn draw_image_fit(
+        &mut self,
+        img: &Image,
+        cell_size: Size,
+        fit: ImageFit,
+        align: (Align, Align),
+        filter: ImageFilter,
+    );
+    /// Draw animated image
+    ///
+    /// If terminal plays animations itself (`TerminalCaps::animation`) the whole
+    /// animation is drawn and `None` is returned. Otherwise the frame shown after
+    /// `elapsed` time since the start of the animation is drawn, and the time left
+    /// until the next frame is returned, `Terminal::run_render` wakes up the
+    /// handler after this time so it can draw the next frame.
+    fn draw_animation(
+        &mut self,
+        anim: &AnimatedImage,
+        caps: &TerminalCaps,
+        elapsed: Duration,
+    ) -> Option<Duration>;
     /// Erase surface with face
     fn erase(&mut self, face: Face);
     /// Write object that can be used to add text to the surface
@@ -363,10 +744,107 @@ where
         });
     }
 
+    fn draw_image_mosaic(&mut self, img: impl Surface<Item = RGBA>, mosaic: ImageMosaic) {
+        let cell_size = mosaic.cell_size();
+        let height = img.height().div_ceil(cell_size.height);
+        let width = img.width().div_ceil(cell_size.width);
+        let mut pixels = Vec::with_capacity(cell_size.height * cell_size.width);
+        self.view_mut(..height, ..width)
+            .fill_with(|row, col, cell| {
+                let base = cell.face.bg;
+                pixels.clear();
+                for pixel_row in 0..cell_size.height {
+                    for pixel_col in 0..cell_size.width {
+                        let color = img
+                            .get(
+                                row * cell_size.height + pixel_row,
+                                col * cell_size.width + pixel_col,
+                            )
+                            .copied()
+                            .unwrap_or_default();
+                        pixels.push(color);
+                    }
+                }
+                let (mut fg, mut bg, mut mask) = if mosaic == ImageMosaic::HalfBlock {
+                    (pixels[0], pixels[1], 1)
+                } else {
+                    mosaic_fit(&pixels)
+                };
+                if mosaic == ImageMosaic::Braille {
+                    let swap = match (fg.0[3] < 128, bg.0[3] < 128) {
+                        // dots are drawn with the visible color
+                        (true, false) => true,
+                        (false, true) => false,
+                        // sparse dots look better than sparse gaps
+                        _ => mask.count_ones() * 2 > pixels.len() as u32,
+                    };
+                    if swap {
+                        std::mem::swap(&mut fg, &mut bg);
+                        mask = !mask;
+                    }
+                }
+                let color = |color: RGBA| match base {
+                    Some(base) if color.0[3] < 255 => Some(base.blend(color, Blend::Over)),
+                    None if color.0[3] < 128 => None,
+                    _ => Some(color.with_alpha(1.0)),
+                };
+                let face = Face::new(color(fg), color(bg), FaceAttrs::EMPTY);
+                Cell::new(face, Some(mosaic.character(mask)))
+            });
+    }
+
     fn draw_image(&mut self, img: Image) {
+        self.draw_image_offset(img, Position::new(0, 0))
+    }
+
+    fn draw_image_offset(&mut self, img: Image, offset: Position) {
+        let size = Size::new(self.height(), self.width());
+        if let Some(cell) = self.get_mut(0, 0) {
+            *cell = Cell::new_image_clipped(img, offset, size);
+        }
+    }
+
+    fn draw_image_fit(
+        &mut self,
+        img: &Image,
+        cell_size: Size,
+        fit: ImageFit,
+        align: (Align, Align),
+        filter: ImageFilter,
+    ) {
+        if cell_size.is_empty() {
+            return;
+        }
+        let region = Size::new(
+            self.height() * cell_size.height,
+            self.width() * cell_size.width,
+        );
+        let (img, offset) = img.fit(region, fit, align, filter);
+        if img.is_empty() {
+            return;
+        }
+        // image can only be placed with a cell precision
+        let row = (offset.row + cell_size.height / 2) / cell_size.height;
+        let col = (offset.col + cell_size.width / 2) / cell_size.width;
+        self.view_mut(row.., col..).draw_image(img);
+    }
+
+    fn draw_animation(
+        &mut self,
+        anim: &AnimatedImage,
+        caps: &TerminalCaps,
+        elapsed: Duration,
+    ) -> Option<Duration> {
+        if caps.animation || anim.frames().len() < 2 {
+            self.draw_image(anim.image());
+            return None;
+        }
+        let (frame, timeout) = anim.frame_at(elapsed);
+        self.draw_image(frame.clone());
         if let Some(cell) = self.get_mut(0, 0) {
-            *cell = Cell::new_image(img);
+            cell.animation = Some(timeout);
         }
+        Some(timeout)
     }
 
     fn erase(&mut self, face: Face) {
@@ -517,7 +995,7 @@ mod tests {
     use super::*;
     use crate::{
         encoder::{Encoder, TTYEncoder},
-        terminal::{Size, TerminalEvent, TerminalSize, TerminalWaker},
+        terminal::{Size, TerminalAction, TerminalEvent, TerminalSize, TerminalWaker},
         TerminalCaps,
     };
     use std::io::Write;
@@ -556,6 +1034,9 @@ mod tests {
         cmds: Vec<TerminalCommand>,
         buffer: Vec<u8>,
         capabiliets: TerminalCaps,
+        image_ready: bool,
+        /// timeouts passed to poll
+        polls: Vec<Option<Duration>>,
     }
 
     impl DummyTerminal {
@@ -571,6 +1052,8 @@ mod tests {
                 cmds: Default::default(),
                 buffer: Default::default(),
                 capabiliets: TerminalCaps::default(),
+                image_ready: true,
+                polls: Vec::new(),
             }
         }
 
@@ -598,8 +1081,9 @@ mod tests {
 
         fn poll(
             &mut self,
-            _timeout: Option<std::time::Duration>,
+            timeout: Option<std::time::Duration>,
         ) -> Result<Option<TerminalEvent>, Error> {
+            self.polls.push(timeout);
             Ok(None)
         }
 
@@ -624,6 +1108,10 @@ mod tests {
         fn capabilities(&self) -> &TerminalCaps {
             &self.capabiliets
         }
+
+        fn image_prepare(&mut self, _img: &crate::Image, _crop: Option<ImageCrop>) -> bool {
+            self.image_ready
+        }
     }
 
     #[test]
@@ -766,4 +1254,373 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_render_wide_char() -> Result<(), Error> {
+        use TerminalCommand::*;
+
+        let mut term = DummyTerminal::new(1, 5);
+        let mut render = TerminalRenderer::new(&mut term, false)?;
+        let mut view = render.view();
+        let mut writer = view.writer();
+        for c in "漢字x".chars() {
+            writer.put(Cell::new(Default::default(), Some(c)));
+        }
+        render.frame(&mut term)?;
+        // second column of wide character is not drawn
+        assert_eq!(
+            term.cmds,
+            vec![
+                Face(Default::default()),
+                CursorTo(Position::new(0, 0)),
+                Char('漢'),
+                Char('字'),
+                Char('x'),
+            ]
+        );
+        term.clear();
+
+        // unchanged wide characters are not redrawn
+        let mut view = render.view();
+        let mut writer = view.writer();
+        for c in "漢ab".chars() {
+            writer.put(Cell::new(Default::default(), Some(c)));
+        }
+        render.frame(&mut term)?;
+        assert_eq!(
+            term.cmds,
+            vec![
+                CursorTo(Position::new(0, 2)),
+                Char('a'),
+                Char('b'),
+                Char(' ')
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_animation() -> Result<(), Error> {
+        let frame = |color| Image::new(SurfaceOwned::new_with(2, 2, |_, _| color));
+        let anim = AnimatedImage::new([
+            (frame(RGBA::new(255, 0, 0, 255)), Duration::from_millis(100)),
+            (frame(RGBA::new(0, 0, 255, 255)), Duration::from_millis(100)),
+        ])?;
+        let mut term = DummyTerminal::new(3, 3);
+        let mut calls = 0;
+        term.run_render(|term, _event, mut view| -> Result<_, Error> {
+            calls += 1;
+            if calls > 1 {
+                return Ok(TerminalAction::Quit(()));
+            }
+            let caps = term.capabilities().clone();
+            view.draw_animation(&anim, &caps, Duration::from_millis(30));
+            Ok(TerminalAction::Wait)
+        })?;
+        // handler is woken up to draw the next frame
+        assert_eq!(
+            term.polls,
+            vec![Some(Duration::ZERO), Some(Duration::from_millis(70))]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_image_pending() -> Result<(), Error> {
+        use TerminalCommand::*;
+
+        let mut term = DummyTerminal::new(4, 6);
+        term.size.pixels = Size::new(40, 60); // 10x10 cell
+        let img = crate::Image::new(SurfaceOwned::new_with(20, 20, |_, _| {
+            RGBA::new(1, 2, 3, 255)
+        }));
+        let mut render = TerminalRenderer::new(&mut term, false)?;
+
+        // image is not ready, preview is rendered instead
+        term.image_ready = false;
+        render.view().view_mut(1.., 1..).draw_image(img.clone());
+        render.frame(&mut term)?;
+        assert!(!term.cmds.iter().any(|cmd| matches!(cmd, Image(..))));
+        assert_eq!(
+            term.cmds
+                .iter()
+                .filter(|cmd| matches!(cmd, Char('\u{2580}')))
+                .count(),
+            4
+        );
+        term.clear();
+
+        // image is ready, it is rendered on the next frame
+        term.image_ready = true;
+        render.view().view_mut(1.., 1..).draw_image(img.clone());
+        render.frame(&mut term)?;
+        let index = term
+            .cmds
+            .iter()
+            .position(|cmd| cmd == &Image(img.clone(), Position::new(1, 1), None))
+            .expect("image is not rendered");
+
+        // reserved area is erased before the image is drawn
+        assert_eq!(
+            term.cmds[index - 5..index],
+            [
+                CursorTo(Position::new(1, 1)),
+                EraseChars(2),
+                CursorTo(Position::new(2, 1)),
+                EraseChars(2),
+                CursorTo(Position::new(1, 1)),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_mosaic() -> Result<(), Error> {
+        assert_eq!(ImageMosaic::Quadrant.character(0b1001), '▚');
+        assert_eq!(ImageMosaic::Sextant.character(1), '\u{1fb00}');
+        assert_eq!(ImageMosaic::Sextant.character(22), '\u{1fb14}');
+        assert_eq!(ImageMosaic::Sextant.character(62), '\u{1fb3b}');
+        assert_eq!(ImageMosaic::Sextant.character(42), '▐');
+        assert_eq!(ImageMosaic::Braille.character(0b1000_0101), '⢃');
+        assert_eq!("braille".parse::<ImageMosaic>()?, ImageMosaic::Braille);
+
+        let red = RGBA::new(255, 0, 0, 255);
+        let blue = RGBA::new(0, 0, 255, 255);
+        let img = SurfaceOwned::new_with(3, 4, |row, col| {
+            if (row == 0 && col < 2) || col == 3 {
+                red
+            } else {
+                blue
+            }
+        });
+        let mut surf = SurfaceOwned::new(1, 2);
+        surf.draw_image_mosaic(&img, ImageMosaic::Sextant);
+        let cell = surf.get(0, 0).unwrap();
+        assert_eq!(cell.character, Some('\u{1fb02}'));
+        assert_eq!(cell.face.fg, Some(red));
+        assert_eq!(cell.face.bg, Some(blue));
+        let cell = surf.get(0, 1).unwrap();
+        assert_eq!(cell.character, Some('▌'));
+        assert_eq!(cell.face.fg, Some(blue));
+        assert_eq!(cell.face.bg, Some(red));
+
+        // transparent pixels are blended with the cell background
+        let img = SurfaceOwned::new_with(
+            2,
+            2,
+            |row, _| {
+                if row == 0 {
+                    red
+                } else {
+                    RGBA::new(0, 0, 0, 0)
+                }
+            },
+        );
+        let mut surf = SurfaceOwned::new(1, 1);
+        surf.draw_image_mosaic(&img, ImageMosaic::Quadrant);
+        let cell = surf.get(0, 0).unwrap();
+        assert_eq!(cell.character, Some('▀'));
+        assert_eq!(cell.face.bg, None);
+        surf.fill(Cell::new(Face::default().with_bg(Some(blue)), None));
+        surf.draw_image_mosaic(&img, ImageMosaic::Quadrant);
+        assert_eq!(surf.get(0, 0).unwrap().face.bg, Some(blue));
+
+        // braille dots use the opaque color even if it covers most of the cell
+        let img = SurfaceOwned::new_with(
+            4,
+            2,
+            |row, _| {
+                if row < 3 {
+                    red
+                } else {
+                    RGBA::new(0, 0, 0, 0)
+                }
+            },
+        );
+        let mut surf = SurfaceOwned::new(1, 1);
+        surf.draw_image_mosaic(&img, ImageMosaic::Braille);
+        let cell = surf.get(0, 0).unwrap();
+        assert_eq!(cell.character, Some('⠿'));
+        assert_eq!(cell.face.fg, Some(red));
+        assert_eq!(cell.face.bg, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_mosaic() -> Result<(), Error> {
+        use TerminalCommand::*;
+
+        let mut term = DummyTerminal::new(4, 6);
+        term.capabiliets.mosaic = Some(ImageMosaic::Quadrant);
+        let img = crate::Image::new(SurfaceOwned::new_with(32, 16, |_, col| {
+            if col % 8 < 4 {
+                RGBA::new(255, 0, 0, 255)
+            } else {
+                RGBA::new(0, 0, 255, 255)
+            }
+        }));
+        // terminal does not report pixel size, cell size is assumed
+        let mut render = TerminalRenderer::new(&mut term, false)?;
+        render.view().view_mut(1.., 1..).draw_image(img);
+        render.frame(&mut term)?;
+        assert!(!term.cmds.iter().any(|cmd| matches!(cmd, Image(..))));
+        assert_eq!(
+            term.cmds
+                .iter()
+                .filter(|cmd| matches!(cmd, Char('▌')))
+                .count(),
+            4
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_glyph_fallback() -> Result<(), Error> {
+        use TerminalCommand::*;
+
+        let mut term = DummyTerminal::new(2, 6);
+        let path: crate::Path = "M1,1 h18 v18 h-18 Z".parse().unwrap();
+        let glyph = Glyph::new(path, Default::default(), None, Size::new(1, 3)).with_fallback("[]");
+        let face = crate::Face::default().with_fg(Some(RGBA::new(1, 2, 3, 255)));
+        let mut render = TerminalRenderer::new(&mut term, false)?;
+        let mut view = render.view();
+        let mut writer = view.writer();
+        writer.put(Cell::new_glyph(face, glyph.clone()));
+        writer.put_char('x', crate::Face::default());
+        render.frame(&mut term)?;
+        assert!(!term.cmds.iter().any(|cmd| matches!(cmd, Image(..))));
+        assert_eq!(
+            term.cmds,
+            vec![
+                Face(Default::default()),
+                CursorTo(Position::new(0, 0)),
+                Face(face),
+                Char('['),
+                Char(']'),
+                Char(' '),
+                Face(Default::default()),
+                Char('x'),
+            ]
+        );
+        term.clear();
+
+        // glyph without fallback is not rasterized if it can not be drawn
+        let cache = GlyphCache::new(1 << 20);
+        let path: crate::Path = "M1,1 h18 v18 h-18 Z".parse().unwrap();
+        let plain = Glyph::new(path, Default::default(), None, Size::new(1, 3));
+        let mut render = TerminalRenderer::new(&mut term, false)?.with_glyph_cache(cache.clone());
+        render.view().writer().put(Cell::new_glyph(face, plain));
+        render.frame(&mut term)?;
+        assert!(!term.cmds.iter().any(|cmd| matches!(cmd, Image(..))));
+        assert_eq!(cache.stats().misses, 0);
+        term.clear();
+
+        // glyph is rendered as an image if terminal supports it
+        term.capabiliets.glyphs = true;
+        term.size.pixels = Size::new(20, 60);
+        let mut render = TerminalRenderer::new(&mut term, false)?;
+        render.view().writer().put(Cell::new_glyph(face, glyph));
+        render.frame(&mut term)?;
+        assert!(term.cmds.iter().any(|cmd| matches!(cmd, Image(..))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_glyph_cache() -> Result<(), Error> {
+        let mut term = DummyTerminal::new(2, 6);
+        term.capabiliets.glyphs = true;
+        term.size.pixels = Size::new(20, 60);
+        let path: crate::Path = "M1,1 h18 v18 h-18 Z".parse().unwrap();
+        let glyph = Glyph::new(path, Default::default(), None, Size::new(1, 2));
+        let cache = GlyphCache::new(1 << 20);
+
+        let mut render = TerminalRenderer::new(&mut term, false)?.with_glyph_cache(cache.clone());
+        render
+            .view()
+            .writer()
+            .put(Cell::new_glyph(Face::default(), glyph.clone()));
+        render.frame(&mut term)?;
+        assert_eq!(cache.stats().misses, 1);
+        assert_eq!(cache.stats().hits, 0);
+
+        // recreated renderer with the same cell size reuses rasterized glyph
+        term.size = TerminalSize {
+            cells: Size::new(4, 12),
+            pixels: Size::new(40, 120),
+        };
+        let mut render = TerminalRenderer::new(&mut term, true)?.with_glyph_cache(cache.clone());
+        render
+            .view()
+            .writer()
+            .put(Cell::new_glyph(Face::default(), glyph));
+        render.frame(&mut term)?;
+        assert_eq!(cache.stats().misses, 1);
+        assert_eq!(cache.stats().hits, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_image_clip() -> Result<(), Error> {
+        use TerminalCommand::*;
+
+        let mut term = DummyTerminal::new(4, 6);
+        term.size.pixels = Size::new(40, 60); // 10x10 cell
+        let img = crate::Image::new(SurfaceOwned::new_with(30, 20, |_, _| {
+            RGBA::new(1, 2, 3, 255)
+        }));
+
+        // image fits into the view
+        let mut render = TerminalRenderer::new(&mut term, false)?;
+        render.view().view_mut(1.., 1..).draw_image(img.clone());
+        render.frame(&mut term)?;
+        assert!(term
+            .cmds
+            .contains(&Image(img.clone(), Position::new(1, 1), None)));
+        term.clear();
+
+        // clipped at the bottom and the right edges
+        let mut render = TerminalRenderer::new(&mut term, false)?;
+        render.view().view_mut(2.., 5..).draw_image(img.clone());
+        render.frame(&mut term)?;
+        assert!(term.cmds.contains(&Image(
+            img.clone(),
+            Position::new(2, 5),
+            Some(ImageCrop::new(0, 0, 20, 10)),
+        )));
+        term.clear();
+
+        // clipped at the top and the left edges
+        let mut render = TerminalRenderer::new(&mut term, false)?;
+        render.view().fill(Cell::new(Default::default(), Some('x')));
+        render
+            .view()
+            .draw_image_offset(img.clone(), Position::new(2, 1));
+        render.frame(&mut term)?;
+        assert!(term.cmds.contains(&Image(
+            img.clone(),
+            Position::new(0, 0),
+            Some(ImageCrop::new(20, 10, 10, 10)),
+        )));
+        // only visible part of the image is ignored
+        assert!(term.cmds.contains(&CursorTo(Position::new(0, 1))));
+        assert!(term.cmds.contains(&CursorTo(Position::new(1, 0))));
+        term.clear();
+
+        // completely scrolled out image is not rendered
+        render
+            .view()
+            .draw_image_offset(img.clone(), Position::new(3, 0));
+        render.frame(&mut term)?;
+        assert!(!term.cmds.iter().any(|cmd| matches!(cmd, Image(..))));
+        assert!(term
+            .cmds
+            .contains(&ImageErase(img, Some(Position::new(0, 0)))));
+
+        Ok(())
+    }
 }
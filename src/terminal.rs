@@ -2,7 +2,8 @@
 use crate::{
     encoder::ColorDepth,
     error::Error,
-    render::{TerminalRenderer, TerminalSurface, TerminalSurfaceExt},
+    image::ImageCrop,
+    render::{ImageMosaic, TerminalRenderer, TerminalSurface, TerminalSurfaceExt},
     Face, Image, Key, KeyMod, KeyName, RGBA,
 };
 use serde::{Deserialize, Serialize};
@@ -100,7 +101,8 @@ pub trait Terminal: Write + Send {
                     // allocate new renderer on resize
                     if let Some(TerminalEvent::Resize(_)) = event {
                         renderer.clear(self)?;
-                        renderer = TerminalRenderer::new(self, true)?;
+                        let glyph_cache = renderer.glyph_cache().clone();
+                        renderer = TerminalRenderer::new(self, true)?.with_glyph_cache(glyph_cache);
                     }
                     // handle event
                     let action = handler(self, event, renderer.view())?;
@@ -126,6 +128,10 @@ pub trait Terminal: Write + Send {
                         TerminalAction::Wait => None,
                         TerminalAction::Sleep(timeout) => Some(timeout),
                     };
+                    // wake up to draw the next frame of animations
+                    if let Some(animation) = renderer.animation_timeout() {
+                        timeout = Some(timeout.map_or(animation, |t| t.min(animation)));
+                    }
                 }
             }
         }
@@ -142,6 +148,14 @@ pub trait Terminal: Write + Send {
 
     /// Get terminal capabilities
     fn capabilities(&self) -> &TerminalCaps;
+
+    /// Prepare image to be drawn
+    ///
+    /// False means image is being encoded in the background, and
+    /// `TerminalEvent::Wake` is going to be issued once it is ready.
+    fn image_prepare(&mut self, _img: &Image, _crop: Option<ImageCrop>) -> bool {
+        true
+    }
 }
 
 impl<'a, T: Terminal + ?Sized> Terminal for &'a mut T {
@@ -176,6 +190,10 @@ impl<'a, T: Terminal + ?Sized> Terminal for &'a mut T {
     fn capabilities(&self) -> &TerminalCaps {
         (**self).capabilities()
     }
+
+    fn image_prepare(&mut self, img: &Image, crop: Option<ImageCrop>) -> bool {
+        (**self).image_prepare(img, crop)
+    }
 }
 
 /// Terminal capabilities
@@ -184,6 +202,10 @@ pub struct TerminalCaps {
     pub depth: ColorDepth,
     pub glyphs: bool,
     pub kitty_keyboard: bool,
+    /// Animated images are played by the terminal itself
+    pub animation: bool,
+    /// Images and glyphs are drawn with character mosaic instead of image protocol
+    pub mosaic: Option<ImageMosaic>,
 }
 
 impl Default for TerminalCaps {
@@ -192,6 +214,8 @@ impl Default for TerminalCaps {
             depth: ColorDepth::EightBit,
             glyphs: false,
             kitty_keyboard: false,
+            animation: false,
+            mosaic: None,
         }
     }
 }
@@ -273,8 +297,8 @@ pub enum TerminalCommand {
     ScrollRegion { start: usize, end: usize },
     /// Full reset of the terminal
     Reset,
-    /// Draw image
-    Image(Image, Position),
+    /// Draw image, optionally showing only the cropped region of it
+    Image(Image, Position, Option<ImageCrop>),
     /// Erase image
     ImageErase(Image, Option<Position>),
     /// Request Termcap/Terminfo XTGETTCAP
@@ -423,6 +447,14 @@ pub enum TerminalEvent {
     Termcap(BTreeMap<String, Option<String>>),
     /// Terminal Attributes DA1 response
     DeviceAttrs(BTreeSet<usize>),
+    /// XTSMGRAPHICS response (`item` 1 - color registers, 2 - sixel geometry)
+    GraphicsAttrs {
+        item: usize,
+        status: usize,
+        values: Vec<usize>,
+    },
+    /// Sixel image (for example, from the output of a child process)
+    Image(Image),
     /// Unrecognized bytes (TODO: remove Vec and just use u8)
     Raw(Vec<u8>),
     /// Color
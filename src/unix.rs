@@ -2,7 +2,10 @@
 use crate::common::{env_cfg, IOQueue};
 use crate::decoder::KEYBOARD_LEVEL;
 use crate::encoder::ColorDepth;
-use crate::image::ImageHandlerKind;
+use crate::image::{
+    ColorDistance, ColorQuantizer, Dither, ImageEncodePool, ImageHandlerKind, KittyImageHandler,
+    KittyMedium, KittyTransfer, SixelImageHandler,
+};
 use crate::{
     decoder::{Decoder, TTYDecoder},
     encoder::{Encoder, TTYEncoder},
@@ -13,7 +16,7 @@ use crate::{
     },
     DecMode, ImageHandler,
 };
-use crate::{TerminalCaps, RGBA};
+use crate::{render::ImageMosaic, Image, ImageCrop, TerminalCaps, RGBA};
 use signal_hook::{
     consts::{SIGINT, SIGQUIT, SIGTERM, SIGWINCH},
     iterator::{backend::SignalDelivery, exfiltrator::SignalOnly},
@@ -155,6 +158,14 @@ impl UnixTerminal {
         &mut self.image_handler
     }
 
+    /// Expect sixel image in the input, should be called after sending a query
+    /// which is answered with sixel image. Image is limited to the terminal size.
+    pub fn sixel_expect(&mut self) -> Result<(), Error> {
+        let max_size = self.size()?.pixels;
+        self.decoder.sixel_expect(max_size);
+        Ok(())
+    }
+
     /// Determine terminal size with ioctl
     ///
     /// Some terminal emulators do not set pixel size, or if it goes through some
@@ -238,6 +249,14 @@ impl UnixTerminal {
     }
 }
 
+/// Image ids used to probe kitty local transfer mediums
+const KITTY_PROBE_SHM_ID: u64 = 32;
+const KITTY_PROBE_TEMPFILE_ID: u64 = 33;
+const KITTY_PROBE_FILE_ID: u64 = 34;
+
+/// Maximum number of threads used to encode images in the background
+const IMAGE_ENCODE_WORKERS: usize = 4;
+
 /// Fallback way to determine terminal size if it is detected to work
 /// and ioctl is not.
 const GET_TERM_SIZE: &[u8] = b"\x1b[18t\x1b[14t";
@@ -248,6 +267,7 @@ fn capabilities_detect(term: &mut UnixTerminal) -> Result<(), Error> {
         // do not try to query anything on dumb terminals
         warn!("[detected] dump terminal");
         term.capabilities.depth = ColorDepth::Gray;
+        term.capabilities.mosaic = Some(ImageMosaic::HalfBlock);
         term.encoder = TTYEncoder::new(term.capabilities.clone());
         return Ok(());
     }
@@ -264,6 +284,29 @@ fn capabilities_detect(term: &mut UnixTerminal) -> Result<(), Error> {
     // 1x1 pixel kitty image (NOTE: it will be consumed by handler if it is already set)
     write!(term, "\x1b_Ga=q,i=31,s=1,v=1,f=24;AAAA\x1b\\")?;
 
+    // Kitty local transfer mediums, succeeds only if terminal is able to read them
+    let mut kitty_probes = Vec::new();
+    for (id, transfer) in [
+        (KITTY_PROBE_SHM_ID, KittyTransfer::SharedMemory),
+        (KITTY_PROBE_TEMPFILE_ID, KittyTransfer::TempFile),
+        (KITTY_PROBE_FILE_ID, KittyTransfer::File),
+    ] {
+        match KittyMedium::create(transfer, &[0, 0, 0]) {
+            Ok(medium) => {
+                write!(
+                    term,
+                    "\x1b_Ga=q,i={},s=1,v=1,f=24,t={};",
+                    id,
+                    transfer.key()
+                )?;
+                term.write_all(&medium.payload()?)?;
+                write!(term, "\x1b\\")?;
+                kitty_probes.push((id, transfer, medium));
+            }
+            Err(error) => debug!("failed to create kitty {:?} medium: {:?}", transfer, error),
+        }
+    }
+
     // OSC - Get default background color for transparent blending
     write!(term, "\x1b]11;?\x1b\\")?;
 
@@ -281,6 +324,10 @@ fn capabilities_detect(term: &mut UnixTerminal) -> Result<(), Error> {
     // Detect kitty keyboard protocol support
     write!(term, "\x1b[?u")?;
 
+    // XTSMGRAPHICS - number of sixel color registers and maximum geometry
+    write!(term, "\x1b[?1;1;0S")?;
+    write!(term, "\x1b[?2;1;0S")?;
+
     // DA1 - sync and sixel info
     // Device Attribute command is used as "sync" event, it is supported
     // by most terminals, at least in its basic form, so we expect to
@@ -288,13 +335,25 @@ fn capabilities_detect(term: &mut UnixTerminal) -> Result<(), Error> {
     write!(term, "\x1b[c")?;
 
     let mut image_handlers = HashSet::new();
+    let mut kitty_transfers = HashSet::new();
     let mut bg: Option<RGBA> = None;
+    let mut color_registers: Option<usize> = None;
+    let mut sixel_geometry: Option<Size> = None;
     let mut size_escape = TerminalSize::default();
     loop {
         match term.poll(Some(Duration::from_secs(1)))? {
-            Some(TerminalEvent::KittyImage { .. }) => {
-                debug!("[detected] kitty image protocol");
-                image_handlers.insert(ImageHandlerKind::Kitty);
+            Some(TerminalEvent::KittyImage { id, error }) => {
+                match kitty_probes.iter().find(|(probe_id, ..)| *probe_id == id) {
+                    Some((_, transfer, _)) if error.is_none() => {
+                        debug!("[detected] kitty {:?} transfer", transfer);
+                        kitty_transfers.insert(*transfer);
+                    }
+                    Some(_) => {}
+                    None => {
+                        debug!("[detected] kitty image protocol");
+                        image_handlers.insert(ImageHandlerKind::Kitty);
+                    }
+                }
             }
             Some(TerminalEvent::Color { color, .. }) => {
                 debug!("[detected] background color: {:?}", color);
@@ -314,6 +373,24 @@ fn capabilities_detect(term: &mut UnixTerminal) -> Result<(), Error> {
                 }
                 break; // this is last "sync" event
             }
+            Some(TerminalEvent::GraphicsAttrs {
+                item: 1,
+                status: 0,
+                values,
+            }) => {
+                debug!("[detected] sixel color registers: {:?}", values);
+                color_registers = values.first().copied();
+            }
+            Some(TerminalEvent::GraphicsAttrs {
+                item: 2,
+                status: 0,
+                values,
+            }) => {
+                debug!("[detected] sixel geometry: {:?}", values);
+                if let [width, height, ..] = values[..] {
+                    sixel_geometry = Some(Size { height, width });
+                }
+            }
             Some(TerminalEvent::Size(size)) => {
                 size_escape = size;
             }
@@ -332,6 +409,12 @@ fn capabilities_detect(term: &mut UnixTerminal) -> Result<(), Error> {
     // drain terminal
     term.drain().count();
 
+    // remove probe mediums which have not been consumed by the terminal, `t=f`
+    // files are never removed by the terminal
+    for (_, _, medium) in kitty_probes {
+        medium.remove();
+    }
+
     // color depth
     if let Some(depth) = env_cfg::<ColorDepth>("depth") {
         caps.depth = depth;
@@ -345,11 +428,63 @@ fn capabilities_detect(term: &mut UnixTerminal) -> Result<(), Error> {
     }
 
     // image handler
-    let image_handler = env_cfg::<ImageHandlerKind>("image")
+    let image_handler_kind = env_cfg::<ImageHandlerKind>("image")
         .or_else(|| image_handlers.get(&ImageHandlerKind::Kitty).copied())
         .or_else(|| image_handlers.get(&ImageHandlerKind::Sixel).copied())
-        .unwrap_or(ImageHandlerKind::Dummy)
-        .into_image_handler(bg);
+        .unwrap_or(ImageHandlerKind::Dummy);
+    // background image encoding, disabled with `image-workers=0`
+    let encode_workers = env_cfg::<usize>("image-workers").unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map_or(1, |count| count.get())
+            .min(IMAGE_ENCODE_WORKERS)
+    });
+    let encode_pool = if encode_workers > 0 {
+        Some(ImageEncodePool::new(encode_workers, term.waker())?)
+    } else {
+        None
+    };
+    let image_handler: Box<dyn ImageHandler> = match image_handler_kind {
+        ImageHandlerKind::Kitty => {
+            let transfer = env_cfg::<KittyTransfer>("kitty-transfer")
+                .or_else(|| kitty_transfers.get(&KittyTransfer::SharedMemory).copied())
+                .or_else(|| kitty_transfers.get(&KittyTransfer::TempFile).copied())
+                .or_else(|| kitty_transfers.get(&KittyTransfer::File).copied())
+                .unwrap_or(KittyTransfer::Direct);
+            debug!("kitty transfer: {:?}", transfer);
+            let mut handler = KittyImageHandler::new().with_transfer(transfer);
+            if let Some(pool) = encode_pool {
+                handler = handler.with_encode_pool(pool);
+            }
+            Box::new(handler)
+        }
+        ImageHandlerKind::Sixel => {
+            let mut handler = SixelImageHandler::new(bg);
+            if let Some(palette_size) = env_cfg::<usize>("sixel-colors") {
+                handler = handler.with_palette_size(palette_size);
+            }
+            if let Some(color_registers) = color_registers {
+                handler = handler.with_color_registers(color_registers);
+            }
+            if let Some(max_size) = sixel_geometry {
+                handler = handler.with_max_size(max_size);
+            }
+            if let Some(quantizer) = env_cfg::<ColorQuantizer>("quantizer") {
+                handler = handler.with_quantizer(quantizer);
+            }
+            if let Some(distance) = env_cfg::<ColorDistance>("color-distance") {
+                handler = handler.with_distance(distance);
+            }
+            if let Some(dither) = env_cfg::<Dither>("dither") {
+                handler = handler.with_dither(dither);
+            }
+            if let Some(pool) = encode_pool {
+                handler = handler.with_encode_pool(pool);
+            }
+            debug!("sixel palette size: {}", handler.palette_size());
+            Box::new(handler)
+        }
+        kind => kind.into_image_handler(bg),
+    };
 
     // glyph support
     caps.glyphs = matches!(
@@ -357,6 +492,13 @@ fn capabilities_detect(term: &mut UnixTerminal) -> Result<(), Error> {
         ImageHandlerKind::Kitty | ImageHandlerKind::Sixel
     ) && !term.size()?.pixels.is_empty();
 
+    // animation support
+    caps.animation = image_handler.kind() == ImageHandlerKind::Kitty;
+
+    // character mosaic fallback for images and glyphs
+    caps.mosaic = env_cfg::<ImageMosaic>("mosaic")
+        .or_else(|| (image_handler.kind() == ImageHandlerKind::Dummy).then(mosaic_detect));
+
     // update terminal
     info!("capabilities: {:?}", caps);
     term.encoder = TTYEncoder::new(caps.clone());
@@ -366,6 +508,20 @@ fn capabilities_detect(term: &mut UnixTerminal) -> Result<(), Error> {
     Ok(())
 }
 
+/// Best character mosaic supported by the terminal font
+fn mosaic_detect() -> ImageMosaic {
+    let term = std::env::var("TERM").unwrap_or_default();
+    let term_program = std::env::var("TERM_PROGRAM").unwrap_or_default();
+    let sextant = ["kitty", "foot", "wezterm", "contour"]
+        .iter()
+        .any(|name| term.contains(name) || term_program.to_ascii_lowercase().contains(name));
+    if sextant {
+        ImageMosaic::Sextant
+    } else {
+        ImageMosaic::Quadrant
+    }
+}
+
 impl std::ops::Drop for UnixTerminal {
     fn drop(&mut self) {
         self.dispose().unwrap_or(())
@@ -486,7 +642,7 @@ impl Terminal for UnixTerminal {
                             self.events_queue.push_back(TerminalEvent::Resize(size));
                         }
                     }
-                    if !self.image_handler.handle(&event)? {
+                    if !self.image_handler.handle(&mut self.write_queue, &event)? {
                         self.events_queue.push_back(event)
                     }
                 }
@@ -506,8 +662,9 @@ impl Terminal for UnixTerminal {
     fn execute(&mut self, cmd: TerminalCommand) -> Result<(), Error> {
         tracing::trace!(?cmd, "execute");
         match cmd {
-            TerminalCommand::Image(img, pos) => {
-                self.image_handler.draw(&mut self.write_queue, &img, pos)
+            TerminalCommand::Image(img, pos, crop) => {
+                self.image_handler
+                    .draw(&mut self.write_queue, &img, pos, crop)
             }
             TerminalCommand::ImageErase(img, pos) => {
                 self.image_handler.erase(&mut self.write_queue, &img, pos)
@@ -542,6 +699,10 @@ impl Terminal for UnixTerminal {
     fn capabilities(&self) -> &TerminalCaps {
         &self.capabilities
     }
+
+    fn image_prepare(&mut self, img: &Image, crop: Option<ImageCrop>) -> bool {
+        self.image_handler.prepare(img, crop)
+    }
 }
 
 fn guard_io<T>(result: Result<T, std::io::Error>, otherwise: T) -> Result<T, std::io::Error> {
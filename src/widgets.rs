@@ -1,8 +1,24 @@
 use crate::{
-    common::clamp, Blend, Cell, Color, Error, Face, FaceAttrs, Key, KeyMod, KeyName, Size, Surface,
-    SurfaceMut, TerminalDisplay, TerminalEvent, TerminalSurfaceExt, RGBA,
+    common::clamp,
+    fuzzy::FuzzyMatcher,
+    keys::KeyMapResult,
+    layout::{Constraint, Direction, Layout, Rect},
+    render::char_width,
+    Blend, Canvas, CanvasUnits, Cell, Color, Error, Face, FaceAttrs, FillRule, ImageMosaic, Key,
+    KeyMap, KeyMod, KeyName, OkLab, Path, Size, Surface, SurfaceMut, SurfaceOwned, Terminal,
+    TerminalAction, TerminalCaps, TerminalDisplay, TerminalEvent, TerminalSize, TerminalSurface,
+    TerminalSurfaceExt, RGBA,
 };
-use std::{cmp::max, io::Write, str::FromStr};
+use std::{
+    any::Any, cmp::max, collections::BTreeSet, fmt, io::Write, ops::Range, str::FromStr, sync::Arc,
+};
+
+/// Number of distinct colors used by chart series
+const CHART_SERIES_COLORS: usize = 6;
+/// Vertical eighth blocks, index is the number of filled eighths
+const BLOCKS_VERTICAL: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+/// Horizontal eighth blocks, index is the number of filled eighths
+const BLOCKS_HORIZONTAL: [char; 9] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉', '█'];
 
 #[derive(Clone, Debug)]
 pub struct Theme {
@@ -11,10 +27,15 @@ pub struct Theme {
     pub accent: RGBA,
     pub cursor: Face,
     pub input: Face,
+    pub input_selected: Face,
     pub list_default: Face,
     pub list_selected: Face,
+    pub list_matched: Face,
+    pub list_header: Face,
     pub scrollbar_on: Face,
     pub scrollbar_off: Face,
+    pub chart_axis: Face,
+    pub chart_series: Vec<RGBA>,
 }
 
 impl Theme {
@@ -25,6 +46,11 @@ impl Theme {
             Face::new(Some(cursor_fg), Some(cursor_bg), FaceAttrs::EMPTY)
         };
         let input = Face::new(Some(fg), Some(bg), FaceAttrs::EMPTY);
+        let input_selected = Face::new(
+            Some(fg),
+            Some(bg.blend(accent.with_alpha(0.3), Blend::Over)),
+            FaceAttrs::EMPTY,
+        );
         let list_default = Face::new(
             Some(bg.blend(fg.with_alpha(0.8), Blend::Over)),
             Some(bg),
@@ -35,19 +61,55 @@ impl Theme {
             Some(bg.blend(fg.with_alpha(0.1), Blend::Over)),
             FaceAttrs::EMPTY,
         );
+        let list_header = Face::new(
+            Some(bg.blend(fg.with_alpha(0.6), Blend::Over)),
+            Some(bg.blend(fg.with_alpha(0.05), Blend::Over)),
+            FaceAttrs::BOLD,
+        );
+        let list_matched = Face::new(Some(accent), None, FaceAttrs::BOLD);
         let scrollbar_on = Face::new(None, Some(accent.with_alpha(0.8)), FaceAttrs::EMPTY);
         let scrollbar_off = Face::new(None, Some(accent.with_alpha(0.5)), FaceAttrs::EMPTY);
+        let chart_axis = Face::new(
+            Some(bg.blend(fg.with_alpha(0.6), Blend::Over)),
+            Some(bg),
+            FaceAttrs::EMPTY,
+        );
+        // series colors are accent color rotated around the hue circle
+        let chart_series = {
+            let OkLab { l, a, b } = accent.into();
+            let (chroma, hue) = (a.hypot(b), b.atan2(a));
+            (0..CHART_SERIES_COLORS)
+                .map(|index| {
+                    let hue =
+                        hue + index as f64 * std::f64::consts::TAU / CHART_SERIES_COLORS as f64;
+                    OkLab::new(l, chroma * hue.cos(), chroma * hue.sin()).into()
+                })
+                .collect()
+        };
         Self {
             fg,
             bg,
             accent,
             cursor,
             input,
+            input_selected,
             list_default,
             list_selected,
+            list_matched,
+            list_header,
             scrollbar_on,
             scrollbar_off,
+            chart_axis,
+            chart_series,
+        }
+    }
+
+    /// Color of the chart series with provided index
+    pub fn series_color(&self, index: usize) -> RGBA {
+        if self.chart_series.is_empty() {
+            return self.accent;
         }
+        self.chart_series[index % self.chart_series.len()]
     }
 
     pub fn light() -> Self {
@@ -104,105 +166,268 @@ pub struct ActionDesc<A> {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum InputAction {
     Insert(char),
+    /// Move cursor to the character index
+    SetCursor(usize),
+    /// Extend selection to the character index
+    SelectTo(usize),
     CursorForward,
     CursorBackward,
     CursorEnd,
     CursorStart,
     CursorNextWord,
     CursorPrevWord,
+    SelectForward,
+    SelectBackward,
+    SelectEnd,
+    SelectStart,
     DeleteBackward,
     DeleteForward,
     DeleteEnd,
+    DeleteStart,
+    DeleteWordBackward,
+    DeleteWordForward,
+    Transpose,
+    Undo,
+    Redo,
+    Yank,
+    YankPop,
+    HistoryPrev,
+    HistoryNext,
+    Complete,
+    CompletePrev,
 }
 
+const INPUT_ACTIONS: &[ActionDesc<InputAction>] = &[
+    ActionDesc {
+        action: InputAction::CursorForward,
+        chord: &[
+            &[key(KeyName::Right, KeyMod::EMPTY)],
+            &[key(KeyName::Char('f'), KeyMod::CTRL)],
+        ],
+        name: "input.move.forward",
+        description: "Move cursor forward in the input field",
+    },
+    ActionDesc {
+        action: InputAction::CursorBackward,
+        chord: &[
+            &[key(KeyName::Left, KeyMod::EMPTY)],
+            &[key(KeyName::Char('b'), KeyMod::CTRL)],
+        ],
+        name: "input.move.backward",
+        description: "Move cursor backward in the input field",
+    },
+    ActionDesc {
+        action: InputAction::CursorEnd,
+        chord: &[
+            &[key(KeyName::Char('e'), KeyMod::CTRL)],
+            &[key(KeyName::End, KeyMod::EMPTY)],
+        ],
+        name: "input.move.end",
+        description: "Move cursor to the end of the input",
+    },
+    ActionDesc {
+        action: InputAction::CursorStart,
+        chord: &[
+            &[key(KeyName::Char('a'), KeyMod::CTRL)],
+            &[key(KeyName::Home, KeyMod::EMPTY)],
+        ],
+        name: "input.move.start",
+        description: "Move cursor to the start of the input",
+    },
+    ActionDesc {
+        action: InputAction::CursorNextWord,
+        chord: &[&[key(KeyName::Char('f'), KeyMod::ALT)]],
+        name: "input.move.next_word",
+        description: "Move cursor to the end of the current word",
+    },
+    ActionDesc {
+        action: InputAction::CursorPrevWord,
+        chord: &[&[key(KeyName::Char('b'), KeyMod::ALT)]],
+        name: "input.move.prev_word",
+        description: "Move cursor to the start of the word",
+    },
+    ActionDesc {
+        action: InputAction::SelectForward,
+        chord: &[&[key(KeyName::Right, KeyMod::SHIFT)]],
+        name: "input.select.forward",
+        description: "Extend selection forward",
+    },
+    ActionDesc {
+        action: InputAction::SelectBackward,
+        chord: &[&[key(KeyName::Left, KeyMod::SHIFT)]],
+        name: "input.select.backward",
+        description: "Extend selection backward",
+    },
+    ActionDesc {
+        action: InputAction::SelectEnd,
+        chord: &[&[key(KeyName::End, KeyMod::SHIFT)]],
+        name: "input.select.end",
+        description: "Extend selection to the end of the input",
+    },
+    ActionDesc {
+        action: InputAction::SelectStart,
+        chord: &[&[key(KeyName::Home, KeyMod::SHIFT)]],
+        name: "input.select.start",
+        description: "Extend selection to the start of the input",
+    },
+    ActionDesc {
+        action: InputAction::DeleteBackward,
+        chord: &[&[key(KeyName::Backspace, KeyMod::EMPTY)]],
+        name: "input.delete.backward",
+        description: "Delete previous char or selection",
+    },
+    ActionDesc {
+        action: InputAction::DeleteForward,
+        chord: &[&[key(KeyName::Delete, KeyMod::EMPTY)]],
+        name: "input.delete.forward",
+        description: "Delete next char or selection",
+    },
+    ActionDesc {
+        action: InputAction::DeleteEnd,
+        chord: &[&[key(KeyName::Char('k'), KeyMod::CTRL)]],
+        name: "input.delete.end",
+        description: "Kill all input after cursor",
+    },
+    ActionDesc {
+        action: InputAction::DeleteStart,
+        chord: &[&[key(KeyName::Char('u'), KeyMod::CTRL)]],
+        name: "input.delete.start",
+        description: "Kill all input before cursor",
+    },
+    ActionDesc {
+        action: InputAction::DeleteWordBackward,
+        chord: &[&[key(KeyName::Char('w'), KeyMod::CTRL)]],
+        name: "input.delete.word_backward",
+        description: "Kill word before cursor",
+    },
+    ActionDesc {
+        action: InputAction::DeleteWordForward,
+        chord: &[&[key(KeyName::Char('d'), KeyMod::ALT)]],
+        name: "input.delete.word_forward",
+        description: "Kill word after cursor",
+    },
+    ActionDesc {
+        action: InputAction::Transpose,
+        chord: &[&[key(KeyName::Char('t'), KeyMod::CTRL)]],
+        name: "input.transpose",
+        description: "Swap chars around cursor",
+    },
+    ActionDesc {
+        action: InputAction::Undo,
+        chord: &[&[key(KeyName::Char('z'), KeyMod::CTRL)]],
+        name: "input.undo",
+        description: "Undo last edit",
+    },
+    ActionDesc {
+        action: InputAction::Redo,
+        chord: &[&[key(KeyName::Char('z'), KeyMod::ALT)]],
+        name: "input.redo",
+        description: "Redo last undone edit",
+    },
+    ActionDesc {
+        action: InputAction::Yank,
+        chord: &[&[key(KeyName::Char('y'), KeyMod::CTRL)]],
+        name: "input.yank",
+        description: "Insert last killed text",
+    },
+    ActionDesc {
+        action: InputAction::YankPop,
+        chord: &[&[key(KeyName::Char('y'), KeyMod::ALT)]],
+        name: "input.yank_pop",
+        description: "Replace yanked text with previous entry of the kill ring",
+    },
+    ActionDesc {
+        action: InputAction::HistoryPrev,
+        chord: &[
+            &[key(KeyName::Up, KeyMod::EMPTY)],
+            &[key(KeyName::Char('p'), KeyMod::CTRL)],
+        ],
+        name: "input.history.prev",
+        description: "Previous history entry starting with text before cursor",
+    },
+    ActionDesc {
+        action: InputAction::HistoryNext,
+        chord: &[
+            &[key(KeyName::Down, KeyMod::EMPTY)],
+            &[key(KeyName::Char('n'), KeyMod::CTRL)],
+        ],
+        name: "input.history.next",
+        description: "Next history entry starting with text before cursor",
+    },
+    ActionDesc {
+        action: InputAction::Complete,
+        chord: &[&[key(KeyName::Tab, KeyMod::EMPTY)]],
+        name: "input.complete.next",
+        description: "Complete word before cursor or select next candidate",
+    },
+    ActionDesc {
+        action: InputAction::CompletePrev,
+        chord: &[&[key(KeyName::Tab, KeyMod::SHIFT)]],
+        name: "input.complete.prev",
+        description: "Select previous completion candidate",
+    },
+];
+
 impl InputAction {
     pub fn description() -> &'static [ActionDesc<Self>] {
-        &[
-            ActionDesc {
-                action: InputAction::CursorForward,
-                chord: &[&[Key {
-                    name: KeyName::Right,
-                    mode: KeyMod::EMPTY,
-                }]],
-                name: "input.move.forward",
-                description: "Move cursor forward in the input field",
-            },
-            ActionDesc {
-                action: InputAction::CursorBackward,
-                chord: &[&[Key {
-                    name: KeyName::Left,
-                    mode: KeyMod::EMPTY,
-                }]],
-                name: "input.move.backward",
-                description: "Move cursor backward in the input field",
-            },
-            ActionDesc {
-                action: InputAction::CursorEnd,
-                chord: &[&[Key {
-                    name: KeyName::Char('e'),
-                    mode: KeyMod::CTRL,
-                }]],
-                name: "input.move.end",
-                description: "Move cursor to the end of the input",
-            },
-            ActionDesc {
-                action: InputAction::CursorStart,
-                chord: &[&[Key {
-                    name: KeyName::Char('a'),
-                    mode: KeyMod::CTRL,
-                }]],
-                name: "input.move.start",
-                description: "Move cursor to the start of the input",
-            },
-            ActionDesc {
-                action: InputAction::CursorNextWord,
-                chord: &[&[Key {
-                    name: KeyName::Char('f'),
-                    mode: KeyMod::ALT,
-                }]],
-                name: "input.move.next_word",
-                description: "Move cursor to the end of the current word",
-            },
-            ActionDesc {
-                action: InputAction::CursorPrevWord,
-                chord: &[&[Key {
-                    name: KeyName::Char('b'),
-                    mode: KeyMod::ALT,
-                }]],
-                name: "input.move.prev_word",
-                description: "Move cursor to the start of the word",
-            },
-            ActionDesc {
-                action: InputAction::DeleteBackward,
-                chord: &[&[Key {
-                    name: KeyName::Backspace,
-                    mode: KeyMod::EMPTY,
-                }]],
-                name: "input.delete.backward",
-                description: "Delete previous char",
-            },
-            ActionDesc {
-                action: InputAction::DeleteForward,
-                chord: &[&[Key {
-                    name: KeyName::Delete,
-                    mode: KeyMod::EMPTY,
-                }]],
-                name: "input.delete.forward",
-                description: "Delete next char",
-            },
-            ActionDesc {
-                action: InputAction::DeleteEnd,
-                chord: &[&[Key {
-                    name: KeyName::Char('k'),
-                    mode: KeyMod::CTRL,
-                }]],
-                name: "input.delete.end",
-                description: "Delete all input after cursor",
-            },
-        ]
+        INPUT_ACTIONS
+    }
+
+    /// Whether action kills text into the kill ring
+    fn is_kill(&self) -> bool {
+        use InputAction::*;
+        matches!(
+            self,
+            DeleteEnd | DeleteStart | DeleteWordBackward | DeleteWordForward
+        )
+    }
+
+    /// Whether action can change the text
+    fn is_edit(&self) -> bool {
+        use InputAction::*;
+        !matches!(
+            self,
+            SetCursor(_)
+                | SelectTo(_)
+                | CursorForward
+                | CursorBackward
+                | CursorEnd
+                | CursorStart
+                | CursorNextWord
+                | CursorPrevWord
+                | SelectForward
+                | SelectBackward
+                | SelectEnd
+                | SelectStart
+        )
     }
 }
 
+/// Maximum number of entries in the input kill ring
+const INPUT_KILL_RING_SIZE: usize = 16;
+/// Maximum number of input undo steps
+const INPUT_UNDO_DEPTH: usize = 256;
+/// Maximum number of completion candidates shown at once
+const INPUT_COMPLETION_HEIGHT: usize = 8;
+
+/// History navigation state
+struct InputHistoryNav {
+    /// index of the current history entry
+    index: usize,
+    /// only entries starting with prefix are visited
+    prefix: String,
+    /// text before navigation started
+    saved: (Vec<char>, Vec<char>),
+}
+
+/// Active completion
+struct InputCompletion {
+    /// start of the completed word
+    start: usize,
+    candidates: Vec<String>,
+    selected: Option<usize>,
+}
+
 pub struct Input {
     /// string before cursor
     before: Vec<char>,
@@ -210,6 +435,25 @@ pub struct Input {
     after: Vec<char>,
     /// visible offset
     offset: usize,
+    /// other end of the selection
+    anchor: Option<usize>,
+    /// size of the text region of the last render
+    size: Size,
+    /// mouse button is held down
+    dragging: bool,
+    undo: Vec<(Vec<char>, Vec<char>)>,
+    redo: Vec<(Vec<char>, Vec<char>)>,
+    /// previously applied action
+    last: Option<InputAction>,
+    kill_ring: Vec<Vec<char>>,
+    /// start of the last yanked text and kill ring index it was taken from
+    yank: Option<(usize, usize)>,
+    history: Vec<String>,
+    history_nav: Option<InputHistoryNav>,
+    completer: Option<Box<dyn Fn(&str) -> Vec<String>>>,
+    completion: Option<InputCompletion>,
+    keymap: KeyMap<InputAction>,
+    chord: Vec<Key>,
 }
 
 impl Default for Input {
@@ -220,94 +464,248 @@ impl Default for Input {
 
 impl Input {
     pub fn new() -> Self {
+        let mut keymap = KeyMap::new();
+        for desc in InputAction::description() {
+            for chord in desc.chord {
+                keymap.register(chord, desc.action);
+            }
+        }
         Self {
             before: Default::default(),
             after: Default::default(),
             offset: 0,
+            anchor: None,
+            size: Size::new(0, 0),
+            dragging: false,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            last: None,
+            kill_ring: Vec::new(),
+            yank: None,
+            history: Vec::new(),
+            history_nav: None,
+            completer: None,
+            completion: None,
+            keymap,
+            chord: Vec::new(),
         }
     }
 
-    pub fn apply(&mut self, action: InputAction) {
+    /// Use completer to produce candidates for the word before cursor
+    pub fn with_completer(mut self, completer: impl Fn(&str) -> Vec<String> + 'static) -> Self {
+        self.completer = Some(Box::new(completer));
+        self
+    }
+
+    /// Bind chord to the action, replacing previous binding
+    pub fn bind(&mut self, chord: &[Key], action: InputAction) {
+        self.keymap.register(chord, action);
+    }
+
+    /// Apply action, returns true if text, cursor, selection or completion has changed
+    pub fn apply(&mut self, action: InputAction) -> bool {
         use InputAction::*;
+        let last = self.last.replace(action);
+        let cursor = self.before.len();
+        let anchor = self.anchor;
+        let completion = (self.completions().len(), self.completion_selected());
+        if !matches!(action, Complete | CompletePrev) {
+            self.completion = None;
+        }
+        if !matches!(action, HistoryPrev | HistoryNext) {
+            self.history_nav = None;
+        }
+        if !matches!(action, Yank | YankPop) {
+            self.yank = None;
+        }
+        let state = action
+            .is_edit()
+            .then(|| (self.before.clone(), self.after.clone()));
+
         match action {
-            Insert(c) => self.before.push(c),
-            CursorForward => self.before.extend(self.after.pop()),
-            CursorBackward => self.after.extend(self.before.pop()),
+            Insert(c) => {
+                self.delete_selection();
+                self.before.push(c);
+            }
+            SetCursor(index) => self.set_cursor(index),
+            CursorForward => match self.selection() {
+                Some(range) => self.set_cursor(range.end),
+                None => self.before.extend(self.after.pop()),
+            },
+            CursorBackward => match self.selection() {
+                Some(range) => self.set_cursor(range.start),
+                None => self.after.extend(self.before.pop()),
+            },
             CursorEnd => self.before.extend(self.after.drain(..).rev()),
             CursorStart => self.after.extend(self.before.drain(..).rev()),
-            CursorNextWord => {
-                while let Some(c) = self.after.pop() {
-                    if is_word_separator(c) {
-                        self.before.push(c);
-                    } else {
-                        self.after.push(c);
-                        break;
-                    }
+            CursorNextWord => self.set_cursor(self.next_word()),
+            CursorPrevWord => self.set_cursor(self.prev_word()),
+            SelectForward | SelectBackward | SelectEnd | SelectStart | SelectTo(_) => {
+                let cursor = self.before.len();
+                let anchor = self.anchor.unwrap_or(cursor);
+                let index = match action {
+                    SelectForward => (cursor + 1).min(self.len()),
+                    SelectBackward => cursor.saturating_sub(1),
+                    SelectEnd => self.len(),
+                    SelectTo(index) => index.min(self.len()),
+                    _ => 0,
+                };
+                self.set_cursor(index);
+                self.anchor = Some(anchor);
+            }
+            DeleteBackward => {
+                if !self.delete_selection() {
+                    self.before.pop();
                 }
-                while let Some(c) = self.after.pop() {
-                    if is_word_separator(c) {
-                        self.after.push(c);
-                        break;
-                    } else {
-                        self.before.push(c);
-                    }
+            }
+            DeleteForward => {
+                if !self.delete_selection() {
+                    self.after.pop();
                 }
             }
-            CursorPrevWord => {
-                while let Some(c) = self.before.pop() {
-                    if is_word_separator(c) {
-                        self.after.push(c);
-                    } else {
-                        self.before.push(c);
-                        break;
+            DeleteEnd => {
+                let killed: Vec<_> = self.after.drain(..).rev().collect();
+                self.kill(killed, false, last.as_ref());
+            }
+            DeleteStart => {
+                let killed = std::mem::take(&mut self.before);
+                self.kill(killed, true, last.as_ref());
+            }
+            DeleteWordBackward => {
+                let start = self.prev_word();
+                let killed = self.before.split_off(start);
+                self.kill(killed, true, last.as_ref());
+            }
+            DeleteWordForward => {
+                let end = self.next_word();
+                let killed: Vec<_> = (self.before.len()..end)
+                    .filter_map(|_| self.after.pop())
+                    .collect();
+                self.kill(killed, false, last.as_ref());
+            }
+            Transpose => {
+                if self.after.is_empty() {
+                    let len = self.before.len();
+                    if len >= 2 {
+                        self.before.swap(len - 2, len - 1);
                     }
+                } else if let Some(prev) = self.before.pop() {
+                    let next = self.after.pop().unwrap_or(prev);
+                    self.before.push(next);
+                    self.before.push(prev);
                 }
-                while let Some(c) = self.before.pop() {
-                    if is_word_separator(c) {
-                        self.before.push(c);
-                        break;
-                    } else {
-                        self.after.push(c);
-                    }
+            }
+            Undo => {
+                if let Some((before, after)) = self.undo.pop() {
+                    let before = std::mem::replace(&mut self.before, before);
+                    let after = std::mem::replace(&mut self.after, after);
+                    self.redo.push((before, after));
                 }
             }
-            DeleteEnd => self.after.clear(),
-            DeleteBackward => {
-                self.before.pop();
+            Redo => {
+                if let Some((before, after)) = self.redo.pop() {
+                    let before = std::mem::replace(&mut self.before, before);
+                    let after = std::mem::replace(&mut self.after, after);
+                    self.undo.push((before, after));
+                }
             }
-            DeleteForward => {
-                self.after.pop();
+            Yank => {
+                if let Some(text) = self.kill_ring.last().cloned() {
+                    self.delete_selection();
+                    self.yank = Some((self.before.len(), self.kill_ring.len() - 1));
+                    self.before.extend(text);
+                }
+            }
+            YankPop => {
+                if let Some((start, index)) = self.yank {
+                    let index = index.checked_sub(1).unwrap_or(self.kill_ring.len() - 1);
+                    self.before.truncate(start);
+                    self.before.extend(self.kill_ring[index].iter().copied());
+                    self.yank = Some((start, index));
+                }
             }
+            HistoryPrev => self.history_move(true),
+            HistoryNext => self.history_move(false),
+            Complete => self.complete(true),
+            CompletePrev => self.complete(false),
         }
+
+        let moved = !matches!(
+            action,
+            SelectForward | SelectBackward | SelectEnd | SelectStart | SelectTo(_)
+        );
+        if moved {
+            self.anchor = None;
+        }
+        let (changed, state) = match state {
+            Some(state) => (state.0 != self.before || state.1 != self.after, Some(state)),
+            None => (false, None),
+        };
+        if let Some(state) = state.filter(|_| changed && !matches!(action, Undo | Redo)) {
+            // consecutive typing of a word is undone at once
+            let coalesce = matches!((last, action), (Some(Insert(_)), Insert(c)) if !c.is_whitespace())
+                || matches!((last, action), (Some(YankPop | Yank), YankPop));
+            if !coalesce || self.undo.is_empty() {
+                self.undo_push(state);
+            }
+            self.redo.clear();
+        }
+        changed
+            || cursor != self.before.len()
+            || anchor != self.anchor
+            || completion != (self.completions().len(), self.completion_selected())
     }
 
-    pub fn handle(&mut self, event: &TerminalEvent) {
-        use KeyName::*;
+    /// Handle terminal event, returns true if the event was consumed
+    ///
+    /// Mouse coordinates are expected to be relative to the input.
+    pub fn handle(&mut self, event: &TerminalEvent) -> bool {
         match event {
-            TerminalEvent::Key(Key { name, mode }) => match *mode {
-                KeyMod::EMPTY => match name {
-                    Char(c) => self.apply(InputAction::Insert(*c)),
-                    Backspace => self.apply(InputAction::DeleteBackward),
-                    Delete => self.apply(InputAction::DeleteForward),
-                    Left => self.apply(InputAction::CursorBackward),
-                    Right => self.apply(InputAction::CursorForward),
-                    _ => {}
-                },
-                KeyMod::CTRL => match name {
-                    KeyName::Char('e') => self.apply(InputAction::CursorEnd),
-                    KeyName::Char('a') => self.apply(InputAction::CursorStart),
-                    KeyName::Char('k') => self.apply(InputAction::DeleteEnd),
-                    _ => {}
-                },
-                KeyMod::ALT => match name {
-                    KeyName::Char('f') => self.apply(InputAction::CursorNextWord),
-                    KeyName::Char('b') => self.apply(InputAction::CursorPrevWord),
-                    _ => {}
-                },
-                _ => {}
-            },
-            TerminalEvent::Paste(text) => self.before.extend(text.chars()),
-            _ => {}
+            TerminalEvent::Key(key) => {
+                if let Some(action) = self.keymap.lookup_state(&mut self.chord, *key) {
+                    // unchanged state lets the event through, for example tab
+                    // moves focus when there is nothing to complete
+                    return self.apply(*action);
+                }
+                if matches!(self.keymap.lookup(&self.chord), KeyMapResult::Continue) {
+                    return true;
+                }
+                match key.name {
+                    KeyName::Char(c) if key.mode == KeyMod::EMPTY || key.mode == KeyMod::SHIFT => {
+                        self.chord.clear();
+                        self.apply(InputAction::Insert(c));
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            TerminalEvent::Paste(text) => {
+                self.paste(text);
+                true
+            }
+            TerminalEvent::Mouse(mouse)
+                if matches!(mouse.name, KeyName::MouseLeft | KeyName::MouseMove) =>
+            {
+                if !mouse.mode.contains(KeyMod::PRESS) {
+                    self.dragging = false;
+                    return true;
+                }
+                if mouse.row >= self.size.height
+                    || (!self.dragging && mouse.name != KeyName::MouseLeft)
+                {
+                    return false;
+                }
+                let index = (self.offset + mouse.row * self.size.width + mouse.col).min(self.len());
+                let action = if self.dragging || mouse.mode.contains(KeyMod::SHIFT) {
+                    InputAction::SelectTo(index)
+                } else {
+                    InputAction::SetCursor(index)
+                };
+                self.dragging = true;
+                self.apply(action);
+                true
+            }
+            _ => false,
         }
     }
 
@@ -320,6 +718,45 @@ impl Input {
         self.after.clear();
         self.before.extend(text.chars());
         self.offset = 0;
+        self.anchor = None;
+        self.completion = None;
+        self.history_nav = None;
+    }
+
+    /// Selected range of character indices
+    pub fn selection(&self) -> Option<Range<usize>> {
+        let anchor = self.anchor?;
+        let cursor = self.before.len();
+        if anchor == cursor {
+            return None;
+        }
+        Some(anchor.min(cursor)..anchor.max(cursor))
+    }
+
+    /// Add entry to the history, consecutive duplicates are ignored
+    pub fn history_push(&mut self, entry: impl Into<String>) {
+        let entry = entry.into();
+        if !entry.is_empty() && self.history.last() != Some(&entry) {
+            self.history.push(entry);
+        }
+    }
+
+    /// History entries, most recent last
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Candidates of the active completion
+    pub fn completions(&self) -> &[String] {
+        match &self.completion {
+            Some(completion) => &completion.candidates,
+            None => &[],
+        }
+    }
+
+    /// Index of the selected completion candidate
+    pub fn completion_selected(&self) -> Option<usize> {
+        self.completion.as_ref()?.selected
     }
 
     pub fn render(
@@ -328,7 +765,16 @@ impl Input {
         mut surf: impl SurfaceMut<Item = Cell>,
     ) -> Result<(), Error> {
         surf.erase(theme.input);
-        let size = surf.width() * surf.height();
+        // completion candidates are shown below the text if there is space
+        let popup_height = self
+            .completions()
+            .len()
+            .min(INPUT_COMPLETION_HEIGHT)
+            .min(surf.height().saturating_sub(1));
+        let text_height = surf.height() - popup_height;
+        self.size = Size::new(text_height, surf.width());
+
+        let size = surf.width() * text_height;
         if size < 2 {
             return Ok(());
         } else if self.offset > self.before.len() {
@@ -336,137 +782,1245 @@ impl Input {
         } else if self.offset + size < self.before.len() + 1 {
             self.offset = self.before.len() - size + 1;
         }
-        let mut writer = surf.writer().face(theme.input);
-        for c in self.before[self.offset..].iter() {
-            writer.put(Cell::new(theme.input, Some(*c)));
+        let cursor = self.before.len();
+        let selection = self.selection().unwrap_or(0..0);
+        let mut text_view = surf.view_mut(..text_height, ..);
+        let mut writer = text_view.writer().face(theme.input);
+        for (index, c) in self.get().enumerate().skip(self.offset) {
+            let face = if index == cursor {
+                theme.cursor
+            } else if selection.contains(&index) {
+                theme.input_selected
+            } else {
+                theme.input
+            };
+            writer.put(Cell::new(face, Some(c)));
+        }
+        if self.after.is_empty() {
+            writer.put(Cell::new(theme.cursor, None));
         }
-        let mut iter = self.after.iter().rev();
-        writer.put(Cell::new(theme.cursor, iter.next().copied()));
-        for c in iter {
-            writer.put(Cell::new(theme.input, Some(*c)));
+
+        if popup_height > 0 {
+            let selected = self.completion_selected();
+            let skip = match selected {
+                Some(selected) if selected >= popup_height => selected + 1 - popup_height,
+                _ => 0,
+            };
+            let candidates = self.completions().iter().enumerate().skip(skip);
+            for (row, (index, candidate)) in candidates.take(popup_height).enumerate() {
+                let face = if Some(index) == selected {
+                    theme.list_selected
+                } else {
+                    theme.list_default
+                };
+                let mut view = surf.view_mut(text_height + row, ..);
+                view.erase(face);
+                write!(view.writer().face(face), " {}", candidate)?;
+            }
         }
         Ok(())
     }
+
+    fn len(&self) -> usize {
+        self.before.len() + self.after.len()
+    }
+
+    /// Move cursor to the character index
+    fn set_cursor(&mut self, index: usize) {
+        while self.before.len() > index {
+            self.after.extend(self.before.pop());
+        }
+        while self.before.len() < index && !self.after.is_empty() {
+            self.before.extend(self.after.pop());
+        }
+    }
+
+    /// Insert pasted text replacing the selection, it is undone at once
+    fn paste(&mut self, text: &str) {
+        let state = (self.before.clone(), self.after.clone());
+        self.delete_selection();
+        self.before.extend(text.chars().filter(|c| !c.is_control()));
+        self.last = None;
+        self.completion = None;
+        self.history_nav = None;
+        self.yank = None;
+        if state.0 != self.before || state.1 != self.after {
+            self.undo_push(state);
+            self.redo.clear();
+        }
+    }
+
+    /// Record undo state, the oldest state is dropped once undo depth is reached
+    fn undo_push(&mut self, state: (Vec<char>, Vec<char>)) {
+        if self.undo.len() >= INPUT_UNDO_DEPTH {
+            self.undo.remove(0);
+        }
+        self.undo.push(state);
+    }
+
+    /// Delete selected text, returns true if there was a selection
+    fn delete_selection(&mut self) -> bool {
+        let Some(range) = self.selection() else {
+            return false;
+        };
+        self.set_cursor(range.end);
+        self.before.truncate(range.start);
+        self.anchor = None;
+        true
+    }
+
+    /// Index of the end of the current or next word
+    fn next_word(&self) -> usize {
+        let mut iter = self.after.iter().rev().peekable();
+        let mut index = self.before.len();
+        while iter.next_if(|c| is_word_separator(**c)).is_some() {
+            index += 1;
+        }
+        while iter.next_if(|c| !is_word_separator(**c)).is_some() {
+            index += 1;
+        }
+        index
+    }
+
+    /// Index of the start of the current or previous word
+    fn prev_word(&self) -> usize {
+        let mut iter = self.before.iter().rev().peekable();
+        let mut index = self.before.len();
+        while iter.next_if(|c| is_word_separator(**c)).is_some() {
+            index -= 1;
+        }
+        while iter.next_if(|c| !is_word_separator(**c)).is_some() {
+            index -= 1;
+        }
+        index
+    }
+
+    /// Put killed text into the kill ring, consecutive kills are merged
+    fn kill(&mut self, text: Vec<char>, backward: bool, last: Option<&InputAction>) {
+        if text.is_empty() {
+            return;
+        }
+        match self.kill_ring.last_mut() {
+            Some(top) if last.is_some_and(|last| last.is_kill()) => {
+                if backward {
+                    top.splice(0..0, text);
+                } else {
+                    top.extend(text);
+                }
+            }
+            _ => {
+                if self.kill_ring.len() >= INPUT_KILL_RING_SIZE {
+                    self.kill_ring.remove(0);
+                }
+                self.kill_ring.push(text);
+            }
+        }
+    }
+
+    /// Move through history entries starting with the text before the cursor
+    fn history_move(&mut self, backward: bool) {
+        let nav = self.history_nav.get_or_insert_with(|| InputHistoryNav {
+            index: self.history.len(),
+            prefix: self.before.iter().collect(),
+            saved: (self.before.clone(), self.after.clone()),
+        });
+        let current: String = self.before.iter().chain(self.after.iter().rev()).collect();
+        let matches = |index: &usize| {
+            let entry = &self.history[*index];
+            entry.starts_with(&nav.prefix) && entry != &current
+        };
+        let found = if backward {
+            (0..nav.index).rev().find(matches)
+        } else {
+            (nav.index + 1..self.history.len()).find(matches)
+        };
+        match found {
+            Some(index) => {
+                nav.index = index;
+                let cursor = nav.prefix.chars().count();
+                self.before = self.history[index].chars().collect();
+                self.after.clear();
+                self.set_cursor(cursor);
+            }
+            None if !backward => {
+                // went past the most recent entry, restore edited text
+                if let Some(nav) = self.history_nav.take() {
+                    (self.before, self.after) = nav.saved;
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// Complete the word before cursor or cycle through candidates
+    fn complete(&mut self, forward: bool) {
+        if let Some(completion) = &mut self.completion {
+            let count = completion.candidates.len();
+            let selected = match completion.selected {
+                None if forward => 0,
+                None => count - 1,
+                Some(selected) if forward => (selected + 1) % count,
+                Some(selected) => (selected + count - 1) % count,
+            };
+            completion.selected = Some(selected);
+            let start = completion.start;
+            let candidate: Vec<_> = completion.candidates[selected].chars().collect();
+            self.before.truncate(start);
+            self.before.extend(candidate);
+            return;
+        }
+        let Some(completer) = &self.completer else {
+            return;
+        };
+        let start = self
+            .before
+            .iter()
+            .rposition(|c| c.is_whitespace())
+            .map_or(0, |index| index + 1);
+        let prefix: String = self.before[start..].iter().collect();
+        let candidates = completer(&prefix);
+        match candidates.as_slice() {
+            [] => {}
+            [candidate] => {
+                self.before.truncate(start);
+                self.before.extend(candidate.chars());
+            }
+            [first, rest @ ..] => {
+                // insert longest common prefix of all candidates
+                let common = rest.iter().fold(first.len(), |common, candidate| {
+                    first
+                        .char_indices()
+                        .zip(candidate.chars())
+                        .find(|((_, f), c)| f != c)
+                        .map_or(common.min(candidate.len()), |((index, _), _)| {
+                            common.min(index)
+                        })
+                });
+                if common > prefix.len() {
+                    self.before.truncate(start);
+                    self.before.extend(first[..common].chars());
+                }
+                self.completion = Some(InputCompletion {
+                    start,
+                    candidates,
+                    selected: None,
+                });
+            }
+        }
+    }
 }
 
 fn is_word_separator(c: char) -> bool {
     c.is_ascii_punctuation() || c.is_ascii_whitespace()
 }
 
+/// Helper to build keys in constant context
+const fn key(name: KeyName, mode: KeyMod) -> Key {
+    Key { name, mode }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub enum ListAction {
-    ItemNext,
-    ItemPrev,
-    PageNext,
-    PagePrev,
+pub enum TextAction {
+    Insert(char),
+    Newline,
+    CursorForward,
+    CursorBackward,
+    CursorUp,
+    CursorDown,
+    CursorLineStart,
+    CursorLineEnd,
+    CursorNextWord,
+    CursorPrevWord,
+    CursorStart,
+    CursorEnd,
+    PageUp,
+    PageDown,
+    SelectForward,
+    SelectBackward,
+    SelectUp,
+    SelectDown,
+    SelectLineStart,
+    SelectLineEnd,
+    SelectAll,
+    DeleteBackward,
+    DeleteForward,
+    DeleteLineEnd,
+    Undo,
+    Redo,
 }
 
-impl ListAction {
+const TEXT_ACTIONS: &[ActionDesc<TextAction>] = &[
+    ActionDesc {
+        action: TextAction::Newline,
+        chord: &[&[key(KeyName::Enter, KeyMod::EMPTY)]],
+        name: "text.newline",
+        description: "Insert new line",
+    },
+    ActionDesc {
+        action: TextAction::CursorForward,
+        chord: &[&[key(KeyName::Right, KeyMod::EMPTY)]],
+        name: "text.move.forward",
+        description: "Move cursor forward",
+    },
+    ActionDesc {
+        action: TextAction::CursorBackward,
+        chord: &[&[key(KeyName::Left, KeyMod::EMPTY)]],
+        name: "text.move.backward",
+        description: "Move cursor backward",
+    },
+    ActionDesc {
+        action: TextAction::CursorUp,
+        chord: &[&[key(KeyName::Up, KeyMod::EMPTY)]],
+        name: "text.move.up",
+        description: "Move cursor to the previous line",
+    },
+    ActionDesc {
+        action: TextAction::CursorDown,
+        chord: &[&[key(KeyName::Down, KeyMod::EMPTY)]],
+        name: "text.move.down",
+        description: "Move cursor to the next line",
+    },
+    ActionDesc {
+        action: TextAction::CursorLineStart,
+        chord: &[
+            &[key(KeyName::Home, KeyMod::EMPTY)],
+            &[key(KeyName::Char('a'), KeyMod::CTRL)],
+        ],
+        name: "text.move.line_start",
+        description: "Move cursor to the start of the line",
+    },
+    ActionDesc {
+        action: TextAction::CursorLineEnd,
+        chord: &[
+            &[key(KeyName::End, KeyMod::EMPTY)],
+            &[key(KeyName::Char('e'), KeyMod::CTRL)],
+        ],
+        name: "text.move.line_end",
+        description: "Move cursor to the end of the line",
+    },
+    ActionDesc {
+        action: TextAction::CursorNextWord,
+        chord: &[&[key(KeyName::Char('f'), KeyMod::ALT)]],
+        name: "text.move.next_word",
+        description: "Move cursor to the end of the current word",
+    },
+    ActionDesc {
+        action: TextAction::CursorPrevWord,
+        chord: &[&[key(KeyName::Char('b'), KeyMod::ALT)]],
+        name: "text.move.prev_word",
+        description: "Move cursor to the start of the word",
+    },
+    ActionDesc {
+        action: TextAction::CursorStart,
+        chord: &[&[key(KeyName::Home, KeyMod::CTRL)]],
+        name: "text.move.start",
+        description: "Move cursor to the start of the text",
+    },
+    ActionDesc {
+        action: TextAction::CursorEnd,
+        chord: &[&[key(KeyName::End, KeyMod::CTRL)]],
+        name: "text.move.end",
+        description: "Move cursor to the end of the text",
+    },
+    ActionDesc {
+        action: TextAction::PageUp,
+        chord: &[&[key(KeyName::PageUp, KeyMod::EMPTY)]],
+        name: "text.move.page_up",
+        description: "Move cursor one page up",
+    },
+    ActionDesc {
+        action: TextAction::PageDown,
+        chord: &[&[key(KeyName::PageDown, KeyMod::EMPTY)]],
+        name: "text.move.page_down",
+        description: "Move cursor one page down",
+    },
+    ActionDesc {
+        action: TextAction::SelectForward,
+        chord: &[&[key(KeyName::Right, KeyMod::SHIFT)]],
+        name: "text.select.forward",
+        description: "Extend selection forward",
+    },
+    ActionDesc {
+        action: TextAction::SelectBackward,
+        chord: &[&[key(KeyName::Left, KeyMod::SHIFT)]],
+        name: "text.select.backward",
+        description: "Extend selection backward",
+    },
+    ActionDesc {
+        action: TextAction::SelectUp,
+        chord: &[&[key(KeyName::Up, KeyMod::SHIFT)]],
+        name: "text.select.up",
+        description: "Extend selection to the previous line",
+    },
+    ActionDesc {
+        action: TextAction::SelectDown,
+        chord: &[&[key(KeyName::Down, KeyMod::SHIFT)]],
+        name: "text.select.down",
+        description: "Extend selection to the next line",
+    },
+    ActionDesc {
+        action: TextAction::SelectLineStart,
+        chord: &[&[key(KeyName::Home, KeyMod::SHIFT)]],
+        name: "text.select.line_start",
+        description: "Extend selection to the start of the line",
+    },
+    ActionDesc {
+        action: TextAction::SelectLineEnd,
+        chord: &[&[key(KeyName::End, KeyMod::SHIFT)]],
+        name: "text.select.line_end",
+        description: "Extend selection to the end of the line",
+    },
+    ActionDesc {
+        action: TextAction::SelectAll,
+        chord: &[&[
+            key(KeyName::Char('x'), KeyMod::CTRL),
+            key(KeyName::Char('h'), KeyMod::EMPTY),
+        ]],
+        name: "text.select.all",
+        description: "Select all text",
+    },
+    ActionDesc {
+        action: TextAction::DeleteBackward,
+        chord: &[&[key(KeyName::Backspace, KeyMod::EMPTY)]],
+        name: "text.delete.backward",
+        description: "Delete previous char or selection",
+    },
+    ActionDesc {
+        action: TextAction::DeleteForward,
+        chord: &[&[key(KeyName::Delete, KeyMod::EMPTY)]],
+        name: "text.delete.forward",
+        description: "Delete next char or selection",
+    },
+    ActionDesc {
+        action: TextAction::DeleteLineEnd,
+        chord: &[&[key(KeyName::Char('k'), KeyMod::CTRL)]],
+        name: "text.delete.line_end",
+        description: "Delete text till the end of the line",
+    },
+    ActionDesc {
+        action: TextAction::Undo,
+        chord: &[&[key(KeyName::Char('z'), KeyMod::CTRL)]],
+        name: "text.undo",
+        description: "Undo last edit",
+    },
+    ActionDesc {
+        action: TextAction::Redo,
+        chord: &[&[key(KeyName::Char('y'), KeyMod::CTRL)]],
+        name: "text.redo",
+        description: "Redo last undone edit",
+    },
+];
+
+impl TextAction {
     pub fn description() -> &'static [ActionDesc<Self>] {
-        &[
-            ActionDesc {
-                action: ListAction::ItemNext,
-                chord: &[
-                    &[Key {
-                        name: KeyName::Down,
-                        mode: KeyMod::EMPTY,
-                    }],
-                    &[Key {
-                        name: KeyName::Char('n'),
-                        mode: KeyMod::CTRL,
-                    }],
-                ],
-                name: "list.item.next",
-                description: "Move to the next item in the list",
-            },
-            ActionDesc {
-                action: ListAction::ItemPrev,
-                chord: &[
-                    &[Key {
-                        name: KeyName::Up,
-                        mode: KeyMod::EMPTY,
-                    }],
-                    &[Key {
-                        name: KeyName::Char('p'),
-                        mode: KeyMod::CTRL,
-                    }],
-                ],
-                name: "list.item.prev",
-                description: "Move to the previous item in the list",
-            },
-            ActionDesc {
-                action: ListAction::PageNext,
-                chord: &[&[Key {
-                    name: KeyName::PageDown,
-                    mode: KeyMod::EMPTY,
-                }]],
-                name: "input.page.next",
-                description: "Move one page down in the list",
-            },
-            ActionDesc {
-                action: ListAction::PagePrev,
-                chord: &[&[Key {
-                    name: KeyName::PageUp,
-                    mode: KeyMod::EMPTY,
-                }]],
-                name: "input.page.prev",
-                description: "Move one page up in the list",
-            },
-        ]
+        TEXT_ACTIONS
     }
 }
 
-pub trait ListItems {
-    type Item: TerminalDisplay;
+/// Gap buffer of characters
+#[derive(Debug, Clone, Default)]
+struct GapBuffer {
+    data: Vec<char>,
+    gap: Range<usize>,
+}
 
-    /// Number of items in the list
-    fn len(&self) -> usize;
+impl GapBuffer {
+    fn len(&self) -> usize {
+        self.data.len() - self.gap.len()
+    }
 
-    /// Get entry in the list by it's index
-    fn get(&self, index: usize) -> Option<Self::Item>;
+    fn get(&self, index: usize) -> Option<char> {
+        if index < self.gap.start {
+            self.data.get(index).copied()
+        } else {
+            self.data.get(index + self.gap.len()).copied()
+        }
+    }
 
-    /// Check if list is empty
-    fn is_empty(&self) -> bool {
-        self.len() == 0
+    fn chars(&self, range: Range<usize>) -> impl Iterator<Item = char> + '_ {
+        range.filter_map(move |index| self.get(index))
+    }
+
+    /// Move gap so it starts at the index
+    fn move_gap(&mut self, index: usize) {
+        let Range { start, end } = self.gap.clone();
+        if index < start {
+            let size = start - index;
+            self.data.copy_within(index..start, end - size);
+            self.gap = index..end - size;
+        } else if index > start {
+            let size = index - start;
+            self.data.copy_within(end..end + size, start);
+            self.gap = index..end + size;
+        }
+    }
+
+    /// Replace range with provided characters, returns removed characters
+    fn replace(&mut self, range: Range<usize>, chars: &[char]) -> Vec<char> {
+        self.move_gap(range.start);
+        let removed_end = self.gap.end + range.len();
+        let removed = self.data[self.gap.end..removed_end].to_vec();
+        self.gap.end = removed_end;
+        if self.gap.len() < chars.len() {
+            let extra = max(chars.len(), max(self.data.len() / 2, 64));
+            let end = self.gap.end;
+            self.data.splice(end..end, (0..extra).map(|_| '\0'));
+            self.gap.end += extra;
+        }
+        self.data[self.gap.start..self.gap.start + chars.len()].copy_from_slice(chars);
+        self.gap.start += chars.len();
+        removed
     }
 }
 
-pub struct List<T> {
-    items: T,
-    offset: usize,
+/// Single undoable edit
+#[derive(Debug, Clone)]
+struct TextEdit {
+    start: usize,
+    removed: Vec<char>,
+    inserted: Vec<char>,
     cursor: usize,
-    height_hint: usize,
+    anchor: Option<usize>,
 }
 
-impl<T: ListItems> List<T> {
-    pub fn new(items: T) -> Self {
+/// Visual (soft wrapped) row of the text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TextRow {
+    start: usize,
+    end: usize,
+    /// row is terminated by a new line or the end of the text
+    last: bool,
+}
+
+/// Multi-line text editor
+pub struct TextArea {
+    text: GapBuffer,
+    /// cursor position (character index)
+    cursor: usize,
+    /// other end of the selection
+    anchor: Option<usize>,
+    /// column preserved by vertical movement
+    goal: Option<usize>,
+    /// first visible visual row
+    offset: usize,
+    /// scroll offset so the cursor is visible on the next render
+    follow: bool,
+    /// size of the last render
+    size: Size,
+    /// visual rows, reset when the text or the width changes
+    rows: Option<Arc<[TextRow]>>,
+    undo: Vec<TextEdit>,
+    redo: Vec<TextEdit>,
+    keymap: KeyMap<TextAction>,
+    chord: Vec<Key>,
+    /// mouse button is held down
+    dragging: bool,
+}
+
+impl Default for TextArea {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextArea {
+    pub fn new() -> Self {
+        let mut keymap = KeyMap::new();
+        for desc in TextAction::description() {
+            for chord in desc.chord {
+                keymap.register(chord, desc.action);
+            }
+        }
         Self {
-            items,
-            offset: 0,
+            text: GapBuffer::default(),
             cursor: 0,
-            height_hint: 1,
+            anchor: None,
+            goal: None,
+            offset: 0,
+            follow: true,
+            size: Size::new(0, 0),
+            rows: None,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            keymap,
+            chord: Vec::new(),
+            dragging: false,
         }
     }
 
-    pub fn items(&self) -> &T {
-        &self.items
+    /// Bind chord to the action, replacing previous binding
+    pub fn bind(&mut self, chord: &[Key], action: TextAction) {
+        self.keymap.register(chord, action);
     }
 
-    pub fn items_set(&mut self, items: T) -> T {
-        self.offset = 0;
-        self.cursor = 0;
-        std::mem::replace(&mut self.items, items)
+    /// Current text
+    pub fn get(&self) -> impl Iterator<Item = char> + '_ {
+        self.text.chars(0..self.text.len())
     }
 
-    pub fn current(&self) -> Option<T::Item> {
-        self.items.get(self.cursor)
+    /// Current text as a string
+    pub fn text(&self) -> String {
+        self.get().collect()
     }
 
-    pub fn apply(&mut self, action: ListAction) {
-        use ListAction::*;
+    /// Replace text, resets cursor and history
+    pub fn set(&mut self, text: &str) {
+        let chars: Vec<_> = text.chars().collect();
+        self.text = GapBuffer::default();
+        self.text.replace(0..0, &chars);
+        self.rows = None;
+        self.cursor = chars.len();
+        self.anchor = None;
+        self.goal = None;
+        self.follow = true;
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    /// Cursor position as a character index
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Selected range of character indices
+    pub fn selection(&self) -> Option<Range<usize>> {
+        let anchor = self.anchor?;
+        if anchor == self.cursor {
+            return None;
+        }
+        Some(anchor.min(self.cursor)..anchor.max(self.cursor))
+    }
+
+    /// Selected text
+    pub fn selected(&self) -> Option<String> {
+        self.selection()
+            .map(|range| self.text.chars(range).collect())
+    }
+
+    pub fn apply(&mut self, action: TextAction) {
+        use TextAction::*;
+        let len = self.text.len();
         match action {
-            ItemNext => self.cursor += 1,
-            ItemPrev => {
+            Insert(c) => self.insert(&[c]),
+            Newline => self.insert(&['\n']),
+            CursorForward | SelectForward => {
+                let index = match self.selection() {
+                    Some(range) if action == CursorForward => range.end,
+                    _ => (self.cursor + 1).min(len),
+                };
+                self.move_to(index, action == SelectForward)
+            }
+            CursorBackward | SelectBackward => {
+                let index = match self.selection() {
+                    Some(range) if action == CursorBackward => range.start,
+                    _ => self.cursor.saturating_sub(1),
+                };
+                self.move_to(index, action == SelectBackward)
+            }
+            CursorUp => self.move_vertical(-1, false),
+            CursorDown => self.move_vertical(1, false),
+            SelectUp => self.move_vertical(-1, true),
+            SelectDown => self.move_vertical(1, true),
+            PageUp => self.move_vertical(-(self.page() as isize), false),
+            PageDown => self.move_vertical(self.page() as isize, false),
+            CursorLineStart | SelectLineStart => {
+                self.move_to(self.line_start(self.cursor), action == SelectLineStart)
+            }
+            CursorLineEnd | SelectLineEnd => {
+                self.move_to(self.line_end(self.cursor), action == SelectLineEnd)
+            }
+            CursorNextWord => {
+                let mut index = self.cursor;
+                while self.text.get(index).is_some_and(is_word_separator) {
+                    index += 1;
+                }
+                while self.text.get(index).is_some_and(|c| !is_word_separator(c)) {
+                    index += 1;
+                }
+                self.move_to(index, false)
+            }
+            CursorPrevWord => {
+                let mut index = self.cursor;
+                while index > 0 && self.text.get(index - 1).is_some_and(is_word_separator) {
+                    index -= 1;
+                }
+                while index > 0
+                    && self
+                        .text
+                        .get(index - 1)
+                        .is_some_and(|c| !is_word_separator(c))
+                {
+                    index -= 1;
+                }
+                self.move_to(index, false)
+            }
+            CursorStart => self.move_to(0, false),
+            CursorEnd => self.move_to(len, false),
+            SelectAll => {
+                self.anchor = Some(0);
+                self.cursor = len;
+                self.goal = None;
+                self.follow = true;
+            }
+            DeleteBackward => match self.selection() {
+                Some(range) => self.edit(range, &[]),
+                None if self.cursor > 0 => self.edit(self.cursor - 1..self.cursor, &[]),
+                None => {}
+            },
+            DeleteForward => match self.selection() {
+                Some(range) => self.edit(range, &[]),
+                None if self.cursor < len => self.edit(self.cursor..self.cursor + 1, &[]),
+                None => {}
+            },
+            DeleteLineEnd => {
+                let end = self.line_end(self.cursor);
+                if end > self.cursor {
+                    self.edit(self.cursor..end, &[])
+                } else if end < len {
+                    // at the end of the line, join with the next one
+                    self.edit(self.cursor..end + 1, &[])
+                }
+            }
+            Undo => {
+                if let Some(edit) = self.undo.pop() {
+                    let end = edit.start + edit.inserted.len();
+                    self.text.replace(edit.start..end, &edit.removed);
+                    self.rows = None;
+                    self.cursor = edit.cursor;
+                    self.anchor = edit.anchor;
+                    self.goal = None;
+                    self.follow = true;
+                    self.redo.push(edit);
+                }
+            }
+            Redo => {
+                if let Some(edit) = self.redo.pop() {
+                    let end = edit.start + edit.removed.len();
+                    self.text.replace(edit.start..end, &edit.inserted);
+                    self.rows = None;
+                    self.cursor = edit.start + edit.inserted.len();
+                    self.anchor = None;
+                    self.goal = None;
+                    self.follow = true;
+                    self.undo.push(edit);
+                }
+            }
+        }
+    }
+
+    /// Handle terminal event, returns true if the event was consumed
+    ///
+    /// Mouse coordinates are expected to be relative to the text area.
+    pub fn handle(&mut self, event: &TerminalEvent) -> bool {
+        match event {
+            TerminalEvent::Key(key) => {
+                if let Some(action) = self.keymap.lookup_state(&mut self.chord, *key) {
+                    self.apply(*action);
+                    return true;
+                }
+                if matches!(self.keymap.lookup(&self.chord), KeyMapResult::Continue) {
+                    return true;
+                }
+                match key.name {
+                    KeyName::Char(c) if key.mode == KeyMod::EMPTY || key.mode == KeyMod::SHIFT => {
+                        self.chord.clear();
+                        self.apply(TextAction::Insert(c));
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            TerminalEvent::Paste(text) => {
+                let chars: Vec<_> = text.chars().filter(|c| *c != '\r').collect();
+                self.insert(&chars);
+                true
+            }
+            TerminalEvent::Mouse(mouse) => match mouse.name {
+                KeyName::MouseLeft | KeyName::MouseMove => {
+                    if !mouse.mode.contains(KeyMod::PRESS) {
+                        self.dragging = false;
+                        if self.anchor == Some(self.cursor) {
+                            self.anchor = None;
+                        }
+                        return true;
+                    }
+                    if mouse.name == KeyName::MouseMove && !self.dragging {
+                        return false;
+                    }
+                    let index = self.index_at(self.offset + mouse.row, mouse.col);
+                    if !self.dragging && !mouse.mode.contains(KeyMod::SHIFT) {
+                        self.anchor = Some(index);
+                    } else if self.anchor.is_none() {
+                        self.anchor = Some(self.cursor);
+                    }
+                    self.dragging = true;
+                    self.cursor = index;
+                    self.goal = None;
+                    self.follow = true;
+                    true
+                }
+                KeyName::MouseWheelUp => {
+                    self.offset = self.offset.saturating_sub(3);
+                    true
+                }
+                KeyName::MouseWheelDown => {
+                    self.offset += 3;
+                    true
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    pub fn render(
+        &mut self,
+        theme: &Theme,
+        mut surf: impl SurfaceMut<Item = Cell>,
+    ) -> Result<(), Error> {
+        surf.erase(theme.input);
+        if self.size.width != surf.width() {
+            self.rows = None;
+        }
+        self.size = Size::new(surf.height(), surf.width());
+        if surf.height() == 0 || surf.width() == 0 {
+            return Ok(());
+        }
+        let rows = self.rows();
+        let cursor_row = row_of(&rows, self.cursor);
+        if self.follow {
+            if cursor_row < self.offset {
+                self.offset = cursor_row;
+            } else if cursor_row >= self.offset + surf.height() {
+                self.offset = cursor_row + 1 - surf.height();
+            }
+            self.follow = false;
+        }
+        self.offset = self.offset.min(rows.len() - 1);
+
+        let selection = self.selection().unwrap_or(0..0);
+        for (index, row) in rows[self.offset..].iter().take(surf.height()).enumerate() {
+            let mut view = surf.view_mut(index, ..);
+            let mut writer = view.writer().face(theme.input);
+            for (index, c) in (row.start..row.end).zip(self.text.chars(row.start..row.end)) {
+                let face = if index == self.cursor {
+                    theme.cursor
+                } else if selection.contains(&index) {
+                    theme.input_selected
+                } else {
+                    theme.input
+                };
+                if char_width(c) == 0 {
+                    continue;
+                }
+                let c = if c.is_control() { ' ' } else { c };
+                writer.put(Cell::new(face, Some(c)));
+            }
+            if row.last && self.cursor == row.end {
+                writer.put(Cell::new(theme.cursor, None));
+            } else if row.last && selection.contains(&row.end) {
+                writer.put(Cell::new(theme.input_selected, None));
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace selection (if any) with provided characters
+    fn insert(&mut self, chars: &[char]) {
+        let range = self.selection().unwrap_or(self.cursor..self.cursor);
+        self.edit(range, chars);
+    }
+
+    /// Replace range with provided characters recording it in undo history
+    fn edit(&mut self, range: Range<usize>, chars: &[char]) {
+        let removed = self.text.replace(range.clone(), chars);
+        self.rows = None;
+        // coalesce consecutive typing, a new group is started by a word following a separator
+        let coalesce = match (self.undo.last(), chars) {
+            (Some(last), [c]) => {
+                let word_start = last.inserted.last().is_some_and(|l| is_word_separator(*l))
+                    && !is_word_separator(*c);
+                removed.is_empty()
+                    && self.anchor.is_none()
+                    && last.removed.is_empty()
+                    && *c != '\n'
+                    && !word_start
+                    && last.start + last.inserted.len() == range.start
+                    && self.cursor == range.start
+            }
+            _ => false,
+        };
+        if coalesce {
+            if let Some(last) = self.undo.last_mut() {
+                last.inserted.extend_from_slice(chars);
+            }
+        } else {
+            self.undo.push(TextEdit {
+                start: range.start,
+                removed,
+                inserted: chars.to_vec(),
+                cursor: self.cursor,
+                anchor: self.anchor,
+            });
+        }
+        self.redo.clear();
+        self.cursor = range.start + chars.len();
+        self.anchor = None;
+        self.goal = None;
+        self.follow = true;
+    }
+
+    fn move_to(&mut self, index: usize, select: bool) {
+        if select {
+            self.anchor.get_or_insert(self.cursor);
+        } else {
+            self.anchor = None;
+        }
+        self.cursor = index;
+        self.goal = None;
+        self.follow = true;
+    }
+
+    /// Move cursor by the number of visual rows preserving goal column
+    fn move_vertical(&mut self, delta: isize, select: bool) {
+        let rows = self.rows();
+        let row = row_of(&rows, self.cursor);
+        let goal = self
+            .goal
+            .unwrap_or_else(|| self.width_of(rows[row].start..self.cursor));
+        let target = row as isize + delta;
+        let index = if target < 0 {
+            0
+        } else if target as usize >= rows.len() {
+            self.text.len()
+        } else {
+            self.index_in_row(rows[target as usize], goal)
+        };
+        self.move_to(index, select);
+        if (0..rows.len() as isize).contains(&target) {
+            self.goal = Some(goal);
+        }
+    }
+
+    /// Number of rows scrolled by page movement
+    fn page(&self) -> usize {
+        max(self.size.height, 1)
+    }
+
+    fn line_start(&self, index: usize) -> usize {
+        let mut index = index;
+        while index > 0 && self.text.get(index - 1) != Some('\n') {
+            index -= 1;
+        }
+        index
+    }
+
+    fn line_end(&self, index: usize) -> usize {
+        let mut index = index;
+        while self.text.get(index).is_some_and(|c| c != '\n') {
+            index += 1;
+        }
+        index
+    }
+
+    /// Display width of the range of characters
+    fn width_of(&self, range: Range<usize>) -> usize {
+        self.text.chars(range).map(char_width).sum()
+    }
+
+    /// Character index in the visual row closest to the column
+    fn index_in_row(&self, row: TextRow, col: usize) -> usize {
+        let mut width = 0;
+        for (index, c) in (row.start..row.end).zip(self.text.chars(row.start..row.end)) {
+            width += char_width(c);
+            if width > col {
+                return index;
+            }
+        }
+        if row.last || row.end == row.start {
+            row.end
+        } else {
+            // keep cursor on this row, end of wrapped row is the start of the next one
+            row.end - 1
+        }
+    }
+
+    /// Character index at the visual row and column
+    fn index_at(&mut self, row: usize, col: usize) -> usize {
+        let rows = self.rows();
+        match rows.get(row) {
+            Some(row) => self.index_in_row(*row, col),
+            None => self.text.len(),
+        }
+    }
+
+    /// Visual rows wrapped at the width of the last render
+    fn rows(&mut self) -> Arc<[TextRow]> {
+        if let Some(rows) = &self.rows {
+            return rows.clone();
+        }
+        let rows: Arc<[TextRow]> = self.rows_wrap().into();
+        self.rows = Some(rows.clone());
+        rows
+    }
+
+    /// Split text into visual rows wrapped at the width of the last render
+    fn rows_wrap(&self) -> Vec<TextRow> {
+        // one column is reserved for the cursor at the end of the row
+        let width = match self.size.width {
+            0 => usize::MAX,
+            width => max(width - 1, 1),
+        };
+        let mut rows = Vec::new();
+        let mut start = 0;
+        let mut col = 0;
+        for (index, c) in self.get().enumerate() {
+            if c == '\n' {
+                rows.push(TextRow {
+                    start,
+                    end: index,
+                    last: true,
+                });
+                start = index + 1;
+                col = 0;
+                continue;
+            }
+            let c_width = char_width(c);
+            if col + c_width > width && index > start {
+                rows.push(TextRow {
+                    start,
+                    end: index,
+                    last: false,
+                });
+                start = index;
+                col = 0;
+            }
+            col += c_width;
+        }
+        rows.push(TextRow {
+            start,
+            end: self.text.len(),
+            last: true,
+        });
+        rows
+    }
+}
+
+/// Visual row containing the character index
+fn row_of(rows: &[TextRow], index: usize) -> usize {
+    rows.partition_point(|row| row.start <= index)
+        .saturating_sub(1)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ListAction {
+    ItemNext,
+    ItemPrev,
+    PageNext,
+    PagePrev,
+    ItemFirst,
+    ItemLast,
+    /// Move cursor to the item with provided index
+    ItemJump(usize),
+    MarkToggle,
+    MarkNext,
+    MarkPrev,
+    MarkAll,
+    MarkClear,
+}
+
+const LIST_ACTIONS: &[ActionDesc<ListAction>] = &[
+    ActionDesc {
+        action: ListAction::ItemNext,
+        chord: &[
+            &[key(KeyName::Down, KeyMod::EMPTY)],
+            &[key(KeyName::Char('n'), KeyMod::CTRL)],
+        ],
+        name: "list.item.next",
+        description: "Move to the next item in the list",
+    },
+    ActionDesc {
+        action: ListAction::ItemPrev,
+        chord: &[
+            &[key(KeyName::Up, KeyMod::EMPTY)],
+            &[key(KeyName::Char('p'), KeyMod::CTRL)],
+        ],
+        name: "list.item.prev",
+        description: "Move to the previous item in the list",
+    },
+    ActionDesc {
+        action: ListAction::PageNext,
+        chord: &[&[key(KeyName::PageDown, KeyMod::EMPTY)]],
+        name: "input.page.next",
+        description: "Move one page down in the list",
+    },
+    ActionDesc {
+        action: ListAction::PagePrev,
+        chord: &[&[key(KeyName::PageUp, KeyMod::EMPTY)]],
+        name: "input.page.prev",
+        description: "Move one page up in the list",
+    },
+    ActionDesc {
+        action: ListAction::ItemFirst,
+        chord: &[&[key(KeyName::Home, KeyMod::EMPTY)]],
+        name: "list.item.first",
+        description: "Move to the first item in the list",
+    },
+    ActionDesc {
+        action: ListAction::ItemLast,
+        chord: &[&[key(KeyName::End, KeyMod::EMPTY)]],
+        name: "list.item.last",
+        description: "Move to the last item in the list",
+    },
+    ActionDesc {
+        action: ListAction::MarkToggle,
+        chord: &[&[key(KeyName::Char(' '), KeyMod::CTRL)]],
+        name: "list.mark.toggle",
+        description: "Toggle mark of the current item",
+    },
+    ActionDesc {
+        action: ListAction::MarkNext,
+        chord: &[&[key(KeyName::Down, KeyMod::SHIFT)]],
+        name: "list.mark.next",
+        description: "Extend marked range to the next item",
+    },
+    ActionDesc {
+        action: ListAction::MarkPrev,
+        chord: &[&[key(KeyName::Up, KeyMod::SHIFT)]],
+        name: "list.mark.prev",
+        description: "Extend marked range to the previous item",
+    },
+    ActionDesc {
+        action: ListAction::MarkAll,
+        chord: &[&[key(KeyName::Char('a'), KeyMod::ALT)]],
+        name: "list.mark.all",
+        description: "Mark all items",
+    },
+    ActionDesc {
+        action: ListAction::MarkClear,
+        chord: &[&[key(KeyName::Char('u'), KeyMod::ALT)]],
+        name: "list.mark.clear",
+        description: "Unmark all items",
+    },
+];
+
+impl ListAction {
+    pub fn description() -> &'static [ActionDesc<Self>] {
+        LIST_ACTIONS
+    }
+}
+
+pub trait ListItems {
+    type Item: TerminalDisplay;
+
+    /// Number of items in the list
+    fn len(&self) -> usize;
+
+    /// Get entry in the list by it's index
+    fn get(&self, index: usize) -> Option<Self::Item>;
+
+    /// Check if list is empty
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+pub struct List<T> {
+    items: T,
+    offset: usize,
+    cursor: usize,
+    height_hint: usize,
+    /// indices of marked items
+    marked: BTreeSet<usize>,
+    /// start of the marked range
+    mark_anchor: Option<usize>,
+    header: Option<Box<dyn TerminalDisplay>>,
+    /// rows occupied by items during the last render as (rows, index)
+    rows: Vec<(Range<usize>, usize)>,
+    keymap: KeyMap<ListAction>,
+    chord: Vec<Key>,
+}
+
+impl<T: ListItems> List<T> {
+    pub fn new(items: T) -> Self {
+        let mut keymap = KeyMap::new();
+        for desc in ListAction::description() {
+            for chord in desc.chord {
+                keymap.register(chord, desc.action);
+            }
+        }
+        Self {
+            items,
+            offset: 0,
+            cursor: 0,
+            height_hint: 1,
+            marked: BTreeSet::new(),
+            mark_anchor: None,
+            header: None,
+            rows: Vec::new(),
+            keymap,
+            chord: Vec::new(),
+        }
+    }
+
+    /// Header shown above the items which is not scrolled with them
+    pub fn with_header(mut self, header: impl TerminalDisplay + 'static) -> Self {
+        self.header = Some(Box::new(header));
+        self
+    }
+
+    /// Bind chord to the action, replacing previous binding
+    pub fn bind(&mut self, chord: &[Key], action: ListAction) {
+        self.keymap.register(chord, action);
+    }
+
+    pub fn items(&self) -> &T {
+        &self.items
+    }
+
+    pub fn items_set(&mut self, items: T) -> T {
+        self.offset = 0;
+        self.cursor = 0;
+        self.marked.clear();
+        self.mark_anchor = None;
+        std::mem::replace(&mut self.items, items)
+    }
+
+    /// Update items in place, cursor is moved to the first item and marks are cleared
+    pub fn items_update(&mut self, update: impl FnOnce(&mut T)) {
+        update(&mut self.items);
+        self.offset = 0;
+        self.cursor = 0;
+        self.marked.clear();
+        self.mark_anchor = None;
+    }
+
+    pub fn current(&self) -> Option<T::Item> {
+        self.items.get(self.cursor)
+    }
+
+    /// Index of the current item
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Indices of the marked items in ascending order
+    pub fn marked(&self) -> impl Iterator<Item = usize> + '_ {
+        self.marked.iter().copied()
+    }
+
+    pub fn is_marked(&self, index: usize) -> bool {
+        self.marked.contains(&index)
+    }
+
+    pub fn apply(&mut self, action: ListAction) {
+        use ListAction::*;
+        if !matches!(action, MarkNext | MarkPrev) {
+            self.mark_anchor = None;
+        }
+        match action {
+            ItemNext => self.cursor += 1,
+            ItemPrev => {
                 if self.cursor > 0 {
                     self.cursor -= 1
                 }
@@ -477,6 +2031,26 @@ impl<T: ListItems> List<T> {
                     self.cursor -= self.height_hint
                 }
             }
+            ItemFirst => self.cursor = 0,
+            ItemLast => self.cursor = self.items.len().saturating_sub(1),
+            ItemJump(index) => self.cursor = index,
+            MarkToggle => {
+                if !self.marked.remove(&self.cursor) && self.cursor < self.items.len() {
+                    self.marked.insert(self.cursor);
+                }
+            }
+            MarkNext | MarkPrev => {
+                let anchor = *self.mark_anchor.get_or_insert(self.cursor);
+                if action == MarkNext {
+                    self.cursor += 1;
+                } else {
+                    self.cursor = self.cursor.saturating_sub(1);
+                }
+                self.cursor = self.cursor.min(self.items.len().saturating_sub(1));
+                self.mark_range(anchor, self.cursor);
+            }
+            MarkAll => self.marked.extend(0..self.items.len()),
+            MarkClear => self.marked.clear(),
         }
         if self.items.len() > 0 {
             self.cursor = clamp(self.cursor, 0, self.items.len() - 1);
@@ -485,23 +2059,51 @@ impl<T: ListItems> List<T> {
         }
     }
 
-    pub fn handle(&mut self, event: &TerminalEvent) {
-        if let TerminalEvent::Key(Key { name, mode }) = event {
-            match *mode {
-                KeyMod::EMPTY => match name {
-                    KeyName::Down => self.apply(ListAction::ItemNext),
-                    KeyName::Up => self.apply(ListAction::ItemPrev),
-                    KeyName::PageDown => self.apply(ListAction::PageNext),
-                    KeyName::PageUp => self.apply(ListAction::PagePrev),
-                    _ => {}
-                },
-                KeyMod::CTRL => match name {
-                    KeyName::Char('n') => self.apply(ListAction::ItemNext),
-                    KeyName::Char('p') => self.apply(ListAction::ItemPrev),
-                    _ => {}
-                },
-                _ => {}
+    /// Handle terminal event, returns true if the event was consumed
+    ///
+    /// Mouse coordinates are expected to be relative to the list.
+    pub fn handle(&mut self, event: &TerminalEvent) -> bool {
+        match event {
+            TerminalEvent::Key(key) => {
+                if let Some(action) = self.keymap.lookup_state(&mut self.chord, *key) {
+                    self.apply(*action);
+                    return true;
+                }
+                matches!(self.keymap.lookup(&self.chord), KeyMapResult::Continue)
             }
+            TerminalEvent::Mouse(mouse) => match mouse.name {
+                KeyName::MouseWheelDown => {
+                    self.apply(ListAction::ItemNext);
+                    true
+                }
+                KeyName::MouseWheelUp => {
+                    self.apply(ListAction::ItemPrev);
+                    true
+                }
+                KeyName::MouseLeft if mouse.mode.contains(KeyMod::PRESS) => {
+                    let Some(index) = self
+                        .rows
+                        .iter()
+                        .find_map(|(rows, index)| rows.contains(&mouse.row).then_some(*index))
+                    else {
+                        return false;
+                    };
+                    if mouse.mode.contains(KeyMod::SHIFT) {
+                        // mark range from the current item to the clicked one
+                        let anchor = *self.mark_anchor.get_or_insert(self.cursor);
+                        self.cursor = index;
+                        self.mark_range(anchor, index);
+                    } else if mouse.mode.contains(KeyMod::CTRL) {
+                        self.apply(ListAction::ItemJump(index));
+                        self.apply(ListAction::MarkToggle);
+                    } else {
+                        self.apply(ListAction::ItemJump(index));
+                    }
+                    true
+                }
+                _ => false,
+            },
+            _ => false,
         }
     }
 
@@ -511,90 +2113,102 @@ impl<T: ListItems> List<T> {
         mut surf: impl SurfaceMut<Item = Cell>,
     ) -> Result<(), Error> {
         surf.erase(theme.list_default);
+        self.rows.clear();
         if surf.height() < 1 || surf.width() < 5 {
             return Ok(());
         }
-        if self.offset > self.cursor {
-            self.offset = self.cursor;
-        } else if self.offset + surf.height() - 1 < self.cursor {
-            self.offset = self.cursor - surf.height() + 1;
+
+        // header
+        let header_height = match &self.header {
+            Some(header) => {
+                let size = Size::new(surf.height(), surf.width());
+                let height = header
+                    .size_hint(size)
+                    .map_or(1, |size| max(1, size.height))
+                    .min(surf.height() - 1);
+                let mut header_surf = surf.view_mut(..height, ..);
+                header_surf.erase(theme.list_header);
+                header.display(&mut header_surf.view_mut(.., 3..-1))?;
+                height
+            }
+            None => 0,
+        };
+        let mut surf = surf.view_mut(header_height.., ..);
+        if surf.height() < 1 {
+            return Ok(());
         }
 
-        // items
+        // items height, items taller than the surface are clipped
         let size = Size {
             width: surf.width() - 4, // exclude left border and scroll bar
             height: surf.height(),
         };
-        let items: Vec<_> = (self.offset..self.offset + surf.height())
-            .filter_map(|index| {
-                let item = self.items.get(index)?;
-                let item_size = match item.size_hint(size) {
-                    Some(item_size) => Size {
-                        height: max(1, item_size.height),
-                        width: item_size.width,
-                    },
-                    None => Size {
-                        height: 1,
-                        width: size.width,
-                    },
-                };
-                Some((index, item_size, item))
-            })
-            .collect();
-        // make sure items will fit
-        let mut cursor_found = false;
-        let mut items_height = 0;
-        let mut first = 0;
-        for (index, size, _item) in items.iter() {
-            items_height += size.height;
-            if items_height > surf.height() {
-                if cursor_found {
+        let item_height = |index: usize| -> usize {
+            self.items
+                .get(index)
+                .and_then(|item| item.size_hint(size))
+                .map_or(1, |item_size| max(1, item_size.height))
+                .min(size.height)
+        };
+
+        // make sure cursor item is visible
+        self.cursor = self.cursor.min(self.items.len().saturating_sub(1));
+        if self.offset > self.cursor {
+            self.offset = self.cursor;
+        } else {
+            let mut first = self.cursor;
+            let mut height = item_height(first);
+            while first > self.offset {
+                let prev_height = item_height(first - 1);
+                if height + prev_height > size.height {
                     break;
                 }
-                while items_height > surf.height() {
-                    items_height -= items[first].1.height;
-                    first += 1;
-                }
+                height += prev_height;
+                first -= 1;
             }
-            cursor_found = cursor_found || *index == self.cursor;
+            self.offset = first;
         }
-        self.height_hint = items.len();
-        self.offset += first;
+
         // render items
         let mut row: usize = 0;
-        for (index, item_size, item) in items[first..].iter() {
-            let mut item_surf = surf.view_mut(row..row + item_size.height, ..-1);
-            row += item_size.height;
-            if item_surf.is_empty() {
+        let mut index = self.offset;
+        while row < surf.height() && index < self.items.len() {
+            let Some(item) = self.items.get(index) else {
                 break;
-            }
-            if *index == self.cursor {
-                item_surf.erase(theme.list_selected);
-                let mut writer = item_surf
-                    .writer()
-                    .face(theme.list_selected.with_fg(Some(theme.accent)));
-                writer.write_all(" ● ".as_ref())?;
+            };
+            let height = item_height(index).min(surf.height() - row);
+            let mut item_surf = surf.view_mut(row..row + height, ..-1);
+            self.rows
+                .push((header_height + row..header_height + row + height, index));
+            row += height;
+
+            let face = if index == self.cursor {
+                theme.list_selected
             } else {
-                let mut writer = item_surf.writer().face(theme.list_default);
-                writer.write_all("   ".as_ref())?;
+                theme.list_default
             };
-            let mut text_surf = item_surf.view_mut(.., 3..);
-            if *index == self.cursor {
-                text_surf.erase(theme.list_selected);
-                item.display(&mut text_surf)?;
+            item_surf.erase(face);
+            let mut writer = item_surf.writer().face(face);
+            if self.marked.contains(&index) {
+                writer.put_char('▌', face.with_fg(Some(theme.accent)));
+            } else {
+                writer.put_char(' ', face);
+            }
+            if index == self.cursor {
+                writer.put_char('●', face.with_fg(Some(theme.accent)));
             } else {
-                text_surf.erase(theme.list_default);
-                item.display(&mut text_surf)?;
+                writer.put_char(' ', face);
             }
+            writer.put_char(' ', face);
+            item.display(&mut item_surf.view_mut(.., 3..))?;
+            index += 1;
         }
+        let visible = index - self.offset;
+        self.height_hint = max(visible, 1);
 
         // scroll bar
         let (sb_offset, sb_filled) = if self.items.len() != 0 {
-            let sb_filled = clamp(
-                surf.height() * items.len() / self.items.len(),
-                1,
-                surf.height(),
-            );
+            let sb_filled = clamp(surf.height() * visible / self.items.len(), 1, surf.height());
             let sb_offset = (surf.height() - sb_filled) * (self.cursor + 1) / self.items.len();
             (sb_offset, sb_filled + sb_offset)
         } else {
@@ -613,4 +2227,2019 @@ impl<T: ListItems> List<T> {
 
         Ok(())
     }
+
+    /// Mark all items between two indices (inclusive)
+    fn mark_range(&mut self, from: usize, to: usize) {
+        self.marked.extend(from.min(to)..=from.max(to));
+    }
+}
+
+/// Number of candidates above which scoring is split between threads
+const FUZZY_PARALLEL_THRESHOLD: usize = 4096;
+
+/// List items filtered and ranked by fuzzy matching against a pattern
+///
+/// Pattern is usually updated from the `Input` on each change, previous
+/// results are refined when new pattern extends the old one.
+pub struct FuzzyItems<T> {
+    items: T,
+    matcher: FuzzyMatcher,
+    /// matched items as (score, index) in ranked order
+    matches: Vec<(i32, usize)>,
+    /// number of items scored with the current matcher
+    scored: usize,
+    highlight: Face,
+    threads: usize,
+}
+
+impl<T> FuzzyItems<T>
+where
+    T: ListItems + Sync,
+    T::Item: AsRef<str>,
+{
+    pub fn new(items: T) -> Self {
+        let mut this = Self {
+            items,
+            matcher: FuzzyMatcher::default(),
+            matches: Vec::new(),
+            scored: 0,
+            highlight: Face::new(None, None, FaceAttrs::BOLD),
+            threads: 1,
+        };
+        this.refresh();
+        this
+    }
+
+    /// Face used to highlight matched characters, usually `Theme::list_matched`
+    pub fn with_highlight(mut self, face: Face) -> Self {
+        self.highlight = face;
+        self
+    }
+
+    /// Score large item sets using up to the specified number of threads
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = max(threads, 1);
+        self
+    }
+
+    pub fn items(&self) -> &T {
+        &self.items
+    }
+
+    /// Mutable access to items, call `refresh` once new items are appended
+    pub fn items_mut(&mut self) -> &mut T {
+        &mut self.items
+    }
+
+    pub fn pattern(&self) -> &str {
+        self.matcher.pattern()
+    }
+
+    /// Update pattern and re-rank items
+    pub fn set_pattern(&mut self, pattern: &str) {
+        if pattern == self.matcher.pattern() {
+            return;
+        }
+        let matcher = FuzzyMatcher::new(pattern);
+        let candidates: Vec<usize> = if self.matcher.is_refined_by(&matcher) {
+            self.matches.iter().map(|(_, index)| *index).collect()
+        } else {
+            (0..self.scored).collect()
+        };
+        self.matcher = matcher;
+        self.matches = self.score(candidates);
+        self.refresh();
+    }
+
+    /// Score items appended since the last update
+    pub fn refresh(&mut self) {
+        let len = self.items.len();
+        if self.scored > len {
+            // items were removed, start from scratch
+            self.scored = 0;
+            self.matches.clear();
+        }
+        let scored = self.score((self.scored..len).collect());
+        self.scored = len;
+        self.matches.extend(scored);
+        self.matches
+            .sort_unstable_by_key(|(score, index)| (std::cmp::Reverse(*score), *index));
+    }
+
+    /// Index in the underlying items of the ranked item
+    pub fn index(&self, index: usize) -> Option<usize> {
+        self.matches.get(index).map(|(_, index)| *index)
+    }
+
+    /// Score candidates, returns matched ones
+    fn score(&self, candidates: Vec<usize>) -> Vec<(i32, usize)> {
+        let score = |index: &usize| {
+            let item = self.items.get(*index)?;
+            Some((self.matcher.score(item.as_ref())?, *index))
+        };
+        if self.threads == 1 || candidates.len() < FUZZY_PARALLEL_THRESHOLD {
+            return candidates.iter().filter_map(score).collect();
+        }
+        let chunk_size = candidates.len().div_ceil(self.threads);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = candidates
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || chunk.iter().filter_map(score).collect::<Vec<_>>())
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("fuzzy scoring thread panicked"))
+                .collect()
+        })
+    }
+}
+
+impl<T> ListItems for FuzzyItems<T>
+where
+    T: ListItems,
+    T::Item: AsRef<str>,
+{
+    type Item = FuzzyListItem<T::Item>;
+
+    fn len(&self) -> usize {
+        self.matches.len()
+    }
+
+    fn get(&self, index: usize) -> Option<Self::Item> {
+        let (_, index) = self.matches.get(index)?;
+        let item = self.items.get(*index)?;
+        let positions = self
+            .matcher
+            .matches(item.as_ref())
+            .map(|result| result.positions)
+            .unwrap_or_default();
+        Some(FuzzyListItem {
+            item,
+            positions,
+            highlight: self.highlight,
+        })
+    }
+}
+
+/// Item of the `FuzzyItems` displayed with matched characters highlighted
+pub struct FuzzyListItem<I> {
+    item: I,
+    positions: Vec<usize>,
+    highlight: Face,
+}
+
+impl<I> FuzzyListItem<I> {
+    pub fn item(&self) -> &I {
+        &self.item
+    }
+
+    pub fn into_item(self) -> I {
+        self.item
+    }
+
+    /// Character indices of the matched characters
+    pub fn positions(&self) -> &[usize] {
+        &self.positions
+    }
+}
+
+impl<I: AsRef<str>> TerminalDisplay for FuzzyListItem<I> {
+    fn display(&self, surf: &mut TerminalSurface<'_>) -> Result<(), Error> {
+        let mut writer = surf.writer();
+        for (index, c) in self.item.as_ref().chars().enumerate() {
+            let face = if self.positions.binary_search(&index).is_ok() {
+                self.highlight
+            } else {
+                Face::default()
+            };
+            writer.put_char(c, face);
+        }
+        Ok(())
+    }
+
+    fn size_hint(&self, _surf_size: Size) -> Option<Size> {
+        None
+    }
+}
+
+/// Sparkline drawn with vertical eighth blocks
+///
+/// The most recent values are shown, aligned to the right edge of the surface.
+#[derive(Debug, Clone, Default)]
+pub struct Sparkline {
+    data: Vec<f64>,
+    range: Option<(f64, f64)>,
+    color: Option<RGBA>,
+}
+
+impl Sparkline {
+    pub fn new(data: impl IntoIterator<Item = f64>) -> Self {
+        Self {
+            data: data.into_iter().collect(),
+            range: None,
+            color: None,
+        }
+    }
+
+    /// Use fixed range of values instead of the one derived from data
+    pub fn with_range(mut self, min: f64, max: f64) -> Self {
+        self.range = Some((min, max));
+        self
+    }
+
+    /// Use color instead of the theme accent
+    pub fn with_color(mut self, color: RGBA) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    pub fn render(
+        &self,
+        theme: &Theme,
+        mut surf: impl SurfaceMut<Item = Cell>,
+    ) -> Result<(), Error> {
+        surf.erase(theme.chart_axis);
+        let (height, width) = (surf.height(), surf.width());
+        if height == 0 || width == 0 {
+            return Ok(());
+        }
+        let (min, max) = self
+            .range
+            .unwrap_or_else(|| chart_bounds(self.data.iter().copied(), true));
+        let face = theme
+            .chart_axis
+            .with_fg(Some(self.color.unwrap_or(theme.accent)));
+        let data = &self.data[self.data.len().saturating_sub(width)..];
+        let offset = width - data.len();
+        for (index, value) in data.iter().enumerate() {
+            if !value.is_finite() {
+                continue;
+            }
+            let eighths = (chart_norm(*value, min, max) * (height * 8) as f64).round() as usize;
+            let mut column = surf.view_mut(.., offset + index);
+            for (row, cell) in column.iter_mut().enumerate() {
+                let filled = eighths.saturating_sub((height - row - 1) * 8).min(8);
+                if filled > 0 {
+                    *cell = Cell::new(face, Some(BLOCKS_VERTICAL[filled]));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Orientation of the bars in the bar chart
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ChartOrientation {
+    /// Bars grow from left to right, one bar per row
+    #[default]
+    Horizontal,
+    /// Bars grow from bottom to top, labels are in the bottom row
+    Vertical,
+}
+
+impl FromStr for ChartOrientation {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "horizontal" => Ok(Self::Horizontal),
+            "vertical" => Ok(Self::Vertical),
+            _ => Err(Error::ParseError("ChartOrientation", s.to_string())),
+        }
+    }
+}
+
+/// Bar chart drawn with eighth blocks
+#[derive(Debug, Clone, Default)]
+pub struct BarChart {
+    bars: Vec<(String, f64)>,
+    orientation: ChartOrientation,
+    range: Option<(f64, f64)>,
+}
+
+impl BarChart {
+    pub fn new<L: Into<String>>(bars: impl IntoIterator<Item = (L, f64)>) -> Self {
+        Self {
+            bars: bars
+                .into_iter()
+                .map(|(label, value)| (label.into(), value))
+                .collect(),
+            orientation: ChartOrientation::default(),
+            range: None,
+        }
+    }
+
+    pub fn with_orientation(mut self, orientation: ChartOrientation) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    /// Use fixed range of values instead of the one derived from data
+    pub fn with_range(mut self, min: f64, max: f64) -> Self {
+        self.range = Some((min, max));
+        self
+    }
+
+    pub fn render(
+        &self,
+        theme: &Theme,
+        mut surf: impl SurfaceMut<Item = Cell>,
+    ) -> Result<(), Error> {
+        surf.erase(theme.chart_axis);
+        if surf.height() == 0 || surf.width() == 0 || self.bars.is_empty() {
+            return Ok(());
+        }
+        let (min, max) = self
+            .range
+            .unwrap_or_else(|| chart_bounds(self.bars.iter().map(|(_, value)| *value), true));
+        match self.orientation {
+            ChartOrientation::Horizontal => self.render_horizontal(theme, surf, min, max),
+            ChartOrientation::Vertical => self.render_vertical(theme, surf, min, max),
+        }
+    }
+
+    fn render_horizontal(
+        &self,
+        theme: &Theme,
+        mut surf: impl SurfaceMut<Item = Cell>,
+        min: f64,
+        max: f64,
+    ) -> Result<(), Error> {
+        let (_, step) = chart_ticks(min, max, 4);
+        let values: Vec<_> = self
+            .bars
+            .iter()
+            .map(|(_, value)| chart_format(*value, step))
+            .collect();
+        let value_width = values.iter().map(|value| value.chars().count()).max();
+        let value_width = value_width.unwrap_or(0);
+        let label_width = self
+            .bars
+            .iter()
+            .map(|(label, _)| label.chars().count())
+            .max();
+        let label_width = label_width.unwrap_or(0).min(surf.width() / 3);
+        let bar_width = surf
+            .width()
+            .saturating_sub(label_width + value_width + 2 * (label_width > 0) as usize);
+        for (index, ((label, value), value_text)) in self.bars.iter().zip(values).enumerate() {
+            if index >= surf.height() {
+                break;
+            }
+            let face = theme.chart_axis.with_fg(Some(theme.series_color(index)));
+            let mut row = surf.view_mut(index, ..);
+            let mut writer = row.writer().face(theme.chart_axis);
+            if label_width > 0 {
+                for c in label
+                    .chars()
+                    .chain(std::iter::repeat(' '))
+                    .take(label_width)
+                {
+                    writer.put_char(c, theme.chart_axis);
+                }
+                writer.put_char(' ', theme.chart_axis);
+            }
+            let eighths = (chart_norm(*value, min, max) * (bar_width * 8) as f64).round() as usize;
+            for col in 0..bar_width {
+                let filled = eighths.saturating_sub(col * 8).min(8);
+                writer.put(Cell::new(face, Some(BLOCKS_HORIZONTAL[filled])));
+            }
+            write!(writer, " {:>1$}", value_text, value_width)?;
+        }
+        Ok(())
+    }
+
+    fn render_vertical(
+        &self,
+        theme: &Theme,
+        mut surf: impl SurfaceMut<Item = Cell>,
+        min: f64,
+        max: f64,
+    ) -> Result<(), Error> {
+        let has_labels = self.bars.iter().any(|(label, _)| !label.is_empty());
+        let height = surf.height().saturating_sub(has_labels as usize);
+        let slot = std::cmp::max(1, surf.width() / self.bars.len());
+        let bar_width = if slot > 1 { slot - 1 } else { slot };
+        for (index, (label, value)) in self.bars.iter().enumerate() {
+            let col = index * slot;
+            if col + bar_width > surf.width() {
+                break;
+            }
+            let face = theme.chart_axis.with_fg(Some(theme.series_color(index)));
+            let eighths = (chart_norm(*value, min, max) * (height * 8) as f64).round() as usize;
+            for row in 0..height {
+                let filled = eighths.saturating_sub((height - row - 1) * 8).min(8);
+                let cell = Cell::new(face, Some(BLOCKS_VERTICAL[filled]));
+                surf.view_mut(row, col..col + bar_width).fill(cell);
+            }
+            if has_labels {
+                let mut row = surf.view_mut(height, col..col + bar_width);
+                let mut writer = row.writer().face(theme.chart_axis);
+                for c in label.chars() {
+                    if !writer.put_char(c, theme.chart_axis) {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How series points are drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SeriesKind {
+    /// Points are connected with lines
+    #[default]
+    Line,
+    /// Each point is drawn as a dot
+    Scatter,
+}
+
+impl FromStr for SeriesKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "line" => Ok(Self::Line),
+            "scatter" => Ok(Self::Scatter),
+            _ => Err(Error::ParseError("SeriesKind", s.to_string())),
+        }
+    }
+}
+
+/// Named series of points drawn by the `Plot`
+#[derive(Debug, Clone, Default)]
+pub struct Series {
+    name: String,
+    points: Vec<(f64, f64)>,
+    kind: SeriesKind,
+    color: Option<RGBA>,
+}
+
+impl Series {
+    pub fn new(name: impl Into<String>, points: impl IntoIterator<Item = (f64, f64)>) -> Self {
+        Self {
+            name: name.into(),
+            points: points.into_iter().collect(),
+            kind: SeriesKind::default(),
+            color: None,
+        }
+    }
+
+    pub fn with_kind(mut self, kind: SeriesKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Use color instead of the theme series color
+    pub fn with_color(mut self, color: RGBA) -> Self {
+        self.color = Some(color);
+        self
+    }
+}
+
+/// Line and scatter plot with axes, tick labels and legend
+///
+/// Plot area is drawn with braille mosaic, or with images if enabled
+/// with `Plot::with_caps` and supported by the terminal.
+#[derive(Debug, Clone)]
+pub struct Plot {
+    series: Vec<Series>,
+    x_range: Option<(f64, f64)>,
+    y_range: Option<(f64, f64)>,
+    legend: bool,
+    image_cell_size: Option<Size>,
+}
+
+impl Default for Plot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Plot {
+    pub fn new() -> Self {
+        Self {
+            series: Vec::new(),
+            x_range: None,
+            y_range: None,
+            legend: true,
+            image_cell_size: None,
+        }
+    }
+
+    pub fn with_series(mut self, series: Series) -> Self {
+        self.series.push(series);
+        self
+    }
+
+    /// Use fixed range of x values instead of the one derived from data
+    pub fn with_x_range(mut self, min: f64, max: f64) -> Self {
+        self.x_range = Some((min, max));
+        self
+    }
+
+    /// Use fixed range of y values instead of the one derived from data
+    pub fn with_y_range(mut self, min: f64, max: f64) -> Self {
+        self.y_range = Some((min, max));
+        self
+    }
+
+    /// Show legend with names of the series (enabled by default)
+    pub fn with_legend(mut self, legend: bool) -> Self {
+        self.legend = legend;
+        self
+    }
+
+    /// Draw plot area with images if terminal supports glyphs
+    pub fn with_caps(mut self, caps: &TerminalCaps, term_size: TerminalSize) -> Self {
+        let cell_size = term_size.cell_size();
+        self.image_cell_size =
+            (caps.glyphs && caps.mosaic.is_none() && !cell_size.is_empty()).then_some(cell_size);
+        self
+    }
+
+    pub fn render(
+        &self,
+        theme: &Theme,
+        mut surf: impl SurfaceMut<Item = Cell>,
+    ) -> Result<(), Error> {
+        surf.erase(theme.chart_axis);
+        let points = || self.series.iter().flat_map(|series| series.points.iter());
+        let (x_min, x_max) = self
+            .x_range
+            .unwrap_or_else(|| chart_bounds(points().map(|(x, _)| *x), false));
+        let (y_min, y_max) = self
+            .y_range
+            .unwrap_or_else(|| chart_bounds(points().map(|(_, y)| *y), false));
+
+        // layout
+        let legend = self.legend && self.series.iter().any(|series| !series.name.is_empty());
+        let top = legend as usize;
+        let rows = surf.height().saturating_sub(top + 2);
+        if rows == 0 {
+            return Ok(());
+        }
+        let (y_ticks, y_step) = chart_ticks(y_min, y_max, max(1, rows / 2));
+        let y_labels: Vec<_> = y_ticks.iter().map(|y| chart_format(*y, y_step)).collect();
+        let label_width = y_labels.iter().map(|label| label.chars().count()).max();
+        let left = label_width.unwrap_or(0) + 1;
+        let cols = surf.width().saturating_sub(left);
+        if cols == 0 {
+            return Ok(());
+        }
+        let (x_ticks, x_step) = chart_ticks(x_min, x_max, max(1, cols / 10));
+
+        // plot area
+        let size = Size::new(rows, cols);
+        let mut canvas = match self.image_cell_size {
+            Some(cell_size) => Canvas::new(size, cell_size),
+            None => Canvas::new(size, Size::new(0, 0)).with_mosaic(ImageMosaic::Braille),
+        };
+        let pixels = canvas.pixel_size();
+        let cell_size = canvas.cell_size();
+        let to_pixel = |x: f64, y: f64| {
+            (
+                chart_norm_unclamped(x, x_min, x_max) * (pixels.width - 1) as f64 + 0.5,
+                (1.0 - chart_norm_unclamped(y, y_min, y_max)) * (pixels.height - 1) as f64 + 0.5,
+            )
+        };
+        let line_width = (cell_size.width as f64 / 6.0).max(1.0);
+        for (index, series) in self.series.iter().enumerate() {
+            let color = series.color.unwrap_or_else(|| theme.series_color(index));
+            let mut builder = Path::builder();
+            let mut connect = false;
+            for (x, y) in series.points.iter() {
+                if !x.is_finite() || !y.is_finite() {
+                    connect = false;
+                    continue;
+                }
+                let point = to_pixel(*x, *y);
+                match series.kind {
+                    SeriesKind::Line if connect => builder.line_to(point),
+                    SeriesKind::Line => builder.move_to(point),
+                    SeriesKind::Scatter => builder.move_to(point).circle(line_width),
+                };
+                connect = true;
+            }
+            let path = builder.build();
+            match series.kind {
+                SeriesKind::Line => canvas.stroke(&path, line_width, color, CanvasUnits::Pixels),
+                SeriesKind::Scatter => {
+                    canvas.fill(&path, FillRule::NonZero, color, CanvasUnits::Pixels)
+                }
+            }
+        }
+        canvas.draw(&mut surf.view_mut(top..top + rows, left..));
+
+        // axes and tick labels
+        let axis = |c| Cell::new(theme.chart_axis, Some(c));
+        surf.view_mut(top..top + rows, left - 1).fill(axis('│'));
+        surf.view_mut(top + rows, left..).fill(axis('─'));
+        surf.view_mut(top + rows, left - 1).fill(axis('└'));
+        for (y, label) in y_ticks.iter().zip(y_labels) {
+            let row = top + (to_pixel(x_min, *y).1 as usize / cell_size.height).min(rows - 1);
+            surf.view_mut(row, left - 1).fill(axis('┤'));
+            let mut view = surf.view_mut(row, ..left - 1);
+            let mut writer = view.writer().face(theme.chart_axis);
+            write!(writer, "{:>1$}", label, left - 1)?;
+        }
+        let mut labels_end = 0;
+        for x in x_ticks.iter() {
+            let col = (to_pixel(*x, y_min).0 as usize / cell_size.width).min(cols - 1);
+            surf.view_mut(top + rows, left + col).fill(axis('┬'));
+            let label = chart_format(*x, x_step);
+            let start = (left + col).saturating_sub(label.chars().count() / 2);
+            if start < labels_end || start + label.chars().count() > surf.width() {
+                continue;
+            }
+            labels_end = start + label.chars().count() + 1;
+            let mut view = surf.view_mut(top + rows + 1, start..);
+            let mut writer = view.writer().face(theme.chart_axis);
+            write!(writer, "{}", label)?;
+        }
+
+        // legend
+        if legend {
+            let mut view = surf.view_mut(0, left..);
+            let mut writer = view.writer().face(theme.chart_axis);
+            for (index, series) in self.series.iter().enumerate() {
+                if series.name.is_empty() {
+                    continue;
+                }
+                let color = series.color.unwrap_or_else(|| theme.series_color(index));
+                writer.put_char('●', theme.chart_axis.with_fg(Some(color)));
+                write!(writer, " {}  ", series.name)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Bounds of finite values, optionally including zero
+fn chart_bounds(values: impl Iterator<Item = f64>, zero: bool) -> (f64, f64) {
+    let init = if zero {
+        (0.0, 0.0)
+    } else {
+        (f64::INFINITY, f64::NEG_INFINITY)
+    };
+    let (min, max) = values
+        .filter(|value| value.is_finite())
+        .fold(init, |(min, max), value| (min.min(value), max.max(value)));
+    if min > max {
+        (0.0, 1.0)
+    } else if min == max && !zero {
+        (min - 0.5, max + 0.5)
+    } else {
+        (min, max)
+    }
+}
+
+/// Position of the value inside the range, zero for an empty range
+fn chart_norm_unclamped(value: f64, min: f64, max: f64) -> f64 {
+    if max > min {
+        (value - min) / (max - min)
+    } else {
+        0.0
+    }
+}
+
+/// Position of the value inside the range clamped to `[0, 1]`
+fn chart_norm(value: f64, min: f64, max: f64) -> f64 {
+    clamp(chart_norm_unclamped(value, min, max), 0.0, 1.0)
+}
+
+/// Ticks with 1, 2 or 5 multiple of the power of ten step inside the range
+///
+/// Returns ticks and the step between them.
+fn chart_ticks(min: f64, max: f64, count: usize) -> (Vec<f64>, f64) {
+    let span = max - min;
+    if span <= 0.0 || !span.is_finite() || count == 0 {
+        return (vec![min], 1.0);
+    }
+    let raw = span / count as f64;
+    let magnitude = 10f64.powf(raw.log10().floor());
+    let step = magnitude
+        * match raw / magnitude {
+            norm if norm <= 1.0 => 1.0,
+            norm if norm <= 2.0 => 2.0,
+            norm if norm <= 5.0 => 5.0,
+            _ => 10.0,
+        };
+    // subnormal range underflows the step
+    if !(step.is_normal() && step > 0.0) {
+        return (vec![min], 1.0);
+    }
+    let ticks = (0..)
+        .map(|index| ((min / step).ceil() + index as f64) * step)
+        .take_while(|tick| *tick <= max + step * 1e-9)
+        .take(count + 2)
+        .collect();
+    (ticks, step)
+}
+
+/// Format value with enough precision to distinguish ticks with provided step
+fn chart_format(value: f64, step: f64) -> String {
+    let precision = (-step.log10().floor()).clamp(0.0, 12.0) as usize;
+    let text = format!("{:.*}", precision, value);
+    if text.starts_with('-') && text[1..].chars().all(|c| c == '0' || c == '.') {
+        text[1..].to_string()
+    } else {
+        text
+    }
+}
+
+/// Identifier of the widget inside the `WidgetTree`
+pub type WidgetId = usize;
+
+/// State of the widget tree available to the widget while handling events and rendering
+pub struct WidgetContext<'a> {
+    theme: &'a Theme,
+    id: WidgetId,
+    area: Rect,
+    focused: bool,
+    dirty: bool,
+    quit: bool,
+}
+
+impl<'a> WidgetContext<'a> {
+    /// Theme of the widget tree
+    pub fn theme(&self) -> &Theme {
+        self.theme
+    }
+
+    /// Identifier of the widget
+    pub fn id(&self) -> WidgetId {
+        self.id
+    }
+
+    /// Area occupied by the widget during the last render
+    pub fn area(&self) -> Rect {
+        self.area
+    }
+
+    /// Whether widget has focus
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Request widget to be rendered again
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Request `WidgetTree::run` to return
+    pub fn quit(&mut self) {
+        self.quit = true;
+    }
+}
+
+/// Component of the `WidgetTree`
+pub trait Widget: Any {
+    /// Handle event
+    ///
+    /// Returns `true` if event was handled, otherwise it is passed to the parent.
+    /// Mouse coordinates are relative to the terminal, see `WidgetContext::area`.
+    fn handle(&mut self, ctx: &mut WidgetContext<'_>, event: &TerminalEvent) -> bool {
+        let _ = (ctx, event);
+        false
+    }
+
+    /// Render widget, children are rendered on top of it
+    fn render(&mut self, ctx: &WidgetContext<'_>, surf: TerminalSurface<'_>) -> Result<(), Error>;
+
+    /// Size of the widget given the size of the parent area
+    fn size_hint(&self, surf_size: Size) -> Option<Size> {
+        let _ = surf_size;
+        None
+    }
+
+    /// Whether widget can take focus
+    fn focusable(&self) -> bool {
+        false
+    }
+}
+
+struct WidgetNode {
+    widget: Box<dyn Widget>,
+    parent: Option<WidgetId>,
+    children: Vec<WidgetId>,
+    constraint: Option<Constraint>,
+    direction: Direction,
+    area: Rect,
+    dirty: bool,
+}
+
+/// Retained tree of widgets
+///
+/// Tree routes events to the focused widget (or to the widget under the mouse)
+/// and bubbles them up to the parents until handled. `Tab` and `Shift+Tab` which
+/// were not handled move focus. Widgets are laid out inside the area of their parent
+/// (see `WidgetTree::set_constraint` and `WidgetTree::set_direction`), and only dirty
+/// widgets are rendered, the rest of the frame is reused from the previous render.
+pub struct WidgetTree {
+    nodes: Vec<WidgetNode>,
+    focus: Option<WidgetId>,
+    theme: Theme,
+    surface: SurfaceOwned<Cell>,
+    quit: bool,
+}
+
+impl WidgetTree {
+    /// Create tree with the root widget occupying the whole surface
+    pub fn new(root: impl Widget, theme: Theme) -> Self {
+        let mut tree = Self {
+            nodes: Vec::new(),
+            focus: None,
+            theme,
+            surface: SurfaceOwned::new(0, 0),
+            quit: false,
+        };
+        tree.insert(None, Box::new(root));
+        tree
+    }
+
+    /// Identifier of the root widget
+    pub fn root(&self) -> WidgetId {
+        0
+    }
+
+    /// Add widget as the last child of the parent
+    pub fn add(&mut self, parent: WidgetId, widget: impl Widget) -> WidgetId {
+        self.insert(Some(parent), Box::new(widget))
+    }
+
+    fn insert(&mut self, parent: Option<WidgetId>, widget: Box<dyn Widget>) -> WidgetId {
+        let id = self.nodes.len();
+        let focusable = widget.focusable();
+        self.nodes.push(WidgetNode {
+            widget,
+            parent,
+            children: Vec::new(),
+            constraint: None,
+            direction: Direction::Vertical,
+            area: Rect::default(),
+            dirty: true,
+        });
+        if let Some(parent) = parent {
+            self.nodes[parent].children.push(id);
+            self.mark_dirty(parent);
+        }
+        if self.focus.is_none() && focusable {
+            self.focus = Some(id);
+        }
+        id
+    }
+
+    /// Size of the widget along the direction of its parent
+    ///
+    /// Widgets without constraint are sized by `Widget::size_hint` or take free space.
+    pub fn set_constraint(&mut self, id: WidgetId, constraint: Constraint) {
+        if let Some(node) = self.nodes.get_mut(id) {
+            node.constraint = Some(constraint);
+            node.dirty = true;
+        }
+    }
+
+    /// Direction in which children of the widget are placed
+    pub fn set_direction(&mut self, id: WidgetId, direction: Direction) {
+        if let Some(node) = self.nodes.get_mut(id) {
+            node.direction = direction;
+            node.dirty = true;
+        }
+    }
+
+    /// Get widget by its identifier
+    pub fn get<W: Widget>(&self, id: WidgetId) -> Option<&W> {
+        let widget: &dyn Any = self.nodes.get(id)?.widget.as_ref();
+        widget.downcast_ref()
+    }
+
+    /// Get mutable widget by its identifier, widget is marked dirty
+    pub fn get_mut<W: Widget>(&mut self, id: WidgetId) -> Option<&mut W> {
+        let node = self.nodes.get_mut(id)?;
+        node.dirty = true;
+        let widget: &mut dyn Any = node.widget.as_mut();
+        widget.downcast_mut()
+    }
+
+    /// Parent of the widget
+    pub fn parent(&self, id: WidgetId) -> Option<WidgetId> {
+        self.nodes.get(id)?.parent
+    }
+
+    /// Area occupied by the widget during the last render
+    pub fn area(&self, id: WidgetId) -> Option<Rect> {
+        Some(self.nodes.get(id)?.area)
+    }
+
+    /// Request widget to be rendered again
+    pub fn mark_dirty(&mut self, id: WidgetId) {
+        if let Some(node) = self.nodes.get_mut(id) {
+            node.dirty = true;
+        }
+    }
+
+    /// Whether any of the widgets needs to be rendered
+    pub fn is_dirty(&self) -> bool {
+        self.nodes.iter().any(|node| node.dirty)
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    /// Replace theme, all widgets are rendered again
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+        self.mark_dirty(self.root());
+    }
+
+    /// Currently focused widget
+    pub fn focus(&self) -> Option<WidgetId> {
+        self.focus
+    }
+
+    /// Focus widget, returns `false` if widget is not focusable
+    pub fn set_focus(&mut self, id: WidgetId) -> bool {
+        if !self
+            .nodes
+            .get(id)
+            .is_some_and(|node| node.widget.focusable())
+        {
+            return false;
+        }
+        if let Some(prev) = self.focus.replace(id) {
+            self.mark_dirty(prev);
+        }
+        self.mark_dirty(id);
+        true
+    }
+
+    /// Move focus to the next focusable widget (in depth first order)
+    pub fn focus_next(&mut self) {
+        self.focus_move(true)
+    }
+
+    /// Move focus to the previous focusable widget (in depth first order)
+    pub fn focus_prev(&mut self) {
+        self.focus_move(false)
+    }
+
+    fn focus_move(&mut self, forward: bool) {
+        let order: Vec<_> = self
+            .preorder()
+            .into_iter()
+            .filter(|id| self.nodes[*id].widget.focusable())
+            .collect();
+        if order.is_empty() {
+            return;
+        }
+        let index = self
+            .focus
+            .and_then(|focus| order.iter().position(|id| *id == focus));
+        let next = match index {
+            None if forward => 0,
+            None => order.len() - 1,
+            Some(index) if forward => (index + 1) % order.len(),
+            Some(index) => (index + order.len() - 1) % order.len(),
+        };
+        self.set_focus(order[next]);
+    }
+
+    /// Widget identifiers in depth first order
+    fn preorder(&self) -> Vec<WidgetId> {
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut stack = vec![self.root()];
+        while let Some(id) = stack.pop() {
+            order.push(id);
+            stack.extend(self.nodes[id].children.iter().rev());
+        }
+        order
+    }
+
+    /// Top most widget containing the cell, children are drawn on top of the parents
+    pub fn hit_test(&self, row: usize, col: usize) -> Option<WidgetId> {
+        self.preorder().into_iter().rev().find(|id| {
+            let area = self.nodes[*id].area;
+            (area.row..area.row + area.height).contains(&row)
+                && (area.col..area.col + area.width).contains(&col)
+        })
+    }
+
+    /// Route event to the widgets, returns `true` if event was handled
+    pub fn handle(&mut self, event: &TerminalEvent) -> bool {
+        let target = match event {
+            TerminalEvent::Resize(_) => {
+                self.mark_dirty(self.root());
+                return true;
+            }
+            TerminalEvent::Mouse(mouse) => {
+                let target = self.hit_test(mouse.row, mouse.col);
+                if mouse.name == KeyName::MouseLeft && mouse.mode.contains(KeyMod::PRESS) {
+                    // focus the closest focusable widget under the mouse
+                    let mut focus = target;
+                    while let Some(id) = focus {
+                        if self.set_focus(id) {
+                            break;
+                        }
+                        focus = self.nodes[id].parent;
+                    }
+                }
+                target
+            }
+            _ => self.focus.or(Some(self.root())),
+        };
+
+        // bubble event from the target to the root
+        let mut current = target;
+        while let Some(id) = current {
+            let WidgetTree {
+                nodes,
+                focus,
+                theme,
+                quit,
+                ..
+            } = self;
+            let node = &mut nodes[id];
+            let mut ctx = WidgetContext {
+                theme,
+                id,
+                area: node.area,
+                focused: *focus == Some(id),
+                dirty: false,
+                quit: false,
+            };
+            let handled = node.widget.handle(&mut ctx, event);
+            node.dirty |= ctx.dirty;
+            *quit |= ctx.quit;
+            if handled {
+                return true;
+            }
+            current = node.parent;
+        }
+
+        // focus traversal
+        match event {
+            TerminalEvent::Key(Key {
+                name: KeyName::Tab,
+                mode,
+            }) if *mode == KeyMod::EMPTY => self.focus_next(),
+            TerminalEvent::Key(Key {
+                name: KeyName::Tab,
+                mode,
+            }) if *mode == KeyMod::SHIFT => self.focus_prev(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Render dirty widgets and copy the frame into the surface
+    pub fn render(&mut self, mut surf: TerminalSurface<'_>) -> Result<(), Error> {
+        let size = Size::new(surf.height(), surf.width());
+        if self.surface.height() != size.height || self.surface.width() != size.width {
+            self.surface = SurfaceOwned::new(size.height, size.width);
+            self.mark_dirty(self.root());
+        }
+        if self.is_dirty() {
+            self.layout(self.root(), Rect::new(0, 0, size.height, size.width));
+            self.render_node(self.root(), false)?;
+        }
+        for (dst, src) in surf.iter_mut().zip(self.surface.iter()) {
+            *dst = src.clone();
+        }
+        Ok(())
+    }
+
+    /// Assign areas to the widget and its children, widgets with changed area are marked dirty
+    fn layout(&mut self, id: WidgetId, area: Rect) {
+        let node = &mut self.nodes[id];
+        if node.area != area {
+            node.area = area;
+            node.dirty = true;
+        }
+        let direction = node.direction;
+        let children = node.children.clone();
+        let mut layout = Layout::new(direction);
+        for child in children.iter() {
+            let child = &self.nodes[*child];
+            let constraint = child.constraint.unwrap_or_else(|| {
+                match (child.widget.size_hint(area.size()), direction) {
+                    (Some(hint), Direction::Horizontal) => Constraint::Fixed(hint.width),
+                    (Some(hint), Direction::Vertical) => Constraint::Fixed(hint.height),
+                    (None, _) => Constraint::Fill(1),
+                }
+            });
+            layout = layout.area(constraint);
+        }
+        for (child, child_area) in children.into_iter().zip(layout.split(area.size())) {
+            let child_area = Rect {
+                row: area.row + child_area.row,
+                col: area.col + child_area.col,
+                ..child_area
+            };
+            // parent has to redraw cells left behind by the moved or shrunk child
+            if self.nodes[child].area != child_area {
+                self.nodes[id].dirty = true;
+            }
+            self.layout(child, child_area);
+        }
+    }
+
+    /// Render dirty widget with all its children, or look for dirty children
+    fn render_node(&mut self, id: WidgetId, force: bool) -> Result<(), Error> {
+        let force = force || self.nodes[id].dirty;
+        if force {
+            let WidgetTree {
+                nodes,
+                focus,
+                theme,
+                surface,
+                ..
+            } = self;
+            let node = &mut nodes[id];
+            let ctx = WidgetContext {
+                theme,
+                id,
+                area: node.area,
+                focused: *focus == Some(id),
+                dirty: false,
+                quit: false,
+            };
+            node.widget.render(&ctx, node.area.view_mut(surface))?;
+            node.dirty = false;
+        }
+        for child in self.nodes[id].children.clone() {
+            self.render_node(child, force)?;
+        }
+        Ok(())
+    }
+
+    /// Run widget tree until one of the widgets requests to quit
+    pub fn run<T: Terminal>(&mut self, term: &mut T) -> Result<(), Error> {
+        self.quit = false;
+        term.run_render(|_term, event, view| -> Result<_, Error> {
+            if let Some(event) = event {
+                self.handle(&event);
+            }
+            if self.quit {
+                return Ok(TerminalAction::Quit(()));
+            }
+            self.render(view)?;
+            Ok(TerminalAction::Wait)
+        })
+    }
+}
+
+impl fmt::Debug for WidgetTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WidgetTree")
+            .field("widgets", &self.nodes.len())
+            .field("focus", &self.focus)
+            .finish()
+    }
+}
+
+/// Translate mouse event coordinates to be relative to the widget area
+///
+/// Mouse events outside of the area are dropped unless the widget captures the mouse.
+fn widget_event(
+    ctx: &WidgetContext<'_>,
+    event: &TerminalEvent,
+    capture: bool,
+) -> Option<TerminalEvent> {
+    match event {
+        TerminalEvent::Mouse(mouse) => {
+            let area = ctx.area();
+            let inside = (area.row..area.row + area.height).contains(&mouse.row)
+                && (area.col..area.col + area.width).contains(&mouse.col);
+            if !inside && !capture {
+                return None;
+            }
+            let mut mouse = *mouse;
+            mouse.row = mouse.row.saturating_sub(area.row);
+            mouse.col = mouse.col.saturating_sub(area.col);
+            Some(TerminalEvent::Mouse(mouse))
+        }
+        event => Some(event.clone()),
+    }
+}
+
+impl Widget for Input {
+    fn handle(&mut self, ctx: &mut WidgetContext<'_>, event: &TerminalEvent) -> bool {
+        let Some(event) = widget_event(ctx, event, self.dragging) else {
+            return false;
+        };
+        let handled = Input::handle(self, &event);
+        if handled {
+            ctx.mark_dirty();
+        }
+        handled
+    }
+
+    fn render(&mut self, ctx: &WidgetContext<'_>, surf: TerminalSurface<'_>) -> Result<(), Error> {
+        Input::render(self, ctx.theme(), surf)
+    }
+
+    fn size_hint(&self, surf_size: Size) -> Option<Size> {
+        let popup = self.completions().len().min(INPUT_COMPLETION_HEIGHT);
+        Some(Size::new(1 + popup, surf_size.width))
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+}
+
+impl Widget for TextArea {
+    fn handle(&mut self, ctx: &mut WidgetContext<'_>, event: &TerminalEvent) -> bool {
+        let Some(event) = widget_event(ctx, event, self.dragging) else {
+            return false;
+        };
+        let handled = TextArea::handle(self, &event);
+        if handled {
+            ctx.mark_dirty();
+        }
+        handled
+    }
+
+    fn render(&mut self, ctx: &WidgetContext<'_>, surf: TerminalSurface<'_>) -> Result<(), Error> {
+        TextArea::render(self, ctx.theme(), surf)
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+}
+
+impl<T: ListItems + 'static> Widget for List<T> {
+    fn handle(&mut self, ctx: &mut WidgetContext<'_>, event: &TerminalEvent) -> bool {
+        let Some(event) = widget_event(ctx, event, false) else {
+            return false;
+        };
+        let handled = List::handle(self, &event);
+        if handled {
+            ctx.mark_dirty();
+        }
+        handled
+    }
+
+    fn render(&mut self, ctx: &WidgetContext<'_>, surf: TerminalSurface<'_>) -> Result<(), Error> {
+        List::render(self, ctx.theme(), surf)
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::Mouse;
+
+    /// Text as displayed by the terminal, cells covered by wide characters are skipped
+    fn surf_text(surf: &SurfaceOwned<Cell>) -> Vec<String> {
+        (0..surf.height())
+            .map(|row| {
+                let mut text = String::new();
+                let mut col = 0;
+                while let Some(cell) = surf.get(row, col) {
+                    let c = cell.character().unwrap_or(' ');
+                    text.push(c);
+                    col += max(1, char_width(c));
+                }
+                text
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chart_ticks() {
+        assert_eq!(
+            chart_ticks(0.0, 10.0, 5),
+            (vec![0.0, 2.0, 4.0, 6.0, 8.0, 10.0], 2.0)
+        );
+        assert_eq!(chart_ticks(-0.3, 0.7, 2).0, vec![0.0, 0.5]);
+        assert_eq!(chart_format(0.5, 0.5), "0.5");
+        assert_eq!(chart_format(-0.0001, 1.0), "0");
+        assert_eq!(chart_format(1500.0, 500.0), "1500");
+
+        // degenerate ranges fall back to a unit step
+        assert_eq!(chart_ticks(0.0, 5e-324, 4), (vec![0.0], 1.0));
+        assert_eq!(chart_format(1.0, 5e-324), "1.000000000000");
+    }
+
+    #[test]
+    fn test_sparkline() -> Result<(), Error> {
+        let theme = Theme::light();
+        let mut surf = SurfaceOwned::new(2, 5);
+        Sparkline::new([0.0, 1.0, 2.0, 3.0, 4.0, 8.0])
+            .with_range(0.0, 16.0)
+            .render(&theme, &mut surf)?;
+        assert_eq!(surf_text(&surf), vec!["     ", "▁▂▃▄█"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bar_chart() -> Result<(), Error> {
+        let theme = Theme::light();
+        let mut surf = SurfaceOwned::new(2, 10);
+        BarChart::new([("a", 2.0), ("bb", 5.0)]).render(&theme, &mut surf)?;
+        assert_eq!(surf_text(&surf), vec!["a  ██    2", "bb █████ 5"]);
+
+        let mut surf = SurfaceOwned::new(3, 4);
+        BarChart::new([("a", 1.0), ("b", 4.0)])
+            .with_orientation("Vertical".parse()?)
+            .render(&theme, &mut surf)?;
+        assert_eq!(surf_text(&surf), vec!["  █ ", "▄ █ ", "a b "]);
+
+        let mut surf = SurfaceOwned::new(2, 10);
+        BarChart::new([("a", 0.0), ("b", 5e-324)]).render(&theme, &mut surf)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_plot() -> Result<(), Error> {
+        let theme = Theme::light();
+        let mut surf = SurfaceOwned::new(6, 20);
+        Plot::new()
+            .with_series(Series::new("up", [(0.0, 0.0), (10.0, 10.0)]))
+            .with_series(Series::new("dots", [(5.0, 2.0)]).with_kind("SCATTER".parse()?))
+            .render(&theme, &mut surf)?;
+        assert_eq!(
+            surf_text(&surf),
+            vec![
+                "   ● up  ● dots     ",
+                "10┤⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⢀⣀⠤⠔⠒⠁",
+                "  │⠀⠀⠀⠀⠀⢀⣀⠤⠔⠒⠉⠁⠀⠀⠀⠀⠀",
+                " 0┤⢀⠤⠔⠒⠉⠁⠀⠀⠒⠀⠀⠀⠀⠀⠀⠀⠀",
+                "  └┬───────────────┬",
+                "   0              10",
+            ]
+        );
+
+        // plot area is drawn as an image when terminal supports glyphs
+        let caps = TerminalCaps {
+            glyphs: true,
+            ..Default::default()
+        };
+        let term_size = TerminalSize {
+            cells: Size::new(10, 10),
+            pixels: Size::new(100, 50),
+        };
+        let mut surf = SurfaceOwned::new(6, 20);
+        Plot::new()
+            .with_series(Series::new("up", [(0.0, 0.0), (10.0, 10.0)]))
+            .with_caps(&caps, term_size)
+            .render(&theme, &mut surf)?;
+        assert_eq!(surf.get(1, 3).and_then(|cell| cell.character()), None);
+        assert_eq!(surf.get(4, 2).and_then(|cell| cell.character()), Some('└'));
+        Ok(())
+    }
+
+    /// Widget counting renders and handled events
+    #[derive(Default)]
+    struct Panel {
+        renders: usize,
+        keys: Vec<char>,
+    }
+
+    impl Widget for Panel {
+        fn handle(&mut self, ctx: &mut WidgetContext<'_>, event: &TerminalEvent) -> bool {
+            match event {
+                TerminalEvent::Key(Key {
+                    name: KeyName::Char('q'),
+                    mode: KeyMod::CTRL,
+                }) => ctx.quit(),
+                TerminalEvent::Key(Key {
+                    name: KeyName::Char(c),
+                    ..
+                }) => {
+                    self.keys.push(*c);
+                    ctx.mark_dirty();
+                }
+                _ => return false,
+            }
+            true
+        }
+
+        fn render(
+            &mut self,
+            ctx: &WidgetContext<'_>,
+            mut surf: TerminalSurface<'_>,
+        ) -> Result<(), Error> {
+            self.renders += 1;
+            surf.erase(ctx.theme().list_default);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_widget_tree() -> Result<(), Error> {
+        let key = |name, mode| TerminalEvent::Key(Key::new(name, mode));
+        let mut tree = WidgetTree::new(Panel::default(), Theme::light());
+        let root = tree.root();
+        let first = tree.add(root, Input::new());
+        let panel = tree.add(root, Panel::default());
+        tree.set_constraint(panel, Constraint::Fixed(2));
+        let second = tree.add(panel, Input::new());
+        assert_eq!(tree.focus(), Some(first));
+
+        let mut surf = SurfaceOwned::new(4, 10);
+        tree.render(surf.as_mut())?;
+        assert_eq!(tree.area(first), Some(Rect::new(0, 0, 1, 10)));
+        assert_eq!(tree.area(panel), Some(Rect::new(1, 0, 2, 10)));
+        assert_eq!(tree.area(second), Some(Rect::new(1, 0, 1, 10)));
+        assert_eq!(tree.get::<Panel>(root).map(|p| p.renders), Some(1));
+
+        // keys go to the focused widget, tab moves focus
+        assert!(tree.handle(&key(KeyName::Char('a'), KeyMod::EMPTY)));
+        assert!(tree.handle(&key(KeyName::Tab, KeyMod::EMPTY)));
+        assert_eq!(tree.focus(), Some(second));
+        assert!(tree.handle(&key(KeyName::Char('b'), KeyMod::EMPTY)));
+        assert!(tree.handle(&key(KeyName::Tab, KeyMod::SHIFT)));
+        assert_eq!(tree.focus(), Some(first));
+        let text = |tree: &WidgetTree, id| -> Option<String> {
+            Some(tree.get::<Input>(id)?.get().collect())
+        };
+        assert_eq!(text(&tree, first).as_deref(), Some("a"));
+        assert_eq!(text(&tree, second).as_deref(), Some("b"));
+
+        // unhandled events bubble to the parents
+        assert!(tree.handle(&key(KeyName::Char('c'), KeyMod::ALT)));
+        assert_eq!(
+            tree.get::<Panel>(root).map(|p| p.keys.clone()),
+            Some(vec!['c'])
+        );
+
+        // clicking focuses widget under the mouse
+        tree.handle(&TerminalEvent::Mouse(Mouse {
+            name: KeyName::MouseLeft,
+            mode: KeyMod::PRESS,
+            row: 1,
+            col: 3,
+        }));
+        assert_eq!(tree.focus(), Some(second));
+        assert_eq!(tree.hit_test(2, 3), Some(panel));
+
+        // dirty widgets are rendered together with their children
+        tree.render(surf.as_mut())?;
+        let renders = |tree: &WidgetTree| {
+            let count = |id| tree.get::<Panel>(id).map_or(0, |p| p.renders);
+            (count(root), count(panel))
+        };
+        assert_eq!(renders(&tree), (2, 2));
+        assert!(!tree.is_dirty());
+        tree.render(surf.as_mut())?;
+        assert_eq!(renders(&tree), (2, 2));
+
+        // dirty child does not cause its parents to be rendered
+        assert!(tree.handle(&key(KeyName::Char('x'), KeyMod::EMPTY)));
+        tree.render(surf.as_mut())?;
+        assert_eq!(renders(&tree), (2, 2));
+        assert_eq!(surf.get(1, 1).and_then(|cell| cell.character()), Some('x'));
+
+        // quit request
+        assert!(tree.handle(&key(KeyName::Char('q'), KeyMod::CTRL)));
+        assert!(tree.quit);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_widget_tree_shrink() -> Result<(), Error> {
+        let mut tree = WidgetTree::new(Panel::default(), Theme::light());
+        let root = tree.root();
+        let panel = tree.add(root, Panel::default());
+        tree.set_constraint(panel, Constraint::Fixed(3));
+
+        let mut surf = SurfaceOwned::new(4, 10);
+        tree.render(surf.as_mut())?;
+        assert_eq!(tree.area(panel), Some(Rect::new(0, 0, 3, 10)));
+        surf.set(2, 0, Cell::new(Default::default(), Some('x')));
+
+        // shrunk child causes its parent to redraw the area left behind
+        tree.set_constraint(panel, Constraint::Fixed(1));
+        tree.render(surf.as_mut())?;
+        assert_eq!(tree.area(panel), Some(Rect::new(0, 0, 1, 10)));
+        let renders = |id| tree.get::<Panel>(id).map_or(0, |p| p.renders);
+        assert_eq!((renders(root), renders(panel)), (2, 2));
+        assert_eq!(surf.get(2, 0).and_then(|cell| cell.character()), None);
+
+        Ok(())
+    }
+
+    fn key_event(chord: &str) -> Result<TerminalEvent, Error> {
+        Ok(TerminalEvent::Key(chord.parse()?))
+    }
+
+    #[test]
+    fn test_text_area_edit() -> Result<(), Error> {
+        let mut text = TextArea::new();
+        for c in "hello world".chars() {
+            assert!(text.handle(&TerminalEvent::Key(Key::from(KeyName::Char(c)))));
+        }
+        text.handle(&key_event("enter")?);
+        text.handle(&TerminalEvent::Paste("second\r\nline".to_string()));
+        assert_eq!(text.text(), "hello world\nsecond\nline");
+
+        text.apply(TextAction::CursorStart);
+        text.apply(TextAction::CursorNextWord);
+        text.apply(TextAction::DeleteLineEnd);
+        assert_eq!(text.text(), "hello\nsecond\nline");
+        text.apply(TextAction::DeleteLineEnd);
+        assert_eq!(text.text(), "hellosecond\nline");
+
+        // undo restores text and cursor, redo reapplies
+        text.apply(TextAction::Undo);
+        text.apply(TextAction::Undo);
+        assert_eq!(text.text(), "hello world\nsecond\nline");
+        assert_eq!(text.cursor(), 5);
+        text.apply(TextAction::Redo);
+        assert_eq!(text.text(), "hello\nsecond\nline");
+
+        // typed words are coalesced into single undo step
+        let mut text = TextArea::new();
+        for c in "one two".chars() {
+            text.apply(TextAction::Insert(c));
+        }
+        text.apply(TextAction::Undo);
+        assert_eq!(text.text(), "one ");
+        text.apply(TextAction::Undo);
+        assert_eq!(text.text(), "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_area_wrap() -> Result<(), Error> {
+        let theme = Theme::light();
+        let mut text = TextArea::new();
+        text.set("abcdefgh\nxy\n漢字漢字");
+        let mut surf = SurfaceOwned::new(5, 5);
+        text.render(&theme, surf.as_mut())?;
+        // wide characters occupy two columns
+        assert_eq!(
+            surf_text(&surf),
+            vec!["abcd ", "efgh ", "xy   ", "漢字 ", "漢字 "]
+        );
+
+        // goal column is preserved while moving through the short line
+        text.apply(TextAction::CursorStart);
+        text.apply(TextAction::CursorForward);
+        text.apply(TextAction::CursorForward);
+        text.apply(TextAction::CursorForward);
+        text.apply(TextAction::CursorDown);
+        assert_eq!(text.cursor(), 7);
+        text.apply(TextAction::CursorDown);
+        assert_eq!(text.cursor(), 11);
+        text.apply(TextAction::CursorDown);
+        assert_eq!(text.cursor(), 13);
+        text.apply(TextAction::CursorUp);
+        assert_eq!(text.cursor(), 11);
+        text.apply(TextAction::CursorUp);
+        assert_eq!(text.cursor(), 7);
+
+        // page movement
+        text.apply(TextAction::PageDown);
+        assert_eq!(text.cursor(), 16);
+        text.apply(TextAction::PageUp);
+        assert_eq!(text.cursor(), 0);
+
+        // view follows the cursor
+        text.apply(TextAction::CursorEnd);
+        let mut surf = SurfaceOwned::new(3, 5);
+        text.render(&theme, surf.as_mut())?;
+        assert_eq!(surf_text(&surf), vec!["xy   ", "漢字 ", "漢字 "]);
+
+        // zero width characters are not drawn
+        text.set("e\u{301}x");
+        let mut surf = SurfaceOwned::new(1, 5);
+        text.render(&theme, surf.as_mut())?;
+        assert_eq!(surf_text(&surf), vec!["ex   "]);
+
+        // emoji are wide
+        text.set("🚀🫠x");
+        let mut surf = SurfaceOwned::new(2, 5);
+        text.render(&theme, surf.as_mut())?;
+        assert_eq!(surf_text(&surf), vec!["🚀🫠 ", "x    "]);
+
+        // wrapped rows are cached until the text or the width changes
+        assert!(text.rows.is_some());
+        text.apply(TextAction::CursorBackward);
+        assert!(text.rows.is_some());
+        text.apply(TextAction::Insert('y'));
+        assert!(text.rows.is_none());
+        text.render(&theme, surf.as_mut())?;
+        assert!(text.rows.is_some());
+        let mut surf = SurfaceOwned::new(2, 3);
+        text.render(&theme, surf.as_mut())?;
+        assert_eq!(surf_text(&surf), vec!["🚀 ", "🫠 "]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_area_selection() -> Result<(), Error> {
+        let theme = Theme::light();
+        let mut text = TextArea::new();
+        text.set("one\ntwo\nthree");
+        text.apply(TextAction::CursorStart);
+        text.handle(&key_event("shift+right")?);
+        text.handle(&key_event("shift+down")?);
+        assert_eq!(text.selected().as_deref(), Some("one\nt"));
+        text.handle(&TerminalEvent::Key(Key::from(KeyName::Char('X'))));
+        assert_eq!(text.text(), "Xwo\nthree");
+        text.apply(TextAction::Undo);
+        assert_eq!(text.text(), "one\ntwo\nthree");
+        assert_eq!(text.selected().as_deref(), Some("one\nt"));
+
+        // mouse press and drag
+        let mut surf = SurfaceOwned::new(3, 10);
+        text.render(&theme, surf.as_mut())?;
+        let mouse = |row, col, mode| {
+            TerminalEvent::Mouse(Mouse {
+                name: KeyName::MouseLeft,
+                mode,
+                row,
+                col,
+            })
+        };
+        text.handle(&mouse(1, 1, KeyMod::PRESS));
+        text.handle(&mouse(2, 3, KeyMod::PRESS));
+        text.handle(&mouse(2, 3, KeyMod::EMPTY));
+        assert_eq!(text.selected().as_deref(), Some("wo\nthr"));
+
+        // select all with a chord
+        text.handle(&key_event("ctrl+x")?);
+        text.handle(&key_event("h")?);
+        assert_eq!(text.selection(), Some(0..13));
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_area_bind() -> Result<(), Error> {
+        let mut text = TextArea::new();
+        text.bind(&Key::chord("ctrl+u")?, TextAction::Undo);
+        text.bind(&Key::chord("ctrl+z")?, TextAction::Insert('z'));
+        text.handle(&key_event("a")?);
+        text.handle(&key_event("ctrl+z")?);
+        assert_eq!(text.text(), "az");
+        text.handle(&key_event("ctrl+u")?);
+        assert_eq!(text.text(), "");
+        Ok(())
+    }
+
+    fn input_keys(input: &mut Input, chords: &str) -> Result<(), Error> {
+        for key in Key::chord(chords)? {
+            input.handle(&TerminalEvent::Key(key));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_input_edit() -> Result<(), Error> {
+        let mut input = Input::new();
+        input.set("one two three");
+        input_keys(&mut input, "ctrl+w ctrl+w")?;
+        assert_eq!(input.get().collect::<String>(), "one ");
+        input_keys(&mut input, "ctrl+a alt+d")?;
+        assert_eq!(input.get().collect::<String>(), " ");
+
+        // kills are merged and can be yanked back
+        input_keys(&mut input, "ctrl+y")?;
+        assert_eq!(input.get().collect::<String>(), "one ");
+        input_keys(&mut input, "alt+y")?;
+        assert_eq!(input.get().collect::<String>(), "two three ");
+        input_keys(&mut input, "ctrl+z")?;
+        assert_eq!(input.get().collect::<String>(), " ");
+        input_keys(&mut input, "alt+z")?;
+        assert_eq!(input.get().collect::<String>(), "two three ");
+
+        input.set("ab");
+        input_keys(&mut input, "ctrl+t")?;
+        assert_eq!(input.get().collect::<String>(), "ba");
+        input_keys(&mut input, "ctrl+a ctrl+f ctrl+t")?;
+        assert_eq!(input.get().collect::<String>(), "ab");
+
+        // yank pop cycles through the kill ring
+        let mut input = Input::new();
+        input.set("first second");
+        input_keys(&mut input, "ctrl+w left ctrl+k ctrl+e ctrl+y alt+y")?;
+        assert_eq!(input.get().collect::<String>(), "firstsecond");
+        input_keys(&mut input, "alt+y")?;
+        assert_eq!(input.get().collect::<String>(), "first ");
+
+        // typing replaces selection, undo restores it
+        input.set("hello");
+        input_keys(&mut input, "shift+left shift+left x y")?;
+        assert_eq!(input.get().collect::<String>(), "helxy");
+        input_keys(&mut input, "ctrl+z")?;
+        assert_eq!(input.get().collect::<String>(), "hello");
+
+        // paste is an edit, so it ends yanking and can be undone
+        input.set("one");
+        input_keys(&mut input, "ctrl+w ctrl+y")?;
+        input.handle(&TerminalEvent::Paste("two".to_string()));
+        input_keys(&mut input, "alt+y")?;
+        assert_eq!(input.get().collect::<String>(), "onetwo");
+        input_keys(&mut input, "ctrl+z")?;
+        assert_eq!(input.get().collect::<String>(), "one");
+
+        // undo depth is bounded, cursor movement is not recorded
+        input.set("");
+        for _ in 0..INPUT_UNDO_DEPTH + 10 {
+            assert!(input.apply(InputAction::Insert(' ')));
+            assert!(input.apply(InputAction::CursorBackward));
+            assert!(input.apply(InputAction::CursorForward));
+        }
+        for _ in 0..INPUT_UNDO_DEPTH + 10 {
+            input.apply(InputAction::Undo);
+        }
+        assert_eq!(input.get().count(), 10);
+
+        // click moves cursor and shift click selects
+        let mut surf = SurfaceOwned::new(1, 10);
+        input.set("hello");
+        input.render(&Theme::light(), surf.as_mut())?;
+        let click = |col, mode| {
+            TerminalEvent::Mouse(Mouse {
+                name: KeyName::MouseLeft,
+                mode,
+                row: 0,
+                col,
+            })
+        };
+        input.handle(&click(1, KeyMod::PRESS));
+        input.handle(&click(1, KeyMod::EMPTY));
+        input.handle(&click(4, KeyMod::PRESS | KeyMod::SHIFT));
+        assert_eq!(input.selection(), Some(1..4));
+        input_keys(&mut input, "x")?;
+        assert_eq!(input.get().collect::<String>(), "hxo");
+        Ok(())
+    }
+
+    #[test]
+    fn test_input_history() -> Result<(), Error> {
+        let mut input = Input::new();
+        // nothing to navigate, event is not consumed
+        assert!(!input.handle(&key_event("up")?));
+
+        for entry in ["git status", "ls", "git log", "ls"] {
+            input.history_push(entry);
+        }
+        assert_eq!(input.history(), &["git status", "ls", "git log", "ls"]);
+
+        input.set("gi");
+        input_keys(&mut input, "up")?;
+        assert_eq!(input.get().collect::<String>(), "git log");
+        input_keys(&mut input, "up")?;
+        assert_eq!(input.get().collect::<String>(), "git status");
+        input_keys(&mut input, "up")?;
+        assert_eq!(input.get().collect::<String>(), "git status");
+        input_keys(&mut input, "down")?;
+        assert_eq!(input.get().collect::<String>(), "git log");
+        input_keys(&mut input, "down")?;
+        assert_eq!(input.get().collect::<String>(), "gi");
+        Ok(())
+    }
+
+    #[test]
+    fn test_input_completion() -> Result<(), Error> {
+        let theme = Theme::light();
+        let words = ["commit", "config", "clone"];
+        let mut input = Input::new().with_completer(move |prefix| {
+            words
+                .iter()
+                .filter(|word| word.starts_with(prefix))
+                .map(|word| word.to_string())
+                .collect()
+        });
+        input.set("git cl");
+        input_keys(&mut input, "tab")?;
+        assert_eq!(input.get().collect::<String>(), "git clone");
+        assert!(input.completions().is_empty());
+
+        input.set("git c");
+        input_keys(&mut input, "tab")?;
+        assert_eq!(input.get().collect::<String>(), "git c");
+        assert_eq!(input.completions().len(), 3);
+        input_keys(&mut input, "tab tab")?;
+        assert_eq!(input.get().collect::<String>(), "git config");
+        assert_eq!(input.completion_selected(), Some(1));
+
+        let mut surf = SurfaceOwned::new(3, 12);
+        input.render(&theme, surf.as_mut())?;
+        assert_eq!(
+            surf_text(&surf),
+            vec!["git config  ", " commit     ", " config     "]
+        );
+        input_keys(&mut input, "shift+tab shift+tab")?;
+        assert_eq!(input.get().collect::<String>(), "git clone");
+
+        // mouse click places cursor and closes completion
+        input.handle(&TerminalEvent::Mouse(Mouse {
+            name: KeyName::MouseLeft,
+            mode: KeyMod::PRESS,
+            row: 0,
+            col: 2,
+        }));
+        assert!(input.completions().is_empty());
+        input_keys(&mut input, "x")?;
+        assert_eq!(input.get().collect::<String>(), "gixt clone");
+
+        // tab without candidates is not consumed
+        input.set("git x");
+        assert!(!input.handle(&key_event("tab")?));
+        assert!(input.handle(&key_event("left")?));
+        Ok(())
+    }
+
+    struct Words(Vec<String>);
+
+    impl ListItems for Words {
+        type Item = Word;
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn get(&self, index: usize) -> Option<Self::Item> {
+            self.0.get(index).cloned().map(Word)
+        }
+    }
+
+    struct Word(String);
+
+    impl AsRef<str> for Word {
+        fn as_ref(&self) -> &str {
+            &self.0
+        }
+    }
+
+    impl TerminalDisplay for Word {
+        fn display(&self, surf: &mut TerminalSurface<'_>) -> Result<(), Error> {
+            surf.writer().write_all(self.0.as_bytes())?;
+            Ok(())
+        }
+
+        fn size_hint(&self, _surf_size: Size) -> Option<Size> {
+            None
+        }
+    }
+
+    /// List items spanning multiple rows, one per line of text
+    struct Paragraphs(Vec<String>);
+
+    impl ListItems for Paragraphs {
+        type Item = Paragraph;
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn get(&self, index: usize) -> Option<Self::Item> {
+            self.0.get(index).cloned().map(Paragraph)
+        }
+    }
+
+    struct Paragraph(String);
+
+    impl TerminalDisplay for Paragraph {
+        fn display(&self, surf: &mut TerminalSurface<'_>) -> Result<(), Error> {
+            surf.writer().write_all(self.0.as_bytes())?;
+            Ok(())
+        }
+
+        fn size_hint(&self, surf_size: Size) -> Option<Size> {
+            Some(Size::new(self.0.lines().count(), surf_size.width))
+        }
+    }
+
+    #[test]
+    fn test_fuzzy_items() -> Result<(), Error> {
+        let words = ["src/widgets.rs", "README.md", "src/render.rs", "Cargo.toml"];
+        let mut items = FuzzyItems::new(Words(words.iter().map(|w| w.to_string()).collect()));
+        let ranked = |items: &FuzzyItems<Words>| -> Vec<usize> {
+            (0..items.len()).filter_map(|i| items.index(i)).collect()
+        };
+        assert_eq!(ranked(&items), vec![0, 1, 2, 3]);
+
+        // equal scores keep the original order
+        items.set_pattern("r");
+        assert_eq!(ranked(&items), vec![1, 0, 2, 3]);
+        items.set_pattern("rs");
+        assert_eq!(ranked(&items), vec![0, 2]);
+        items.set_pattern("rsw");
+        assert!(items.is_empty());
+        items.set_pattern("md");
+        assert_eq!(ranked(&items), vec![1]);
+        assert_eq!(
+            items.get(0).map(|item| item.positions().to_vec()),
+            Some(vec![7, 8])
+        );
+
+        // appended items are scored on refresh
+        items.items_mut().0.push("docs/index.md".to_string());
+        items.refresh();
+        assert_eq!(ranked(&items), vec![1, 4]);
+
+        // parallel scoring produces the same ranking
+        let words: Vec<_> = (0..10000).map(|i| format!("item_{}", i)).collect();
+        let mut seq = FuzzyItems::new(Words(words.clone()));
+        let mut par = FuzzyItems::new(Words(words)).with_threads(4);
+        seq.set_pattern("i_99");
+        par.set_pattern("i_99");
+        assert_eq!(ranked(&seq), ranked(&par));
+        assert!(!seq.is_empty());
+
+        // matched characters are highlighted
+        let theme = Theme::light();
+        let mut list = List::new(items.with_highlight(theme.list_matched));
+        list.items_update(|items| items.set_pattern("rdm"));
+        let mut surf = SurfaceOwned::new(1, 16);
+        list.render(&theme, surf.as_mut())?;
+        assert_eq!(surf_text(&surf), vec![" ● README.md    "]);
+        let bold: Vec<_> = (0..surf.width())
+            .filter(|col| {
+                surf.get(0, *col)
+                    .is_some_and(|cell| cell.face().attrs.contains(FaceAttrs::BOLD))
+            })
+            .collect();
+        assert_eq!(bold, vec![3, 6, 7]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_list() -> Result<(), Error> {
+        let theme = Theme::light();
+        let words = ["a", "b\nb2", "c", "d\nd2\nd3", "e"];
+        let mut list = List::new(Paragraphs(words.iter().map(|w| w.to_string()).collect()))
+            .with_header(Paragraph("NAME".to_string()));
+        let mut surf = SurfaceOwned::new(5, 8);
+        list.render(&theme, surf.as_mut())?;
+        assert_eq!(
+            surf_text(&surf),
+            vec!["   NAME ", " ● a    ", "   b    ", "   b2   ", "   c    "]
+        );
+
+        // multi-row items are scrolled into view
+        assert!(list.handle(&key_event("end")?));
+        list.render(&theme, surf.as_mut())?;
+        assert_eq!(
+            surf_text(&surf),
+            vec!["   NAME ", "   d    ", "   d2   ", "   d3   ", " ● e    "]
+        );
+
+        // click selects item under the cursor, header is ignored
+        let click = |row, mode| {
+            TerminalEvent::Mouse(Mouse {
+                name: KeyName::MouseLeft,
+                mode: KeyMod::PRESS | mode,
+                row,
+                col: 4,
+            })
+        };
+        assert!(!list.handle(&click(0, KeyMod::EMPTY)));
+        assert!(list.handle(&click(2, KeyMod::EMPTY)));
+        assert_eq!(list.cursor(), 3);
+        list.handle(&click(4, KeyMod::CTRL));
+        assert_eq!(list.marked().collect::<Vec<_>>(), vec![4]);
+        list.render(&theme, surf.as_mut())?;
+        assert_eq!(surf_text(&surf)[4], "▌● e    ");
+
+        // range marking
+        list.handle(&key_event("home")?);
+        list.handle(&key_event("shift+down")?);
+        list.handle(&key_event("shift+down")?);
+        assert_eq!(list.marked().collect::<Vec<_>>(), vec![0, 1, 2, 4]);
+        list.handle(&key_event("ctrl+space")?);
+        assert!(!list.is_marked(2));
+        list.handle(&key_event("alt+u")?);
+        assert_eq!(list.marked().count(), 0);
+        list.handle(&key_event("alt+a")?);
+        assert_eq!(list.marked().count(), 5);
+        list.handle(&key_event("alt+u")?);
+        list.handle(&key_event("home")?);
+        list.render(&theme, surf.as_mut())?;
+        list.handle(&click(1, KeyMod::EMPTY));
+        list.handle(&click(4, KeyMod::SHIFT));
+        assert_eq!(list.marked().collect::<Vec<_>>(), vec![0, 1, 2]);
+
+        // wheel moves the cursor and jump selects by index
+        list.handle(&TerminalEvent::Mouse(Mouse {
+            name: KeyName::MouseWheelDown,
+            mode: KeyMod::EMPTY,
+            row: 0,
+            col: 0,
+        }));
+        assert_eq!(list.cursor(), 3);
+        list.apply(ListAction::ItemJump(1));
+        assert_eq!(list.current().map(|word| word.0), Some("b\nb2".to_string()));
+        Ok(())
+    }
 }